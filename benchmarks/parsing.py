@@ -0,0 +1,108 @@
+"""
+Benchmarks of the hot paths from loading a CLI schema through to parsing args.
+
+Run from the repository root with:
+
+    python3 benchmarks/parsing.py [--repeat N] [--filter TEXT]
+
+The cases cover loading small and large schemas, constructing the parser, and
+matching args against commands with many options, giving the median time of
+each. See 'benchmarks/README.md' for baseline results.
+
+"""
+
+import argparse
+import pathlib
+import statistics
+import sys
+import tempfile
+import timeit
+from typing import Callable, List, Tuple
+
+import yaml
+
+
+sys.path.insert(0, str(pathlib.Path(__file__).parent.parent))
+
+import dcli  # noqa: E402
+from schema_cache import make_schema  # noqa: E402
+
+
+Case = Tuple[str, Callable[[], object]]
+
+
+def _make_cases(tmp_dir: pathlib.Path) -> List[Case]:
+    small = make_schema(5, args_per_command=3)
+    large = make_schema(500)
+    small_file = tmp_dir / "small.yaml"
+    small_file.write_text(yaml.safe_dump(small))
+    large_file = tmp_dir / "large.yaml"
+    large_file.write_text(yaml.safe_dump(large))
+    small_schema = dcli.load_from_path(small_file)
+    large_schema = dcli.load_from_path(large_file)
+    small_parser = dcli.CLIParser(small_schema, prog="bench")
+    large_parser = dcli.CLIParser(large_schema, prog="bench")
+    # Values for the options of each type, see `make_schema()`.
+    values = ["value", "42", "a/b", "30s", "10K"]
+    options = []
+    for i in range(10):
+        options += ["--option-{}".format(i), values[i % len(values)]]
+    many_args = ["command-250", "target"] + options + ["--mode", "fast"]
+    return [
+        ("load small schema (5 commands)", lambda: dcli.load_from_path(small_file)),
+        (
+            "load large schema (500 commands)",
+            lambda: dcli.load_from_path(large_file),
+        ),
+        ("build from dict (500 commands)", lambda: dcli.RootNode.from_dict(large)),
+        (
+            "construct parser (small)",
+            lambda: dcli.CLIParser(small_schema, prog="bench"),
+        ),
+        (
+            "construct parser (large)",
+            lambda: dcli.CLIParser(large_schema, prog="bench"),
+        ),
+        (
+            "parse command (small)",
+            lambda: small_parser.parse_args(["command-2", "x"]),
+        ),
+        (
+            "parse command (large)",
+            lambda: large_parser.parse_args(["command-250", "x"]),
+        ),
+        ("parse many options (large)", lambda: large_parser.parse_args(many_args)),
+    ]
+
+
+def _time(func: Callable[[], object], repeat: int) -> float:
+    """The median time of a call, in seconds."""
+    timer = timeit.Timer(func)
+    number, _ = timer.autorange()
+    return statistics.median([t / number for t in timer.repeat(repeat, number)])
+
+
+def _format_time(seconds: float) -> str:
+    if seconds < 1e-3:
+        return "{:8.1f} us".format(seconds * 1e6)
+    return "{:8.2f} ms".format(seconds * 1e3)
+
+
+def main(argv=None) -> None:
+    parser = argparse.ArgumentParser(description=__doc__.strip().splitlines()[0])
+    parser.add_argument("--repeat", type=int, default=5)
+    parser.add_argument("--filter", help="Only run the cases containing the text")
+    args = parser.parse_args(argv)
+
+    with tempfile.TemporaryDirectory() as tmp_dir:
+        cases = _make_cases(pathlib.Path(tmp_dir))
+        width = max(len(name) for name, _ in cases)
+        for name, func in cases:
+            if args.filter and args.filter not in name:
+                continue
+            seconds = _time(func, args.repeat)
+            print("{:<{}}  {}".format(name, width, _format_time(seconds)))
+
+
+if __name__ == "__main__":
+    main()
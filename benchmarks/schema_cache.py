@@ -0,0 +1,86 @@
+"""
+Benchmark of loading a large CLI schema with and without the schema cache.
+
+Run from the repository root with:
+
+    python3 benchmarks/schema_cache.py [--commands N] [--repeat N]
+
+"""
+
+import argparse
+import pathlib
+import sys
+import tempfile
+import timeit
+
+import yaml
+
+
+sys.path.insert(0, str(pathlib.Path(__file__).parent.parent))
+
+import dcli  # noqa: E402
+
+
+def make_schema(num_commands: int, args_per_command: int = 10) -> dict:
+    """Create a schema with many commands, each with a variety of args."""
+    types = ["string", "integer", "path", "duration", "size"]
+    subtree = []
+    for i in range(num_commands):
+        args = [{"name": "target", "positional": True, "help": "The target"}]
+        for j in range(args_per_command):
+            args.append(
+                {
+                    "name": "option-{}".format(j),
+                    "type": types[j % len(types)],
+                    "help": "Option {} of command {}".format(j, i),
+                }
+            )
+        args.append(
+            {
+                "name": "mode",
+                "enum": ["fast", "safe", "full"],
+                "default": "safe",
+                "help": "The mode",
+            }
+        )
+        subtree.append(
+            {
+                "keyword": "command-{}".format(i),
+                "help": "Command number {}".format(i),
+                "command": "command-{}".format(i),
+                "args": args,
+            }
+        )
+    return {"help": "Benchmark CLI", "subtree": subtree}
+
+
+def main(argv=None) -> None:
+    parser = argparse.ArgumentParser(description=__doc__.strip().splitlines()[0])
+    parser.add_argument("--commands", type=int, default=500)
+    parser.add_argument("--repeat", type=int, default=10)
+    args = parser.parse_args(argv)
+
+    with tempfile.TemporaryDirectory() as tmp_dir:
+        schema_file = pathlib.Path(tmp_dir) / "cli.yaml"
+        schema_file.write_text(yaml.safe_dump(make_schema(args.commands)))
+        size = schema_file.stat().st_size
+        cache_dir = pathlib.Path(tmp_dir) / "cache"
+        # Populate the cache.
+        dcli.load_from_path(schema_file, cache_dir=cache_dir)
+
+        uncached = timeit.timeit(
+            lambda: dcli.load_from_path(schema_file), number=args.repeat
+        )
+        cached = timeit.timeit(
+            lambda: dcli.load_from_path(schema_file, cache_dir=cache_dir),
+            number=args.repeat,
+        )
+
+    print("Schema with {} commands ({} KiB):".format(args.commands, size // 1024))
+    print("  uncached: {:8.2f} ms per load".format(uncached / args.repeat * 1000))
+    print("  cached:   {:8.2f} ms per load".format(cached / args.repeat * 1000))
+    print("  speedup:  {:8.1f}x".format(uncached / cached))
+
+
+if __name__ == "__main__":
+    main()
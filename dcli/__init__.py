@@ -3,19 +3,120 @@
 """
 Declarative CLI package.
 
+The names exported here are the package's public API, which other projects
+can depend on (the underscore-prefixed modules are internal). The main entry
+points are:
+ - `load_from_path()`, giving the schema as a `RootNode`.
+ - `CLIParser` (or `create_cli_parser()`), for parsing args against a schema.
+ - `Dispatcher` and `run_and_exit()`, for calling handlers for the commands.
+
 """
 
-__all__ = ("Frontend", "create_cli_parser")
+__version__ = "0.1.0"
 
-import enum
-from typing import Type
+__all__ = (
+    "Arg",
+    "ArgType",
+    "CLIError",
+    "CLIParser",
+    "CancellationToken",
+    "ChainError",
+    "CommandCancelledError",
+    "CommandTerminatedError",
+    "CommandTimeoutError",
+    "ConfigError",
+    "DispatchError",
+    "Dispatcher",
+    "Frontend",
+    "NodeBuilder",
+    "ParseError",
+    "Progress",
+    "RootNode",
+    "SchemaChange",
+    "SchemaEditor",
+    "SchemaError",
+    "SchemaFormat",
+    "SchemaValidationError",
+    "Secret",
+    "SourceSpan",
+    "SubNode",
+    "Telemetry",
+    "UnauthorizedError",
+    "UsageEvent",
+    "build_argparse_parser",
+    "canonicalize",
+    "check_examples",
+    "check_schema",
+    "confirm_destructive",
+    "create_cli_parser",
+    "diff_schemas",
+    "generate_aliases",
+    "generate_completions",
+    "generate_rust",
+    "json_schema",
+    "load_from_path",
+    "migrate",
+    "render_manpages",
+    "render_markdown",
+    "render_output",
+    "render_tree",
+    "run_and_exit",
+    "run_repl",
+    "self_update",
+    "setup_logging",
+    "validate",
+)
 
-import yaml
+import enum
+from typing import Optional, Type
 
-from ._schema import RootNode
+from ._aliases import generate_aliases
+from ._builder import NodeBuilder
+from ._canonical import canonicalize
+from ._codegen import generate_rust
+from ._completions import generate_completions
+from ._diff import SchemaChange, diff_schemas
+from ._docs import render_manpages, render_markdown, render_tree
+from ._dispatch import CancellationToken, Dispatcher
+from ._edit import SchemaEditor
+from ._examples import check_examples
+from ._errors import (
+    CLIError,
+    ChainError,
+    CommandCancelledError,
+    CommandTerminatedError,
+    CommandTimeoutError,
+    ConfigError,
+    DispatchError,
+    ParseError,
+    SchemaError,
+    SchemaValidationError,
+    UnauthorizedError,
+)
+from ._logging import setup_logging
+from ._output import render_output
+from ._progress import Progress
+from ._repl import run_repl
+from ._run import confirm_destructive, run_and_exit
+from ._schema import (
+    Arg,
+    RootNode,
+    SchemaFormat,
+    SourceSpan,
+    SubNode,
+    check_schema,
+    json_schema,
+    load_from_path,
+    migrate,
+    validate,
+)
+from ._self_update import self_update
+from ._telemetry import Telemetry, UsageEvent
+from ._types import ArgType, Secret
 from ._utils import PathLike
 from .clis import bot as bot_cli
 from .clis import standard as standard_cli
+from .clis.standard import CLIParser, build_argparse_parser
 
 
 class Frontend(enum.Enum):
@@ -34,20 +135,22 @@ class Frontend(enum.Enum):
 
 
 def create_cli_parser(
-    file: PathLike, frontend: Frontend = Frontend.ARGPARSE, **kwargs
+    file: Optional[PathLike] = None, frontend: Frontend = Frontend.ARGPARSE, **kwargs
 ) -> clis.AbstractCLIParser:
     """
     Create and return a CLI parser.
 
     :param file:
-        The file declaring the CLI.
+        The file declaring the CLI, or None to look for 'cli.yaml' in the
+        default locations.
     :param frontend:
         The frontend to use for parsing the CLI args.
     :param kwargs:
         Passed on the to CLI parser at creation.
     :return:
         A CLI parser instance.
+    :raises SchemaError:
+        If the schema file can't be loaded.
     """
-    with open(file) as f:
-        loaded_schema = RootNode.from_dict(yaml.safe_load(f))
+    loaded_schema = load_from_path(file)
     return frontend.get_parser()(loaded_schema, **kwargs)
@@ -0,0 +1,335 @@
+"""
+Command-line tooling for working with CLI schemas.
+
+"""
+
+import argparse
+import json
+import os
+import sys
+from typing import List, Optional
+
+from . import (
+    SchemaError,
+    SchemaValidationError,
+    check_examples,
+    diff_schemas,
+    generate_aliases,
+    generate_completions,
+    generate_rust,
+    json_schema,
+    load_from_path,
+    migrate,
+    render_manpages,
+    render_markdown,
+    render_tree,
+    validate,
+)
+from ._aliases import ALIAS_SHELLS
+from ._completions import SHELLS
+from .clis.standard import CLIParser
+
+
+def _write_output(text: str, output: Optional[str]) -> None:
+    """Write generated text to a file, or to stdout if none is given."""
+    if output:
+        with open(output, "w") as f:
+            f.write(text)
+    else:
+        sys.stdout.write(text)
+
+
+def _load_schema(args: argparse.Namespace):
+    """
+    :raises SchemaError:
+        If the schema file is invalid.
+    """
+    return load_from_path(args.file)
+
+
+def _check(args: argparse.Namespace) -> int:
+    try:
+        schema = _load_schema(args)
+    except SchemaValidationError as e:
+        # Report each of the problems found loading the schema, as below.
+        for problem in e.problems:
+            print("ERROR:", problem, file=sys.stderr)
+        return 1
+    problems = validate(schema, commands=args.commands)
+    if args.check_examples:
+        # Check the examples of all commands, whatever roles they require.
+        roles = {
+            r for n in schema.walk() for r in getattr(n, "requires_role", None) or []
+        }
+        parser = CLIParser(schema, prog="prog", role_provider=lambda: roles)
+        problems += check_examples(parser)
+    for problem in problems:
+        print("ERROR:", problem, file=sys.stderr)
+    if problems:
+        return 1
+    print("Schema is valid")
+    return 0
+
+
+def _diff(args: argparse.Namespace) -> int:
+    old, new = (load_from_path(f) for f in (args.old, args.new))
+    changes = diff_schemas(old, new)
+    for change in changes:
+        print(change)
+    if any(c.breaking for c in changes):
+        return 1
+    if not changes:
+        print("No changes")
+    return 0
+
+
+def _migrate(args: argparse.Namespace) -> int:
+    if migrate(args.file):
+        print("Schema migrated to the current format")
+    else:
+        print("Schema is already in the current format")
+    return 0
+
+
+def _tree(args: argparse.Namespace) -> int:
+    schema = _load_schema(args)
+    sys.stdout.write(render_tree(schema, prog=args.prog, with_args=args.args))
+    return 0
+
+
+def _gen_rust(args: argparse.Namespace) -> int:
+    _write_output(generate_rust(_load_schema(args), prog=args.prog), args.output)
+    return 0
+
+
+def _gen_completions(args: argparse.Namespace) -> int:
+    schema = _load_schema(args)
+    _write_output(generate_completions(schema, args.shell, prog=args.prog), args.output)
+    return 0
+
+
+def _gen_aliases(args: argparse.Namespace) -> int:
+    schema = _load_schema(args)
+    _write_output(generate_aliases(schema, args.shell, prog=args.prog), args.output)
+    return 0
+
+
+def _gen_json_schema(args: argparse.Namespace) -> int:
+    _write_output(json.dumps(json_schema(), indent=2) + "\n", args.output)
+    return 0
+
+
+def _docs_man(args: argparse.Namespace) -> int:
+    pages = render_manpages(
+        _load_schema(args), prog=args.prog, section=args.section, version=args.version
+    )
+    os.makedirs(args.output, exist_ok=True)
+    for filename, page in pages.items():
+        with open(os.path.join(args.output, filename), "w") as f:
+            f.write(page)
+    print("Wrote {} man pages to {}".format(len(pages), args.output))
+    return 0
+
+
+def _docs_markdown(args: argparse.Namespace) -> int:
+    _write_output(render_markdown(_load_schema(args), prog=args.prog), args.output)
+    return 0
+
+
+def _run_legacy(args: argparse.Namespace) -> int:
+    """Handle the options used before subcommands were introduced."""
+    if args.json_schema:
+        return _gen_json_schema(args)
+    if args.legacy_migrate:
+        args.file = args.legacy_migrate
+        return _migrate(args)
+    if args.legacy_diff:
+        args.old, args.new = args.legacy_diff
+        return _diff(args)
+    if args.gen_rust:
+        args.file = args.gen_rust
+        return _gen_rust(args)
+    args.file = args.validate_schema
+    return _check(args)
+
+
+def _add_file_arg(parser: argparse.ArgumentParser) -> None:
+    parser.add_argument("file", metavar="FILE", help="The schema file")
+
+
+def _add_prog_arg(parser: argparse.ArgumentParser, *, required: bool) -> None:
+    parser.add_argument(
+        "--prog",
+        required=required,
+        help="The program name, as invoked by users",
+    )
+
+
+def _add_output_arg(parser: argparse.ArgumentParser) -> None:
+    parser.add_argument(
+        "-o",
+        "--output",
+        metavar="FILE",
+        help="The file to write to, defaults to stdout",
+    )
+
+
+def _add_check_args(parser: argparse.ArgumentParser, *, hidden: bool) -> None:
+    parser.add_argument(
+        "--commands",
+        metavar="COMMAND",
+        nargs="+",
+        help=argparse.SUPPRESS
+        if hidden
+        else "The commands available, to check the schema's command references",
+    )
+    parser.add_argument(
+        "--check-examples",
+        action="store_true",
+        help=argparse.SUPPRESS
+        if hidden
+        else "Also check that the examples declared in the schema parse",
+    )
+
+
+def _make_parser() -> argparse.ArgumentParser:
+    parser = argparse.ArgumentParser(prog="declarative-cli", description=__doc__)
+    subparsers = parser.add_subparsers(metavar="COMMAND")
+
+    check_parser = subparsers.add_parser(
+        "check", help="Check a schema file for problems"
+    )
+    _add_file_arg(check_parser)
+    _add_check_args(check_parser, hidden=False)
+    check_parser.set_defaults(func=_check)
+
+    diff_parser = subparsers.add_parser(
+        "diff",
+        help="Compare two versions of a schema file, failing on breaking changes",
+    )
+    diff_parser.add_argument("old", metavar="OLD", help="The old schema file")
+    diff_parser.add_argument("new", metavar="NEW", help="The new schema file")
+    diff_parser.set_defaults(func=_diff)
+
+    migrate_parser = subparsers.add_parser(
+        "migrate",
+        help="Rewrite a schema file in an older format to the current format",
+    )
+    _add_file_arg(migrate_parser)
+    migrate_parser.set_defaults(func=_migrate)
+
+    tree_parser = subparsers.add_parser(
+        "tree", help="Print an outline of a schema's command tree"
+    )
+    _add_file_arg(tree_parser)
+    tree_parser.add_argument(
+        "--args", action="store_true", help="Also show the args of each command"
+    )
+    tree_parser.add_argument(
+        "--prog", default="prog", help="The program name to show for the root"
+    )
+    tree_parser.set_defaults(func=_tree)
+
+    gen_parser = subparsers.add_parser("gen", help="Generate code from a schema file")
+    gen_subparsers = gen_parser.add_subparsers(metavar="KIND")
+    gen_subparsers.required = True
+    rust_parser = gen_subparsers.add_parser(
+        "rust", help="Generate Rust source declaring the CLI with clap's derive API"
+    )
+    _add_file_arg(rust_parser)
+    _add_prog_arg(rust_parser, required=False)
+    _add_output_arg(rust_parser)
+    rust_parser.set_defaults(func=_gen_rust)
+    completions_parser = gen_subparsers.add_parser(
+        "completions", help="Generate a shell completion script"
+    )
+    completions_parser.add_argument("shell", choices=SHELLS, help="The shell")
+    _add_file_arg(completions_parser)
+    _add_prog_arg(completions_parser, required=True)
+    _add_output_arg(completions_parser)
+    completions_parser.set_defaults(func=_gen_completions)
+    aliases_parser = gen_subparsers.add_parser(
+        "aliases", help="Generate shell functions for the schema's 'aliases_script'"
+    )
+    aliases_parser.add_argument("shell", choices=ALIAS_SHELLS, help="The shell")
+    _add_file_arg(aliases_parser)
+    _add_prog_arg(aliases_parser, required=True)
+    _add_output_arg(aliases_parser)
+    aliases_parser.set_defaults(func=_gen_aliases)
+    json_schema_parser = gen_subparsers.add_parser(
+        "json-schema", help="Generate the JSON Schema describing the schema file format"
+    )
+    _add_output_arg(json_schema_parser)
+    json_schema_parser.set_defaults(func=_gen_json_schema)
+
+    docs_parser = subparsers.add_parser(
+        "docs", help="Generate documentation from a schema file"
+    )
+    docs_subparsers = docs_parser.add_subparsers(metavar="FORMAT")
+    docs_subparsers.required = True
+    man_parser = docs_subparsers.add_parser(
+        "man", help="Generate a man page for each command"
+    )
+    _add_file_arg(man_parser)
+    _add_prog_arg(man_parser, required=True)
+    man_parser.add_argument(
+        "-o",
+        "--output",
+        metavar="DIR",
+        required=True,
+        help="The directory to write the man pages to",
+    )
+    man_parser.add_argument(
+        "--section", type=int, default=1, help="The manual section, defaults to 1"
+    )
+    man_parser.add_argument("--version", help="The version shown in the footer")
+    man_parser.set_defaults(func=_docs_man)
+    markdown_parser = docs_subparsers.add_parser(
+        "markdown", help="Generate a Markdown reference of all commands"
+    )
+    _add_file_arg(markdown_parser)
+    _add_prog_arg(markdown_parser, required=True)
+    _add_output_arg(markdown_parser)
+    markdown_parser.set_defaults(func=_docs_markdown)
+
+    _add_check_args(parser, hidden=True)
+
+    # The options used before subcommands were introduced, kept for
+    # compatibility but no longer shown in the help.
+    parser.add_argument("--validate-schema", help=argparse.SUPPRESS)
+    parser.add_argument("--json-schema", action="store_true", help=argparse.SUPPRESS)
+    parser.add_argument("--migrate", dest="legacy_migrate", help=argparse.SUPPRESS)
+    parser.add_argument("--gen-rust", help=argparse.SUPPRESS)
+    parser.add_argument("--diff", dest="legacy_diff", nargs=2, help=argparse.SUPPRESS)
+    parser.add_argument("--prog", dest="legacy_prog", help=argparse.SUPPRESS)
+    parser.add_argument("-o", "--output", dest="legacy_output", help=argparse.SUPPRESS)
+    return parser
+
+
+def main(argv: Optional[List[str]] = None) -> int:
+    parser = _make_parser()
+    args = parser.parse_args(argv)
+    is_legacy = (
+        args.validate_schema
+        or args.json_schema
+        or args.legacy_migrate
+        or args.legacy_diff
+        or args.gen_rust
+    )
+    if is_legacy and hasattr(args, "func"):
+        parser.error("the legacy options can't be used with a command")
+    if not is_legacy and not hasattr(args, "func"):
+        parser.error("a command is required")
+    try:
+        if is_legacy:
+            args.prog = args.legacy_prog
+            args.output = args.legacy_output
+            return _run_legacy(args)
+        return args.func(args)
+    except SchemaError as e:
+        print("ERROR:", e, file=sys.stderr)
+        return e.exit_code
+
+
+if __name__ == "__main__":
+    sys.exit(main())
@@ -0,0 +1,90 @@
+"""
+Generation of shell functions for the aliases declared in a schema's
+'aliases_script' section, giving short names to deeply nested commands.
+
+"""
+
+__all__ = ("ALIAS_SHELLS", "generate_aliases")
+
+import re
+import shlex
+from typing import List
+
+from ._completions import _first_line, _fish_quote
+from ._schema import RootNode
+
+
+ALIAS_SHELLS = ("bash", "zsh", "fish")
+
+
+def _fish_word(value: str) -> str:
+    """Quote a word for fish, if necessary."""
+    if re.fullmatch(r"[\w@%+=:,./-]+", value):
+        return value
+    return _fish_quote(value)
+
+
+def _generate_posix(schema: RootNode, shell: str, prog: str) -> str:
+    lines = [
+        "# {} aliases for {}, generated from the CLI schema.".format(
+            shell.capitalize(), prog
+        ),
+        "# Load with: source <this file>",
+    ]
+    for name, words in sorted(schema.aliases_script.items()):
+        argv = [prog] + shlex.split(words)
+        lines += [
+            "",
+            "# " + _first_line(schema.get_alias_node(name).help),
+            '{}() {{ {} "$@"; }}'.format(
+                name, " ".join(shlex.quote(w) for w in argv)
+            ),
+        ]
+    return "\n".join(lines) + "\n"
+
+
+def _generate_fish(schema: RootNode, prog: str) -> str:
+    lines = [
+        "# Fish aliases for {}, generated from the CLI schema.".format(prog),
+        "# Load with: source <this file>",
+    ]  # type: List[str]
+    for name, words in sorted(schema.aliases_script.items()):
+        argv = [prog] + shlex.split(words)
+        lines += [
+            "",
+            "function {} --wraps {} --description {}".format(
+                name,
+                _fish_quote(" ".join(shlex.quote(w) for w in argv)),
+                _fish_quote(_first_line(schema.get_alias_node(name).help)),
+            ),
+            "    {} $argv".format(" ".join(_fish_word(w) for w in argv)),
+            "end",
+        ]
+    return "\n".join(lines) + "\n"
+
+
+def generate_aliases(schema: RootNode, shell: str, *, prog: str) -> str:
+    """
+    Generate a script defining a shell function for each alias in the schema's
+    'aliases_script' section, passing on any args given to the alias.
+
+    :param schema:
+        The schema to generate aliases for.
+    :param shell:
+        The shell to generate the script for, one of `ALIAS_SHELLS`.
+    :param prog:
+        The name of the program the aliases run.
+    :return:
+        The script, to be sourced by the shell.
+    :raises ValueError:
+        If the shell is not supported.
+    """
+    if shell not in ALIAS_SHELLS:
+        raise ValueError(
+            "Unsupported shell {!r}, supported shells are: {}".format(
+                shell, ", ".join(ALIAS_SHELLS)
+            )
+        )
+    if shell == "fish":
+        return _generate_fish(schema, prog)
+    return _generate_posix(schema, shell, prog)
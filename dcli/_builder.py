@@ -0,0 +1,107 @@
+"""
+Building of schema nodes programmatically.
+
+"""
+
+__all__ = ("NodeBuilder",)
+
+from typing import Any, Dict, Optional
+
+from ._schema import NodeBase, RootNode, SubNode
+
+
+class NodeBuilder:
+    """
+    Builder for schema nodes, taking the same fields as schema files.
+
+    For example, to register a command at startup alongside those declared in
+    a schema file:
+
+        node = (
+            NodeBuilder("sync", help="Sync the files", command="sync")
+            .arg("force", help="Overwrite changes", type="flag")
+            .subnode("status", help="Show the sync status", command="sync-status")
+            .end()
+            .build()
+        )
+        schema.add_subnode(node)
+
+    A builder created without a keyword builds a root node.
+    """
+
+    def __init__(
+        self,
+        keyword: Optional[str] = None,
+        *,
+        _parent: Optional["NodeBuilder"] = None,
+        **fields: Any
+    ):
+        """
+        :param keyword:
+            The node's keyword, or None for a root node.
+        :param fields:
+            The node's fields, as in a schema file, e.g. 'help' and 'command'.
+        """
+        self._data = dict(fields)  # type: Dict[str, Any]
+        if keyword is not None:
+            self._data["keyword"] = keyword
+        self._parent = _parent
+
+    def arg(self, name: str, **fields: Any) -> "NodeBuilder":
+        """
+        Add an arg to the node.
+
+        :param name:
+            The arg's name.
+        :param fields:
+            The arg's fields, as in a schema file, e.g. 'help' and 'type'.
+        :return:
+            This builder.
+        """
+        self._data.setdefault("args", []).append(dict(fields, name=name))
+        return self
+
+    def subnode(self, keyword: str, **fields: Any) -> "NodeBuilder":
+        """
+        Add a node to the node's subtree.
+
+        :param keyword:
+            The subnode's keyword.
+        :param fields:
+            The subnode's fields, as in a schema file.
+        :return:
+            A builder for the subnode, see `end()`.
+        """
+        builder = NodeBuilder(keyword, _parent=self, **fields)
+        self._data.setdefault("subtree", []).append(builder._data)
+        return builder
+
+    def end(self) -> "NodeBuilder":
+        """
+        Finish building a subnode.
+
+        :return:
+            The builder for the parent node.
+        :raises ValueError:
+            If this builder is not for a subnode.
+        """
+        if self._parent is None:
+            raise ValueError("No parent node to return to")
+        return self._parent
+
+    def build(self) -> NodeBase:
+        """
+        Build the node, or the root of the builder chain if called on a
+        subnode's builder.
+
+        :return:
+            A `RootNode` if the top builder has no keyword, otherwise a
+            `SubNode`.
+        :raises SchemaError:
+            If the node's fields are invalid.
+        """
+        if self._parent is not None:
+            return self._parent.build()
+        if "keyword" in self._data:
+            return SubNode.from_dict(self._data, location=self._data["keyword"])
+        return RootNode.from_dict(self._data)
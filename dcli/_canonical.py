@@ -0,0 +1,97 @@
+"""
+Reconstruction of a normalized command line from parsed args, e.g. for audit
+logs or for showing how to re-run a command without being prompted.
+
+"""
+
+__all__ = ("canonicalize",)
+
+import datetime
+from typing import Any, List, Optional
+
+from ._schema import Arg, NodeBase
+from ._types import ArgType, Secret
+from .clis import ArgSource, Namespace
+
+
+def _format_value(value: Any) -> str:
+    """Format a converted value so that it converts back to the same value."""
+    if isinstance(value, Secret):
+        return Secret.REDACTED
+    if isinstance(value, bool):
+        return "true" if value else "false"
+    if isinstance(value, datetime.timedelta):
+        seconds = value.total_seconds()
+        if seconds == int(seconds):
+            seconds = int(seconds)
+        # Negative durations can only be given as a plain number of seconds.
+        return "{}s".format(seconds) if seconds >= 0 else str(seconds)
+    if isinstance(value, datetime.datetime):
+        return value.isoformat()
+    return str(value)
+
+
+def _format_option(arg: Arg, value: Any) -> List[str]:
+    """The args giving a value for a non-positional arg."""
+    option = "--" + arg.long
+    if arg.count:
+        return [option] * value
+    if arg.type is ArgType.FLAG:
+        return [option] if value else []
+    if arg.type is ArgType.MAP:
+        return [
+            "{}={}={}".format(option, k, _format_value(v)) for k, v in value.items()
+        ]
+    if arg.multiple and not arg.repeat:
+        return [option] + [_format_value(x) for x in value]
+    values = value if arg.is_list else [value]
+    return ["{}={}".format(option, _format_value(x)) for x in values]
+
+
+def canonicalize(
+    node: NodeBase, namespace: Namespace, *, prog: Optional[str] = None
+) -> List[str]:
+    """
+    Rebuild a normalized command line from parsed args.
+
+    All values not taken from a default are given explicitly, including those
+    from the environment, config files and prompts, using long option names
+    and '--name=value' where possible. Secret values are redacted.
+
+    The result can be joined with `shlex.quote()` for display, e.g. as a hint
+    for re-running a command after its values were prompted for.
+
+    :param node:
+        The node of the command that was parsed.
+    :param namespace:
+        The parsed args.
+    :param prog:
+        The program name to start the command line with, if any.
+    :return:
+        The command line, including the keywords leading to the command.
+    """
+    sources = getattr(namespace, "arg_sources", {})
+    positionals = []
+    options = []
+    passthrough = []
+    for arg in node.all_args:
+        value = getattr(namespace, arg.dest, None)
+        if sources.get(arg.dest, ArgSource.DEFAULT) is ArgSource.DEFAULT:
+            continue
+        if value is None:
+            continue
+        if arg.passthrough:
+            passthrough += [_format_value(x) for x in value]
+        elif arg.positional:
+            values = value if arg.is_list else [value]
+            positionals += [_format_value(x) for x in values]
+        else:
+            options += _format_option(arg, value)
+    result = ([prog] if prog is not None else []) + list(node.path)
+    if any(x.startswith("-") for x in positionals) and not passthrough:
+        # Positionals like options can only be given after '--'.
+        return result + options + ["--"] + positionals
+    result += positionals + options
+    if passthrough:
+        result += ["--"] + passthrough
+    return result
@@ -0,0 +1,242 @@
+"""
+Generation of standalone source code from a CLI schema.
+
+The generated code declares the same commands and args as the schema, so that
+the schema can remain the source of truth without a runtime dependency on
+this package. Only the command-line structure is translated, i.e. features
+handled after parsing (such as config files, templates, validation and
+constraints other than conflicts) are not included.
+
+"""
+
+__all__ = ("generate_rust",)
+
+import re
+from typing import List, Optional
+
+from ._schema import Arg, NodeBase, RootNode
+from ._types import ArgType
+
+
+# Rust keywords that need to be written as raw identifiers.
+_RUST_KEYWORDS = set(
+    """
+    as async await break const continue crate dyn else enum extern false fn for
+    if impl in let loop match mod move mut pub ref return static struct trait
+    true type unsafe use where while abstract become box do final macro override
+    priv try typeof unsized virtual yield
+    """.split()
+)
+
+_RUST_TYPES = {
+    ArgType.STRING: "String",
+    ArgType.INTEGER: "i64",
+    ArgType.FLOAT: "f64",
+    ArgType.BOOLEAN: "bool",
+    ArgType.FLAG: "bool",
+    ArgType.TEXT: "String",
+    ArgType.PATH: "std::path::PathBuf",
+    # Types without a standard library equivalent are left as strings.
+    ArgType.URL: "String",
+    ArgType.DURATION: "String",
+    ArgType.DATETIME: "String",
+    ArgType.SIZE: "String",
+    ArgType.MAP: "String",
+}
+
+
+def _pascal_case(words: List[str]) -> str:
+    parts = [p for w in words for p in re.split(r"[^0-9A-Za-z]+", w) if p]
+    name = "".join(p[0].upper() + p[1:] for p in parts)
+    return "_" + name if name[:1].isdigit() else name
+
+
+def _field_name(arg: Arg) -> str:
+    name = re.sub(r"[^0-9A-Za-z_]", "_", arg.dest).lower()
+    if name[:1].isdigit():
+        name = "_" + name
+    return "r#" + name if name in _RUST_KEYWORDS else name
+
+
+def _rust_string(value: str) -> str:
+    return '"{}"'.format(
+        str(value).replace("\\", "\\\\").replace('"', '\\"').replace("\n", "\\n")
+    )
+
+
+def _doc_lines(text: str, indent: str) -> List[str]:
+    return [(indent + "/// " + x).rstrip() for x in text.strip().splitlines()]
+
+
+def _arg_attrs(arg: Arg, *, is_global: bool) -> List[str]:
+    """The attributes for an arg's field, inside '#[arg(...)]'."""
+    attrs = []
+    if not arg.positional:
+        # Clap only supports single character short names.
+        if arg.short and len(arg.short) == 1:
+            attrs.append("short = '{}'".format(arg.short))
+        attrs.append("long = {}".format(_rust_string(arg.long)))
+    if arg.count:
+        attrs.append("action = clap::ArgAction::Count")
+    elif arg.type is ArgType.FLAG:
+        attrs.append("action = clap::ArgAction::SetTrue")
+    elif arg.type is ArgType.BOOLEAN:
+        attrs.append("action = clap::ArgAction::Set")
+    elif arg.repeat or arg.type is ArgType.MAP:
+        attrs.append("action = clap::ArgAction::Append")
+    if arg.type is ArgType.MAP:
+        attrs.append('value_name = "KEY=VALUE"')
+    if arg.multiple:
+        if arg.min is not None:
+            min_count = arg.min
+        elif arg.positional and arg.default is not None:
+            min_count = 0
+        else:
+            min_count = 1
+        max_count = "={}".format(arg.max) if arg.max is not None else ""
+        attrs.append("num_args = {}..{}".format(min_count, max_count))
+    elif arg.type is ArgType.TEXT:
+        attrs += ["num_args = 0..", "trailing_var_arg = true"]
+        attrs.append("allow_hyphen_values = true")
+    if arg.passthrough:
+        attrs.append("last = true")
+    if arg.enum is not None:
+        choices = ", ".join(_rust_string(x) for x in arg.enum)
+        attrs.append("value_parser = [{}]".format(choices))
+    if arg.default is not None and arg.type is not ArgType.FLAG:
+        default = arg.default
+        if isinstance(default, bool):
+            default = "true" if default else "false"
+        attrs.append("default_value = {}".format(_rust_string(default)))
+    if arg.env:
+        attrs.append("env = {}".format(_rust_string(arg.env)))
+    if arg.conflicts_with:
+        names = ", ".join(_rust_string(n.replace("-", "_")) for n in arg.conflicts_with)
+        attrs.append("conflicts_with_all = [{}]".format(names))
+    if arg.hidden:
+        attrs.append("hide = true")
+    if is_global:
+        attrs.append("global = true")
+    return attrs
+
+
+def _field_type(arg: Arg) -> str:
+    if arg.count:
+        return "u8"
+    type_ = "String" if arg.enum is not None else _RUST_TYPES[arg.type]
+    if arg.is_list or arg.type in (ArgType.TEXT, ArgType.MAP):
+        return "Vec<{}>".format(type_)
+    if arg.type is ArgType.FLAG or arg.default is not None:
+        return type_
+    if arg.positional:
+        is_required = not (arg.env is not None or arg.prompt)
+    else:
+        is_required = arg.required
+    return type_ if is_required else "Option<{}>".format(type_)
+
+
+def _format_field(arg: Arg, *, is_global: bool = False) -> List[str]:
+    lines = _doc_lines(arg.render_help(), "    ")
+    attrs = _arg_attrs(arg, is_global=is_global)
+    if attrs:
+        lines.append("    #[arg({})]".format(", ".join(attrs)))
+    lines.append("    pub {}: {},".format(_field_name(arg), _field_type(arg)))
+    return lines
+
+
+def _struct_name(node: NodeBase, name: str) -> str:
+    return name if not node.path else _pascal_case(node.path) + "Args"
+
+
+def _enum_name(node: NodeBase, name: str) -> str:
+    return _pascal_case(node.path) + "Command" if node.path else name + "Command"
+
+
+def _format_node(node: NodeBase, *, name: str, prog: Optional[str]) -> List[str]:
+    """Format the struct for a node and any enum of its subcommands."""
+    subtree = node.subtree
+    lines = _doc_lines(node.help, "")
+    if not node.path:
+        lines.append("#[derive(Debug, clap::Parser)]")
+        command_attrs = []
+        if prog:
+            command_attrs.append("name = {}".format(_rust_string(prog)))
+        if isinstance(node, RootNode) and node.version is not None:
+            command_attrs.append("version")
+        if command_attrs:
+            lines.append("#[command({})]".format(", ".join(command_attrs)))
+    else:
+        lines.append("#[derive(Debug, clap::Args)]")
+    lines.append("pub struct {} {{".format(_struct_name(node, name)))
+    fields = []
+    if isinstance(node, RootNode):
+        for arg in node.all_global_args:
+            fields.append(_format_field(arg, is_global=True))
+    for arg in node.args:
+        fields.append(_format_field(arg))
+    if subtree:
+        enum_name = _enum_name(node, name)
+        if node.command is None and node.default is None:
+            type_ = enum_name
+        else:
+            type_ = "Option<{}>".format(enum_name)
+        fields.append(
+            ["    #[command(subcommand)]", "    pub command: {},".format(type_)]
+        )
+    for i, field in enumerate(fields):
+        if i:
+            lines.append("")
+        lines += field
+    lines.append("}")
+    if subtree:
+        lines += ["", "#[derive(Debug, clap::Subcommand)]"]
+        lines.append("pub enum {} {{".format(_enum_name(node, name)))
+        for i, subnode in enumerate(subtree):
+            if i:
+                lines.append("")
+            lines += _doc_lines(subnode.help, "    ")
+            attrs = ["name = {}".format(_rust_string(subnode.keyword))]
+            for alias in subnode.aliases:
+                attrs.append("visible_alias = {}".format(_rust_string(alias)))
+            for alias in subnode.hidden_aliases:
+                attrs.append("alias = {}".format(_rust_string(alias)))
+            if subnode.hidden:
+                attrs.append("hide = true")
+            lines.append("    #[command({})]".format(", ".join(attrs)))
+            lines.append(
+                "    {}({}),".format(
+                    _pascal_case([subnode.keyword]), _struct_name(subnode, name)
+                )
+            )
+        lines.append("}")
+        for subnode in subtree:
+            lines += [""] + _format_node(subnode, name=name, prog=prog)
+    return lines
+
+
+def generate_rust(
+    schema: RootNode, *, name: str = "Cli", prog: Optional[str] = None
+) -> str:
+    """
+    Generate Rust source declaring the CLI using clap's derive API.
+
+    The root node becomes a struct implementing 'clap::Parser', each node
+    with subcommands gets an enum of them implementing 'clap::Subcommand', and
+    each subcommand gets a struct of its args implementing 'clap::Args'. Args
+    with an 'env' field require clap's 'env' feature.
+
+    :param schema:
+        The CLI schema.
+    :param name:
+        The name of the root struct.
+    :param prog:
+        The program name, defaults to the name of the binary.
+    :return:
+        The Rust source, ending with a newline.
+    """
+    lines = [
+        "// Generated from a declarative CLI schema, do not edit by hand.",
+        "",
+    ]
+    lines += _format_node(schema, name=name, prog=prog)
+    return "\n".join(lines) + "\n"
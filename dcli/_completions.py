@@ -0,0 +1,470 @@
+"""
+Shell completion script generation.
+
+"""
+
+__all__ = ("SHELLS", "generate_completions")
+
+import re
+import shlex
+from typing import Callable, Dict, Iterable, List, NamedTuple, Optional, Tuple
+
+from ._schema import CompleteSpec, NodeBase, RootNode
+
+
+SHELLS = ("bash", "zsh", "fish", "powershell")
+
+
+_Option = NamedTuple(
+    "_Option",
+    [
+        ("short", List[str]),
+        ("long", List[str]),
+        ("help", str),
+        ("takes_value", bool),
+        ("choices", List[str]),
+        ("complete", Optional[CompleteSpec]),
+    ],
+)
+
+
+class _CompletionNode(
+    NamedTuple(
+        "_CompletionNode",
+        [
+            ("path", str),
+            ("keywords", List[Tuple[str, str]]),
+            ("options", List[_Option]),
+            ("values", List[str]),
+            # The completion settings of positional args, for their values.
+            ("completes", List[CompleteSpec]),
+        ],
+    )
+):
+    __slots__ = ()
+
+    @property
+    def words(self) -> List[str]:
+        words = [k for k, _ in self.keywords] + self.values
+        for opt in self.options:
+            words += opt.short + opt.long
+        return words
+
+
+def _node_id(node: NodeBase) -> str:
+    return "/".join(["root"] + node.path)
+
+
+def _get_completion_nodes(
+    schema: RootNode, roles: Optional[Iterable[str]]
+) -> List[_CompletionNode]:
+    """Gather the completion information for each node in the schema."""
+    result = []
+    for node in schema.walk():
+        if node.is_hidden or node.find_unauthorized(roles) is not None:
+            continue
+        options = [
+            _Option(["-h"], ["--help"], "Show help and exit", False, [], None)
+        ]
+        values = []
+        completes = []
+        for arg in node.all_args:
+            if arg.hidden:
+                continue
+            choices = [str(x) for x in arg.enum] if arg.enum else []
+            if arg.positional:
+                values += choices
+                if arg.complete is not None:
+                    completes.append(arg.complete)
+                continue
+            opts = arg.option_strings
+            options.append(
+                _Option(
+                    [o for o in opts if not o.startswith("--")],
+                    [o for o in opts if o.startswith("--")],
+                    arg.render_help(),
+                    arg.type.takes_value,
+                    choices,
+                    arg.complete,
+                )
+            )
+        keywords = [
+            (n.keyword, n.help)
+            for n in node.subtree
+            if not n.hidden and n.find_unauthorized(roles) is None
+        ]
+        result.append(
+            _CompletionNode(_node_id(node), keywords, options, values, completes)
+        )
+    return result
+
+
+def _func_name(prog: str) -> str:
+    return "_" + re.sub(r"\W", "_", prog) + "_completions"
+
+
+def _first_line(text: str) -> str:
+    lines = text.strip().splitlines()
+    return lines[0] if lines else ""
+
+
+def _bash_complete_lines(spec: Optional[CompleteSpec]) -> List[str]:
+    """The lines adding the values completed for an arg's 'complete' settings."""
+    if spec is None or spec.provider is not None:
+        return []
+    if spec.command is not None:
+        return ['words="${{words}} $({} 2>/dev/null)"'.format(spec.command)]
+    return ["glob={}".format(shlex.quote(spec.files))]
+
+
+def _bash_case(pattern: str, lines: List[str]) -> str:
+    return "        {})\n{}\n            ;;".format(
+        pattern, "\n".join(" " * 12 + x for x in lines)
+    )
+
+
+def _generate_bash(nodes: List[_CompletionNode], prog: str) -> str:
+    func = _func_name(prog)
+    node_ids = " | ".join(shlex.quote(n.path) for n in nodes[1:])
+    value_cases = []
+    word_cases = []
+    for node in nodes:
+        for opt in node.options:
+            if opt.takes_value:
+                patterns = " | ".join(
+                    shlex.quote(node.path + " " + o) for o in opt.short + opt.long
+                )
+                lines = ["words=" + shlex.quote(" ".join(opt.choices))]
+                value_cases.append(
+                    _bash_case(patterns, lines + _bash_complete_lines(opt.complete))
+                )
+        lines = ["words=" + shlex.quote(" ".join(node.words))]
+        for spec in node.completes:
+            lines += _bash_complete_lines(spec)
+        word_cases.append(_bash_case(shlex.quote(node.path), lines))
+    indent = " " * 8
+    word_cases = [indent + x.replace("\n", "\n" + indent) for x in word_cases]
+    return """\
+# Bash completion for {prog}, generated from the CLI schema.
+
+{func}() {{
+    local cur prev node_path words glob i
+    cur="${{COMP_WORDS[COMP_CWORD]}}"
+    prev="${{COMP_WORDS[COMP_CWORD-1]}}"
+    node_path="root"
+    for ((i = 1; i < COMP_CWORD; i++)); do
+        case "${{node_path}}/${{COMP_WORDS[i]}}" in
+        {node_ids})
+            node_path="${{node_path}}/${{COMP_WORDS[i]}}"
+            ;;
+        esac
+    done
+    # Complete values for options that take them, falling back to files.
+    case "${{node_path}} ${{prev}}" in
+{value_cases}
+        *)
+            case "${{node_path}}" in
+{word_cases}
+            esac
+            ;;
+    esac
+    COMPREPLY=($(compgen -W "${{words}}" -- "${{cur}}"))
+    if [[ -n "${{glob}}" ]]; then
+        COMPREPLY+=($(compgen -d -- "${{cur}}"))
+        COMPREPLY+=($(compgen -f -X "!${{glob}}" -- "${{cur}}"))
+    fi
+}}
+
+complete -o default -F {func} {prog_quoted}
+""".format(
+        prog=prog,
+        prog_quoted=shlex.quote(prog),
+        func=func,
+        node_ids=node_ids,
+        value_cases="\n".join(value_cases),
+        word_cases="\n".join(word_cases),
+    )
+
+
+def _zsh_complete_action(spec: Optional[CompleteSpec]) -> Optional[str]:
+    """The action completing the values for an arg's 'complete' settings."""
+    if spec is None or spec.provider is not None:
+        return None
+    if spec.command is not None:
+        return 'compadd -- ${{(f)"$({} 2>/dev/null)"}}'.format(spec.command)
+    return "_files -g " + shlex.quote(spec.files)
+
+
+def _generate_zsh(nodes: List[_CompletionNode], prog: str) -> str:
+    func = _func_name(prog)
+    node_ids = " | ".join(shlex.quote(n.path) for n in nodes[1:])
+    value_cases = []
+    word_cases = []
+    for node in nodes:
+        for opt in node.options:
+            if opt.takes_value:
+                patterns = " | ".join(
+                    shlex.quote(node.path + " " + o) for o in opt.short + opt.long
+                )
+                if opt.choices:
+                    action = "compadd -- " + " ".join(
+                        shlex.quote(c) for c in opt.choices
+                    )
+                else:
+                    action = _zsh_complete_action(opt.complete) or "_files"
+                value_cases.append(
+                    "        {})\n            {}\n            return\n"
+                    "            ;;".format(patterns, action)
+                )
+        actions = ["compadd -- " + " ".join(shlex.quote(w) for w in node.words)]
+        actions += [_zsh_complete_action(x) for x in node.completes]
+        word_cases.append(
+            "        {})\n{}\n            ;;".format(
+                shlex.quote(node.path),
+                "\n".join(" " * 12 + x for x in actions if x is not None),
+            )
+        )
+    return """\
+#compdef {prog}
+# Zsh completion for {prog}, generated from the CLI schema.
+
+{func}() {{
+    local node_path="root" prev i
+    for ((i = 2; i < CURRENT; i++)); do
+        case "${{node_path}}/${{words[i]}}" in
+        {node_ids})
+            node_path="${{node_path}}/${{words[i]}}"
+            ;;
+        esac
+    done
+    prev="${{words[CURRENT-1]}}"
+    case "${{node_path}} ${{prev}}" in
+{value_cases}
+    esac
+    case "${{node_path}}" in
+{word_cases}
+    esac
+}}
+
+compdef {func} {prog_quoted}
+""".format(
+        prog=prog,
+        prog_quoted=shlex.quote(prog),
+        func=func,
+        node_ids=node_ids,
+        value_cases="\n".join(value_cases),
+        word_cases="\n".join(word_cases),
+    )
+
+
+def _fish_quote(value: str) -> str:
+    return "'" + value.replace("\\", "\\\\").replace("'", "\\'") + "'"
+
+
+def _fish_complete_flags(spec: Optional[CompleteSpec]) -> Optional[str]:
+    """The flags completing the values for an arg's 'complete' settings."""
+    if spec is None or spec.provider is not None:
+        return None
+    if spec.command is not None:
+        return "-x -a " + _fish_quote("({} 2>/dev/null)".format(spec.command))
+    match = re.fullmatch(r"\*(\.\w+)", spec.files)
+    if match:
+        return "-x -a " + _fish_quote(
+            "(__fish_complete_suffix {})".format(match.group(1))
+        )
+    # Fish can only filter files by suffix.
+    return "-r -F"
+
+
+def _generate_fish(nodes: List[_CompletionNode], prog: str) -> str:
+    func = _func_name(prog)
+    node_ids = " ".join(_fish_quote(n.path) for n in nodes[1:])
+    lines = [
+        "# Fish completion for {}, generated from the CLI schema.".format(prog),
+        "",
+        "function {}_node_path".format(func),
+        "    set -l node_path root",
+        "    for token in (commandline -opc)[2..-1]",
+        '        if contains -- "$node_path/$token" {}'.format(node_ids),
+        '            set node_path "$node_path/$token"',
+        "        end",
+        "    end",
+        "    echo $node_path",
+        "end",
+        "",
+        "complete -c {} -f".format(_fish_quote(prog)),
+    ]
+    for node in nodes:
+        condition = "-n {}".format(
+            _fish_quote("test ({}_node_path) = {}".format(func, node.path))
+        )
+        for keyword, help_ in node.keywords:
+            lines.append(
+                "complete -c {} {} -a {} -d {}".format(
+                    _fish_quote(prog),
+                    condition,
+                    _fish_quote(keyword),
+                    _fish_quote(_first_line(help_)),
+                )
+            )
+        if node.values:
+            lines.append(
+                "complete -c {} {} -a {}".format(
+                    _fish_quote(prog), condition, _fish_quote(" ".join(node.values))
+                )
+            )
+        for spec in node.completes:
+            flags = _fish_complete_flags(spec)
+            if flags is not None:
+                lines.append(
+                    "complete -c {} {} {}".format(_fish_quote(prog), condition, flags)
+                )
+        for opt in node.options:
+            parts = ["complete -c {} {}".format(_fish_quote(prog), condition)]
+            parts += [("-s " if len(o) == 2 else "-o ") + o[1:] for o in opt.short]
+            parts += ["-l " + o[2:] for o in opt.long]
+            if opt.choices:
+                parts.append("-x -a " + _fish_quote(" ".join(opt.choices)))
+            elif opt.takes_value:
+                parts.append(_fish_complete_flags(opt.complete) or "-r -F")
+            parts.append("-d " + _fish_quote(_first_line(opt.help)))
+            lines.append(" ".join(parts))
+    return "\n".join(lines) + "\n"
+
+
+def _ps_quote(value: str) -> str:
+    return "'" + value.replace("'", "''") + "'"
+
+
+def _ps_array(values: List[str]) -> str:
+    return "@(" + ", ".join(_ps_quote(v) for v in values) + ")"
+
+
+def _ps_complete_block(spec: Optional[CompleteSpec]) -> Optional[str]:
+    """A script block giving the values for an arg's 'complete' settings."""
+    if spec is None or spec.provider is not None:
+        return None
+    if spec.command is not None:
+        return "{{ Invoke-Expression {} 2>$null }}".format(_ps_quote(spec.command))
+    return (
+        "{{ Get-ChildItem -Name -Directory; "
+        "Get-ChildItem -Name -File -Filter {} }}".format(_ps_quote(spec.files))
+    )
+
+
+def _generate_powershell(nodes: List[_CompletionNode], prog: str) -> str:
+    node_words = []
+    node_values = []
+    option_values = []
+    for node in nodes:
+        node_words.append(
+            "        {} = {}".format(_ps_quote(node.path), _ps_array(node.words))
+        )
+        blocks = [_ps_complete_block(x) for x in node.completes]
+        blocks = [x for x in blocks if x is not None]
+        if blocks:
+            node_values.append(
+                "        {} = @({})".format(_ps_quote(node.path), ", ".join(blocks))
+            )
+        for opt in node.options:
+            values = _ps_array(opt.choices) if opt.choices else None
+            if values is None:
+                values = _ps_complete_block(opt.complete)
+            if values is not None:
+                for o in opt.short + opt.long:
+                    option_values.append(
+                        "        {} = {}".format(_ps_quote(node.path + " " + o), values)
+                    )
+    return """\
+# PowerShell completion for {prog}, generated from the CLI schema.
+
+Register-ArgumentCompleter -Native -CommandName {prog_quoted} -ScriptBlock {{
+    param($wordToComplete, $commandAst, $cursorPosition)
+    $nodeWords = @{{
+{node_words}
+    }}
+    $nodeValues = @{{
+{node_values}
+    }}
+    $optionValues = @{{
+{option_values}
+    }}
+    $elements = @($commandAst.CommandElements | Select-Object -Skip 1 |
+        ForEach-Object {{ $_.ToString() }})
+    if ($wordToComplete) {{
+        $elements = @($elements | Select-Object -SkipLast 1)
+    }}
+    $nodePath = 'root'
+    $prev = $null
+    foreach ($element in $elements) {{
+        if ($nodeWords.ContainsKey("$nodePath/$element")) {{
+            $nodePath = "$nodePath/$element"
+        }}
+        $prev = $element
+    }}
+    $candidates = $nodeWords[$nodePath]
+    foreach ($block in $nodeValues[$nodePath]) {{
+        $candidates += @(& $block)
+    }}
+    if ($prev -and $optionValues.ContainsKey("$nodePath $prev")) {{
+        $candidates = $optionValues["$nodePath $prev"]
+        if ($candidates -is [scriptblock]) {{
+            $candidates = @(& $candidates)
+        }}
+    }}
+    $candidates | Where-Object {{ $_ -like "$wordToComplete*" }} | ForEach-Object {{
+        [System.Management.Automation.CompletionResult]::new(
+            $_, $_, 'ParameterValue', $_
+        )
+    }}
+}}
+""".format(
+        prog=prog,
+        prog_quoted=_ps_quote(prog),
+        node_words="\n".join(node_words),
+        node_values="\n".join(node_values),
+        option_values="\n".join(option_values),
+    )
+
+
+_GENERATORS = {
+    "bash": _generate_bash,
+    "zsh": _generate_zsh,
+    "fish": _generate_fish,
+    "powershell": _generate_powershell,
+}  # type: Dict[str, Callable[[List[_CompletionNode], str], str]]
+
+
+def generate_completions(
+    schema: RootNode,
+    shell: str,
+    *,
+    prog: str,
+    roles: Optional[Iterable[str]] = None
+) -> str:
+    """
+    Generate a shell completion script for the argparse-style CLI.
+
+    :param schema:
+        The schema to generate completions for.
+    :param shell:
+        The shell to generate the script for, one of `SHELLS`.
+    :param prog:
+        The name of the program being completed.
+    :param roles:
+        The roles of the user, to leave out the commands with 'requires_role'
+        they can't run, or None to include all commands.
+    :return:
+        The completion script.
+    :raises ValueError:
+        If the shell is not supported.
+    """
+    try:
+        generator = _GENERATORS[shell]
+    except KeyError:
+        raise ValueError(
+            "Unsupported shell {!r}, supported shells are: {}".format(
+                shell, ", ".join(SHELLS)
+            )
+        ) from None
+    return generator(_get_completion_nodes(schema, roles), prog)
@@ -0,0 +1,105 @@
+"""
+Loading of arg values from config files.
+
+"""
+
+__all__ = ("ConfigError", "extract_config_option", "get_node_config", "load_config")
+
+import pathlib
+from typing import Any, Dict, List, Mapping, Optional, Tuple
+
+from ._errors import ConfigError, SchemaError
+from ._schema import NodeBase, RootNode, SchemaFormat
+from ._utils import PathLike, extract_option
+
+
+def extract_config_option(args: List[str]) -> Tuple[Optional[str], List[str]]:
+    """
+    Extract a '--config' option from a list of args.
+
+    Args after a '--' are not considered.
+
+    :param args:
+        The args to search.
+    :return:
+        A tuple of the config file path (or None if not given), and the
+        remaining args.
+    :raises ConfigError:
+        If the option is given without a value.
+    """
+    try:
+        return extract_option(args, "--config")
+    except ValueError as e:
+        raise ConfigError(str(e)) from None
+
+
+def load_config(schema: RootNode, path: Optional[PathLike] = None) -> Dict[str, Any]:
+    """
+    Load the config file for a schema.
+
+    The config file is a mapping of arg names to values for the root node,
+    with nested mappings under subcommand keywords for args of subnodes, e.g.
+    'server: {start: {port: 8080}}'.
+
+    :param schema:
+        The schema.
+    :param path:
+        The path to the config file, or None to use the file declared in the
+        schema, if it exists.
+    :return:
+        The loaded config, empty if there is no config file.
+    :raises ConfigError:
+        If the config file can't be loaded.
+    """
+    if path is None:
+        if schema.config is None or schema.config.file is None:
+            return {}
+        path = pathlib.Path(schema.config.file).expanduser()
+        if not path.is_file():
+            return {}
+    path = pathlib.Path(path)
+    try:
+        format_ = SchemaFormat.from_path(path)
+        with open(path) as f:
+            data = format_.loads(f.read())
+    except SchemaError as e:
+        raise ConfigError(
+            e.message.replace("CLI schema", "config"), location=str(path)
+        ) from None
+    except OSError as e:
+        raise ConfigError(
+            "Unable to read config file: {}".format(
+                e.strerror or e.__class__.__name__
+            ),
+            location=str(path),
+        ) from None
+    except ValueError as e:
+        raise ConfigError(
+            "Invalid config file: {}".format(e), location=str(path)
+        ) from None
+    if data is None:
+        return {}
+    if not isinstance(data, dict):
+        raise ConfigError(
+            "Expected a mapping at the top level of config file", location=str(path)
+        )
+    return data
+
+
+def get_node_config(config: Mapping[str, Any], node: NodeBase) -> Mapping[str, Any]:
+    """
+    Get the section of a loaded config that applies to a schema node.
+
+    :param config:
+        The loaded config.
+    :param node:
+        The schema node.
+    :return:
+        The config section, empty if there is none.
+    """
+    section = config
+    for keyword in node.path:
+        section = section.get(keyword)
+        if not isinstance(section, dict):
+            return {}
+    return section
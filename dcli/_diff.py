@@ -0,0 +1,236 @@
+"""
+Comparison of two versions of a CLI schema, to detect changes that would break
+existing invocations of the CLI.
+
+"""
+
+__all__ = ("SchemaChange", "diff_schemas")
+
+from typing import List, NamedTuple
+
+from ._schema import Arg, NodeBase, RootNode
+from ._types import ArgType
+
+
+class SchemaChange(
+    NamedTuple(
+        "SchemaChange", [("location", str), ("description", str), ("breaking", bool)]
+    )
+):
+    """A difference between two versions of a schema."""
+
+    __slots__ = ()
+
+    def __str__(self):
+        return "{}: {}: {}".format(
+            "BREAKING" if self.breaking else "non-breaking",
+            self.location,
+            self.description,
+        )
+
+
+# Type changes that accept all the values accepted before.
+_WIDENING_TYPE_CHANGES = {
+    (ArgType.INTEGER, ArgType.FLOAT),
+    (ArgType.PATH, ArgType.STRING),
+    (ArgType.URL, ArgType.STRING),
+    (ArgType.DURATION, ArgType.STRING),
+    (ArgType.DATETIME, ArgType.STRING),
+    (ArgType.SIZE, ArgType.STRING),
+    (ArgType.INTEGER, ArgType.STRING),
+    (ArgType.FLOAT, ArgType.STRING),
+}
+
+
+def _node_location(node: NodeBase) -> str:
+    return ".".join(node.path) if node.keyword else "root"
+
+
+def _is_required(arg: Arg) -> bool:
+    """Whether an arg must be given on the command line."""
+    if arg.env is not None or arg.prompt or arg.stdin or arg.default is not None:
+        return False
+    if arg.positional:
+        return not (arg.multiple and arg.min == 0)
+    return arg.required
+
+
+def _diff_args(old: Arg, new: Arg, location: str) -> List[SchemaChange]:
+    changes = []
+
+    def add(description: str, breaking: bool):
+        changes.append(SchemaChange(location, description, breaking))
+
+    if old.positional != new.positional:
+        kinds = ["positional arg" if a.positional else "option" for a in (old, new)]
+        add("changed from {} to {}".format(*kinds), True)
+        return changes
+    removed = [x for x in old.option_strings if x not in new.option_strings]
+    added = [x for x in new.option_strings if x not in old.option_strings]
+    if removed and added:
+        add("renamed {} to {}".format(", ".join(removed), ", ".join(added)), True)
+    elif removed:
+        add("removed {}".format(", ".join(removed)), True)
+    elif added:
+        add("added {}".format(", ".join(added)), False)
+    if old.type is not new.type:
+        add(
+            "type changed from {!r} to {!r}".format(old.type.value, new.type.value),
+            (old.type, new.type) not in _WIDENING_TYPE_CHANGES,
+        )
+    if old.enum is not None or new.enum is not None:
+        if new.enum is None:
+            add("no longer restricted to enum values", False)
+        elif old.enum is None:
+            add("restricted to enum values", True)
+        else:
+            removed = [x for x in old.enum if x not in new.enum]
+            added = [x for x in new.enum if x not in old.enum]
+            if removed:
+                add("removed enum values {}".format(removed), True)
+            if added:
+                add("added enum values {}".format(added), False)
+    if _is_required(old) != _is_required(new):
+        add(
+            "now required" if _is_required(new) else "no longer required",
+            _is_required(new),
+        )
+    if old.default != new.default:
+        add("default changed from {!r} to {!r}".format(old.default, new.default), True)
+    for field in ("multiple", "repeat", "count", "passthrough"):
+        if getattr(old, field) != getattr(new, field):
+            add(
+                "{} '{}'".format(
+                    "now has" if getattr(new, field) else "no longer has", field
+                ),
+                True,
+            )
+    old_min = old.min or 0
+    new_min = new.min or 0
+    if old_min != new_min:
+        add(
+            "minimum number of values changed from {} to {}".format(old_min, new_min),
+            new_min > old_min,
+        )
+    if old.max != new.max:
+        add(
+            "maximum number of values changed from {} to {}".format(old.max, new.max),
+            new.max is not None and (old.max is None or new.max < old.max),
+        )
+    if old.env != new.env:
+        if old.env is None:
+            add("added environment variable {}".format(new.env), False)
+        else:
+            add("removed environment variable {}".format(old.env), True)
+    return changes
+
+
+def _diff_arg_lists(
+    old_args: List[Arg], new_args: List[Arg], location: str, kind: str
+) -> List[SchemaChange]:
+    changes = []
+    new_by_name = {a.name: a for a in new_args}
+    old_names = {a.name for a in old_args}
+    for old_arg in old_args:
+        arg_location = "{}: {} {!r}".format(location, kind, old_arg.name)
+        new_arg = new_by_name.get(old_arg.name)
+        if new_arg is None:
+            changes.append(SchemaChange(arg_location, "removed", True))
+        else:
+            changes += _diff_args(old_arg, new_arg, arg_location)
+    for new_arg in new_args:
+        if new_arg.name not in old_names:
+            arg_location = "{}: {} {!r}".format(location, kind, new_arg.name)
+            changes.append(
+                SchemaChange(
+                    arg_location,
+                    "added required arg" if _is_required(new_arg) else "added",
+                    _is_required(new_arg),
+                )
+            )
+    return changes
+
+
+def _diff_nodes(old: NodeBase, new: NodeBase) -> List[SchemaChange]:
+    location = _node_location(new)
+    changes = []
+    if old.command and not new.command:
+        changes.append(SchemaChange(location, "no longer runs a command", True))
+    elif new.command and not old.command:
+        changes.append(SchemaChange(location, "now runs a command", False))
+    elif old.command != new.command:
+        changes.append(
+            SchemaChange(
+                location,
+                "command changed from {!r} to {!r}".format(old.command, new.command),
+                False,
+            )
+        )
+    if old.default != new.default:
+        changes.append(
+            SchemaChange(
+                location,
+                "default changed from {!r} to {!r}".format(old.default, new.default),
+                old.default is not None,
+            )
+        )
+    changes += _diff_arg_lists(old.args, new.args, location, "arg")
+    matched = []
+    for old_subnode in old.subtree:
+        new_subnode = new.get_subnode(old_subnode.keyword)
+        if new_subnode is None:
+            changes.append(
+                SchemaChange(_node_location(old_subnode), "command removed", True)
+            )
+            continue
+        matched.append(new_subnode)
+        if new_subnode.keyword != old_subnode.keyword:
+            changes.append(
+                SchemaChange(
+                    _node_location(old_subnode),
+                    "renamed to {!r}, with the old keyword kept as an alias".format(
+                        new_subnode.keyword
+                    ),
+                    False,
+                )
+            )
+        removed_aliases = [
+            a for a in old_subnode.all_aliases if new.get_subnode(a) is not new_subnode
+        ]
+        if removed_aliases:
+            changes.append(
+                SchemaChange(
+                    _node_location(new_subnode),
+                    "removed aliases {}".format(", ".join(removed_aliases)),
+                    True,
+                )
+            )
+        changes += _diff_nodes(old_subnode, new_subnode)
+    for new_subnode in new.subtree:
+        if new_subnode not in matched:
+            changes.append(
+                SchemaChange(_node_location(new_subnode), "command added", False)
+            )
+    return changes
+
+
+def diff_schemas(old: RootNode, new: RootNode) -> List[SchemaChange]:
+    """
+    Compare two versions of a schema.
+
+    Commands are matched by keyword (or alias) and args by name. Changes are
+    classified as breaking if a command line accepted by the old schema could
+    be rejected or interpreted differently by the new schema, e.g. removed
+    commands or options, new required args, tightened types or enum values and
+    changed defaults. Changes to help text are ignored.
+
+    :param old:
+        The old version of the schema.
+    :param new:
+        The new version of the schema.
+    :return:
+        The changes found, empty if there are none.
+    """
+    changes = _diff_arg_lists(old.global_args, new.global_args, "root", "global arg")
+    changes += _diff_nodes(old, new)
+    return changes
@@ -0,0 +1,871 @@
+"""
+Dispatching of parsed args to command handlers.
+
+"""
+
+__all__ = (
+    "CancellationToken",
+    "ChainError",
+    "CleanupHook",
+    "CommandCancelledError",
+    "CommandTerminatedError",
+    "CommandTimeoutError",
+    "DispatchError",
+    "Dispatcher",
+    "Handler",
+    "PostHook",
+    "PreHook",
+)
+
+import asyncio
+import inspect
+import signal
+import threading
+import time
+from typing import (
+    Any,
+    Awaitable,
+    Callable,
+    Dict,
+    Iterable,
+    List,
+    Optional,
+    Sequence,
+    Tuple,
+    Union,
+)
+
+from ._errors import (
+    CLIError,
+    ChainError,
+    CommandCancelledError,
+    CommandTerminatedError,
+    CommandTimeoutError,
+    DispatchError,
+    UnauthorizedError,
+)
+from ._process import (
+    STOP_GRACE,
+    command_environment,
+    run_cleanup_command,
+    run_external_command,
+)
+from ._progress import Progress
+from ._schema import NodeBase, SignalsSpec
+from ._telemetry import Telemetry, UsageEvent, _make_event
+from .clis import Namespace, RoleProvider, _unauthorized_message
+
+
+# Handlers may be coroutine functions, or otherwise return an awaitable.
+# Handlers accepting a second positional parameter are also passed a
+# `CancellationToken`, and those accepting a 'progress' parameter are passed a
+# `Progress` handle.
+Handler = Callable[..., Any]
+
+# Hooks called before a handler, which may raise an error (e.g. a `CLIError`)
+# to stop the handler being called.
+PreHook = Callable[[Namespace], Any]
+
+# Hooks called after a handler returns, with the handler's return value.
+PostHook = Callable[[Namespace, Any], Any]
+
+# Hooks called when a handler is cancelled, e.g. with Ctrl-C, with the error
+# about to be raised.
+CleanupHook = Callable[[Namespace, CommandCancelledError], Any]
+
+
+def _run_awaitable(awaitable: Awaitable) -> Any:
+    """Run an awaitable to completion in a new event loop."""
+    loop = asyncio.new_event_loop()
+    try:
+        return loop.run_until_complete(awaitable)
+    finally:
+        loop.close()
+
+
+def _maybe_run_awaitable(value: Any) -> Any:
+    return _run_awaitable(value) if inspect.isawaitable(value) else value
+
+
+async def _maybe_await(value: Any) -> Any:
+    return await value if inspect.isawaitable(value) else value
+
+
+class CancellationToken:
+    """
+    Token passed to handlers that accept one, which is cancelled when the
+    command times out or is interrupted with Ctrl-C.
+
+    Long-running handlers should check the token periodically (or wait on it
+    instead of sleeping) and return early once it's cancelled, e.g.:
+
+        @dispatcher.register("sync")
+        def sync(args, token):
+            for item in items:
+                token.raise_if_cancelled()
+                process(item)
+    """
+
+    def __init__(self):
+        self._event = threading.Event()
+        self.reason = None  # type: Optional[str]
+
+    @property
+    def cancelled(self) -> bool:
+        """Whether the token has been cancelled."""
+        return self._event.is_set()
+
+    def cancel(self, reason: str = "cancelled") -> None:
+        """
+        Cancel the token, if not already cancelled.
+
+        :param reason:
+            Why the token was cancelled, e.g. 'timeout' or 'interrupted'.
+        """
+        if not self.cancelled:
+            self.reason = reason
+            self._event.set()
+
+    def wait(self, timeout: Optional[float] = None) -> bool:
+        """
+        Wait for the token to be cancelled.
+
+        :param timeout:
+            The maximum number of seconds to wait for, defaults to no limit.
+        :return:
+            Whether the token was cancelled.
+        """
+        return self._event.wait(timeout)
+
+    def raise_if_cancelled(self) -> None:
+        """
+        :raises CommandCancelledError:
+            If the token has been cancelled.
+        """
+        if self.cancelled:
+            raise CommandCancelledError("Command cancelled ({})".format(self.reason))
+
+
+def _accepts_token(handler: Handler) -> bool:
+    """
+    Whether a handler accepts a cancellation token, i.e. has a second
+    positional parameter without a default, other than 'progress'.
+    """
+    try:
+        parameters = inspect.signature(handler).parameters.values()
+    except (TypeError, ValueError):
+        return False
+    positional = [
+        p
+        for p in parameters
+        if p.kind in (p.POSITIONAL_ONLY, p.POSITIONAL_OR_KEYWORD)
+        and p.name != "progress"
+    ]
+    return len(positional) >= 2 and positional[1].default is inspect.Parameter.empty
+
+
+def _accepts_progress(handler: Handler) -> bool:
+    """Whether a handler accepts a 'progress' keyword arg."""
+    try:
+        parameters = inspect.signature(handler).parameters
+    except (TypeError, ValueError):
+        return False
+    param = parameters.get("progress")
+    return param is not None and param.kind in (
+        param.POSITIONAL_OR_KEYWORD,
+        param.KEYWORD_ONLY,
+    )
+
+
+def _timeout_error(command: str, timeout: float) -> CommandTimeoutError:
+    return CommandTimeoutError(
+        "Command {!r} timed out after {:g} seconds".format(command, timeout)
+    )
+
+
+class _Terminated(BaseException):
+    """Raised in the main thread on SIGTERM while a handler is running."""
+
+
+def _raise_terminated(signum, frame) -> None:
+    raise _Terminated()
+
+
+def _run_in_thread(
+    func: Callable[[], Any],
+    token: CancellationToken,
+    timeout: Optional[float],
+    command: str,
+    signals: Optional[SignalsSpec] = None,
+    *,
+    stop_wait: Optional[float] = None
+) -> Any:
+    """
+    Run a function in a worker thread, cancelling the token if it times out
+    or on Ctrl-C.
+
+    On timeout the worker thread is left running in the background, since it
+    can't be stopped if it doesn't check the token. On Ctrl-C the function is
+    given the chance to finish gracefully, unless Ctrl-C is pressed again or
+    the signal settings have 'interrupt' set to 'exit'. If the signal settings
+    have a 'terminate_grace', SIGTERM similarly cancels the token and the
+    function has the grace period to finish, unless SIGTERM is sent again.
+
+    Functions that stop promptly once the token is cancelled, such as external
+    commands, may be given a 'stop_wait': the number of seconds to wait for
+    them to return after a timeout, which is also the grace period for SIGTERM
+    if the signal settings don't have one.
+
+    :raises CommandTimeoutError:
+        If the function doesn't finish within the timeout.
+    :raises CommandCancelledError:
+        If interrupted with Ctrl-C.
+    :raises CommandTerminatedError:
+        If terminated with SIGTERM.
+    """
+    outcome = {}  # type: Dict[str, Any]
+    # Waited on rather than joining the thread, since interrupting a join can
+    # leave the thread looking finished.
+    done = threading.Event()
+
+    def target():
+        try:
+            outcome["result"] = func()
+        except BaseException as e:
+            outcome["error"] = e
+        finally:
+            done.set()
+
+    interrupt = signals.interrupt if signals is not None else "cancel"
+    grace = signals.get_terminate_grace() if signals is not None else None
+    if grace is None:
+        grace = stop_wait
+    # Signal handlers can only be set from the main thread.
+    handle_term = (
+        grace is not None and threading.current_thread() is threading.main_thread()
+    )
+    if handle_term:
+        old_handler = signal.signal(signal.SIGTERM, _raise_terminated)
+        if old_handler is None:
+            old_handler = signal.SIG_DFL
+    thread = threading.Thread(target=target, daemon=True)
+    thread.start()
+    try:
+        # Wait in short steps so that Ctrl-C is handled while waiting.
+        remaining = timeout
+        while not done.is_set() and (remaining is None or remaining > 0):
+            step = 0.1 if remaining is None else min(0.1, remaining)
+            done.wait(step)
+            if remaining is not None:
+                remaining -= step
+    except KeyboardInterrupt:
+        token.cancel("interrupted")
+        if interrupt == "cancel":
+            done.wait()
+        raise CommandCancelledError(
+            "Command {!r} interrupted".format(command)
+        ) from None
+    except _Terminated:
+        # A second SIGTERM exits immediately, as usual.
+        signal.signal(signal.SIGTERM, old_handler)
+        handle_term = False
+        token.cancel("terminated")
+        done.wait(grace)
+        raise CommandTerminatedError(
+            "Command {!r} terminated".format(command)
+        ) from None
+    finally:
+        if handle_term:
+            signal.signal(signal.SIGTERM, old_handler)
+    if not done.is_set():
+        token.cancel("timeout")
+        if stop_wait is not None:
+            done.wait(stop_wait)
+        raise _timeout_error(command, timeout)
+    if "error" in outcome:
+        raise outcome["error"]
+    return outcome["result"]
+
+
+class Dispatcher:
+    """
+    Dispatcher of parsed args to registered command handlers.
+
+    Handlers are registered against the command names given by the 'command'
+    field of schema nodes, and are called with the parsed args namespace.
+    Where several nodes run the same command, a handler may instead be
+    registered for the path of keywords to one of them, which takes
+    precedence.
+
+    Handlers may be async, in which case `dispatch()` runs them in a new event
+    loop, or they can be awaited within an existing event loop using
+    `dispatch_async()`.
+
+    The schema settings used are those of the node the args were parsed for,
+    as recorded by the CLI parser, or else of the node found for the command
+    in the schema if it's given (which must then be the only node running the
+    command).
+
+    Commands may have a 'timeout' in the schema. Handlers for commands with a
+    timeout or 'signals' settings, or that accept a `CancellationToken` as a
+    second arg, are run in a worker thread (or as a separate task if async)
+    so that the timeout and Ctrl-C can be handled by cancelling the token: a
+    `CommandTimeoutError` or `CommandCancelledError` is then raised. With a
+    'terminate_grace' in the 'signals' settings, SIGTERM is handled similarly
+    when dispatching from the main thread, raising a `CommandTerminatedError`.
+
+    Handlers accepting a 'progress' keyword arg are passed a `Progress`
+    handle for showing spinners, progress bars and step messages, which are
+    hidden by '--quiet' and for output formats such as JSON.
+
+    When a handler is cancelled, the cleanup hooks are called (see
+    `add_cleanup_hook()`), followed by the 'cleanup' command in the command's
+    'signals' settings, before the error is raised.
+
+    Handlers are run in the working directory and with the environment
+    variables declared by the command's 'cwd' and 'env' settings, if any.
+    External commands declared in the schema (with a list for 'command') are
+    run without needing a handler, returning their exit code, unless a handler
+    is registered for them. The external command's process is stopped if
+    it's cancelled, with SIGTERM forwarded to it.
+
+    Hooks may be added to run before and after handlers, either for all
+    commands or for a specific command, e.g. for logging, timing or checking
+    authorisation. Hooks for all commands run outside those for a specific
+    command, i.e. first before the handler and last after it. Hooks may also
+    be async.
+
+    Telemetry sinks may be added to record a `UsageEvent` after each dispatch,
+    see `Telemetry`.
+    """
+
+    def __init__(
+        self,
+        handlers: Optional[Dict[str, Handler]] = None,
+        *,
+        schema: Optional[NodeBase] = None,
+        role_provider: Optional[RoleProvider] = None
+    ):
+        """
+        :param handlers:
+            Initial mapping of command names to handlers.
+        :param schema:
+            The CLI schema, for the settings of commands dispatched with args
+            that weren't parsed by a CLI parser, e.g. their timeouts.
+        :param role_provider:
+            Callback giving the roles of the user, called for each dispatch of
+            a command unless the roles are passed to `dispatch()`. Commands
+            with 'requires_role' are rejected unless the user has one of their
+            roles, including when no roles are given.
+        """
+        self._handlers = dict(handlers) if handlers else {}  # type: Dict[str, Handler]
+        self._path_handlers = {}  # type: Dict[Tuple[str, ...], Handler]
+        self._schema = schema
+        self._role_provider = role_provider
+        self._pre_hooks = {}  # type: Dict[Optional[str], List[PreHook]]
+        self._post_hooks = {}  # type: Dict[Optional[str], List[PostHook]]
+        self._cleanup_hooks = {}  # type: Dict[Optional[str], List[CleanupHook]]
+        self._telemetry = []  # type: List[Telemetry]
+
+    @property
+    def commands(self) -> Dict[str, Handler]:
+        """The handlers registered by command name."""
+        return dict(self._handlers)
+
+    def register(
+        self, command: Union[str, Sequence[str]], handler: Optional[Handler] = None
+    ):
+        """
+        Register a handler for a command.
+
+        May be used as a decorator if the handler is not given, e.g.:
+
+            @dispatcher.register("make-venv")
+            def make_venv(args): ...
+
+            @dispatcher.register(["prod", "deploy"])
+            def deploy_prod(args): ...
+
+        :param command:
+            The command name, as given in the schema, or the keywords of the
+            node to register the handler for, for commands run by more than
+            one node.
+        :param handler:
+            The handler to call with the parsed args.
+        :raises ValueError:
+            If a handler is already registered for the command or node.
+        """
+        if handler is None:
+
+            def decorator(func: Handler) -> Handler:
+                self.register(command, func)
+                return func
+
+            return decorator
+
+        if not isinstance(command, str):
+            path = tuple(command)
+            if path in self._path_handlers:
+                raise ValueError(
+                    "Handler already registered for node {!r}".format(" ".join(path))
+                )
+            self._path_handlers[path] = handler
+        elif command in self._handlers:
+            raise ValueError(
+                "Handler already registered for command {!r}".format(command)
+            )
+        else:
+            self._handlers[command] = handler
+        return handler
+
+    def add_pre_hook(
+        self, hook: Optional[PreHook] = None, *, command: Optional[str] = None
+    ):
+        """
+        Add a hook to call with the parsed args before a handler is called.
+
+        Hooks are called in the order they're added. A hook may raise an error
+        to stop the handler (and any further hooks) being called, e.g.:
+
+            @dispatcher.add_pre_hook(command="deploy")
+            def check_auth(args):
+                if not have_credentials():
+                    raise CLIError("Not logged in")
+
+        May be used as a decorator if the hook is not given.
+
+        :param hook:
+            The hook to add.
+        :param command:
+            The command to call the hook for, defaults to all commands.
+        """
+        if hook is None:
+            return lambda func: self.add_pre_hook(func, command=command)
+        self._pre_hooks.setdefault(command, []).append(hook)
+        return hook
+
+    def add_post_hook(
+        self, hook: Optional[PostHook] = None, *, command: Optional[str] = None
+    ):
+        """
+        Add a hook to call with the parsed args and the handler's return value
+        after a handler returns successfully.
+
+        Hooks are called in the reverse order they're added, so that hooks
+        added as pairs nest. May be used as a decorator if the hook is not
+        given.
+
+        :param hook:
+            The hook to add.
+        :param command:
+            The command to call the hook for, defaults to all commands.
+        """
+        if hook is None:
+            return lambda func: self.add_post_hook(func, command=command)
+        self._post_hooks.setdefault(command, []).append(hook)
+        return hook
+
+    def add_cleanup_hook(
+        self, hook: Optional[CleanupHook] = None, *, command: Optional[str] = None
+    ):
+        """
+        Add a hook to call with the parsed args and the error when a handler is
+        cancelled, e.g. with Ctrl-C or by its timeout, such as for removing
+        temporary files. The hook is called before the process exits, after
+        the handler has returned or its grace period has ended.
+
+        Hooks are called in the reverse order they're added, as for post
+        hooks. May be used as a decorator if the hook is not given.
+
+        :param hook:
+            The hook to add.
+        :param command:
+            The command to call the hook for, defaults to all commands.
+        """
+        if hook is None:
+            return lambda func: self.add_cleanup_hook(func, command=command)
+        self._cleanup_hooks.setdefault(command, []).append(hook)
+        return hook
+
+    def add_telemetry(self, sink: Telemetry) -> None:
+        """
+        Add a sink to record usage events with, after each dispatch.
+
+        :param sink:
+            The telemetry sink.
+        """
+        self._telemetry.append(sink)
+
+    def record_event(self, event: UsageEvent) -> None:
+        """
+        Send a usage event to the telemetry sinks, e.g. for a failure to parse
+        args. Exceptions raised by the sinks are ignored.
+
+        :param event:
+            The event to record.
+        """
+        for sink in self._telemetry:
+            try:
+                sink.record(event)
+            except Exception:
+                pass
+
+    def _record_outcome(
+        self,
+        args: Namespace,
+        node: Optional[NodeBase],
+        start: float,
+        error: Optional[BaseException],
+    ) -> None:
+        if not self._telemetry:
+            return
+        if error is None:
+            outcome = "success"
+        elif isinstance(error, CLIError):
+            outcome = error.category
+        elif isinstance(error, KeyboardInterrupt):
+            outcome = "cancelled"
+        else:
+            outcome = "error"
+        duration = time.monotonic() - start
+        self.record_event(_make_event(args, node, duration, outcome))
+
+    def _get_pre_hooks(self, command: str) -> List[PreHook]:
+        return self._pre_hooks.get(None, []) + self._pre_hooks.get(command, [])
+
+    def _get_post_hooks(self, command: str) -> List[PostHook]:
+        hooks = self._post_hooks.get(None, []) + self._post_hooks.get(command, [])
+        return hooks[::-1]
+
+    def _get_cleanup_hooks(self, command: str) -> List[CleanupHook]:
+        hooks = self._cleanup_hooks.get(None, []) + self._cleanup_hooks.get(command, [])
+        return hooks[::-1]
+
+    def _get_node(
+        self, args: Namespace, node: Optional[NodeBase]
+    ) -> Optional[NodeBase]:
+        """
+        Get the node of the command being dispatched, see `dispatch()`.
+
+        :raises DispatchError:
+            If the node has to be found in the schema and more than one node
+            runs the command.
+        """
+        if node is not None:
+            return node
+        parsed_node = getattr(args, "command_node", None)
+        command = getattr(args, "command", None)
+        if parsed_node is not None or self._schema is None or command is None:
+            return parsed_node
+        # The commands of a multi-call binary's programs may also be run.
+        schemas = [self._schema] + list(getattr(self._schema, "binaries", {}).values())
+        nodes = [
+            n
+            for schema in schemas
+            for n in schema.walk(load=False)
+            if n.command == command
+        ]
+        if len(nodes) > 1:
+            raise DispatchError(
+                "Command {!r} is run by more than one node in the schema, pass the "
+                "node to dispatch".format(command)
+            )
+        return nodes[0] if nodes else None
+
+    @staticmethod
+    def _get_timeout(
+        node: Optional[NodeBase], timeout: Optional[float]
+    ) -> Optional[float]:
+        if timeout is not None:
+            return timeout
+        return node.get_timeout() if node is not None else None
+
+    def dispatch(
+        self,
+        args: Namespace,
+        *,
+        timeout: Optional[float] = None,
+        node: Optional[NodeBase] = None,
+        roles: Optional[Iterable[str]] = None
+    ) -> Any:
+        """
+        Call the handler for a parsed command.
+
+        Any hooks are called around the handler. Async handlers and hooks are
+        run to completion in a new event loop, so this must not be called from
+        within a running event loop if they're async.
+
+        :param args:
+            The parsed args, as returned by a CLI parser.
+        :param timeout:
+            The number of seconds the handler has to run, overriding the
+            timeout in the schema.
+        :param node:
+            The schema node of the command, for its timeout, working directory
+            and environment, defaults to the node the args were parsed for or
+            else the node found in the schema (if given).
+        :param roles:
+            The roles of the user, for commands with 'requires_role', defaults
+            to those given by the dispatcher's 'role_provider'.
+        :return:
+            The return value of the handler.
+        :raises DispatchError:
+            If no command was selected, there is no handler registered for
+            the command or the command's node can't be told apart from
+            others running it.
+        :raises UnauthorizedError:
+            If the command requires a role the user doesn't have.
+        :raises CommandTimeoutError:
+            If the handler doesn't finish within the timeout.
+        :raises CommandCancelledError:
+            If the handler is cancelled with Ctrl-C.
+        :raises CommandTerminatedError:
+            If the handler is cancelled by SIGTERM, for commands with a
+            'terminate_grace'.
+        """
+        start = time.monotonic()
+        try:
+            node = self._get_node(args, node)
+            result = self._dispatch(args, timeout, node, roles)
+        except BaseException as e:
+            self._record_outcome(args, node, start, e)
+            raise
+        self._record_outcome(args, node, start, None)
+        return result
+
+    def _dispatch(
+        self,
+        args: Namespace,
+        timeout: Optional[float],
+        node: Optional[NodeBase],
+        roles: Optional[Iterable[str]],
+    ) -> Any:
+        handler = self._get_handler(args, node)
+        self._check_authorized(node, roles)
+        timeout = self._get_timeout(node, timeout)
+        signals = node.get_signals() if node is not None else None
+        for pre_hook in self._get_pre_hooks(args.command):
+            _maybe_run_awaitable(pre_hook(args))
+        with command_environment(node, args):
+            if timeout is None and signals is None and not _accepts_token(handler):
+                result = _maybe_run_awaitable(self._call_handler(handler, args))
+            else:
+                token = CancellationToken()
+                external = self._find_handler(args, node) is None
+                try:
+                    result = _run_in_thread(
+                        lambda: _maybe_run_awaitable(
+                            self._call_handler(handler, args, token)
+                        ),
+                        token,
+                        timeout,
+                        args.command,
+                        signals,
+                        # Allow for the external command being killed.
+                        stop_wait=STOP_GRACE + 1 if external else None,
+                    )
+                except CommandCancelledError as e:
+                    for cleanup_hook in self._get_cleanup_hooks(args.command):
+                        _maybe_run_awaitable(cleanup_hook(args, e))
+                    if node is not None:
+                        run_cleanup_command(node, args)
+                    raise
+        for post_hook in self._get_post_hooks(args.command):
+            _maybe_run_awaitable(post_hook(args, result))
+        return result
+
+    def dispatch_chain(
+        self, chain: List[Namespace], *, on_error: Optional[str] = None
+    ) -> List[Any]:
+        """
+        Call the handlers for multiple parsed commands in order, as returned by
+        `AbstractCLIParser.parse_chain()`.
+
+        :param chain:
+            The parsed args for each command.
+        :param on_error:
+            Whether to 'stop' at the first command that raises a `CLIError`,
+            re-raising it, or 'continue' running the remaining commands and
+            raise a `ChainError` at the end. Defaults to the schema's
+            'chaining' settings, or 'stop' if there are none.
+        :return:
+            The return values of the handlers.
+        :raises ChainError:
+            If commands failed when continuing after failures.
+        """
+        if on_error is None:
+            chaining = getattr(self._schema, "chaining", None)
+            on_error = chaining.on_error if chaining is not None else "stop"
+        results = []
+        errors = []
+        for args in chain:
+            try:
+                results.append(self.dispatch(args))
+            except CLIError as e:
+                if on_error == "stop":
+                    raise
+                errors.append(e)
+                results.append(None)
+        if errors:
+            raise ChainError(errors)
+        return results
+
+    async def dispatch_async(
+        self,
+        args: Namespace,
+        *,
+        timeout: Optional[float] = None,
+        node: Optional[NodeBase] = None,
+        roles: Optional[Iterable[str]] = None
+    ) -> Any:
+        """
+        Call the handler for a parsed command, and any hooks around it,
+        awaiting those that are async.
+
+        If the awaiting task is cancelled while the handler is running, the
+        handler's cancellation token is cancelled too. Handling Ctrl-C and
+        SIGTERM is left to the owner of the event loop, so the 'interrupt' and
+        'terminate_grace' signal settings don't apply, though the cleanup
+        hooks are still called if the handler times out.
+
+        :param args:
+            The parsed args, as returned by a CLI parser.
+        :param timeout:
+            The number of seconds the handler has to run, overriding the
+            timeout in the schema.
+        :param node:
+            The schema node of the command, for its timeout, working directory
+            and environment, defaults to the node the args were parsed for or
+            else the node found in the schema (if given).
+        :param roles:
+            The roles of the user, for commands with 'requires_role', defaults
+            to those given by the dispatcher's 'role_provider'.
+        :return:
+            The return value of the handler.
+        :raises DispatchError:
+            If no command was selected, there is no handler registered for
+            the command or the command's node can't be told apart from
+            others running it.
+        :raises UnauthorizedError:
+            If the command requires a role the user doesn't have.
+        :raises CommandTimeoutError:
+            If the handler doesn't finish within the timeout.
+        """
+        start = time.monotonic()
+        try:
+            node = self._get_node(args, node)
+            result = await self._dispatch_async(args, timeout, node, roles)
+        except BaseException as e:
+            self._record_outcome(args, node, start, e)
+            raise
+        self._record_outcome(args, node, start, None)
+        return result
+
+    async def _dispatch_async(
+        self,
+        args: Namespace,
+        timeout: Optional[float],
+        node: Optional[NodeBase],
+        roles: Optional[Iterable[str]],
+    ) -> Any:
+        handler = self._get_handler(args, node)
+        self._check_authorized(node, roles)
+        timeout = self._get_timeout(node, timeout)
+        for pre_hook in self._get_pre_hooks(args.command):
+            await _maybe_await(pre_hook(args))
+        with command_environment(node, args):
+            if timeout is None and not _accepts_token(handler):
+                result = await _maybe_await(self._call_handler(handler, args))
+            else:
+                try:
+                    result = await self._run_task(handler, args, timeout)
+                except CommandCancelledError as e:
+                    for cleanup_hook in self._get_cleanup_hooks(args.command):
+                        await _maybe_await(cleanup_hook(args, e))
+                    if node is not None:
+                        run_cleanup_command(node, args)
+                    raise
+        for post_hook in self._get_post_hooks(args.command):
+            await _maybe_await(post_hook(args, result))
+        return result
+
+    @staticmethod
+    def _call_handler(
+        handler: Handler, args: Namespace, token: Optional[CancellationToken] = None
+    ):
+        """Call a handler, passing a token and progress handle if it accepts them."""
+        positional = [args, token] if _accepts_token(handler) else [args]
+        if _accepts_progress(handler):
+            return handler(*positional, progress=Progress.for_args(args))
+        return handler(*positional)
+
+    async def _run_task(
+        self, handler: Handler, args: Namespace, timeout: Optional[float]
+    ) -> Any:
+        """
+        Run a handler as a separate task, with sync handlers run in the event
+        loop's default executor.
+        """
+        token = CancellationToken()
+        loop = asyncio.get_event_loop()
+        if inspect.iscoroutinefunction(handler):
+            awaitable = self._call_handler(handler, args, token)
+        else:
+            awaitable = loop.run_in_executor(
+                None, self._call_handler, handler, args, token
+            )
+        task = asyncio.ensure_future(_maybe_await(awaitable))
+        try:
+            done, _ = await asyncio.wait([task], timeout=timeout)
+        except asyncio.CancelledError:
+            token.cancel("cancelled")
+            task.cancel()
+            raise
+        if not done:
+            token.cancel("timeout")
+            task.cancel()
+            raise _timeout_error(args.command, timeout)
+        return task.result()
+
+    def _check_authorized(
+        self, node: Optional[NodeBase], roles: Optional[Iterable[str]]
+    ) -> None:
+        """
+        :raises UnauthorizedError:
+            If the command requires a role the user doesn't have.
+        """
+        if node is None or node.find_unauthorized([]) is None:
+            # The command doesn't require any roles.
+            return
+        if roles is None:
+            roles = self._role_provider() if self._role_provider is not None else []
+        message = _unauthorized_message(node, roles or [])
+        if message is not None:
+            raise UnauthorizedError(message)
+
+    def _find_handler(
+        self, args: Namespace, node: Optional[NodeBase]
+    ) -> Optional[Handler]:
+        """Find the registered handler for a command, preferring that for its node."""
+        if node is not None and tuple(node.path) in self._path_handlers:
+            return self._path_handlers[tuple(node.path)]
+        return self._handlers.get(args.command)
+
+    def _get_handler(self, args: Namespace, node: Optional[NodeBase]) -> Handler:
+        command = getattr(args, "command", None)
+        if command is None:
+            raise DispatchError("No command selected to dispatch")
+        handler = self._find_handler(args, node)
+        if handler is not None:
+            return handler
+        if node is not None and node.external_command is not None:
+            # Accepting a token runs the command in a worker thread, so that
+            # it can be stopped on timeout, Ctrl-C or SIGTERM.
+            return lambda args, token: run_external_command(node, args, token)
+        if node is None and self._schema is None:
+            raise DispatchError(
+                "No handler registered for command {!r} (the args must be parsed "
+                "by a CLI parser, or the schema given to the dispatcher, to run "
+                "external commands)".format(command)
+            )
+        raise DispatchError("No handler registered for command {!r}".format(command))
@@ -0,0 +1,351 @@
+"""
+Documentation generation from a CLI schema.
+
+"""
+
+__all__ = ("render_manpages", "render_markdown", "render_tree")
+
+import datetime
+import re
+from typing import Dict, List, Optional
+
+from ._schema import Arg, NodeBase, RootNode
+from ._types import ArgType
+
+
+def _metavar(arg: Arg) -> str:
+    if arg.type is ArgType.MAP:
+        return "KEY=VALUE"
+    return arg.name.upper().replace("-", "_")
+
+
+def _format_arg_usage(arg: Arg) -> str:
+    """Format the usage of a single arg, e.g. '[-p PORT]'."""
+    if arg.positional:
+        metavar = _metavar(arg)
+        if arg.passthrough:
+            return "[-- {} ...]".format(metavar)
+        if arg.type is ArgType.TEXT:
+            return "[{} ...]".format(metavar)
+        if arg.multiple:
+            usage = "{} ...".format(metavar)
+            if arg.min == 0 or (arg.min is None and arg.default is not None):
+                usage = "[{}]".format(usage)
+            return usage
+        return "[{}]".format(metavar) if arg.default is not None else metavar
+    option = arg.option_strings[0]
+    if arg.type is ArgType.FLAG:
+        usage = option
+    elif arg.multiple:
+        usage = "{} {} ...".format(option, _metavar(arg))
+    else:
+        usage = "{} {}".format(option, _metavar(arg))
+    usage = usage if arg.required else "[{}]".format(usage)
+    is_repeatable = arg.count or arg.repeat or arg.type is ArgType.MAP
+    return usage + "..." if is_repeatable else usage
+
+
+def _visible_args(node: NodeBase) -> List[Arg]:
+    return [a for a in node.all_args if not a.hidden]
+
+
+def _visible_subtree(node: NodeBase) -> List[NodeBase]:
+    return [n for n in node.subtree if not n.hidden]
+
+
+def _describe_arg_deprecation(arg: Arg) -> str:
+    what = "This argument" if arg.positional else "This option"
+    return arg.deprecated.describe(what)
+
+
+def _format_usage(node: NodeBase, prog: str) -> str:
+    """Format the usage line for a node, e.g. 'prog server start [-p PORT]'."""
+    parts = [prog] + node.path
+    args = _visible_args(node)
+    parts += [_format_arg_usage(a) for a in args if not a.positional]
+    parts += [_format_arg_usage(a) for a in args if a.positional]
+    if _visible_subtree(node):
+        choices = "{" + "|".join(n.keyword for n in _visible_subtree(node)) + "}"
+        parts.append("[{}]".format(choices) if node.command else choices)
+    return " ".join(parts)
+
+
+def _roff_escape(text: str) -> str:
+    """Escape text for use in roff."""
+    text = text.replace("\\", "\\e").replace("-", "\\-")
+    lines = []
+    for line in text.splitlines():
+        if line.startswith((".", "'")):
+            line = "\\&" + line
+        lines.append(line)
+    return "\n".join(lines)
+
+
+def _manpage_name(node: NodeBase, prog: str) -> str:
+    return "-".join([prog] + node.path)
+
+
+def _render_manpage(
+    node: NodeBase, *, prog: str, section: int, date: str, version: Optional[str]
+) -> str:
+    name = _manpage_name(node, prog)
+    help_lines = node.help.strip().splitlines()
+    summary = help_lines[0] if help_lines else ""
+    lines = [
+        '.TH "{}" "{}" "{}" "{}"'.format(
+            name.upper(), section, date, _roff_escape(version or "")
+        ),
+        ".SH NAME",
+        "{} \\- {}".format(_roff_escape(name), _roff_escape(summary)),
+        ".SH SYNOPSIS",
+        ".B {}".format(_roff_escape(_format_usage(node, prog))),
+        ".SH DESCRIPTION",
+        # Blank lines separate paragraphs.
+        _roff_escape(node.help.strip()).replace("\n\n", "\n.PP\n"),
+    ]
+    if node.deprecated is not None:
+        lines += [
+            ".PP",
+            _roff_escape(node.deprecated.describe("This command") + "."),
+        ]
+    if _visible_args(node):
+        lines.append(".SH OPTIONS")
+        for arg in _visible_args(node):
+            if arg.positional:
+                term = _metavar(arg)
+            elif arg.type is ArgType.FLAG:
+                term = ", ".join(arg.option_strings)
+            else:
+                term = ", ".join(
+                    "{} {}".format(o, _metavar(arg)) for o in arg.option_strings
+                )
+            lines += [".TP", ".B {}".format(_roff_escape(term))]
+            lines.append(_roff_escape(arg.render_help().strip()))
+            if arg.enum:
+                lines.append(
+                    "Choices: {}.".format(_roff_escape(", ".join(map(str, arg.enum))))
+                )
+            if arg.default is not None:
+                lines.append("Default: {}.".format(_roff_escape(str(arg.default))))
+            if arg.env is not None:
+                lines.append(
+                    "Environment variable: \\fB{}\\fR.".format(_roff_escape(arg.env))
+                )
+            if arg.deprecated is not None:
+                lines.append(_roff_escape(_describe_arg_deprecation(arg) + "."))
+    if _visible_subtree(node):
+        lines.append(".SH COMMANDS")
+        for subnode in _visible_subtree(node):
+            sub_help = subnode.help.strip().splitlines()
+            lines += [
+                ".TP",
+                ".B {}".format(
+                    _roff_escape(", ".join([subnode.keyword] + subnode.aliases))
+                ),
+                _roff_escape(sub_help[0] if sub_help else ""),
+                "See \\fB{}\\fR({}).".format(
+                    _roff_escape(_manpage_name(subnode, prog)), section
+                ),
+            ]
+    if node.examples:
+        lines.append(".SH EXAMPLES")
+        command_prefix = " ".join([prog] + node.path)
+        for example in node.examples:
+            if example.description:
+                lines += [".PP", _roff_escape(example.description.strip())]
+            lines += [
+                ".PP",
+                ".nf",
+                ".RS",
+                _roff_escape("{} {}".format(command_prefix, example.command)),
+                ".RE",
+                ".fi",
+            ]
+    if node.parent is not None:
+        lines += [
+            ".SH SEE ALSO",
+            "\\fB{}\\fR({})".format(
+                _roff_escape(_manpage_name(node.parent, prog)), section
+            ),
+        ]
+    return "\n".join(lines) + "\n"
+
+
+def render_manpages(
+    schema: RootNode,
+    *,
+    prog: str,
+    section: int = 1,
+    version: Optional[str] = None,
+    date: Optional[datetime.date] = None
+) -> Dict[str, str]:
+    """
+    Render man pages for each node in a schema's command tree.
+
+    :param schema:
+        The schema to document.
+    :param prog:
+        The program name.
+    :param section:
+        The man page section.
+    :param version:
+        The program version to include in the page footers.
+    :param date:
+        The date to include in the page footers, defaults to today.
+    :return:
+        Mapping of man page filename (e.g. 'prog-server-start.1') to the roff
+        source of the page.
+    """
+    if date is None:
+        date = datetime.date.today()
+    pages = {}
+    for node in schema.walk():
+        if node.is_hidden:
+            continue
+        filename = "{}.{}".format(_manpage_name(node, prog), section)
+        pages[filename] = _render_manpage(
+            node, prog=prog, section=section, date=date.isoformat(), version=version
+        )
+    return pages
+
+
+def _markdown_anchor(title: str) -> str:
+    """Get the anchor used for a heading, as generated by GitHub."""
+    anchor = re.sub(r"[^\w\- ]", "", title.strip().lower())
+    return anchor.replace(" ", "-")
+
+
+def _markdown_escape_cell(text: str) -> str:
+    return " ".join(text.split()).replace("|", "\\|")
+
+
+def _render_markdown_node(node: NodeBase, prog: str) -> List[str]:
+    title = " ".join([prog] + node.path)
+    lines = ["## {}".format(title), "", node.help.strip(), ""]
+    if node.deprecated is not None:
+        lines += ["**{}.**".format(node.deprecated.describe("This command")), ""]
+    lines += ["```", _format_usage(node, prog), "```", ""]
+    if _visible_args(node):
+        lines += ["| Argument | Description | Default |", "| --- | --- | --- |"]
+        for arg in _visible_args(node):
+            if arg.positional:
+                term = "`{}`".format(_metavar(arg))
+            else:
+                term = ", ".join("`{}`".format(o) for o in arg.option_strings)
+            description = _markdown_escape_cell(arg.render_help())
+            if arg.enum:
+                description += " (one of: {})".format(
+                    ", ".join("`{}`".format(x) for x in arg.enum)
+                )
+            if arg.env is not None:
+                description += " (env: `{}`)".format(arg.env)
+            if arg.deprecated is not None:
+                description += " **{}.**".format(
+                    _markdown_escape_cell(_describe_arg_deprecation(arg))
+                )
+            default = "" if arg.default is None else "`{}`".format(arg.default)
+            lines.append("| {} | {} | {} |".format(term, description, default))
+        lines.append("")
+    if node.examples:
+        lines += ["Examples:", ""]
+        for example in node.examples:
+            if example.description:
+                lines += [example.description.strip(), ""]
+            lines += ["```", "{} {}".format(title, example.command), "```", ""]
+    if _visible_subtree(node):
+        lines += ["Subcommands:", ""]
+        for subnode in _visible_subtree(node):
+            sub_title = " ".join([prog] + subnode.path)
+            line = "- [`{}`](#{}): {}".format(
+                subnode.keyword,
+                _markdown_anchor(sub_title),
+                _markdown_escape_cell(subnode.help.strip().splitlines()[0]),
+            )
+            if subnode.aliases:
+                line += " (aliases: {})".format(
+                    ", ".join("`{}`".format(a) for a in subnode.aliases)
+                )
+            lines.append(line)
+        lines.append("")
+    return lines
+
+
+def render_markdown(schema: RootNode, *, prog: str) -> str:
+    """
+    Render a Markdown reference for a schema's command tree.
+
+    The output has a table of contents followed by a section for each
+    command, giving its usage and arguments.
+
+    :param schema:
+        The schema to document.
+    :param prog:
+        The program name.
+    :return:
+        The Markdown text.
+    """
+    lines = ["# {} command reference".format(prog), ""]
+    nodes = [n for n in schema.walk() if not n.is_hidden]
+    for node in nodes:
+        title = " ".join([prog] + node.path)
+        lines.append(
+            "{}- [`{}`](#{})".format(
+                "  " * len(node.path), title, _markdown_anchor(title)
+            )
+        )
+    lines.append("")
+    for node in nodes:
+        lines += _render_markdown_node(node, prog)
+    return "\n".join(lines)
+
+
+def _tree_label(node: NodeBase, *, with_args: bool) -> str:
+    label = node.keyword if node.keyword else ""
+    if node.keyword and node.aliases:
+        label += " ({})".format(", ".join(node.aliases))
+    if node.command:
+        label += " [{}]".format(node.command)
+    help_lines = node.help.strip().splitlines()
+    if help_lines:
+        label += " - " + help_lines[0]
+    if with_args:
+        usage = " ".join(_format_arg_usage(a) for a in node.args if not a.hidden)
+        if usage:
+            label += "\n    " + usage
+    return label
+
+
+def render_tree(schema: RootNode, *, prog: str, with_args: bool = False) -> str:
+    """
+    Render an outline of a schema's command tree, excluding hidden nodes.
+
+    Each node is shown with its keyword, aliases, command name (in square
+    brackets) and the first line of its help.
+
+    :param schema:
+        The schema to render.
+    :param prog:
+        The program name, shown for the root node.
+    :param with_args:
+        Whether to show the usage of each node's args beneath it.
+    :return:
+        The outline, ending with a newline.
+    """
+    lines = []
+
+    def add_node(node: NodeBase, prefix: str, child_prefix: str) -> None:
+        label_lines = _tree_label(node, with_args=with_args).split("\n")
+        lines.append(prefix + label_lines[0])
+        subtree = _visible_subtree(node)
+        for line in label_lines[1:]:
+            lines.append(child_prefix + ("│" if subtree else " ") + line[1:])
+        for i, subnode in enumerate(subtree):
+            last = i == len(subtree) - 1
+            add_node(
+                subnode,
+                child_prefix + ("└── " if last else "├── "),
+                child_prefix + ("    " if last else "│   "),
+            )
+
+    add_node(schema, prog, "")
+    return "\n".join(lines) + "\n"
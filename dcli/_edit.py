@@ -0,0 +1,365 @@
+"""
+Editing of YAML schema files in place, e.g. for scripts that update the CLI
+definitions of large schemas.
+
+"""
+
+__all__ = ("SchemaEditor",)
+
+import json
+import pathlib
+from typing import Any, Iterable, List, Optional
+
+import yaml
+
+from ._errors import SchemaError
+from ._schema import SubNode, _IncludingLoader
+from ._utils import PathLike
+
+
+class _IndentedDumper(yaml.SafeDumper):
+    """YAML dumper that indents sequences inside mappings, as in schema files."""
+
+    def increase_indent(self, flow=False, indentless=False):
+        return super().increase_indent(flow, False)
+
+
+def _compose(text: str) -> yaml.MappingNode:
+    """
+    Compose YAML text into its node tree, which records where each value is.
+
+    :raises SchemaError:
+        If the text is not valid YAML or isn't a mapping.
+    """
+    # Aliases to anchors from included files are composed as null.
+    loader = _IncludingLoader(text, anchors={}, lenient=True)
+    try:
+        node = loader.get_single_node()
+    except yaml.YAMLError as e:
+        raise SchemaError(str(e)) from e
+    finally:
+        loader.dispose()
+    if not isinstance(node, yaml.MappingNode):
+        raise SchemaError("Expected the schema to be a mapping")
+    return node
+
+
+def _get_item(node: yaml.Node, key: str) -> Optional[yaml.Node]:
+    """Get the key node of an item of a mapping node, if present."""
+    if isinstance(node, yaml.MappingNode):
+        for key_node, _ in node.value:
+            if isinstance(key_node, yaml.ScalarNode) and key_node.value == key:
+                return key_node
+    return None
+
+
+def _get_value(node: yaml.Node, key: str) -> Optional[yaml.Node]:
+    """Get the value node of an item of a mapping node, if present."""
+    if isinstance(node, yaml.MappingNode):
+        for key_node, value_node in node.value:
+            if isinstance(key_node, yaml.ScalarNode) and key_node.value == key:
+                return value_node
+    return None
+
+
+def _find_entry(node: yaml.Node, field: str, value: str) -> Optional[yaml.Node]:
+    """Find the mapping in a sequence node with a field set to a value."""
+    if not isinstance(node, yaml.SequenceNode):
+        return None
+    for item in node.value:
+        value_node = _get_value(item, field)
+        if isinstance(value_node, yaml.ScalarNode) and value_node.value == value:
+            return item
+    return None
+
+
+def _end_index(node: yaml.Node) -> int:
+    """
+    The index in the text at which a node's last value ends, which excludes
+    any comments following the end of a block mapping or sequence.
+    """
+    if isinstance(node, yaml.MappingNode) and node.value:
+        return _end_index(node.value[-1][1])
+    if isinstance(node, yaml.SequenceNode) and node.value:
+        return _end_index(node.value[-1])
+    return node.end_mark.index
+
+
+def _format_scalar(value: str, style: Optional[str]) -> str:
+    """Format a string as a YAML flow scalar, keeping the quoting if possible."""
+    if style == '"' or (style == "'" and "\n" in value):
+        return json.dumps(value, ensure_ascii=False)
+    if style == "'":
+        return "'{}'".format(value.replace("'", "''"))
+    text = yaml.safe_dump(
+        [value], default_flow_style=True, allow_unicode=True, width=float("inf")
+    )
+    return text.strip()[1:-1]
+
+
+def _format_block_scalar(value: str, style: str, indent: int) -> str:
+    """Format a string as a YAML literal or folded block scalar."""
+    chomping = "" if value.endswith("\n") else "-"
+    lines = [
+        " " * indent + line if line else "" for line in value.rstrip("\n").split("\n")
+    ]
+    return "{}{}\n{}\n".format(style, chomping, "\n".join(lines))
+
+
+class SchemaEditor:
+    """
+    Editor for a YAML schema file, which changes the text in place so that
+    comments, formatting and the order of keys are kept, e.g.:
+
+        editor = SchemaEditor.from_path("cli.yaml")
+        editor.add_command(["venv", "destroy"], help="Remove the venv")
+        editor.rename_arg(["venv"], "dev", "develop")
+        editor.set_help(["venv"], "Set up the virtual environment")
+        editor.save()
+
+    Commands are given by the path of keywords leading to them, with an empty
+    path for the root of the schema. Only the nodes and args written in this
+    file can be edited, not those from included files.
+    """
+
+    def __init__(self, text: str, *, path: Optional[PathLike] = None):
+        """
+        :param text:
+            The YAML text of the schema.
+        :param path:
+            The file the text is from, the default for `save()`.
+        :raises SchemaError:
+            If the text is not a valid YAML mapping.
+        """
+        self.path = pathlib.Path(path) if path is not None else None
+        self._text = text
+        self._root = _compose(text)
+
+    @classmethod
+    def from_path(cls, path: PathLike) -> "SchemaEditor":
+        """
+        Create an editor for a schema file.
+
+        :param path:
+            The path to the YAML schema file.
+        :raises SchemaError:
+            If the file can't be read or is not a valid YAML mapping.
+        """
+        try:
+            text = pathlib.Path(path).read_text()
+        except OSError as e:
+            raise SchemaError(
+                "Unable to read schema file: {}".format(e), location=str(path)
+            ) from e
+        return cls(text, path=path)
+
+    @property
+    def text(self) -> str:
+        """The edited YAML text."""
+        return self._text
+
+    def save(self, path: Optional[PathLike] = None) -> None:
+        """
+        Write the edited schema to a file.
+
+        :param path:
+            The file to write to, defaults to the file the schema was read from.
+        :raises ValueError:
+            If no path is given and the schema wasn't read from a file.
+        """
+        if path is None:
+            if self.path is None:
+                raise ValueError("No path to save the schema to")
+            path = self.path
+        pathlib.Path(path).write_text(self._text)
+
+    def _edit(self, start: int, end: int, text: str) -> None:
+        """Replace a range of the text, checking the result is still valid."""
+        new_text = self._text[:start] + text + self._text[end:]
+        self._root = _compose(new_text)
+        self._text = new_text
+
+    def _line_end(self, index: int) -> int:
+        """The index of the start of the line after that containing an index."""
+        if index > 0 and self._text[index - 1] == "\n":
+            return index
+        end = self._text.find("\n", index)
+        return len(self._text) if end == -1 else end + 1
+
+    def _insert_lines(self, index: int, text: str) -> int:
+        """
+        Insert lines of text at the start of a line, or at the end of the text,
+        returning the index of the end of the inserted text.
+        """
+        if index > 0 and self._text[index - 1] != "\n":
+            text = "\n" + text
+        self._edit(index, index, text)
+        return index + len(text)
+
+    def _find_node(self, path: List[str]) -> yaml.MappingNode:
+        node = self._root
+        for i, keyword in enumerate(path):
+            subnode = _find_entry(_get_value(node, "subtree"), "keyword", keyword)
+            if subnode is None:
+                raise ValueError(
+                    "No command {!r} in the schema".format(" ".join(path[: i + 1]))
+                )
+            node = subnode
+        return node
+
+    def _find_arg(self, path: List[str], name: str) -> yaml.MappingNode:
+        arg = _find_entry(_get_value(self._find_node(path), "args"), "name", name)
+        if arg is None:
+            raise ValueError(
+                "No arg {!r} on command {!r}".format(name, " ".join(path) or "root")
+            )
+        return arg
+
+    def _set_scalar(self, key: yaml.Node, node: yaml.Node, value: str) -> None:
+        """Replace the value of a mapping item, which must be a scalar."""
+        if not isinstance(node, yaml.ScalarNode):
+            raise ValueError(
+                "Can't replace the non-scalar value of {!r}".format(key.value)
+            )
+        if node.style in ("|", ">"):
+            text = _format_block_scalar(value, node.style, key.start_mark.column + 2)
+            # Keep the blank lines after the block, which are part of its span.
+            old_text = self._text[node.start_mark.index : node.end_mark.index]
+            text += "\n" * max(len(old_text) - len(old_text.rstrip("\n")) - 1, 0)
+        else:
+            text = _format_scalar(value, node.style)
+        self._edit(node.start_mark.index, node.end_mark.index, text)
+
+    def add_command(self, path: Iterable[str], **fields: Any) -> None:
+        """
+        Add a node to the subtree of a command, after its existing subnodes.
+
+        :param path:
+            The path of keywords to the new node, ending with its keyword.
+        :param fields:
+            The node's fields, as in a schema file, e.g. 'help' and 'command'.
+        :raises ValueError:
+            If the parent command isn't found, already has a node with the
+            keyword or has a flow-style subtree.
+        :raises SchemaError:
+            If the fields are invalid.
+        """
+        path = list(path)
+        if not path:
+            raise ValueError("The path to the new command can't be empty")
+        data = dict(keyword=path[-1], **fields)
+        SubNode.from_dict(data)
+        parent = self._find_node(path[:-1])
+        subtree = _get_value(parent, "subtree")
+        if _find_entry(subtree, "keyword", path[-1]) is not None:
+            raise ValueError("Command {!r} already exists".format(" ".join(path)))
+        if isinstance(subtree, yaml.SequenceNode) and subtree.value:
+            if subtree.flow_style:
+                raise ValueError("Can't add to a flow-style subtree")
+        entry = yaml.dump(
+            [data],
+            Dumper=_IndentedDumper,
+            sort_keys=False,
+            allow_unicode=True,
+            default_flow_style=False,
+        )
+        if isinstance(subtree, yaml.SequenceNode) and subtree.value:
+            items = subtree.value
+            # Mappings in a sequence start after the '- ' of the entry.
+            indent = items[0].start_mark.column - 2
+            index = self._line_end(_end_index(items[-1]))
+            if len(items) > 1:
+                gap_start = self._line_end(_end_index(items[-2]))
+                gap = self._text[gap_start : items[-1].start_mark.index]
+                if gap.startswith("\n"):
+                    # Keep the blank line between entries.
+                    entry = "\n" + entry
+        else:
+            column = parent.start_mark.column if path[:-1] else 0
+            indent = column + 2
+            if subtree is None:
+                index = self._line_end(_end_index(parent))
+                index = self._insert_lines(index, " " * column + "subtree:\n")
+            else:
+                key = _get_item(parent, "subtree")
+                if isinstance(subtree, yaml.SequenceNode):
+                    # Remove the empty flow sequence, i.e. 'subtree: []'.
+                    start = subtree.start_mark.index
+                    while self._text[start - 1] == " ":
+                        start -= 1
+                    self._edit(start, subtree.end_mark.index, "")
+                index = self._line_end(key.end_mark.index)
+        lines = [" " * indent + x if x else x for x in entry.split("\n")]
+        self._insert_lines(index, "\n".join(lines))
+
+    def rename_arg(self, path: Iterable[str], name: str, new_name: str) -> None:
+        """
+        Rename an arg of a command, changing its option name unless it has a
+        'long' name set. References to the arg, e.g. in 'conflicts_with', are
+        not changed.
+
+        :param path:
+            The path of keywords to the command.
+        :param name:
+            The arg's current name.
+        :param new_name:
+            The new name.
+        :raises ValueError:
+            If the command or arg isn't found, or the command already has an
+            arg with the new name.
+        """
+        path = list(path)
+        arg = self._find_arg(path, name)
+        args = _get_value(self._find_node(path), "args")
+        if _find_entry(args, "name", new_name) is not None:
+            raise ValueError(
+                "Command {!r} already has an arg {!r}".format(
+                    " ".join(path) or "root", new_name
+                )
+            )
+        self._set_scalar(_get_item(arg, "name"), _get_value(arg, "name"), new_name)
+
+    def set_help(
+        self,
+        path: Iterable[str],
+        text: str,
+        *,
+        arg: Optional[str] = None,
+        lang: Optional[str] = None
+    ) -> None:
+        """
+        Change the help text of a command or arg.
+
+        :param path:
+            The path of keywords to the command.
+        :param text:
+            The new help text.
+        :param arg:
+            The name of the arg to change the help of, or None for the command.
+        :param lang:
+            The language of the translation to change, for help given as a
+            mapping of language to text.
+        :raises ValueError:
+            If the command or arg isn't found, or the help is a mapping of
+            translations and the language isn't given.
+        """
+        path = list(path)
+        node = self._find_node(path) if arg is None else self._find_arg(path, arg)
+        key = _get_item(node, "help")
+        if key is None:
+            index = self._line_end(_end_index(node))
+            column = node.start_mark.column if node is not self._root else 0
+            line = "{}help: {}\n".format(" " * column, _format_scalar(text, None))
+            self._insert_lines(index, line)
+            return
+        value = _get_value(node, "help")
+        if isinstance(value, yaml.MappingNode):
+            if lang is None:
+                raise ValueError(
+                    "The help has translations, so the language must be given"
+                )
+            key = _get_item(value, lang)
+            if key is None:
+                raise ValueError("No help translation for {!r}".format(lang))
+            value = _get_value(value, lang)
+        self._set_scalar(key, value, text)
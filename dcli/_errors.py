@@ -0,0 +1,172 @@
+"""
+Error types raised by the package.
+
+All errors derive from `CLIError`, so applications can catch them together or
+match on the specific failure mode.
+
+"""
+
+__all__ = (
+    "CLIError",
+    "ChainError",
+    "CommandCancelledError",
+    "CommandTerminatedError",
+    "CommandTimeoutError",
+    "ConfigError",
+    "DispatchError",
+    "ParseError",
+    "SchemaError",
+    "SchemaValidationError",
+    "UnauthorizedError",
+)
+
+from typing import List, Optional
+
+
+class CLIError(Exception):
+    """
+    Base class for errors raised by the package.
+
+    The 'exit_code' attribute gives the suggested process exit code for the
+    error, and 'location' gives where the error occurred (e.g. a location in
+    the schema or a file path), if known. The 'category' attribute names the
+    kind of error, which a schema's 'exit_codes' may map to an exit code.
+    """
+
+    exit_code = 1
+    category = "error"
+
+    def __init__(
+        self,
+        message: str,
+        *,
+        location: Optional[str] = None,
+        exit_code: Optional[int] = None,
+        category: Optional[str] = None
+    ):
+        """
+        :param message:
+            Description of the error.
+        :param location:
+            Where the error occurred, included at the start of the message.
+        :param exit_code:
+            Suggested process exit code, overriding the class default.
+        :param category:
+            The kind of error, overriding the class default.
+        """
+        super().__init__(message)
+        self.message = message
+        self.location = location
+        if exit_code is not None:
+            self.exit_code = exit_code
+        if category is not None:
+            self.category = category
+
+    def __str__(self):
+        if self.location:
+            return "{}: {}".format(self.location, self.message)
+        return self.message
+
+
+class SchemaError(CLIError):
+    """Error loading or interpreting a CLI schema."""
+
+    category = "schema"
+
+
+class SchemaValidationError(SchemaError):
+    """Problems found when validating a loaded CLI schema."""
+
+    def __init__(self, problems: List[str], **kwargs):
+        """
+        :param problems:
+            The problems found, as returned by `validate()`.
+        :param kwargs:
+            Passed to base class.
+        """
+        super().__init__(
+            "Found {} problem(s) in CLI schema:\n{}".format(
+                len(problems), "\n".join("  " + p for p in problems)
+            ),
+            **kwargs
+        )
+        self.problems = problems
+
+
+class ConfigError(CLIError):
+    """Error loading a config file."""
+
+    category = "config"
+
+
+class ParseError(CLIError):
+    """Error parsing command line args."""
+
+    exit_code = 2
+    category = "usage"
+
+
+class DispatchError(CLIError):
+    """Error dispatching parsed args to a command handler."""
+
+    category = "dispatch"
+
+
+class CommandCancelledError(DispatchError):
+    """A command's handler was cancelled, e.g. with Ctrl-C."""
+
+    exit_code = 130
+    category = "cancelled"
+
+
+class CommandTerminatedError(CommandCancelledError):
+    """
+    A command's handler was cancelled by SIGTERM, for commands with a
+    'terminate_grace' in their 'signals' settings.
+    """
+
+    exit_code = 143
+    category = "terminated"
+
+
+class CommandTimeoutError(CommandCancelledError):
+    """A command's handler didn't finish within the command's timeout."""
+
+    exit_code = 124
+    category = "timeout"
+
+
+class UnauthorizedError(CLIError):
+    """
+    A command requires a role that the user doesn't have, given by the
+    'role_provider' of the CLI parser or dispatcher.
+    """
+
+    exit_code = 77
+    category = "unauthorized"
+
+
+class ChainError(DispatchError):
+    """
+    Commands in a chain failed, when continuing after failures, see
+    `Dispatcher.dispatch_chain()`.
+
+    The exit code and category are taken from the first failure.
+    """
+
+    def __init__(self, errors: List[CLIError], **kwargs):
+        """
+        :param errors:
+            The errors raised by the failed commands, in order.
+        :param kwargs:
+            Passed to base class.
+        """
+        kwargs.setdefault("exit_code", errors[0].exit_code)
+        kwargs.setdefault("category", errors[0].category)
+        super().__init__(
+            "{} command(s) failed:\n{}".format(
+                len(errors), "\n".join("  " + str(e) for e in errors)
+            ),
+            **kwargs
+        )
+        self.errors = errors
@@ -0,0 +1,51 @@
+"""
+Checking that the examples declared in a schema still parse.
+
+"""
+
+__all__ = ("check_examples",)
+
+import contextlib
+import io
+import shlex
+from typing import List
+
+from .clis import AbstractCLIParser
+
+
+def check_examples(parser: AbstractCLIParser) -> List[str]:
+    """
+    Parse each example declared in the parser's schema.
+
+    An example fails if parsing it errors or selects a different command to the
+    node it is declared on.
+
+    :param parser:
+        The CLI parser to check the examples with.
+    :return:
+        A list of the failures, empty if all examples parse.
+    """
+    problems = []
+    for node in parser.schema.walk():
+        location = ".".join(node.path) if node.keyword else "root"
+        for example in node.examples:
+            args = node.path + shlex.split(example.command)
+            stderr = io.StringIO()
+            try:
+                with contextlib.redirect_stderr(stderr):
+                    namespace = parser.parse_args(args)
+            except SystemExit:
+                error = stderr.getvalue().strip().splitlines()
+                problems.append(
+                    "{}: example {!r} failed to parse: {}".format(
+                        location, example.command, error[-1] if error else "exited"
+                    )
+                )
+                continue
+            if namespace.command != node.command:
+                problems.append(
+                    "{}: example {!r} selects command {!r}, expected {!r}".format(
+                        location, example.command, namespace.command, node.command
+                    )
+                )
+    return problems
@@ -0,0 +1,256 @@
+"""
+Rendering of help text, optionally from templates declared in the schema.
+
+Templates are format strings that may contain the following placeholders,
+each of which is replaced with a section of the help (or an empty string if
+the section has no content):
+ - {usage}: The usage line, without the 'usage: ' prefix.
+ - {description}: The command's help text.
+ - {commands}: The table of subcommands.
+ - {arguments}: The table of positional arguments.
+ - {options}: The table of options.
+ - {examples}: The example command lines declared in the schema.
+ - {footer}: The footer text declared in the schema.
+
+Literal braces must be doubled, i.e. '{{' and '}}'.
+
+"""
+
+__all__ = (
+    "COLOR_MODES",
+    "DEFAULT_HELP_TEMPLATE",
+    "DEFAULT_THEME",
+    "HELP_PLACEHOLDERS",
+    "HelpTheme",
+    "check_template",
+    "render_help",
+    "use_color",
+)
+
+import os
+import re
+import shutil
+import string
+import sys
+import textwrap
+from typing import List, NamedTuple, Optional, TextIO, Tuple
+
+
+HELP_PLACEHOLDERS = (
+    "usage",
+    "description",
+    "commands",
+    "arguments",
+    "options",
+    "examples",
+    "footer",
+)
+
+DEFAULT_HELP_TEMPLATE = """\
+usage: {usage}
+
+{description}
+
+{commands}
+
+{arguments}
+
+{options}
+
+{examples}
+
+{footer}
+"""
+
+# The maximum width of the first column of a table before the second column
+# starts on the next line.
+_MAX_TERM_WIDTH = 24
+
+
+def check_template(template: str) -> None:
+    """
+    Check a help template is valid.
+
+    :param template:
+        The template string.
+    :raises ValueError:
+        If the template is malformed or contains unknown placeholders.
+    """
+    try:
+        fields = [f for _, f, _, _ in string.Formatter().parse(template)]
+    except ValueError as e:
+        raise ValueError("Invalid help template: {}".format(e)) from None
+    for field in fields:
+        if field is not None and field not in HELP_PLACEHOLDERS:
+            raise ValueError(
+                "Unknown help template placeholder {{{}}}, accepted placeholders "
+                "are: {}".format(field, ", ".join(HELP_PLACEHOLDERS))
+            )
+
+
+class HelpTheme(
+    NamedTuple(
+        "HelpTheme",
+        [("heading", str), ("command", str), ("option", str), ("placeholder", str)],
+    )
+):
+    """
+    ANSI SGR sequences for colouring help output, e.g. '1' for bold or '36'
+    for cyan. Empty strings leave the element uncoloured.
+    """
+
+    __slots__ = ()
+
+
+HelpTheme.__new__.__defaults__ = ("1", "1;36", "36", "33")
+
+
+DEFAULT_THEME = HelpTheme()
+
+COLOR_MODES = ("auto", "always", "never")
+
+_OPTION_REGEX = re.compile(r"(?<![\w-])(--?[\w][\w-]*)")
+_PLACEHOLDER_REGEX = re.compile(r"\b([A-Z][A-Z0-9_]*)\b")
+
+
+def use_color(mode: str, stream: TextIO = sys.stdout) -> bool:
+    """
+    Determine whether to use colour in output.
+
+    In 'auto' mode colour is used if the stream is a terminal, unless the
+    'NO_COLOR' environment variable is set or the terminal is 'dumb'.
+
+    :param mode:
+        One of `COLOR_MODES`.
+    :param stream:
+        The stream being written to.
+    :raises ValueError:
+        If the mode is not recognised.
+    """
+    if mode not in COLOR_MODES:
+        raise ValueError(
+            "Invalid color mode {!r}, expected one of: {}".format(
+                mode, ", ".join(COLOR_MODES)
+            )
+        )
+    if mode != "auto":
+        return mode == "always"
+    if os.environ.get("NO_COLOR") or os.environ.get("TERM") == "dumb":
+        return False
+    return hasattr(stream, "isatty") and stream.isatty()
+
+
+def _style(text: str, sgr: str) -> str:
+    return "\033[{}m{}\033[0m".format(sgr, text) if sgr else text
+
+
+def _style_term(term: str, theme: HelpTheme, *, is_command: bool) -> str:
+    if is_command:
+        return _style(term, theme.command)
+    term = _PLACEHOLDER_REGEX.sub(lambda m: _style(m.group(1), theme.placeholder), term)
+    return _OPTION_REGEX.sub(lambda m: _style(m.group(1), theme.option), term)
+
+
+def _format_table(
+    title: str,
+    rows: List[Tuple[str, str]],
+    *,
+    width: int,
+    theme: Optional[HelpTheme],
+    is_command: bool = False
+) -> str:
+    """Format a titled two-column table, e.g. of options and their help."""
+    if not rows:
+        return ""
+    term_width = min(max(len(term) for term, _ in rows), _MAX_TERM_WIDTH)
+    indent = " " * (term_width + 4)
+    help_width = max(width - len(indent), 20)
+    lines = [_style("{}:".format(title), theme.heading if theme else "")]
+    for term, help_ in rows:
+        help_lines = []
+        for paragraph in help_.strip().splitlines():
+            help_lines += textwrap.wrap(paragraph, help_width) or [""]
+        if not help_lines:
+            help_lines = [""]
+        styled = term
+        if theme:
+            styled = _style_term(term, theme, is_command=is_command)
+        if len(term) > term_width:
+            lines.append("  " + styled)
+            lines += [indent + h for h in help_lines]
+        else:
+            padding = " " * (term_width - len(term))
+            lines.append("  {}{}  {}".format(styled, padding, help_lines[0]).rstrip())
+            lines += [indent + h for h in help_lines[1:]]
+    return "\n".join(lines)
+
+
+def _format_examples(
+    examples: List[Tuple[str, str]], *, theme: Optional[HelpTheme]
+) -> str:
+    if not examples:
+        return ""
+    lines = [_style("examples:", theme.heading if theme else "")]
+    for command, description in examples:
+        if description:
+            lines.append("  # {}".format(description.strip()))
+        lines.append("  " + command.rstrip())
+    return "\n".join(lines)
+
+
+def render_help(
+    template: str,
+    *,
+    usage: str,
+    description: str,
+    commands: List[Tuple[str, str]],
+    arguments: List[Tuple[str, str]],
+    options: List[Tuple[str, str]],
+    examples: List[Tuple[str, str]],
+    footer: str,
+    width: Optional[int] = None,
+    theme: Optional[HelpTheme] = None
+) -> str:
+    """
+    Render help text using a template.
+
+    The help strings in tables are wrapped to the width, and runs of blank
+    lines left by empty sections are collapsed.
+
+    :param template:
+        The template, see the module docstring for the placeholders.
+    :param usage:
+        The usage line.
+    :param description:
+        The command's help text.
+    :param commands:
+        Rows of subcommand names and their help.
+    :param arguments:
+        Rows of positional arg names and their help.
+    :param options:
+        Rows of option strings and their help.
+    :param examples:
+        Pairs of full example command line and description.
+    :param footer:
+        The footer text.
+    :param width:
+        The width to wrap to, defaults to the width of the terminal.
+    :param theme:
+        The colour theme to use, or None for no colour.
+    :return:
+        The rendered help, ending with a newline.
+    """
+    if width is None:
+        width = shutil.get_terminal_size().columns - 2
+    table_kwargs = dict(width=width, theme=theme)
+    text = template.format(
+        usage=usage,
+        description=description.strip(),
+        commands=_format_table("commands", commands, is_command=True, **table_kwargs),
+        arguments=_format_table("arguments", arguments, **table_kwargs),
+        options=_format_table("options", options, **table_kwargs),
+        examples=_format_examples(examples, theme=theme),
+        footer=footer.strip(),
+    )
+    text = re.sub(r"\n\s*\n(\s*\n)+", "\n\n", text)
+    return text.strip("\n") + "\n"
@@ -0,0 +1,117 @@
+"""
+Recording the commands run in a history file, and the built-in handlers for
+the 'history list' and 'history rerun' subcommands, see `HistorySpec`.
+
+"""
+
+__all__ = ("history_handlers", "load_history", "record_history")
+
+import shlex
+import sys
+from typing import Any, Callable, Dict, List
+
+from ._canonical import canonicalize
+from ._errors import CLIError
+from ._schema import HistorySpec, NodeBase, RootNode
+from ._types import Secret
+from .clis import Namespace
+
+
+def load_history(spec: HistorySpec) -> List[str]:
+    """
+    Load the commands recorded in the history file.
+
+    :param spec:
+        The schema's history settings.
+    :return:
+        The recorded command lines, oldest first, empty if there's no history
+        file.
+    """
+    try:
+        text = spec.get_path().read_text(encoding="utf-8")
+    except OSError:
+        return []
+    return [line for line in text.splitlines() if line.strip()]
+
+
+def record_history(spec: HistorySpec, node: NodeBase, namespace: Namespace) -> None:
+    """
+    Append a command to the history file, dropping the oldest commands beyond
+    the history size.
+
+    Commands are recorded in their canonical form with secrets redacted, see
+    `canonicalize()`. Errors writing the file are ignored, so that the history
+    can never break the CLI.
+
+    :param spec:
+        The schema's history settings.
+    :param node:
+        The node of the command being run.
+    :param namespace:
+        The parsed args.
+    """
+    if node.command in (HistorySpec.LIST_COMMAND, HistorySpec.RERUN_COMMAND):
+        return
+    line = " ".join(shlex.quote(w) for w in canonicalize(node, namespace))
+    lines = (load_history(spec) + [line])[-spec.size :]
+    path = spec.get_path()
+    try:
+        path.parent.mkdir(parents=True, exist_ok=True)
+        path.write_text("\n".join(lines) + "\n", encoding="utf-8")
+    except OSError:
+        pass
+
+
+def _drop_redacted(schema: RootNode, words: List[str]) -> List[str]:
+    """
+    Remove the redacted values of secret args from a recorded command, so that
+    they're taken from the environment or prompted for again.
+    """
+    redacted = set()
+    for node in schema.walk(load=False):
+        for arg in node.all_args:
+            if arg.secret:
+                redacted.add("--{}={}".format(arg.long, Secret.REDACTED))
+    return [w for w in words if w not in redacted]
+
+
+def history_handlers(
+    schema: RootNode, run: Callable[[List[str]], Any]
+) -> Dict[str, Callable[[Namespace], Any]]:
+    """
+    Create the handlers for the 'history list' and 'history rerun' commands.
+
+    :param schema:
+        The CLI schema, which must have 'history' settings.
+    :param run:
+        Function to parse and dispatch the args of a recorded command,
+        returning the handler's result.
+    :return:
+        The handlers, by command name.
+    """
+    spec = schema.history
+
+    def list_handler(args: Namespace) -> None:
+        lines = load_history(spec)
+        width = len(str(len(lines)))
+        for i, line in enumerate(lines, start=1):
+            print("{:>{}}  {}".format(i, width, line))
+
+    def rerun_handler(args: Namespace) -> Any:
+        lines = load_history(spec)
+        if not 1 <= args.number <= len(lines):
+            raise CLIError(
+                "No command {} in the history, which has {} commands".format(
+                    args.number, len(lines)
+                ),
+                category="usage",
+                exit_code=2,
+            )
+        line = lines[args.number - 1]
+        print(line, file=sys.stderr)
+        return run(_drop_redacted(schema, shlex.split(line)))
+
+    return {
+        HistorySpec.LIST_COMMAND: list_handler,
+        HistorySpec.RERUN_COMMAND: rerun_handler,
+    }
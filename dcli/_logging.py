@@ -0,0 +1,56 @@
+"""
+Configuring logging from the standard verbosity options, as declared by a
+schema's 'logging' settings.
+
+"""
+
+__all__ = ("get_log_level", "setup_logging")
+
+import logging
+
+from ._schema import LoggingSpec, RootNode
+from .clis import ArgSource, Namespace
+
+
+def get_log_level(spec: LoggingSpec, namespace: Namespace) -> int:
+    """
+    Get the log level selected by the parsed args.
+
+    A level given with '--log-level' (or its environment variable) takes
+    precedence, otherwise the default level is lowered by one step for each
+    '--verbose' and raised by one step for each '--quiet'.
+
+    :param spec:
+        The schema's logging settings.
+    :param namespace:
+        The parsed args.
+    :return:
+        The log level, e.g. `logging.INFO`.
+    """
+    sources = getattr(namespace, "arg_sources", {})
+    if sources.get("log_level", ArgSource.DEFAULT) is not ArgSource.DEFAULT:
+        name = namespace.log_level
+    else:
+        index = spec.LEVELS.index(spec.level)
+        index += getattr(namespace, "quiet", 0) - getattr(namespace, "verbose", 0)
+        name = spec.LEVELS[min(max(index, 0), len(spec.LEVELS) - 1)]
+    return getattr(logging, name.upper())
+
+
+def setup_logging(schema: RootNode, namespace: Namespace) -> None:
+    """
+    Configure the root logger according to the parsed args, if the schema has
+    logging settings.
+
+    :param schema:
+        The CLI schema.
+    :param namespace:
+        The parsed args.
+    """
+    if schema.logging is None:
+        return
+    if schema.logging.format:
+        logging.basicConfig(format=schema.logging.format)
+    else:
+        logging.basicConfig()
+    logging.getLogger().setLevel(get_log_level(schema.logging, namespace))
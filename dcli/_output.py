@@ -0,0 +1,191 @@
+"""
+Rendering of the data returned by command handlers in the output format
+selected with '--format', for commands with 'output' settings in the schema.
+
+Handlers return plain data, i.e. mappings, sequences and scalars, which may
+also contain objects with a 'to_dict()' method or named tuples. For table
+output the data should be a list of rows, each a mapping of column name to
+value, or a sequence of values for tables without a header.
+
+"""
+
+__all__ = ("render_output", "render_table")
+
+import enum
+import json
+import shutil
+from typing import Any, List, Optional
+
+import yaml
+
+
+def _to_data(value: Any) -> Any:
+    """Convert a value to plain data, with unrecognised scalars as strings."""
+    if hasattr(value, "to_dict"):
+        return _to_data(value.to_dict())
+    elif hasattr(value, "_asdict"):
+        return _to_data(value._asdict())
+    elif isinstance(value, dict):
+        return {str(k): _to_data(v) for k, v in value.items()}
+    elif isinstance(value, (list, tuple, set, frozenset)):
+        return [_to_data(x) for x in value]
+    elif isinstance(value, enum.Enum):
+        return value.value
+    elif value is None or isinstance(value, (str, int, float, bool)):
+        return value
+    return str(value)
+
+
+def _format_scalar(value: Any) -> str:
+    if value is None:
+        return ""
+    elif isinstance(value, bool):
+        return "true" if value else "false"
+    return str(value)
+
+
+def _render_text(data: Any) -> List[str]:
+    """Render data as plain text lines, 'key: value' for mappings."""
+    if isinstance(data, dict):
+        lines = []
+        for key, value in data.items():
+            if isinstance(value, (dict, list)):
+                lines.append("{}:".format(key))
+                lines += ["  " + line for line in _render_text(value)]
+            else:
+                lines.append("{}: {}".format(key, _format_scalar(value)).rstrip())
+        return lines
+    elif isinstance(data, list):
+        lines = []
+        for item in data:
+            if isinstance(item, dict) and lines:
+                lines.append("")
+            lines += _render_text(item)
+        return lines
+    elif data is None:
+        return []
+    return _format_scalar(data).splitlines()
+
+
+_COLUMN_SEP = "  "
+
+_MIN_COLUMN_WIDTH = 6
+
+_ELLIPSIS = "..."
+
+
+def _format_cell(value: Any) -> str:
+    if isinstance(value, list):
+        return ", ".join(_format_cell(x) for x in value)
+    elif isinstance(value, dict):
+        return json.dumps(value)
+    return " ".join(_format_scalar(value).splitlines())
+
+
+def _is_number(value: Any) -> bool:
+    return isinstance(value, (int, float)) and not isinstance(value, bool)
+
+
+def _fit_widths(widths: List[int], max_width: int) -> List[int]:
+    """Shrink the widest columns until the table fits in the width."""
+    widths = list(widths)
+    total = sum(widths) + len(_COLUMN_SEP) * (len(widths) - 1)
+    while total > max_width:
+        widest = max(range(len(widths)), key=lambda i: widths[i])
+        if widths[widest] <= _MIN_COLUMN_WIDTH:
+            break
+        widths[widest] -= 1
+        total -= 1
+    return widths
+
+
+def _truncate(text: str, width: int) -> str:
+    if len(text) <= width:
+        return text
+    return text[: width - len(_ELLIPSIS)] + _ELLIPSIS
+
+
+def render_table(
+    rows: List[Any], *, header: bool = True, width: Optional[int] = None
+) -> str:
+    """
+    Render rows of data as a table with aligned columns.
+
+    Numeric columns are right-aligned, and the widest columns are truncated
+    if the table doesn't fit in the width.
+
+    :param rows:
+        The rows, each a mapping of column name to value or a sequence of
+        values. The columns of mappings are the keys in the order they're
+        first seen.
+    :param header:
+        Whether to include a header row of the column names, if the rows are
+        mappings.
+    :param width:
+        The width to fit the table in, defaults to the width of the terminal.
+    :return:
+        The rendered table, ending with a newline unless there are no rows.
+    """
+    rows = [_to_data(r) for r in rows]
+    if not rows:
+        return ""
+    if width is None:
+        width = shutil.get_terminal_size().columns
+    if all(isinstance(r, dict) for r in rows):
+        columns = []  # type: List[str]
+        for row in rows:
+            columns += [k for k in row if k not in columns]
+        values = [[row.get(c) for c in columns] for row in rows]
+        headings = [c.upper().replace("_", " ") for c in columns] if header else []
+    else:
+        values = [r if isinstance(r, list) else [r] for r in rows]
+        num_columns = max(len(r) for r in values)
+        values = [r + [None] * (num_columns - len(r)) for r in values]
+        headings = []
+    cells = [[_format_cell(v) for v in row] for row in values]
+    table = ([headings] if headings else []) + cells
+    widths = [max(len(row[i]) for row in table) for i in range(len(table[0]))]
+    widths = _fit_widths(widths, width)
+    right_align = [
+        all(_is_number(row[i]) or row[i] is None for row in values)
+        for i in range(len(widths))
+    ]
+    lines = []
+    for row in table:
+        parts = []
+        for i, cell in enumerate(row):
+            cell = _truncate(cell, widths[i])
+            justify = cell.rjust if right_align[i] else cell.ljust
+            parts.append(justify(widths[i]))
+        lines.append(_COLUMN_SEP.join(parts).rstrip())
+    return "\n".join(lines) + "\n"
+
+
+def render_output(data: Any, format_: str, *, header: bool = True) -> str:
+    """
+    Render the data returned by a handler.
+
+    :param data:
+        The data to render.
+    :param format_:
+        The output format, one of `OutputSpec.FORMATS`.
+    :param header:
+        Whether to include the header row for table output.
+    :return:
+        The rendered output, ending with a newline unless empty.
+    :raises ValueError:
+        If the format is not recognised.
+    """
+    data = _to_data(data)
+    if format_ == "json":
+        return json.dumps(data, indent=2) + "\n"
+    elif format_ == "yaml":
+        return yaml.safe_dump(data, default_flow_style=False, sort_keys=False)
+    elif format_ == "table":
+        if not isinstance(data, list):
+            data = [data] if data is not None else []
+        return render_table(data, header=header)
+    elif format_ == "text":
+        lines = _render_text(data)
+        return "\n".join(lines) + "\n" if lines else ""
+    raise ValueError("Unrecognised output format {!r}".format(format_))
@@ -0,0 +1,55 @@
+"""
+Paging of long output through the user's pager.
+
+"""
+
+__all__ = ("page",)
+
+import os
+import shlex
+import shutil
+import subprocess
+import sys
+from typing import Optional, TextIO
+
+
+_DEFAULT_PAGER = "less -R"
+
+
+def page(text: str, *, stream: Optional[TextIO] = None) -> None:
+    """
+    Write text, through the pager if it's longer than the terminal height.
+
+    The pager is only used when writing to a terminal. The command is taken
+    from the 'PAGER' environment variable, defaulting to 'less -R', where an
+    empty value disables paging. If the pager can't be run the text is written
+    directly.
+
+    :param text:
+        The text to write.
+    :param stream:
+        The stream to write to, defaults to stdout.
+    """
+    if stream is None:
+        stream = sys.stdout
+    command = shlex.split(os.environ.get("PAGER", _DEFAULT_PAGER))
+    if (
+        not command
+        or not (hasattr(stream, "isatty") and stream.isatty())
+        or text.count("\n") < shutil.get_terminal_size().lines
+    ):
+        stream.write(text)
+        return
+    stream.flush()
+    try:
+        proc = subprocess.Popen(
+            command, stdin=subprocess.PIPE, stdout=stream, universal_newlines=True
+        )
+    except OSError:
+        stream.write(text)
+        return
+    try:
+        proc.communicate(text)
+    except (BrokenPipeError, KeyboardInterrupt):
+        # The pager was quit before reading all of the text.
+        proc.wait()
@@ -0,0 +1,127 @@
+"""
+Discovery of external plugin commands, git-style.
+
+A plugin is an executable named '<prefix><command>', found in the directories
+declared in the schema or in 'PATH'. A plugin may ship a schema fragment for
+its command alongside the executable, named '<prefix><command>.yaml' (or with
+any other schema file extension), which is used for help and completions.
+
+"""
+
+__all__ = ("add_plugin_nodes", "discover_plugins", "find_plugin")
+
+import os
+import pathlib
+import shutil
+from typing import Dict, List, Optional
+
+from ._errors import SchemaError
+from ._schema import (
+    _FORMAT_SUFFIXES,
+    PluginSpec,
+    RootNode,
+    SubNode,
+    _load_schema_file,
+)
+
+
+_SCHEMA_SUFFIXES = [x for suffixes in _FORMAT_SUFFIXES.values() for x in suffixes]
+
+
+def _search_dirs(spec: PluginSpec) -> List[pathlib.Path]:
+    dirs = [pathlib.Path(d).expanduser() for d in spec.dirs]
+    if spec.path:
+        dirs += [pathlib.Path(d) for d in os.get_exec_path() if d]
+    return dirs
+
+
+def _strip_exe_suffix(name: str) -> str:
+    """Strip any executable extension from a filename, e.g. '.exe' on Windows."""
+    return os.path.splitext(name)[0] if os.name == "nt" else name
+
+
+def find_plugin(spec: PluginSpec, command: str) -> Optional[pathlib.Path]:
+    """
+    Find the executable for a plugin command.
+
+    :param spec:
+        The schema's plugin settings.
+    :param command:
+        The command name.
+    :return:
+        The path to the executable, or None if not found.
+    """
+    if not command or "/" in command or os.sep in command:
+        return None
+    for directory in _search_dirs(spec):
+        found = shutil.which(spec.prefix + command, path=str(directory))
+        if found:
+            return pathlib.Path(found)
+    return None
+
+
+def discover_plugins(spec: PluginSpec) -> Dict[str, pathlib.Path]:
+    """
+    Find all available plugin commands.
+
+    :param spec:
+        The schema's plugin settings.
+    :return:
+        Mapping of command name to executable path, where executables found
+        earlier in the search order take precedence.
+    """
+    plugins = {}  # type: Dict[str, pathlib.Path]
+    for directory in _search_dirs(spec):
+        try:
+            entries = sorted(directory.iterdir())
+        except OSError:
+            continue
+        for entry in entries:
+            name = entry.name
+            if not name.startswith(spec.prefix) or entry.suffix in _SCHEMA_SUFFIXES:
+                continue
+            command = _strip_exe_suffix(name[len(spec.prefix) :])
+            if (
+                command
+                and command not in plugins
+                and shutil.which(name, path=str(directory))
+            ):
+                plugins[command] = entry
+    return plugins
+
+
+def _find_fragment(path: pathlib.Path) -> Optional[pathlib.Path]:
+    """Find the schema fragment shipped with a plugin executable."""
+    for suffix in _SCHEMA_SUFFIXES:
+        fragment = path.with_name(_strip_exe_suffix(path.name) + suffix)
+        if fragment.is_file():
+            return fragment
+    return None
+
+
+def add_plugin_nodes(schema: RootNode) -> None:
+    """
+    Add nodes to a schema for the plugins that ship a schema fragment.
+
+    Fragments are node schemas, with the keyword defaulting to the plugin's
+    command name. Plugins that clash with keywords declared in the schema are
+    ignored.
+
+    :param schema:
+        The schema to add nodes to, which must have plugin settings.
+    :raises SchemaError:
+        If a plugin's schema fragment is invalid.
+    """
+    keywords = {k for n in schema.subtree for k in [n.keyword] + n.all_aliases}
+    for command, path in discover_plugins(schema.plugins).items():
+        fragment = _find_fragment(path)
+        if command in keywords or fragment is None:
+            continue
+        data, _ = _load_schema_file(fragment, None, stack=(fragment.resolve(),))
+        data.setdefault("keyword", command)
+        node = SubNode.from_dict(data, location=str(fragment))
+        node.plugin_path = path
+        try:
+            schema.add_subnode(node)
+        except ValueError as e:
+            raise SchemaError(str(e), location=str(fragment)) from None
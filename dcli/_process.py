@@ -0,0 +1,277 @@
+"""
+Setting up the process for running a command, from the working directory and
+environment declared for the command in the schema, and running external
+commands declared in the schema.
+
+"""
+
+__all__ = (
+    "STOP_GRACE",
+    "command_environment",
+    "expand_command_templates",
+    "expand_external_command",
+    "run_cleanup_command",
+    "run_external_command",
+)
+
+import contextlib
+import os
+import re
+import subprocess
+from typing import Any, Dict, Iterator, List, Optional, Tuple
+
+from ._errors import DispatchError
+from ._schema import NodeBase
+from ._templates import TemplateError, expand_template
+from ._types import ArgType, Secret
+from .clis import Namespace
+
+
+# An element of an external command consisting of a single arg reference.
+_WHOLE_ARG_REGEX = re.compile(r"\$\{args\.([^}]*)\}")
+
+# How often to check whether an external command has been cancelled, and how
+# long it has to exit once stopped before being killed, in seconds.
+_POLL_INTERVAL = 0.1
+STOP_GRACE = 5.0
+
+
+def _describe_command(node: NodeBase) -> str:
+    return "command {!r}".format(" ".join(node.path)) if node.path else "root"
+
+
+def _template_value(value: Any) -> Any:
+    """Convert an arg value for substituting into a template."""
+    if isinstance(value, Secret):
+        return value.reveal()
+    if isinstance(value, list):
+        return " ".join(str(_template_value(x)) for x in value)
+    return value
+
+
+def _get_arg_values(node: NodeBase, namespace: Namespace) -> Dict[str, Any]:
+    return {
+        a.name: _template_value(getattr(namespace, a.dest, None))
+        for a in node.all_args
+    }
+
+
+def expand_command_templates(
+    node: NodeBase, namespace: Namespace
+) -> Tuple[Optional[str], Dict[str, str]]:
+    """
+    Expand the templates in a command's 'cwd' and 'env' settings.
+
+    :param node:
+        The node of the command being run.
+    :param namespace:
+        The parsed args, for '${args.name}' references.
+    :return:
+        The working directory (or None if not set) and environment variables
+        for the command.
+    :raises DispatchError:
+        If a template can't be expanded, e.g. an arg has no value.
+    """
+    location = _describe_command(node)
+    values = _get_arg_values(node, namespace)
+    cwd = node.get_cwd()
+    env = {}
+    try:
+        if cwd is not None:
+            cwd = os.path.expanduser(
+                expand_template(cwd, args=values, environ=os.environ)
+            )
+        for name, template in node.get_env().items():
+            env[name] = expand_template(template, args=values, environ=os.environ)
+    except TemplateError as e:
+        raise DispatchError(
+            "unable to expand environment for {}: {}".format(location, e)
+        ) from None
+    return cwd, env
+
+
+@contextlib.contextmanager
+def command_environment(
+    node: Optional[NodeBase], namespace: Namespace
+) -> Iterator[None]:
+    """
+    Change to a command's working directory and set its environment
+    variables, restoring them afterwards.
+
+    Note that these are process-wide, so commands with 'cwd' or 'env'
+    settings shouldn't be run concurrently.
+
+    :param node:
+        The node of the command being run, or None to do nothing.
+    :param namespace:
+        The parsed args, for '${args.name}' references.
+    :raises DispatchError:
+        If a template can't be expanded or the directory can't be entered.
+    """
+    if node is None:
+        yield
+        return
+    cwd, env = expand_command_templates(node, namespace)
+    old_cwd = None
+    if cwd is not None:
+        old_cwd = os.getcwd()
+        try:
+            os.chdir(cwd)
+        except OSError as e:
+            raise DispatchError(
+                "unable to change directory to {}: {}".format(cwd, e.strerror)
+            ) from None
+    old_env = {name: os.environ.get(name) for name in env}
+    os.environ.update(env)
+    try:
+        yield
+    finally:
+        for name, value in old_env.items():
+            if value is None:
+                os.environ.pop(name, None)
+            else:
+                os.environ[name] = value
+        if old_cwd is not None:
+            os.chdir(old_cwd)
+
+
+def expand_external_command(node: NodeBase, namespace: Namespace) -> List[str]:
+    """
+    Expand the templates in an external command's program and args.
+
+    An element consisting of a single '${args.name}' reference is replaced by
+    each of the arg's values if it's a list (e.g. a passthrough arg), by the
+    flag's long option if it's a flag that was given (e.g. '--release'), and
+    is omitted if the arg has no value or is a flag that wasn't given.
+
+    :param node:
+        The node of the external command.
+    :param namespace:
+        The parsed args.
+    :return:
+        The args to run, starting with the program.
+    :raises DispatchError:
+        If a template can't be expanded.
+    """
+    return _expand_argv(node, namespace, node.external_command, _describe_command(node))
+
+
+def _expand_argv(
+    node: NodeBase, namespace: Namespace, elements: List[str], description: str
+) -> List[str]:
+    """Expand the templates in a command's args, see `expand_external_command()`."""
+    values = _get_arg_values(node, namespace)
+    argv = []
+    for element in elements:
+        match = _WHOLE_ARG_REGEX.fullmatch(element)
+        if match and match.group(1) in values:
+            arg = next(a for a in node.all_args if a.name == match.group(1))
+            value = getattr(namespace, arg.dest, None)
+            if arg.type is ArgType.FLAG:
+                if value:
+                    argv.append("--" + arg.long)
+            elif isinstance(value, list):
+                argv += [str(_template_value(x)) for x in value]
+            elif value is not None:
+                argv.append(str(_template_value(value)))
+            continue
+        try:
+            argv.append(expand_template(element, args=values, environ=os.environ))
+        except TemplateError as e:
+            raise DispatchError(
+                "unable to expand {}: {}".format(description, e)
+            ) from None
+    return argv
+
+
+def _stop_process(proc: subprocess.Popen, reason: str) -> None:
+    """
+    Stop a child process whose command was cancelled, sending it SIGTERM and
+    then killing it if it doesn't exit within `STOP_GRACE` seconds.
+
+    On Ctrl-C SIGTERM isn't sent, since the child receives the SIGINT itself
+    as part of the terminal's foreground process group.
+    """
+    if reason != "interrupted":
+        proc.terminate()
+    try:
+        proc.wait(STOP_GRACE)
+    except subprocess.TimeoutExpired:
+        proc.kill()
+        proc.wait()
+
+
+def run_external_command(
+    node: NodeBase, namespace: Namespace, token: Optional[Any] = None
+) -> int:
+    """
+    Run an external command declared in the schema, waiting for it to exit.
+
+    The child process is stopped if the command is cancelled, i.e. when the
+    token is cancelled (e.g. by a timeout or SIGTERM, which is forwarded to
+    the child) or an exception such as `KeyboardInterrupt` is raised while
+    waiting, so that it isn't left running. It's sent SIGTERM (other than on
+    Ctrl-C, which it receives itself) and killed if it doesn't then exit
+    within `STOP_GRACE` seconds.
+
+    :param node:
+        The node of the external command.
+    :param namespace:
+        The parsed args.
+    :param token:
+        The command's `CancellationToken`, if it can be cancelled.
+    :return:
+        The command's exit code.
+    :raises DispatchError:
+        If the command can't be run.
+    """
+    argv = expand_external_command(node, namespace)
+    try:
+        proc = subprocess.Popen(argv)
+    except OSError as e:
+        raise DispatchError(
+            "unable to run {}: {}".format(argv[0], e.strerror)
+        ) from None
+    try:
+        while True:
+            try:
+                return proc.wait(_POLL_INTERVAL)
+            except subprocess.TimeoutExpired:
+                pass
+            if token is not None and token.cancelled:
+                _stop_process(proc, token.reason)
+                return proc.returncode
+    except BaseException as e:
+        if proc.poll() is None:
+            reason = "interrupted" if isinstance(e, KeyboardInterrupt) else "error"
+            _stop_process(proc, reason)
+        raise
+
+
+def run_cleanup_command(node: NodeBase, namespace: Namespace) -> None:
+    """
+    Run the cleanup command from a command's 'signals' settings, if it has
+    one, waiting for it to exit. Its exit code is ignored.
+
+    :param node:
+        The node of the command that was cancelled.
+    :param namespace:
+        The parsed args.
+    :raises DispatchError:
+        If the cleanup command can't be run.
+    """
+    signals = node.get_signals()
+    if signals is None or signals.cleanup is None:
+        return
+    argv = _expand_argv(
+        node,
+        namespace,
+        signals.cleanup,
+        "cleanup command of {}".format(_describe_command(node)),
+    )
+    try:
+        subprocess.call(argv)
+    except OSError as e:
+        raise DispatchError(
+            "unable to run cleanup command {}: {}".format(argv[0], e.strerror)
+        ) from None
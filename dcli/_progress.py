@@ -0,0 +1,214 @@
+"""
+Progress reporting for long-running command handlers, with spinners, progress
+bars and step messages written to stderr.
+
+"""
+
+__all__ = ("Progress", "ProgressBar")
+
+import contextlib
+import itertools
+import sys
+import threading
+from typing import Iterator, Optional, TextIO
+
+from .clis import Namespace
+
+
+# Output formats meant for other programs, for which progress isn't shown.
+_MACHINE_FORMATS = ("json", "yaml")
+
+_SPINNER_FRAMES = "|/-\\"
+
+_BAR_WIDTH = 30
+
+
+class ProgressBar:
+    """
+    A progress bar towards a total, created with `Progress.bar()`.
+
+    The bar is redrawn as it advances when writing to a terminal. Otherwise
+    only its message is written, once when it's started.
+    """
+
+    def __init__(self, progress: "Progress", total: int, message: str):
+        self._progress = progress
+        self.total = total
+        self.message = message
+        self.completed = 0
+
+    def advance(self, amount: int = 1) -> None:
+        """
+        Advance the bar.
+
+        :param amount:
+            How much to advance by, in the same units as the total.
+        """
+        self.update(self.completed + amount)
+
+    def update(self, completed: int) -> None:
+        """
+        Set how much of the total has been completed.
+
+        :param completed:
+            The amount completed, capped at the total.
+        """
+        self.completed = min(max(completed, 0), self.total)
+        if self._progress.interactive:
+            self._progress._draw(self.render())
+
+    def render(self) -> str:
+        """The bar as a line of text, e.g. 'Copying [####------]  40% (4/10)'."""
+        fraction = self.completed / self.total if self.total else 1.0
+        filled = int(fraction * _BAR_WIDTH)
+        bar = "#" * filled + "-" * (_BAR_WIDTH - filled)
+        text = "[{}] {:>3.0%} ({}/{})".format(bar, fraction, self.completed, self.total)
+        return "{} {}".format(self.message, text) if self.message else text
+
+
+class Progress:
+    """
+    Handle for reporting the progress of a command, passed to handlers that
+    accept a 'progress' keyword arg, e.g.:
+
+        @dispatcher.register("sync")
+        def sync(args, *, progress):
+            progress.step("Fetching items")
+            with progress.spinner("Connecting"):
+                client = connect()
+            with progress.bar(len(items), "Syncing") as bar:
+                for item in items:
+                    client.sync(item)
+                    bar.advance()
+
+    Nothing is written if '--quiet' was given or the command's output format
+    is for other programs (e.g. '--format json'). When not writing to a
+    terminal, spinners and bars aren't animated: their messages are written
+    once, as for steps, so that logs stay readable.
+    """
+
+    def __init__(
+        self,
+        *,
+        stream: Optional[TextIO] = None,
+        enabled: bool = True,
+        interactive: Optional[bool] = None
+    ):
+        """
+        :param stream:
+            The stream to write to, defaults to stderr.
+        :param enabled:
+            Whether to write anything.
+        :param interactive:
+            Whether to animate spinners and bars, defaults to whether the
+            stream is a terminal.
+        """
+        self.stream = stream if stream is not None else sys.stderr
+        self.enabled = enabled
+        if interactive is None:
+            interactive = hasattr(self.stream, "isatty") and self.stream.isatty()
+        self.interactive = enabled and interactive
+        # The length of the line being redrawn, if any, for clearing it.
+        self._line_length = 0
+        self._lock = threading.Lock()
+
+    @classmethod
+    def for_args(
+        cls, namespace: Namespace, *, stream: Optional[TextIO] = None
+    ) -> "Progress":
+        """
+        Create the progress handle for a parsed command, disabled by '--quiet'
+        and by output formats for other programs.
+
+        :param namespace:
+            The parsed args.
+        :param stream:
+            The stream to write to, defaults to stderr.
+        """
+        enabled = (
+            not getattr(namespace, "quiet", 0)
+            and getattr(namespace, "format", None) not in _MACHINE_FORMATS
+        )
+        return cls(stream=stream, enabled=enabled)
+
+    def _draw(self, line: str) -> None:
+        """Redraw the current line on the terminal."""
+        with self._lock:
+            padding = " " * max(self._line_length - len(line), 0)
+            self.stream.write("\r" + line + padding)
+            self.stream.flush()
+            self._line_length = len(line)
+
+    def _finish_line(self, line: Optional[str] = None) -> None:
+        """Clear the line being redrawn, replacing it with a line if given."""
+        with self._lock:
+            if self._line_length:
+                self.stream.write("\r" + " " * self._line_length + "\r")
+                self._line_length = 0
+            if line is not None:
+                self.stream.write(line + "\n")
+            self.stream.flush()
+
+    def step(self, message: str) -> None:
+        """
+        Report the start of a step of the command.
+
+        :param message:
+            Description of the step, e.g. 'Installing dependencies'.
+        """
+        if self.enabled:
+            self._finish_line(message)
+
+    @contextlib.contextmanager
+    def spinner(self, message: str) -> Iterator[None]:
+        """
+        Show a spinner while the body of the 'with' statement runs, for work
+        of unknown length.
+
+        :param message:
+            Description of the work.
+        """
+        if not self.interactive:
+            self.step(message)
+            yield
+            return
+        stopped = threading.Event()
+
+        def spin():
+            for frame in itertools.cycle(_SPINNER_FRAMES):
+                self._draw("{} {}".format(message, frame))
+                if stopped.wait(0.1):
+                    break
+
+        thread = threading.Thread(target=spin, daemon=True)
+        thread.start()
+        try:
+            yield
+        finally:
+            stopped.set()
+            thread.join()
+            self._finish_line(message)
+
+    @contextlib.contextmanager
+    def bar(self, total: int, message: str = "") -> Iterator[ProgressBar]:
+        """
+        Show a progress bar while the body of the 'with' statement runs.
+
+        :param total:
+            The total amount of work, e.g. the number of items.
+        :param message:
+            Description of the work, shown before the bar.
+        :return:
+            The bar, to advance as the work is done.
+        """
+        bar = ProgressBar(self, total, message)
+        if not self.interactive:
+            if message:
+                self.step(message)
+            yield bar
+            return
+        bar.update(0)
+        try:
+            yield bar
+        finally:
+            self._finish_line(bar.render())
@@ -0,0 +1,323 @@
+"""
+Interactive shell mode, where each line entered is parsed against the schema
+and dispatched to the command's handler.
+
+"""
+
+__all__ = ("run_repl",)
+
+import fnmatch
+import glob
+import os
+import shlex
+import subprocess
+import sys
+from typing import Callable, Iterable, List, Mapping, Optional, Tuple
+
+from ._canonical import canonicalize
+from ._dispatch import Dispatcher
+from ._logging import setup_logging
+from ._run import _register_builtin_handlers, _run_command
+from ._schema import CompleteSpec, NodeBase, RootNode
+from .clis import AbstractCLIParser, ArgSource, Namespace
+
+
+try:
+    import readline
+except ImportError:  # E.g. on Windows
+    readline = None
+
+
+_EXIT_WORDS = ("exit", "quit")
+
+# The number of seconds to wait for the command given in an arg's 'complete'
+# settings.
+_COMPLETE_COMMAND_TIMEOUT = 5
+
+# A function giving the completions for the start of a value.
+CompletionProvider = Callable[[str], Iterable[str]]
+
+
+def _complete_values(
+    spec: CompleteSpec, text: str, providers: Mapping[str, CompletionProvider]
+) -> List[str]:
+    """Get the candidate values for an arg's 'complete' settings."""
+    if spec.provider is not None:
+        provider = providers.get(spec.provider)
+        return list(provider(text)) if provider is not None else []
+    if spec.command is not None:
+        try:
+            result = subprocess.run(
+                spec.command,
+                shell=True,
+                stdout=subprocess.PIPE,
+                stderr=subprocess.DEVNULL,
+                universal_newlines=True,
+                timeout=_COMPLETE_COMMAND_TIMEOUT,
+            )
+        except (OSError, subprocess.TimeoutExpired):
+            return []
+        return [x.strip() for x in result.stdout.splitlines() if x.strip()]
+    return [
+        p + os.sep if os.path.isdir(p) else p
+        for p in glob.glob(text + "*")
+        if os.path.isdir(p) or fnmatch.fnmatch(os.path.basename(p), spec.files)
+    ]
+
+
+def _get_completions(
+    schema: RootNode,
+    words: List[str],
+    text: str,
+    providers: Optional[Mapping[str, CompletionProvider]] = None,
+) -> List[str]:
+    """
+    Get the completions for a partially entered line.
+
+    :param schema:
+        The CLI schema.
+    :param words:
+        The complete words before the one being completed.
+    :param text:
+        The start of the word being completed.
+    :param providers:
+        The completion providers for args with 'complete' settings naming
+        a provider.
+    :return:
+        The matching completions, sorted.
+    """
+    if providers is None:
+        providers = {}
+    node = schema  # type: NodeBase
+    value_arg = None
+    for word in words:
+        subnode = node.get_subnode(word)
+        if value_arg is None and subnode is not None:
+            node = subnode
+            continue
+        value_arg = None
+        for arg in node.all_args:
+            if word in arg.option_strings and arg.type.takes_value:
+                value_arg = arg
+                break
+    if value_arg is not None:
+        candidates = [str(x) for x in value_arg.enum] if value_arg.enum else []
+        if value_arg.complete is not None:
+            candidates = _complete_values(value_arg.complete, text, providers)
+    elif text.startswith("-"):
+        candidates = ["--help"]
+        for arg in node.all_args:
+            if not arg.hidden:
+                candidates += arg.option_strings
+    else:
+        candidates = [n.keyword for n in node.subtree if not n.hidden]
+        for arg in node.args:
+            if arg.positional and arg.enum and not arg.hidden:
+                candidates += [str(x) for x in arg.enum]
+            if arg.positional and arg.complete is not None and not arg.hidden:
+                candidates += _complete_values(arg.complete, text, providers)
+        if node is schema:
+            candidates += _EXIT_WORDS
+    return sorted(c for c in set(candidates) if c.startswith(text))
+
+
+class _Completer:
+    """Readline completer for the commands in a schema."""
+
+    def __init__(
+        self, schema: RootNode, providers: Mapping[str, CompletionProvider]
+    ):
+        self._schema = schema
+        self._providers = providers
+        self._matches = []  # type: List[str]
+
+    def __call__(self, text: str, state: int) -> Optional[str]:
+        if state == 0:
+            line = readline.get_line_buffer()[: readline.get_begidx()]
+            try:
+                words = shlex.split(line)
+            except ValueError:
+                words = line.split()
+            self._matches = [
+                m if m.endswith(os.sep) else m + " "
+                for m in _get_completions(self._schema, words, text, self._providers)
+            ]
+        return self._matches[state] if state < len(self._matches) else None
+
+
+def _setup_readline(
+    schema: RootNode,
+    history_file: Optional[str],
+    providers: Mapping[str, CompletionProvider],
+) -> None:
+    readline.set_completer(_Completer(schema, providers))
+    readline.set_completer_delims(" \t\n")
+    if "libedit" in (readline.__doc__ or ""):
+        readline.parse_and_bind("bind ^I rl_complete")
+    else:
+        readline.parse_and_bind("tab: complete")
+    if history_file and os.path.exists(history_file):
+        try:
+            readline.read_history_file(history_file)
+        except OSError:
+            pass
+
+
+def _history_line(
+    schema: RootNode,
+    line: str,
+    node: Optional[NodeBase],
+    namespace: Optional[Namespace],
+) -> Optional[str]:
+    """
+    The line to keep in the line history for a line entered, with the values
+    of secret args redacted, or None to leave the line out.
+
+    Lines that parsed are kept in their normalized form if they gave a value
+    for a secret arg, see `canonicalize()`. Other lines mentioning secret
+    args are left out, since their values can't be told apart.
+    """
+    if namespace is not None and node is not None:
+        sources = getattr(namespace, "arg_sources", {})
+        if any(
+            arg.secret and sources.get(arg.dest) is ArgSource.CLI
+            for arg in node.all_args
+        ):
+            return " ".join(shlex.quote(w) for w in canonicalize(node, namespace))
+        return line
+    options = set()
+    for schema_node in schema.walk(load=False):
+        for arg in schema_node.all_args:
+            if arg.secret:
+                options.update(arg.option_strings)
+    for word in line.split():
+        # Options may be given as '--token=...' or, if short, as '-t...'.
+        if word.split("=", 1)[0] in options or word[:2] in options:
+            return None
+    return line
+
+
+def _update_line_history(line: str, history_line: Optional[str]) -> None:
+    """Replace or remove the line just entered in the readline history."""
+    if history_line == line:
+        return
+    index = readline.get_current_history_length() - 1
+    if index < 0 or readline.get_history_item(index + 1) != line:
+        return
+    if history_line is None:
+        readline.remove_history_item(index)
+    else:
+        readline.replace_history_item(index, history_line)
+
+
+def _run_line(
+    parser: AbstractCLIParser, dispatcher: Dispatcher, words: List[str], prog: str
+) -> Tuple[Optional[NodeBase], Optional[Namespace]]:
+    """
+    Parse and run a single line as for `run_and_exit()`, reporting any errors
+    and non-zero exit codes.
+
+    :return:
+        The node of the command and the parsed args, or None for each if the
+        line didn't parse.
+    """
+    try:
+        namespace = parser.parse_args(words)
+    except SystemExit:
+        # Argparse has already printed the help or usage error.
+        return None, None
+    setup_logging(parser.schema, namespace)
+    code = _run_command(parser, dispatcher, namespace, prog=prog)
+    if code != 0:
+        print("{}: exit code {}".format(prog, code), file=sys.stderr)
+    return namespace.command_node, namespace
+
+
+def run_repl(
+    parser: AbstractCLIParser,
+    dispatcher: Dispatcher,
+    *,
+    prompt: Optional[str] = None,
+    history_file: Optional[str] = None,
+    prog: Optional[str] = None,
+    completion_providers: Optional[Mapping[str, CompletionProvider]] = None
+) -> None:
+    """
+    Run an interactive shell for a CLI.
+
+    Each line entered is parsed as the args of a single invocation of the
+    CLI and run as by `run_and_exit()`, including asking for confirmation if
+    the command is destructive and printing the output of commands with
+    'output' settings. Errors and non-zero exit codes are reported without
+    exiting the shell, which ends on 'exit', 'quit' or end of input.
+
+    Tab completion of keywords, options and arg values, and line history,
+    are available where the 'readline' module is supported, with the values
+    of secret args redacted in the line history. If the schema has
+    'history' settings, each command run is also recorded in the schema's
+    history file, for replaying with the 'history' commands.
+
+    :param parser:
+        The CLI parser.
+    :param dispatcher:
+        The dispatcher holding the command handlers.
+    :param prompt:
+        The prompt to show, defaults to the program name followed by '> '.
+    :param history_file:
+        File to load the line history from and save it to on exit.
+    :param prog:
+        The program name for the prompt and error messages.
+    :param completion_providers:
+        Mapping of names to functions giving the completions for the start of
+        a value, for args whose 'complete' settings name a provider.
+    """
+    if prog is None:
+        prog = os.path.basename(sys.argv[0])
+    if prompt is None:
+        prompt = "{}> ".format(prog)
+    _register_builtin_handlers(parser, dispatcher, prog=prog)
+    if readline:
+        _setup_readline(parser.schema, history_file, completion_providers or {})
+    try:
+        while True:
+            try:
+                line = input(prompt)
+            except KeyboardInterrupt:
+                # Discard the current line.
+                print()
+                continue
+            except EOFError:
+                print()
+                break
+            try:
+                words = shlex.split(line)
+            except ValueError as e:
+                print("{}: error: {}".format(prog, e), file=sys.stderr)
+                if readline:
+                    _update_line_history(
+                        line, _history_line(parser.schema, line, None, None)
+                    )
+                continue
+            if not words:
+                continue
+            if (
+                words[0] in _EXIT_WORDS
+                and len(words) == 1
+                and parser.schema.get_subnode(words[0]) is None
+            ):
+                break
+            node = namespace = None
+            try:
+                node, namespace = _run_line(parser, dispatcher, words, prog)
+            except KeyboardInterrupt:
+                print()
+            if readline:
+                _update_line_history(
+                    line, _history_line(parser.schema, line, node, namespace)
+                )
+    finally:
+        if readline and history_file:
+            try:
+                readline.write_history_file(history_file)
+            except OSError:
+                pass
@@ -0,0 +1,242 @@
+"""
+Running a CLI from parsing through to the process exit code.
+
+"""
+
+__all__ = ("confirm_destructive", "run_and_exit")
+
+import os
+import sys
+import time
+from typing import Any, List, NoReturn, Optional
+
+from ._dispatch import Dispatcher
+from ._errors import CLIError, DispatchError
+from ._history import history_handlers, record_history
+from ._logging import setup_logging
+from ._output import render_output
+from ._pager import page
+from ._schema import NodeBase, SelfUpdateSpec
+from ._self_update import self_update_handler
+from ._telemetry import UsageEvent
+from .clis import AbstractCLIParser, Namespace
+
+
+def confirm_destructive(node: NodeBase, namespace: Namespace, *, prog: str) -> None:
+    """
+    Ask for confirmation before running a destructive command, unless
+    '--yes' or '--dry-run' was given.
+
+    :param node:
+        The node of the command being run.
+    :param namespace:
+        The parsed args.
+    :param prog:
+        The program name, for the prompt.
+    :raises CLIError:
+        With category 'aborted' if the command isn't confirmed, including when
+        stdin isn't a terminal.
+    """
+    if not node.destructive or namespace.yes or namespace.dry_run:
+        return
+    command = " ".join([prog] + node.path)
+    if not sys.stdin.isatty():
+        raise CLIError(
+            "refusing to run '{}' without confirmation, pass --yes to "
+            "confirm".format(command),
+            category="aborted",
+        )
+    try:
+        answer = input("This will run '{}'. Are you sure? [y/N] ".format(command))
+    except EOFError:
+        answer = ""
+    if answer.strip().lower() not in ("y", "yes"):
+        raise CLIError("aborted", category="aborted")
+
+
+def _get_exit_code(node: NodeBase, category: str, default: int) -> int:
+    code = node.get_exit_code(category)
+    return default if code is None else code
+
+
+def _result_to_exit_code(node: NodeBase, result: Any) -> int:
+    """
+    Convert a handler's return value to an exit code.
+
+    :raises DispatchError:
+        If the result is a category with no declared exit code, or is of an
+        unsupported type.
+    """
+    if result is None:
+        return 0
+    elif isinstance(result, bool):
+        return 0 if result else 1
+    elif isinstance(result, int):
+        return result
+    elif isinstance(result, str):
+        code = node.get_exit_code(result)
+        if code is None:
+            raise DispatchError(
+                "No exit code declared in the schema for {!r}".format(result)
+            )
+        return code
+    raise DispatchError(
+        "Unsupported handler return value {!r}, expected None, an integer or an "
+        "exit code name".format(result)
+    )
+
+
+def _register_builtin_handlers(
+    parser: AbstractCLIParser, dispatcher: Dispatcher, *, prog: str
+) -> None:
+    """
+    Register the handlers for the commands added by the schema's
+    'self_update' and 'history' settings, unless the dispatcher already has
+    them.
+    """
+    schema = parser.schema
+    handlers = {}
+    if schema.self_update is not None:
+        handlers[SelfUpdateSpec.COMMAND] = self_update_handler(schema)
+    if schema.history is not None:
+
+        def rerun(args: List[str]) -> Any:
+            try:
+                namespace = parser.parse_args(args)
+            except SystemExit as e:
+                if e.code == 0:
+                    return None
+                # Argparse has already printed the usage error.
+                raise CLIError(
+                    "invalid command in the history", category="usage", exit_code=2
+                ) from None
+            node = namespace.command_node
+            confirm_destructive(node, namespace, prog=prog)
+            return dispatcher.dispatch(namespace, node=node, roles=parser.roles)
+
+        handlers.update(history_handlers(schema, rerun))
+    for command, handler in handlers.items():
+        if command not in dispatcher.commands:
+            dispatcher.register(command, handler)
+
+
+def _run_command(
+    parser: AbstractCLIParser,
+    dispatcher: Dispatcher,
+    namespace: Namespace,
+    *,
+    prog: str
+) -> int:
+    """Run a parsed command, returning the exit code."""
+    schema = parser.schema
+    node = namespace.command_node
+    try:
+        confirm_destructive(node, namespace, prog=prog)
+        try:
+            result = dispatcher.dispatch(namespace, node=node, roles=parser.roles)
+        finally:
+            # Only commands that were run are recorded, including if they fail.
+            if schema.history is not None:
+                record_history(schema.history, node, namespace)
+        if node.output is not None:
+            header = not getattr(namespace, "no_header", False)
+            text = render_output(result, namespace.format, header=header)
+            if schema.pager and not namespace.no_pager:
+                page(text)
+            else:
+                print(text, end="")
+            return 0
+        return _result_to_exit_code(node, result)
+    except CLIError as e:
+        print("{}: error: {}".format(prog, e), file=sys.stderr)
+        return _get_exit_code(node, e.category, e.exit_code)
+
+
+def run_and_exit(
+    parser: AbstractCLIParser,
+    dispatcher: Dispatcher,
+    args: Optional[List[str]] = None,
+    *,
+    prog: Optional[str] = None
+) -> NoReturn:
+    """
+    Parse args, dispatch to the command's handler and exit the process.
+
+    The exit code is determined as follows:
+     - Usage errors exit with the schema's 'usage' exit code, defaulting to 2.
+     - Handlers may return None (exit code 0), an integer exit code, or the
+       name of an exit code declared in the schema's 'exit_codes'. Async
+       handlers are run to completion first.
+     - Handlers for commands with 'output' settings instead return data, which
+       is printed in the format selected with '--format' and exits with 0.
+       The output is paged if the schema has 'pager' set.
+     - Commands with 'destructive' set ask for confirmation first, see
+       `confirm_destructive()`, exiting with the 'aborted' exit code if not
+       confirmed.
+     - Commands with a 'timeout' exit with the 'timeout' exit code if their
+       handler doesn't finish in time, defaulting to 124, and with the
+       'cancelled' exit code on Ctrl-C if their handler accepts a
+       cancellation token, defaulting to 130.
+     - Commands with 'signals' settings exit with the 'cancelled' exit code on
+       Ctrl-C once their handler is cancelled, and with the 'terminated' exit
+       code on SIGTERM if they have a 'terminate_grace', defaulting to 143.
+     - If a `CLIError` is raised, it is reported on stderr and the exit code
+       declared for its category is used, falling back to its 'exit_code'.
+
+    Exit codes declared on a command's node take precedence over those
+    declared above it.
+
+    If the schema has 'self_update' settings, the built-in handler for the
+    'self update' command is registered unless the dispatcher already has one,
+    and similarly for the 'history' commands if the schema has 'history'
+    settings. Each command run is then recorded in the history file.
+
+    If the schema has 'chaining' settings, each of the commands given is run
+    in turn, see `AbstractCLIParser.parse_chain()`. The exit code is that of
+    the first command to fail, after which the remaining commands are skipped
+    unless 'on_error' is set to 'continue'.
+
+    Logging is configured before the handler is called if the schema has
+    'logging' settings, see `setup_logging()`. Usage errors are recorded with
+    the dispatcher's telemetry sinks, as well as each dispatch.
+
+    :param parser:
+        The CLI parser.
+    :param dispatcher:
+        The dispatcher holding the command handlers.
+    :param args:
+        The args to parse, defaults to the process's args.
+    :param prog:
+        The program name for error messages.
+    """
+    if prog is None:
+        prog = os.path.basename(sys.argv[0])
+    if args is None:
+        args = sys.argv[1:]
+    schema = parser.schema
+    start = time.monotonic()
+    try:
+        chain = parser.parse_chain(args)
+    except SystemExit as e:
+        # Argparse exits with code 2 for usage errors.
+        if e.code == 2:
+            event = UsageEvent(
+                command=None,
+                path=[],
+                args=[],
+                duration=time.monotonic() - start,
+                outcome="usage",
+            )
+            dispatcher.record_event(event)
+            sys.exit(_get_exit_code(schema, "usage", 2))
+        raise
+    _register_builtin_handlers(parser, dispatcher, prog=prog)
+    setup_logging(schema, chain[0])
+    exit_code = 0
+    for namespace in chain:
+        code = _run_command(parser, dispatcher, namespace, prog=prog)
+        if code != 0 and exit_code == 0:
+            exit_code = code
+            if schema.chaining is None or schema.chaining.on_error == "stop":
+                break
+    sys.exit(exit_code)
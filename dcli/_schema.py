@@ -5,15 +5,926 @@ Parsing of a CLI schema.
 
 """
 
-__all__ = ("Arg", "NodeBase", "RootNode", "SubNode")
+__all__ = (
+    "DEFAULT_SCHEMA_FILENAMES",
+    "Arg",
+    "ArgGroup",
+    "ChainSpec",
+    "CompleteSpec",
+    "ConfigSpec",
+    "Deprecation",
+    "Example",
+    "GlobSpec",
+    "HistorySpec",
+    "LocalizedText",
+    "LoggingSpec",
+    "NodeBase",
+    "OutputSpec",
+    "PluginSpec",
+    "RootNode",
+    "SCHEMA_VERSION",
+    "SchemaError",
+    "SchemaFormat",
+    "SelfUpdateSpec",
+    "SignalsSpec",
+    "SourceSpan",
+    "SubNode",
+    "Validation",
+    "VersionSpec",
+    "check_schema",
+    "find_schema_file",
+    "json_schema",
+    "load_from_path",
+    "migrate",
+    "validate",
+)
 
+import bisect
+import datetime
+import enum
+import glob
+import hashlib
+import json
+import os
+import pathlib
+import pickle
+import re
+import shlex
+import sys
+import tempfile
 import typing
-from typing import Dict, List, Optional
+import warnings
+from typing import Dict, Iterable, List, Optional
+
+import yaml
+
+from . import __version__
+from ._errors import SchemaError, SchemaValidationError
+from ._help import check_template
+from ._templates import TemplateError, get_arg_references, is_template
+from ._types import ArgType, parse_duration, parse_size
+from ._utils import PathLike
+
+
+DEFAULT_SCHEMA_FILENAMES = ("cli.yaml", "cli.yml", "cli.json", "cli.toml")
+
+# The current version of the schema format, see `migrate()`. Schemas without a
+# 'schema_version' field are taken to be version 1.
+SCHEMA_VERSION = 1
+
+# Functions upgrading the data of a schema in each older version of the format
+# to the next version, in place, keyed by the older version.
+_MIGRATIONS = {}  # type: Dict[int, typing.Callable[[Dict[str, typing.Any]], None]]
+
+
+class SourceSpan(
+    typing.NamedTuple(
+        "SourceSpan",
+        [
+            # The path of the file, if known.
+            ("file", Optional[str]),
+            ("line", int),
+            ("column", Optional[int]),
+        ],
+    )
+):
+    """Where in a schema file a mapping starts."""
+
+    __slots__ = ()
+
+    def __str__(self):
+        if self.file is None:
+            if self.column is None:
+                return "line {}".format(self.line)
+            return "line {}, column {}".format(self.line, self.column)
+        if self.column is None:
+            return "{}:{}".format(self.file, self.line)
+        return "{}:{}:{}".format(self.file, self.line, self.column)
+
+
+class _LineTrackingLoader(yaml.SafeLoader):
+    """YAML loader that records the position of each mapping."""
+
+
+class _MarkedDict(dict):
+    """A dictionary loaded from a file, marked with its position."""
+
+    lineno = None  # type: Optional[int]
+    column = None  # type: Optional[int]
+    file = None  # type: Optional[str]
+
+
+def _construct_marked_mapping(loader: yaml.SafeLoader, node: yaml.MappingNode):
+    data = _MarkedDict()
+    data.lineno = node.start_mark.line + 1
+    data.column = node.start_mark.column + 1
+    yield data
+    data.update(loader.construct_mapping(node))
+
+
+_LineTrackingLoader.add_constructor(
+    yaml.resolver.BaseResolver.DEFAULT_MAPPING_TAG, _construct_marked_mapping
+)
+
+
+class _IncludingLoader(_LineTrackingLoader):
+    """
+    YAML loader that resolves aliases to anchors defined in included files.
+
+    In lenient mode undefined aliases are loaded as null, which allows finding
+    the files included by a document before their anchors are known.
+    """
+
+    def __init__(self, stream, *, anchors: Dict[str, yaml.Node], lenient: bool):
+        super().__init__(stream)
+        self.extra_anchors = anchors
+        self.lenient = lenient
+        self.defined_anchors = {}  # type: Dict[str, yaml.Node]
+        # Whether any undefined aliases were loaded as null in lenient mode.
+        self.found_undefined = False
+
+    def compose_node(self, parent, index):
+        if self.check_event(yaml.AliasEvent):
+            event = self.peek_event()
+            if event.anchor not in self.anchors:
+                if event.anchor in self.extra_anchors:
+                    self.get_event()
+                    return self.extra_anchors[event.anchor]
+                elif self.lenient:
+                    self.get_event()
+                    self.found_undefined = True
+                    return yaml.ScalarNode(
+                        "tag:yaml.org,2002:null", "", event.start_mark, event.end_mark
+                    )
+        return super().compose_node(parent, index)
+
+    def compose_document(self):
+        # As in the base class, but recording the anchors before they're reset.
+        self.get_event()
+        node = self.compose_node(None, None)
+        self.get_event()
+        self.defined_anchors = self.anchors
+        self.anchors = {}
+        return node
+
+
+def _load_yaml(
+    text: str, *, anchors: Dict[str, yaml.Node], lenient: bool = False
+) -> typing.Tuple[typing.Any, Dict[str, yaml.Node], bool]:
+    """
+    Load YAML text, returning the data, the anchors it defines and whether any
+    undefined aliases were loaded as null (in lenient mode).
+
+    :raises ValueError:
+        If the text is not valid YAML.
+    """
+    loader = _IncludingLoader(text, anchors=anchors, lenient=lenient)
+    try:
+        data = loader.get_single_data()
+    except yaml.YAMLError as e:
+        raise ValueError(str(e)) from e
+    finally:
+        loader.dispose()
+    return data, dict(anchors, **loader.defined_anchors), loader.found_undefined
+
+
+def _get_lineno(data: typing.Any) -> Optional[int]:
+    """Get the line number a mapping was loaded from, if known."""
+    return getattr(data, "lineno", None)
+
+
+def _get_span(data: typing.Any) -> Optional[SourceSpan]:
+    """Get the position a mapping was loaded from, if known."""
+    if _get_lineno(data) is None:
+        return None
+    return SourceSpan(
+        getattr(data, "file", None), data.lineno, getattr(data, "column", None)
+    )
+
+
+def _mark_file(data: typing.Any, path: str) -> None:
+    """Record the file the mappings in loaded data are from, where not known."""
+    if isinstance(data, _MarkedDict) and data.file is None:
+        data.file = path
+    if isinstance(data, dict):
+        for value in data.values():
+            _mark_file(value, path)
+    elif isinstance(data, list):
+        for x in data:
+            _mark_file(x, path)
+
+
+def _format_location(location: str, span: Optional[SourceSpan]) -> str:
+    """Format a schema location for an error message, including its position."""
+    if span is None:
+        return location
+    return "{} ({})".format(location, span)
+
+
+# Mapping of field name to (accepted types, required).
+_FieldSpec = Dict[str, typing.Tuple[typing.Tuple[typing.Type, ...], bool]]
+
+_TYPE_NAMES = {
+    str: "a string",
+    bool: "a boolean",
+    int: "an integer",
+    float: "a number",
+    datetime.datetime: "a timestamp",
+    list: "a list",
+    dict: "a mapping",
+}
+
+
+def _check_fields(data: typing.Any, fields: _FieldSpec, *, location: str) -> None:
+    """
+    Check the fields of a schema mapping.
+
+    :param data:
+        The loaded mapping to check.
+    :param fields:
+        The accepted fields.
+    :param location:
+        Description of where in the schema the mapping is, for error messages.
+    :raises SchemaError:
+        If there are unknown keys, missing required keys or values of the wrong
+        type.
+    """
+    if not isinstance(data, dict):
+        raise SchemaError(
+            "expected a mapping, got {}".format(type(data).__name__),
+            location=location,
+        )
+    unknown = [k for k in data if k not in fields]
+    if unknown:
+        raise SchemaError(
+            "unknown key {!r}, accepted keys are: {}".format(
+                unknown[0], ", ".join(fields)
+            ),
+            location=location,
+        )
+    for key, (types, required) in fields.items():
+        if key not in data:
+            if required:
+                raise SchemaError(
+                    "missing required key {!r}".format(key), location=location
+                )
+            continue
+        value = data[key]
+        # Note that bool is a subclass of int, so must be excluded explicitly.
+        if not isinstance(value, types) or (
+            isinstance(value, bool) and bool not in types
+        ):
+            raise SchemaError(
+                "key {!r} should be {}, got {!r}".format(
+                    key, " or ".join(_TYPE_NAMES[t] for t in types), value
+                ),
+                location=location,
+            )
+
+
+def _raise_errors(errors: List[SchemaError]) -> None:
+    """
+    Raise the errors found in a list of schema entries together, so that all
+    of them are reported at once.
+
+    :raises SchemaError:
+        If there are any errors, as a `SchemaValidationError` if there's more
+        than one.
+    """
+    if len(errors) == 1:
+        raise errors[0]
+    if errors:
+        problems = []  # type: List[str]
+        for e in errors:
+            if isinstance(e, SchemaValidationError):
+                problems += e.problems
+            else:
+                problems.append(str(e))
+        raise SchemaValidationError(problems)
+
+
+def _to_plain(value: typing.Any) -> typing.Any:
+    """Convert a value to plain data, e.g. for JSON."""
+    if hasattr(value, "to_dict"):
+        return value.to_dict()
+    elif isinstance(value, list):
+        return [_to_plain(x) for x in value]
+    elif isinstance(value, dict):
+        return {k: _to_plain(v) for k, v in value.items()}
+    elif isinstance(value, enum.Enum):
+        return value.value
+    elif isinstance(value, datetime.datetime):
+        return value.isoformat()
+    return value
+
+
+def _fields_to_dict(obj: typing.Any) -> Dict[str, typing.Any]:
+    """Get the schema fields of an object, omitting those that are unset."""
+    return {
+        key: _to_plain(getattr(obj, key))
+        for key in obj._FIELDS
+        if getattr(obj, key) is not None
+    }
+
+
+def _timeout_to_seconds(value: typing.Union[str, int, float]) -> float:
+    """
+    Convert a node's 'timeout' value to seconds.
+
+    :raises ValueError:
+        If the value isn't a valid duration.
+    """
+    if isinstance(value, bool):
+        raise ValueError("Invalid duration {!r}".format(value))
+    if isinstance(value, (int, float)):
+        return float(value)
+    return parse_duration(value).total_seconds()
+
+
+def _upgrade_schema_data(data: Dict[str, typing.Any], *, location: str) -> int:
+    """
+    Upgrade the loaded data of a schema to the current version of the format,
+    in place.
+
+    :param data:
+        The loaded mapping of the root node.
+    :param location:
+        Description of the schema, for error messages.
+    :return:
+        The version the schema was in.
+    :raises SchemaError:
+        If the schema version is invalid or newer than supported.
+    """
+    version = data.get("schema_version", 1)
+    if not isinstance(version, int) or isinstance(version, bool) or version < 1:
+        raise SchemaError(
+            "Invalid schema version {!r}, expected a positive integer".format(
+                version
+            ),
+            location=_format_location(location, _get_span(data)),
+        )
+    if version > SCHEMA_VERSION:
+        raise SchemaError(
+            "Schema version {} is newer than the latest supported version {}, "
+            "a newer version of dcli is required".format(version, SCHEMA_VERSION),
+            location=_format_location(location, _get_span(data)),
+        )
+    for old_version in range(version, SCHEMA_VERSION):
+        _MIGRATIONS[old_version](data)
+    if version != SCHEMA_VERSION:
+        data["schema_version"] = SCHEMA_VERSION
+    return version
+
+
+def _get_env_lang() -> Optional[str]:
+    """Get the user's language from the locale environment variables."""
+    for name in ("LC_ALL", "LC_MESSAGES", "LANG"):
+        value = os.environ.get(name)
+        if value and value not in ("C", "POSIX"):
+            return value
+    return None
+
+
+def _lang_chain(lang: str) -> List[str]:
+    """
+    Get the languages to try for a locale name, most specific first, e.g.
+    ['fr_CA', 'fr'] for 'fr_CA.UTF-8'.
+    """
+    lang = re.split(r"[.@]", lang, 1)[0].replace("-", "_")
+    chain = [lang]
+    if "_" in lang:
+        chain.append(lang.split("_", 1)[0])
+    return chain
+
+
+# The platforms nodes and args may be restricted to, where 'unix' is any
+# platform other than Windows.
+PLATFORMS = ("linux", "macos", "windows", "unix")
+
+
+def _current_platforms() -> List[str]:
+    """The values from `PLATFORMS` that match the current platform."""
+    if sys.platform.startswith("win"):
+        return ["windows"]
+    platforms = ["unix"]
+    if sys.platform.startswith("linux"):
+        platforms.append("linux")
+    elif sys.platform == "darwin":
+        platforms.append("macos")
+    return platforms
+
+
+def _check_platforms(platforms: Optional[List[str]]) -> None:
+    for platform in platforms or []:
+        if platform not in PLATFORMS:
+            raise ValueError(
+                "Unrecognised platform {!r}, accepted platforms are: {}".format(
+                    platform, ", ".join(PLATFORMS)
+                )
+            )
+
+
+def _is_platform_available(platforms: Optional[List[str]]) -> bool:
+    return platforms is None or any(p in platforms for p in _current_platforms())
+
+
+def _filter_platform_args(
+    args: List["Arg"], groups: List["ArgGroup"]
+) -> typing.Tuple[List["Arg"], List["ArgGroup"]]:
+    """
+    Remove the args not available on the current platform, along with any
+    references to them in other args' constraints and in groups.
+    """
+    removed = {a.name for a in args if not a.is_available}
+    if not removed:
+        return args, groups
+    args = [a for a in args if a.name not in removed]
+    for arg in args:
+        arg.required_if = {
+            k: v for k, v in arg.required_if.items() if k not in removed
+        }
+        arg.conflicts_with = [x for x in arg.conflicts_with if x not in removed]
+    kept_groups = []
+    for group in groups:
+        group.args = [x for x in group.args if x not in removed]
+        group.requires = [x for x in group.requires if x not in removed]
+        if group.args and (len(group.args) >= 2 if group.exclusive else group.requires):
+            kept_groups.append(group)
+    return args, kept_groups
+
+
+class LocalizedText(str):
+    """
+    Help text with translations, whose string value is the translation for
+    the selected language.
+    """
+
+    def __new__(cls, translations: Dict[str, str], langs: Iterable[str] = ()):
+        """
+        :param translations:
+            Mapping of language to text, e.g. {'en': ..., 'fr': ...}.
+        :param langs:
+            The languages to select, in order of preference, falling back to
+            the first translation.
+        """
+        text = next(
+            (translations[x] for x in langs if x in translations),
+            next(iter(translations.values())),
+        )
+        obj = super().__new__(cls, text)
+        obj.translations = dict(translations)
+        obj.langs = tuple(langs)
+        return obj
+
+    def __getnewargs__(self):
+        # Select the same translation when copied or unpickled.
+        return self.translations, self.langs
+
+    def localize(self, langs: Iterable[str]) -> "LocalizedText":
+        """Get the text with the translation selected for the languages."""
+        return LocalizedText(self.translations, langs)
+
+    def to_dict(self) -> Dict[str, str]:
+        """The translations, as in a loaded schema file."""
+        return dict(self.translations)
+
+
+def _process_help_field(value: typing.Any, *, location: str) -> str:
+    """Convert a loaded 'help' value, which may be a mapping of translations."""
+    if not isinstance(value, dict):
+        return value
+    if not value:
+        raise SchemaError("help translations can't be empty", location=location)
+    for lang, text in value.items():
+        if not isinstance(text, str):
+            raise SchemaError(
+                "help for language {!r} should be a string, got {!r}".format(
+                    lang, text
+                ),
+                location=location,
+            )
+    return LocalizedText({str(k): v for k, v in value.items()})
+
+
+class Deprecation:
+    """Schema deprecation annotation for a command or arg."""
+
+    def __init__(
+        self,
+        *,
+        since: Optional[str] = None,
+        use: Optional[str] = None,
+        message: Optional[str] = None,
+    ):
+        """
+        :param since:
+            The version the deprecation was introduced in.
+        :param use:
+            The replacement to suggest.
+        :param message:
+            Custom text to add to the warning.
+        """
+        self.since = since
+        self.use = use
+        self.message = message
+
+    def describe(self, what: str) -> str:
+        """
+        Describe the deprecation, e.g. "'old' is deprecated since 2.0, use 'new'".
+
+        :param what:
+            Description of the deprecated command or arg.
+        """
+        text = "{} is deprecated".format(what)
+        if self.since:
+            text += " since {}".format(self.since)
+        if self.use:
+            text += ", use {!r} instead".format(self.use)
+        if self.message:
+            text += " ({})".format(self.message)
+        return text
+
+    _FIELDS = {
+        "since": ((str,), False),
+        "use": ((str,), False),
+        "message": ((str,), False),
+    }  # type: _FieldSpec
+
+    @classmethod
+    def from_value(cls, value: typing.Any, *, location: str) -> Optional["Deprecation"]:
+        """
+        Create a deprecation from the loaded 'deprecated' value.
+
+        :param value:
+            Either a bool or a mapping.
+        :param location:
+            Description of where in the schema the value is, for error messages.
+        :return:
+            The deprecation, or None if the value is false.
+        :raises SchemaError:
+            If the value is invalid.
+        """
+        if isinstance(value, bool):
+            return cls() if value else None
+        location = _format_location(location, _get_span(value))
+        _check_fields(value, cls._FIELDS, location=location)
+        return cls(**value)
+
+    def to_dict(self) -> Dict[str, typing.Any]:
+        """The schema fields, as in a loaded schema file."""
+        return _fields_to_dict(self)
+
+
+class GlobSpec:
+    """Schema settings for expanding glob patterns given for a path arg."""
+
+    SORT_ORDERS = ("name", "mtime", "none")
+    NO_MATCH_POLICIES = ("error", "keep", "ignore")
+
+    def __init__(
+        self, *, sort: str = "name", hidden: bool = False, no_match: str = "error"
+    ):
+        """
+        :param sort:
+            The order of the paths a pattern matches, one of `SORT_ORDERS`,
+            where 'mtime' is oldest first and 'none' is filesystem order.
+        :param hidden:
+            Whether wildcards match names starting with '.'.
+        :param no_match:
+            What to do with a pattern matching no paths, one of
+            `NO_MATCH_POLICIES`, where 'keep' passes on the pattern itself.
+        :raises ValueError:
+            If an option is invalid.
+        """
+        if sort not in self.SORT_ORDERS:
+            raise ValueError(
+                "Invalid glob 'sort' value {!r}, expected one of: {}".format(
+                    sort, ", ".join(self.SORT_ORDERS)
+                )
+            )
+        if no_match not in self.NO_MATCH_POLICIES:
+            raise ValueError(
+                "Invalid glob 'no_match' value {!r}, expected one of: {}".format(
+                    no_match, ", ".join(self.NO_MATCH_POLICIES)
+                )
+            )
+        self.sort = sort
+        self.hidden = hidden
+        self.no_match = no_match
+
+    _FIELDS = {
+        "sort": ((str,), False),
+        "hidden": ((bool,), False),
+        "no_match": ((str,), False),
+    }  # type: _FieldSpec
+
+    @classmethod
+    def from_value(cls, value: typing.Any, *, location: str) -> Optional["GlobSpec"]:
+        """
+        Create glob settings from the loaded 'glob' value.
+
+        :param value:
+            Either a bool or a mapping.
+        :param location:
+            Description of where in the schema the value is, for error messages.
+        :return:
+            The glob settings, or None if the value is false.
+        :raises SchemaError:
+            If the value is invalid.
+        """
+        if isinstance(value, bool):
+            return cls() if value else None
+        location = _format_location(location, _get_span(value))
+        _check_fields(value, cls._FIELDS, location=location)
+        try:
+            return cls(**value)
+        except ValueError as e:
+            raise SchemaError(str(e), location=location) from None
+
+    def to_dict(self) -> Dict[str, typing.Any]:
+        """The schema fields, as in a loaded schema file."""
+        return _fields_to_dict(self)
+
+    def _glob(self, pattern: str) -> List[str]:
+        if not self.hidden:
+            return glob.glob(pattern, recursive=True)
+        if sys.version_info >= (3, 11):
+            return glob.glob(pattern, recursive=True, include_hidden=True)
+        # Pathlib's globbing matches hidden names, but needs a relative pattern.
+        path = pathlib.Path(pattern)
+        base = pathlib.Path(path.anchor)
+        return [str(p) for p in base.glob(str(path.relative_to(base)))]
+
+    def expand(self, pattern: str) -> List[str]:
+        """
+        Expand a glob pattern, e.g. 'src/**/*.py'.
+
+        Values without wildcards ('*', '?' or '[') are not expanded.
+
+        :param pattern:
+            The pattern to expand.
+        :return:
+            The matching paths.
+        :raises ValueError:
+            If the pattern matches no paths and 'no_match' is 'error'.
+        """
+        if not re.search(r"[*?[]", pattern):
+            return [pattern]
+        paths = self._glob(pattern)
+        if not paths:
+            if self.no_match == "error":
+                raise ValueError("No paths match {!r}".format(pattern))
+            return [pattern] if self.no_match == "keep" else []
+        if self.sort == "name":
+            paths.sort()
+        elif self.sort == "mtime":
+            paths.sort(key=os.path.getmtime)
+        return paths
+
+
+class CompleteSpec:
+    """
+    Schema settings for completing an arg's values in shell completion scripts
+    and the interactive shell, from the output of a command, matching files, or
+    a provider registered by the application.
+    """
+
+    def __init__(
+        self,
+        *,
+        command: Optional[str] = None,
+        files: Optional[str] = None,
+        provider: Optional[str] = None
+    ):
+        """
+        :param command:
+            A shell command printing the candidate values, one per line, e.g.
+            'kubectl get ns -o name'.
+        :param files:
+            A glob pattern for the names of the files to complete, e.g.
+            '*.toml', with directories always completed.
+        :param provider:
+            The name of a completion provider registered by the application,
+            which is only used by the interactive shell, since completion
+            scripts can't call into the application.
+        :raises ValueError:
+            If not exactly one source is given.
+        """
+        if sum(x is not None for x in (command, files, provider)) != 1:
+            raise ValueError(
+                "Exactly one of 'command', 'files' or 'provider' must be given"
+            )
+        self.command = command
+        self.files = files
+        self.provider = provider
+
+    _FIELDS = {
+        "command": ((str,), False),
+        "files": ((str,), False),
+        "provider": ((str,), False),
+    }  # type: _FieldSpec
+
+    @classmethod
+    def from_dict(
+        cls, data: Dict[str, typing.Any], *, location: str
+    ) -> "CompleteSpec":
+        location = _format_location(location, _get_span(data))
+        _check_fields(data, cls._FIELDS, location=location)
+        try:
+            return cls(**data)
+        except ValueError as e:
+            raise SchemaError(str(e), location=location) from None
+
+    def to_dict(self) -> Dict[str, typing.Any]:
+        """The schema fields, as in a loaded schema file."""
+        return _fields_to_dict(self)
+
+
+class Validation:
+    """Schema rules for validating an arg's values after type conversion."""
+
+    _NUMBER_TYPES = (ArgType.INTEGER, ArgType.FLOAT, ArgType.SIZE, ArgType.DURATION)
+    _STRING_TYPES = (ArgType.STRING, ArgType.PATH, ArgType.URL)
+    # Types whose bounds may be given in the format of the type, e.g. '10K'.
+    _CONVERTED_BOUND_TYPES = (ArgType.SIZE, ArgType.DURATION, ArgType.DATETIME)
+
+    def __init__(
+        self,
+        *,
+        type_: ArgType,
+        min: Optional[typing.Union[float, str]] = None,
+        max: Optional[typing.Union[float, str]] = None,
+        regex: Optional[str] = None,
+        min_len: Optional[int] = None,
+        max_len: Optional[int] = None,
+        exists: bool = False,
+        writable: bool = False,
+        after: Optional[typing.Union[str, datetime.datetime]] = None,
+        before: Optional[typing.Union[str, datetime.datetime]] = None,
+    ):
+        """
+        :param type_:
+            The type of the arg being validated.
+        :param min:
+            The minimum value, for numeric args. For 'size' and 'duration' args
+            this may be a string in the format of the type, e.g. '10K' or '5m'.
+        :param max:
+            The maximum value, for numeric args, as for 'min'.
+        :param regex:
+            A regular expression values must fully match, for string args.
+        :param min_len:
+            The minimum length, for string args.
+        :param max_len:
+            The maximum length, for string args.
+        :param exists:
+            Whether the path must exist, for path args.
+        :param writable:
+            Whether the path must be writable, or be creatable in a writable
+            directory if it doesn't exist, for path args.
+        :param after:
+            The timestamp values must be after, for datetime args.
+        :param before:
+            The timestamp values must be before, for datetime args.
+        :raises ValueError:
+            If a rule doesn't apply to the arg's type.
+        """
+        if (min is not None or max is not None) and type_ not in self._NUMBER_TYPES:
+            raise ValueError("Only numeric args can have 'min' or 'max' validation")
+        if (after is not None or before is not None) and type_ is not ArgType.DATETIME:
+            raise ValueError(
+                "Only datetime args can have 'after' or 'before' validation"
+            )
+        # The bounds, converted to the arg's type for comparing with values.
+        self._bounds = {}  # type: Dict[str, typing.Any]
+        bounds = dict(min=min, max=max, after=after, before=before)
+        for name, bound in bounds.items():
+            if bound is None:
+                continue
+            if type_ in self._CONVERTED_BOUND_TYPES:
+                try:
+                    bound = type_.convert(bound)
+                except ValueError as e:
+                    raise ValueError(
+                        "Invalid '{}' value: {}".format(name, e)
+                    ) from None
+            elif isinstance(bound, str):
+                raise ValueError("The '{}' value must be a number".format(name))
+            self._bounds[name] = bound
+        if (
+            regex is not None or min_len is not None or max_len is not None
+        ) and type_ not in self._STRING_TYPES:
+            raise ValueError(
+                "Only string args can have 'regex', 'min_len' or 'max_len' validation"
+            )
+        if (exists or writable) and type_ is not ArgType.PATH:
+            raise ValueError(
+                "Only path args can have 'exists' or 'writable' validation"
+            )
+        if regex is not None:
+            try:
+                re.compile(regex)
+            except re.error as e:
+                raise ValueError("Invalid regex {!r}: {}".format(regex, e)) from None
+        self.min = min
+        self.max = max
+        self.regex = regex
+        self.min_len = min_len
+        self.max_len = max_len
+        self.exists = exists
+        self.writable = writable
+        self.after = after
+        self.before = before
+
+    _FIELDS = {
+        "min": ((int, float, str), False),
+        "max": ((int, float, str), False),
+        "regex": ((str,), False),
+        "min_len": ((int,), False),
+        "max_len": ((int,), False),
+        "exists": ((bool,), False),
+        "writable": ((bool,), False),
+        "after": ((str, datetime.datetime), False),
+        "before": ((str, datetime.datetime), False),
+    }  # type: _FieldSpec
+
+    @classmethod
+    def from_dict(
+        cls, data: Dict[str, typing.Any], *, type_: ArgType, location: str
+    ) -> "Validation":
+        location = _format_location(location, _get_span(data))
+        _check_fields(data, cls._FIELDS, location=location)
+        try:
+            return cls(type_=type_, **data)
+        except ValueError as e:
+            raise SchemaError(str(e), location=location) from None
+
+    def to_dict(self) -> Dict[str, typing.Any]:
+        """The schema fields, as in a loaded schema file."""
+        data = _fields_to_dict(self)
+        return {k: v for k, v in data.items() if v is not False}
+
+    def check(self, value: typing.Any) -> Optional[str]:
+        """
+        Check a converted value against the rules.
+
+        :param value:
+            The value to check.
+        :return:
+            The rule the value fails, e.g. "max: 65535", or None if it passes.
+        """
+        bounds = self._bounds
+        if "min" in bounds and value < bounds["min"]:
+            return "min: {}".format(self.min)
+        if "max" in bounds and value > bounds["max"]:
+            return "max: {}".format(self.max)
+        if "after" in bounds and value <= bounds["after"]:
+            return "after: {}".format(_to_plain(self.after))
+        if "before" in bounds and value >= bounds["before"]:
+            return "before: {}".format(_to_plain(self.before))
+        text = str(value)
+        if self.regex is not None and not re.fullmatch(self.regex, text):
+            return "regex: {}".format(self.regex)
+        if self.min_len is not None and len(text) < self.min_len:
+            return "min_len: {}".format(self.min_len)
+        if self.max_len is not None and len(text) > self.max_len:
+            return "max_len: {}".format(self.max_len)
+        if self.exists and not os.path.exists(value):
+            return "exists: true"
+        if self.writable:
+            if os.path.exists(value):
+                writable = os.access(value, os.W_OK)
+            else:
+                writable = os.access(os.path.dirname(value) or ".", os.W_OK)
+            if not writable:
+                return "writable: true"
+        return None
+
+
+# Placeholders in arg help that are filled from the arg's metadata.
+_HELP_PLACEHOLDER_RE = re.compile(r"\{(default|env|choices)\}")
+
+
+def _format_help_value(value: typing.Any) -> str:
+    """Format an arg's default or choice for filling a help placeholder."""
+    if isinstance(value, bool):
+        return "true" if value else "false"
+    if isinstance(value, (list, tuple)):
+        return ", ".join(_format_help_value(x) for x in value)
+    return "none" if value is None else str(value)
 
 
 class Arg:
     """Schema arg."""
 
+    # The ways of handling a key given multiple times for a 'map' arg.
+    DUPLICATE_POLICIES = ("error", "last")
+
+    # Where the values of args with 'source' set may be loaded from.
+    SOURCES = ("literal", "file", "url")
+
+    # The default limit on the size of values loaded from files or URLs.
+    DEFAULT_MAX_SIZE = 10 * 1024 * 1024
+
     def __init__(
         self,
         *,
@@ -21,107 +932,3155 @@ class Arg:
         help_: str,
         command: Optional[str] = None,
         positional: bool = False,
-        type_: typing.Type = str,
+        short: Optional[str] = None,
+        long: Optional[str] = None,
+        required: bool = False,
+        multiple: bool = False,
+        min: Optional[int] = None,
+        max: Optional[int] = None,
+        type_: ArgType = ArgType.STRING,
         enum: Optional[List] = None,
         default: Optional = None,
+        env: Optional[str] = None,
+        configurable: bool = False,
+        required_if: Optional[Dict[str, typing.Any]] = None,
+        conflicts_with: Optional[List[str]] = None,
+        hidden: bool = False,
+        deprecated: Optional[Deprecation] = None,
+        prompt: bool = False,
+        password: bool = False,
+        stdin: bool = False,
+        validate: Optional[Validation] = None,
+        validator: Optional[str] = None,
+        count: bool = False,
+        repeat: bool = False,
+        keys: Optional[Dict[str, ArgType]] = None,
+        duplicates: str = "error",
+        platforms: Optional[List[str]] = None,
+        experimental: bool = False,
+        passthrough: bool = False,
+        glob: Optional[GlobSpec] = None,
+        secret: bool = False,
+        file_option: bool = False,
+        source: Optional[List[str]] = None,
+        max_size: Optional[typing.Union[int, str]] = None,
+        complete: Optional[CompleteSpec] = None,
     ):
+        if positional and (short or long):
+            raise ValueError("Positional args can't have 'short' or 'long' names")
+        if short is not None and (not short or short.startswith("-")):
+            raise ValueError("Short names can't be empty or start with '-'")
+        if (min is not None or max is not None) and not (multiple or repeat):
+            raise ValueError(
+                "Only args with 'multiple' or 'repeat' set can have 'min' or 'max'"
+            )
+        if multiple and type_ in (ArgType.FLAG, ArgType.TEXT):
+            raise ValueError("Args of type 'flag' or 'text' can't have 'multiple' set")
+        if count and type_ is not ArgType.FLAG:
+            raise ValueError("Only args of type 'flag' can have 'count' set")
+        if repeat and (positional or multiple or type_ in (ArgType.FLAG, ArgType.TEXT)):
+            raise ValueError(
+                "Positional args, args with 'multiple' set and args of type 'flag' "
+                "or 'text' can't have 'repeat' set"
+            )
+        if min is not None and max is not None and min > max:
+            raise ValueError("The 'min' value can't be greater than the 'max' value")
+        if enum is not None:
+            if type_ in (ArgType.FLAG, ArgType.TEXT):
+                raise ValueError("Args of type 'flag' or 'text' can't have 'enum' set")
+            for choice in enum:
+                try:
+                    type_.convert(choice)
+                except ValueError:
+                    raise ValueError(
+                        "Enum value {!r} is not {}".format(choice, type_.description)
+                    ) from None
+            if (
+                default is not None
+                and not is_template(default)
+                and default not in enum
+            ):
+                raise ValueError(
+                    "Default {!r} is not one of the 'enum' values".format(default)
+                )
+        if prompt and type_ is ArgType.FLAG:
+            raise ValueError("Args of type 'flag' can't have 'prompt' set")
+        if passthrough and (
+            not positional
+            or multiple
+            or enum is not None
+            or default is not None
+            or type_ is not ArgType.STRING
+            or stdin
+            or prompt
+        ):
+            raise ValueError(
+                "Only positional args of type 'string' without 'multiple', "
+                "'enum', 'default', 'stdin' or 'prompt' can have 'passthrough' set"
+            )
+        if glob is not None and (
+            type_ is not ArgType.PATH or not (multiple or repeat)
+        ):
+            raise ValueError(
+                "Only args of type 'path' with 'multiple' or 'repeat' set can have "
+                "'glob' set"
+            )
+        if secret and (
+            positional
+            or type_ is not ArgType.STRING
+            or multiple
+            or repeat
+            or enum is not None
+            or default is not None
+            or stdin
+        ):
+            raise ValueError(
+                "Only non-positional args of type 'string' without 'multiple', "
+                "'repeat', 'enum', 'default' or 'stdin' can have 'secret' set"
+            )
+        if file_option and not secret:
+            raise ValueError("Only args with 'secret' set can have 'file_option' set")
+        if source is not None:
+            for x in source:
+                if x not in self.SOURCES:
+                    raise ValueError(
+                        "Invalid 'source' value {!r}, expected one of: {}".format(
+                            x, ", ".join(self.SOURCES)
+                        )
+                    )
+            if not source:
+                raise ValueError("At least one 'source' must be given")
+            if type_ is not ArgType.STRING or passthrough or stdin:
+                raise ValueError(
+                    "Only args of type 'string' without 'passthrough' or 'stdin' "
+                    "can have 'source' set"
+                )
+        if max_size is not None:
+            if source is None:
+                raise ValueError("Only args with 'source' set can have 'max_size' set")
+            try:
+                max_size_bytes = (
+                    max_size if isinstance(max_size, int) else parse_size(max_size)
+                )
+            except ValueError:
+                raise ValueError(
+                    "Invalid 'max_size' value {!r}, expected a size such as "
+                    "'1MiB'".format(max_size)
+                ) from None
+            if max_size_bytes <= 0:
+                raise ValueError("'max_size' must be positive")
+        if complete is not None and (
+            type_ is ArgType.FLAG or enum is not None or passthrough
+        ):
+            raise ValueError(
+                "Args of type 'flag' and args with 'enum' or 'passthrough' set can't "
+                "have 'complete' set"
+            )
+        if experimental and (positional or required):
+            raise ValueError("Positional or required args can't be experimental")
+        if password and not prompt:
+            raise ValueError("Only args with 'prompt' set can have 'password' set")
+        if stdin and type_ not in (ArgType.STRING, ArgType.PATH):
+            raise ValueError(
+                "Only args of type 'string' or 'path' can have 'stdin' set"
+            )
+        if stdin and (multiple or repeat):
+            raise ValueError(
+                "Args with 'multiple' or 'repeat' set can't have 'stdin' set"
+            )
+        if type_ is ArgType.MAP:
+            if positional or multiple or repeat or enum is not None or stdin:
+                raise ValueError(
+                    "Args of type 'map' can't be positional or have 'multiple', "
+                    "'repeat', 'enum' or 'stdin' set"
+                )
+        elif keys is not None or duplicates != "error":
+            raise ValueError(
+                "Only args of type 'map' can have 'keys' or 'duplicates' set"
+            )
+        for key, key_type in (keys or {}).items():
+            if key_type in (ArgType.FLAG, ArgType.TEXT, ArgType.MAP):
+                raise ValueError(
+                    "Map key {!r} can't be of type {!r}".format(key, key_type.value)
+                )
+        _check_platforms(platforms)
+        if duplicates not in self.DUPLICATE_POLICIES:
+            raise ValueError(
+                "Invalid 'duplicates' value {!r}, expected one of: {}".format(
+                    duplicates, ", ".join(self.DUPLICATE_POLICIES)
+                )
+            )
         self.name = name
         self.help = help_
         self.command = command
         self.positional = positional
+        self.short = short
+        self.long = long if long else name
+        self.required = required
+        self.multiple = multiple
+        self.min = min
+        self.max = max
         self.type = type_
         self.enum = enum
         self.default = default
+        self.env = env
+        self.configurable = configurable
+        self.required_if = required_if if required_if else {}
+        self.conflicts_with = conflicts_with if conflicts_with else []
+        self.hidden = hidden or experimental
+        self.deprecated = deprecated
+        self.prompt = prompt
+        self.password = password
+        self.stdin = stdin
+        self.validate = validate
+        self.validator = validator
+        self.count = count
+        self.repeat = repeat
+        self.keys = keys
+        self.duplicates = duplicates
+        self.platforms = platforms
+        self.experimental = experimental
+        self.passthrough = passthrough
+        self.glob = glob
+        self.secret = secret
+        self.file_option = file_option
+        self.source = source
+        self.max_size = max_size
+        self.complete = complete
+        self.lineno = None  # type: Optional[int]
+        self.span = None  # type: Optional[SourceSpan]
+
+    @property
+    def is_available(self) -> bool:
+        """Whether the arg is available on the current platform."""
+        return _is_platform_available(self.platforms)
+
+    @property
+    def is_list(self) -> bool:
+        """
+        Whether the arg's value is a list, for 'multiple', 'repeat' or
+        'passthrough' args.
+        """
+        return self.multiple or self.repeat or self.passthrough
+
+    def get_max_size(self) -> int:
+        """
+        The maximum number of bytes of a value loaded from a file or URL, for
+        args with 'source' set.
+        """
+        if self.max_size is None:
+            return self.DEFAULT_MAX_SIZE
+        if isinstance(self.max_size, int):
+            return self.max_size
+        return parse_size(self.max_size)
+
+    @property
+    def file_option_string(self) -> Optional[str]:
+        """
+        The option for reading the value from a file, e.g. '--token-file', for
+        args with 'file_option' set.
+        """
+        return "--{}-file".format(self.long) if self.file_option else None
+
+    @property
+    def help_placeholders(self) -> List[str]:
+        """
+        The placeholders used in the help text, e.g. ['default', 'env'], see
+        `render_help()`.
+        """
+        return _HELP_PLACEHOLDER_RE.findall(self.help)
+
+    def render_help(self) -> str:
+        """
+        The help text with its placeholders filled from the arg's metadata:
+         - {default}: The default value, or 'none'.
+         - {env}: The environment variable the value can be taken from.
+         - {choices}: The allowed values, comma-separated.
+
+        Other braces are left as they are.
+        """
+        values = {
+            "default": _format_help_value(self.default),
+            "env": self.env or "",
+            "choices": _format_help_value(self.enum or []),
+        }
+        return _HELP_PLACEHOLDER_RE.sub(lambda m: values[m.group(1)], self.help)
+
+    @property
+    def dest(self) -> str:
+        """The attribute name used for the arg in the parsed namespace."""
+        return self.name.replace("-", "_")
+
+    @property
+    def option_strings(self) -> List[str]:
+        """The option strings for a non-positional arg, e.g. ['-p', '--port']."""
+        if self.positional:
+            return []
+        options = ["--" + self.long]
+        if self.short:
+            options.insert(0, "-" + self.short)
+        return options
+
+    _FIELDS = {
+        "name": ((str,), True),
+        "help": ((str, dict), True),
+        "command": ((str,), False),
+        "positional": ((bool,), False),
+        "short": ((str,), False),
+        "long": ((str,), False),
+        "required": ((bool,), False),
+        "multiple": ((bool,), False),
+        "min": ((int,), False),
+        "max": ((int,), False),
+        "type": ((str,), False),
+        "enum": ((list,), False),
+        "default": ((str, int, float, bool, datetime.datetime), False),
+        "env": ((str,), False),
+        "configurable": ((bool,), False),
+        "required_if": ((dict,), False),
+        "conflicts_with": ((list,), False),
+        "hidden": ((bool,), False),
+        "deprecated": ((bool, dict), False),
+        "prompt": ((bool,), False),
+        "password": ((bool,), False),
+        "stdin": ((bool,), False),
+        "validate": ((dict,), False),
+        "validator": ((str,), False),
+        "count": ((bool,), False),
+        "repeat": ((bool,), False),
+        "keys": ((dict,), False),
+        "duplicates": ((str,), False),
+        "platforms": ((list,), False),
+        "experimental": ((bool,), False),
+        "passthrough": ((bool,), False),
+        "glob": ((bool, dict), False),
+        "secret": ((bool,), False),
+        "file_option": ((bool,), False),
+        "source": ((list,), False),
+        "max_size": ((int, str), False),
+        "complete": ((dict,), False),
+    }  # type: _FieldSpec
 
     @classmethod
-    def from_dict(cls, data: Dict[str, typing.Any]) -> "Arg":
+    def from_dict(cls, data: Dict[str, typing.Any], *, location: str = "arg") -> "Arg":
+        """
+        Create an arg from its loaded schema representation.
+
+        :param data:
+            The loaded mapping.
+        :param location:
+            Description of where in the schema the arg is, for error messages.
+        :raises SchemaError:
+            If the mapping is invalid.
+        """
+        if isinstance(data, dict) and isinstance(data.get("name"), str):
+            location = "{} {!r}".format(location, data["name"])
+        location = _format_location(location, _get_span(data))
+        _check_fields(data, cls._FIELDS, location=location)
         kwargs = data.copy()
-        kwargs["help_"] = kwargs.pop("help")
-        kwargs["type_"] = cls._process_type_field(kwargs.pop("type", "string"))
-        return cls(**kwargs)
+        kwargs["help_"] = _process_help_field(kwargs.pop("help"), location=location)
+        if "deprecated" in kwargs:
+            kwargs["deprecated"] = Deprecation.from_value(
+                kwargs["deprecated"], location=location + ": deprecated"
+            )
+        try:
+            kwargs["type_"] = cls._process_type_field(kwargs.pop("type", "string"))
+        except ValueError as e:
+            raise SchemaError(str(e), location=location) from None
+        if "keys" in kwargs:
+            try:
+                kwargs["keys"] = {
+                    str(k): cls._process_type_field(v)
+                    for k, v in kwargs["keys"].items()
+                }
+            except ValueError as e:
+                raise SchemaError(str(e), location=location + ": keys") from None
+        if "glob" in kwargs:
+            kwargs["glob"] = GlobSpec.from_value(
+                kwargs["glob"], location=location + ": glob"
+            )
+        if "complete" in kwargs:
+            kwargs["complete"] = CompleteSpec.from_dict(
+                kwargs["complete"], location=location + ": complete"
+            )
+        if "validate" in kwargs:
+            kwargs["validate"] = Validation.from_dict(
+                kwargs["validate"],
+                type_=kwargs["type_"],
+                location=location + ": validate",
+            )
+        try:
+            arg = cls(**kwargs)
+        except ValueError as e:
+            raise SchemaError(str(e), location=location) from None
+        arg.lineno = _get_lineno(data)
+        arg.span = _get_span(data)
+        return arg
 
     @staticmethod
-    def _process_type_field(value: str) -> typing.Type:
-        # TODO: 'type' should be an enum rather than a Python type.
-        accepted_types = {
-            "integer": int,
-            "string": str,
-            "float": float,
-            "flag": bool,
-            "text": list,
-        }
-        if value in accepted_types:
-            value = accepted_types[value]
-        else:
+    def _process_type_field(value: str) -> ArgType:
+        try:
+            return ArgType(value)
+        except ValueError:
             raise ValueError(
                 "Unrecognised type {!r}, accepted types are: {}".format(
-                    value, ", ".join(accepted_types)
+                    value, ", ".join(t.value for t in ArgType)
                 )
-            )
-        return value
+            ) from None
 
+    def to_dict(self) -> Dict[str, typing.Any]:
+        """The schema fields, as in a loaded schema file."""
+        data = _fields_to_dict(self)
+        if self.positional:
+            del data["long"]
+        return data
 
-class NodeBase:
-    """Base class for nodes."""
+
+class ArgGroup:
+    """Schema group expressing a relationship between a node's args."""
 
     def __init__(
         self,
         *,
-        keyword: Optional[str] = None,
-        help_: str,
-        command: Optional[str] = None,
-        args: Optional[List[Arg]] = None,
-        subtree: Optional[List["NodeBase"]] = None,
+        args: List[str],
+        exclusive: bool = False,
+        requires: Optional[List[str]] = None,
     ):
-        self.keyword = keyword
-        self.help = help_
+        """
+        :param args:
+            The names of the args in the group.
+        :param exclusive:
+            Whether at most one of the args may be given.
+        :param requires:
+            The names of args that must be given if any of the group's args
+            are given.
+        """
+        if not exclusive and not requires:
+            raise ValueError("Groups must set 'exclusive' or 'requires'")
+        if exclusive and len(args) < 2:
+            raise ValueError("Exclusive groups must contain at least two args")
+        self.args = args
+        self.exclusive = exclusive
+        self.requires = requires if requires else []
+
+    _FIELDS = {
+        "args": ((list,), True),
+        "exclusive": ((bool,), False),
+        "requires": ((list,), False),
+    }  # type: _FieldSpec
+
+    @classmethod
+    def from_dict(
+        cls, data: Dict[str, typing.Any], *, arg_names: List[str], location: str
+    ) -> "ArgGroup":
+        """
+        Create a group from its loaded schema representation.
+
+        :param data:
+            The loaded mapping.
+        :param arg_names:
+            The names of the args of the node the group is declared on.
+        :param location:
+            Description of where in the schema the group is, for error messages.
+        :raises SchemaError:
+            If the mapping is invalid.
+        """
+        location = _format_location(location, _get_span(data))
+        _check_fields(data, cls._FIELDS, location=location)
+        for name in data["args"] + data.get("requires", []):
+            if name not in arg_names:
+                raise SchemaError(
+                    "unknown arg {!r}".format(name), location=location
+                )
+        try:
+            return cls(**data)
+        except ValueError as e:
+            raise SchemaError(str(e), location=location) from None
+
+    def to_dict(self) -> Dict[str, typing.Any]:
+        """The schema fields, as in a loaded schema file."""
+        return _fields_to_dict(self)
+
+
+class Example:
+    """Schema example usage of a command."""
+
+    def __init__(self, *, command: str, description: str = ""):
+        """
+        :param command:
+            The command line, relative to the node the example is declared on
+            (i.e. without the program name or the node's keywords).
+        :param description:
+            Description of what the example does.
+        """
         self.command = command
-        self.args = args if args else []
-        self.subtree = subtree if subtree else []
-        self.parent = None  # type: Optional[NodeBase]
-        for x in subtree:
-            x.parent = self
+        self.description = description
+
+    _FIELDS = {
+        "command": ((str,), True),
+        "description": ((str,), False),
+    }  # type: _FieldSpec
 
     @classmethod
-    def from_dict(cls, data: Dict[str, typing.Any]) -> "NodeBase":
-        kwargs = data.copy()
-        kwargs["help_"] = kwargs.pop("help")
-        kwargs["args"] = cls._process_args_field(kwargs.pop("args", []))
-        kwargs["subtree"] = cls._process_subtree_field(kwargs.pop("subtree", []))
-        return cls(**kwargs)
+    def from_dict(cls, data: Dict[str, typing.Any], *, location: str) -> "Example":
+        _check_fields(
+            data, cls._FIELDS, location=_format_location(location, _get_span(data))
+        )
+        return cls(**data)
 
-    @staticmethod
-    def _process_subtree_field(value: List[Dict[str, typing.Any]]) -> List["NodeBase"]:
-        return [SubNode.from_dict(x) for x in value]
+    def to_dict(self) -> Dict[str, typing.Any]:
+        """The schema fields, as in a loaded schema file."""
+        return _fields_to_dict(self)
 
-    @staticmethod
-    def _process_args_field(value: List[Dict[str, typing.Any]]) -> List[Arg]:
-        return [Arg.from_dict(x) for x in value]
 
+class OutputSpec:
+    """
+    Schema settings for a command whose handler returns data to be output in
+    a format selected with the '--format' option.
+    """
 
-class RootNode(NodeBase):
-    """Root schema node."""
+    FORMATS = ("text", "json", "yaml", "table")
 
-    def __init__(self, **kwargs):
-        if "keyword" in kwargs:
-            raise TypeError("__init__() got an unexpected keyword argument 'keyword'")
-        super().__init__(**kwargs)
+    def __init__(self, *, formats: List[str], default: Optional[str] = None):
+        """
+        :param formats:
+            The supported formats, from `FORMATS`.
+        :param default:
+            The default format, defaults to the first format.
+        """
+        for format_ in formats:
+            if format_ not in self.FORMATS:
+                raise ValueError(
+                    "Unrecognised output format {!r}, accepted formats are: "
+                    "{}".format(format_, ", ".join(self.FORMATS))
+                )
+        if not formats:
+            raise ValueError("At least one output format must be given")
+        if default is not None and default not in formats:
+            raise ValueError(
+                "Default output format {!r} is not one of the formats".format(default)
+            )
+        self.formats = formats
+        self.default = default if default is not None else formats[0]
+        self.args = [
+            Arg(
+                name="format",
+                help_="The output format",
+                enum=list(formats),
+                default=self.default,
+            )
+        ]
+        if "table" in formats:
+            self.args.append(
+                Arg(
+                    name="no-header",
+                    help_="Omit the header row of table output",
+                    type_=ArgType.FLAG,
+                )
+            )
 
-    def __repr__(self):
-        return "<RootNode>"
+    _FIELDS = {
+        "formats": ((list,), True),
+        "default": ((str,), False),
+    }  # type: _FieldSpec
 
+    @classmethod
+    def from_value(cls, value: typing.Any, *, location: str) -> "OutputSpec":
+        """
+        Create the output settings from the loaded 'output' value.
 
-class SubNode(NodeBase):
-    """Sub schema node."""
+        :param value:
+            Either a list of formats or a mapping.
+        :param location:
+            Description of where in the schema the value is, for error messages.
+        :raises SchemaError:
+            If the value is invalid.
+        """
+        location = _format_location(location, _get_span(value))
+        if isinstance(value, list):
+            value = {"formats": value}
+        _check_fields(value, cls._FIELDS, location=location)
+        try:
+            return cls(**value)
+        except ValueError as e:
+            raise SchemaError(str(e), location=location) from None
 
-    def __init__(self, *, keyword: str, **kwargs):
-        kwargs["keyword"] = keyword
-        super().__init__(**kwargs)
+    def to_dict(self) -> Dict[str, typing.Any]:
+        """The schema fields, as in a loaded schema file."""
+        return _fields_to_dict(self)
 
-    def __repr__(self):
-        keywords = []
-        node = self
-        while node.keyword:
+
+class SignalsSpec:
+    """
+    Schema settings for how a command's handler is stopped by Ctrl-C and
+    SIGTERM, giving it the chance to finish gracefully instead of the process
+    dying mid-handler.
+    """
+
+    # What Ctrl-C does while the handler is running.
+    INTERRUPT_POLICIES = ("cancel", "exit")
+
+    def __init__(
+        self,
+        *,
+        interrupt: str = "cancel",
+        terminate_grace: Optional[typing.Union[str, int, float]] = None,
+        cleanup: Optional[List[str]] = None
+    ):
+        """
+        :param interrupt:
+            Whether Ctrl-C should 'cancel' the handler's cancellation token
+            and wait for the handler to return, or 'exit' without waiting.
+        :param terminate_grace:
+            How long the handler has to return after SIGTERM cancels its
+            token, as a duration or number of seconds. If not given, SIGTERM
+            exits immediately as usual.
+        :param cleanup:
+            An external command to run when the handler is cancelled, before
+            exiting, which may contain references as for 'cwd'.
+        """
+        if interrupt not in self.INTERRUPT_POLICIES:
+            raise ValueError(
+                "Invalid 'interrupt' value {!r}, expected one of: {}".format(
+                    interrupt, ", ".join(self.INTERRUPT_POLICIES)
+                )
+            )
+        if terminate_grace is not None:
+            try:
+                seconds = _timeout_to_seconds(terminate_grace)
+            except ValueError:
+                raise ValueError(
+                    "Invalid 'terminate_grace' value {!r}, expected a duration "
+                    "such as '10s'".format(terminate_grace)
+                ) from None
+            if seconds < 0:
+                raise ValueError(
+                    "'terminate_grace' can't be negative, got {!r}".format(
+                        terminate_grace
+                    )
+                )
+        if cleanup is not None and (
+            not cleanup or not all(isinstance(x, str) for x in cleanup)
+        ):
+            raise ValueError(
+                "Invalid 'cleanup' value {!r}, expected a non-empty list of "
+                "strings".format(cleanup)
+            )
+        self.interrupt = interrupt
+        self.terminate_grace = terminate_grace
+        self.cleanup = cleanup
+
+    _FIELDS = {
+        "interrupt": ((str,), False),
+        "terminate_grace": ((str, int, float), False),
+        "cleanup": ((list,), False),
+    }  # type: _FieldSpec
+
+    @classmethod
+    def from_value(cls, value: typing.Any, *, location: str) -> "SignalsSpec":
+        """
+        Create the signal settings from the loaded 'signals' value.
+
+        :param value:
+            The loaded mapping.
+        :param location:
+            Description of where in the schema the value is, for error messages.
+        :raises SchemaError:
+            If the value is invalid.
+        """
+        location = _format_location(location, _get_span(value))
+        _check_fields(value, cls._FIELDS, location=location)
+        try:
+            return cls(**value)
+        except ValueError as e:
+            raise SchemaError(str(e), location=location) from None
+
+    def to_dict(self) -> Dict[str, typing.Any]:
+        """The schema fields, as in a loaded schema file."""
+        return _fields_to_dict(self)
+
+    def get_terminate_grace(self) -> Optional[float]:
+        """
+        The number of seconds the handler has to return after SIGTERM, or None
+        to exit immediately.
+        """
+        if self.terminate_grace is None:
+            return None
+        return _timeout_to_seconds(self.terminate_grace)
+
+
+class _SubtreeIndex:
+    """
+    Index of the keywords and aliases of a node's subtree, for looking up
+    subnodes in large command trees without scanning the whole subtree.
+    """
+
+    def __init__(self, subtree: List["NodeBase"]):
+        self.subtree = subtree
+        self.size = len(subtree)
+        # The first subnode with each keyword or alias, including hidden ones.
+        self.keywords = {}  # type: Dict[str, NodeBase]
+        # The keywords and visible aliases of visible subnodes, sorted, with
+        # the subnode's position in the subtree.
+        visible = []  # type: List[typing.Tuple[str, int]]
+        for i, node in enumerate(subtree):
+            for keyword in [node.keyword] + node.all_aliases:
+                self.keywords.setdefault(keyword, node)
+            if not node.hidden:
+                visible += [(k, i) for k in [node.keyword] + node.aliases]
+        visible.sort()
+        self._visible_keywords = [k for k, _ in visible]
+        self._visible_positions = [i for _, i in visible]
+
+    def is_current(self, subtree: List["NodeBase"]) -> bool:
+        """Whether the index is of the subtree as it currently is."""
+        return subtree is self.subtree and len(subtree) == self.size
+
+    def find_prefix(self, prefix: str) -> List["NodeBase"]:
+        """The visible subnodes matching a prefix, in subtree order."""
+        start = bisect.bisect_left(self._visible_keywords, prefix)
+        positions = set()
+        for i in range(start, len(self._visible_keywords)):
+            if not self._visible_keywords[i].startswith(prefix):
+                break
+            positions.add(self._visible_positions[i])
+        return [self.subtree[i] for i in sorted(positions)]
+
+
+class NodeBase:
+    """Base class for nodes."""
+
+    # The ways of handling unrecognised args given for a node's command.
+    UNKNOWN_ARGS_POLICIES = ("error", "collect", "ignore")
+
+    # The 'default' value for showing the node's help when no subcommand is
+    # given, rather than running a default subcommand.
+    DEFAULT_HELP = "help"
+
+    def __init__(
+        self,
+        *,
+        keyword: Optional[str] = None,
+        help_: str,
+        command: Optional[typing.Union[str, List[str]]] = None,
+        args: Optional[List[Arg]] = None,
+        groups: Optional[List[ArgGroup]] = None,
+        exit_codes: Optional[Dict[str, int]] = None,
+        help_template: Optional[str] = None,
+        help_footer: Optional[str] = None,
+        examples: Optional[List[Example]] = None,
+        subtree: Optional[List["NodeBase"]] = None,
+        output: Optional[OutputSpec] = None,
+        abbreviations: Optional[bool] = None,
+        unknown_args: str = "error",
+        negative_numbers: Optional[bool] = None,
+        destructive: bool = False,
+        timeout: Optional[typing.Union[str, int, float]] = None,
+        subtree_file: Optional[str] = None,
+        default: Optional[str] = None,
+        cwd: Optional[str] = None,
+        env: Optional[Dict[str, str]] = None,
+        signals: Optional[SignalsSpec] = None,
+    ):
+        if unknown_args not in self.UNKNOWN_ARGS_POLICIES:
+            raise ValueError(
+                "Invalid 'unknown_args' value {!r}, expected one of: {}".format(
+                    unknown_args, ", ".join(self.UNKNOWN_ARGS_POLICIES)
+                )
+            )
+        if unknown_args == "collect" and any(
+            a.dest == "unknown_args" for a in args or []
+        ):
+            raise ValueError(
+                "an arg named 'unknown-args' can't be declared with 'unknown_args' "
+                "set to 'collect'"
+            )
+        if subtree and subtree_file is not None:
+            raise ValueError("'subtree' and 'subtree_file' can't both be given")
+        if default is not None:
+            if command is not None:
+                raise ValueError("'default' can't be given with 'command'")
+            if not subtree and subtree_file is None:
+                raise ValueError("'default' can only be given with a subtree")
+        if timeout is not None:
+            try:
+                seconds = _timeout_to_seconds(timeout)
+            except ValueError:
+                raise ValueError(
+                    "Invalid 'timeout' value {!r}, expected a duration such as "
+                    "'30s'".format(timeout)
+                ) from None
+            if seconds <= 0:
+                raise ValueError("'timeout' must be positive, got {!r}".format(timeout))
+        # The program and args to run for an external command, given as a list
+        # for 'command', in which case the command name is them joined.
+        self.external_command = None  # type: Optional[List[str]]
+        if isinstance(command, list):
+            if not command or not all(isinstance(x, str) for x in command):
+                raise ValueError(
+                    "Invalid 'command' value {!r}, expected a name or a non-empty "
+                    "list of strings".format(command)
+                )
+            self.external_command = command
+            command = " ".join(command)
+        self.keyword = keyword
+        self.help = help_
+        self.command = command
+        self.args = args if args else []
+        self.groups = groups if groups else []
+        self.exit_codes = exit_codes if exit_codes else {}
+        self.help_template = help_template
+        self.help_footer = help_footer
+        self.examples = examples if examples else []
+        self._subtree = subtree if subtree else []  # type: List[NodeBase]
+        self._subtree_index = None  # type: Optional[_SubtreeIndex]
+        self.subtree_file = subtree_file
+        self.default = default
+        self.cwd = cwd
+        self.env = env if env else {}
+        self._subtree_loaded = subtree_file is None
+        self.output = output
+        self.abbreviations = abbreviations
+        self.unknown_args = unknown_args
+        self.negative_numbers = negative_numbers
+        self.destructive = destructive
+        self.timeout = timeout
+        self.signals = signals
+        if output is not None:
+            self.args += output.args
+        # The '--yes' and '--dry-run' flags added for destructive commands.
+        self.destructive_args = []  # type: List[Arg]
+        if destructive:
+            self.destructive_args = [
+                Arg(
+                    name="yes",
+                    help_="Don't ask for confirmation before running",
+                    type_=ArgType.FLAG,
+                ),
+                Arg(
+                    name="dry-run",
+                    help_="Show what would be done without doing it",
+                    type_=ArgType.FLAG,
+                ),
+            ]
+            for arg in self.destructive_args:
+                if any(a.name == arg.name for a in self.args):
+                    raise ValueError(
+                        "an arg named {!r} can't be declared with 'destructive' "
+                        "set".format(arg.name)
+                    )
+            self.args += self.destructive_args
+        self.hidden = False
+        self.deprecated = None  # type: Optional[Deprecation]
+        self.parent = None  # type: Optional[NodeBase]
+        self.lineno = None  # type: Optional[int]
+        self.span = None  # type: Optional[SourceSpan]
+        for x in self._subtree:
+            x.parent = self
+
+    @property
+    def subtree(self) -> List["NodeBase"]:
+        """
+        The nodes beneath this node, loaded from the node's 'subtree_file' on
+        first access if it has one.
+
+        :raises SchemaError:
+            If the subtree file can't be loaded.
+        """
+        if not self._subtree_loaded:
+            self._load_subtree()
+        return self._subtree
+
+    @subtree.setter
+    def subtree(self, value: List["NodeBase"]) -> None:
+        self._subtree = value
+        self._subtree_loaded = True
+        self._subtree_index = None
+
+    def _get_subtree_index(self) -> _SubtreeIndex:
+        """
+        Get the index of the subtree, built on first use and rebuilt if nodes
+        are added to the subtree.
+        """
+        subtree = self.subtree
+        if self._subtree_index is None or not self._subtree_index.is_current(subtree):
+            self._subtree_index = _SubtreeIndex(subtree)
+        return self._subtree_index
+
+    @property
+    def is_subtree_loaded(self) -> bool:
+        """Whether the subtree has been loaded, see `subtree`."""
+        return self._subtree_loaded
+
+    def _load_subtree(self) -> None:
+        """Load the subtree from the node's 'subtree_file'."""
+        path = pathlib.Path(self.subtree_file)
+        location = str(path)
+        if self.path:
+            location += ": " + ".".join(self.path)
+        data, _ = _load_schema_file(path, None, stack=(path.resolve(),))
+        _check_fields(
+            data,
+            {"subtree": ((list,), True)},
+            location=_format_location(str(path), _get_span(data)),
+        )
+        nodes = [
+            n
+            for n in self._process_subtree_field(data["subtree"], location=location)
+            if n.is_available
+        ]
+        for node in nodes:
+            node.parent = self
+        self.subtree = nodes
+        root = self.root
+        if isinstance(root, RootNode):
+            try:
+                root._init_loaded_nodes(nodes)
+            except ValueError as e:
+                raise SchemaError(str(e), location=location) from None
+
+    @property
+    def shows_help_by_default(self) -> bool:
+        """Whether the node's help is shown when no subcommand is given."""
+        return self.default == self.DEFAULT_HELP
+
+    def get_default_subnode(self) -> Optional["NodeBase"]:
+        """
+        Get the subnode to run when no subcommand is given, see the 'default'
+        field.
+
+        :return:
+            The subnode, or None if the node has no default subcommand.
+        """
+        if self.default is None or self.shows_help_by_default:
+            return None
+        return self.get_subnode(self.default)
+
+    @property
+    def path(self) -> List[str]:
+        """The keywords leading to this node from the root node."""
+        keywords = []
+        node = self
+        while node.keyword:
             keywords.insert(0, node.keyword)
             node = node.parent
-        return "<SubNode({})>".format(".".join(keywords))
+        return keywords
+
+    @property
+    def is_hidden(self) -> bool:
+        """Whether this node or any node above it is hidden."""
+        node = self
+        while node is not None:
+            if node.hidden:
+                return True
+            node = node.parent
+        return False
+
+    @property
+    def root(self) -> "NodeBase":
+        """The node at the top of the tree this node is in."""
+        root = self
+        while root.parent is not None:
+            root = root.parent
+        return root
+
+    @property
+    def all_args(self) -> List[Arg]:
+        """The args accepted at this node, including the schema's global args."""
+        root = self.root
+        global_args = root.all_global_args if isinstance(root, RootNode) else []
+        return global_args + self.args
+
+    def walk(self, *, load: bool = True) -> typing.Iterator["NodeBase"]:
+        """
+        Iterate over this node and all nodes beneath it, depth first.
+
+        :param load:
+            Whether to load subtrees declared with 'subtree_file' that haven't
+            been loaded yet, rather than skipping them.
+        """
+        yield self
+        if load or self._subtree_loaded:
+            for subnode in self.subtree:
+                yield from subnode.walk(load=load)
+
+    def find_unauthorized(
+        self, roles: Optional[Iterable[str]]
+    ) -> Optional["SubNode"]:
+        """
+        Find the node requiring a role that the user doesn't have, out of this
+        node and the nodes above it.
+
+        :param roles:
+            The user's roles, or None to allow all commands.
+        :return:
+            The topmost such node, or None if the user may run this node's
+            command.
+        """
+        if roles is None:
+            return None
+        roles = frozenset(roles)
+        unauthorized = None
+        node = self  # type: Optional[NodeBase]
+        while node is not None:
+            required = getattr(node, "requires_role", None)
+            if required is not None and roles.isdisjoint(required):
+                unauthorized = node
+            node = node.parent
+        return unauthorized
+
+    def get_exit_code(self, category: str) -> Optional[int]:
+        """
+        Get the exit code declared for a category, e.g. 'usage' or 'not_found'.
+
+        Codes declared on this node take precedence over those declared on the
+        nodes above it.
+
+        :param category:
+            The category name.
+        :return:
+            The exit code, or None if none is declared.
+        """
+        node = self
+        while node is not None:
+            if category in node.exit_codes:
+                return node.exit_codes[category]
+            node = node.parent
+        return None
+
+    def get_help_template(self) -> Optional[str]:
+        """The help template for this node, inherited from the nodes above."""
+        node = self
+        while node is not None:
+            if node.help_template is not None:
+                return node.help_template
+            node = node.parent
+        return None
+
+    def get_help_footer(self) -> Optional[str]:
+        """The help footer for this node, inherited from the nodes above."""
+        node = self
+        while node is not None:
+            if node.help_footer is not None:
+                return node.help_footer
+            node = node.parent
+        return None
+
+    def get_abbreviations(self) -> bool:
+        """
+        Whether unambiguous prefixes of this node's subnode keywords are
+        accepted, inherited from the nodes above.
+        """
+        node = self
+        while node is not None:
+            if node.abbreviations is not None:
+                return node.abbreviations
+            node = node.parent
+        return False
+
+    def get_negative_numbers(self) -> bool:
+        """
+        Whether values that look like negative numbers (e.g. '-5') are taken as
+        the values of numeric options rather than as options, inherited from
+        the nodes above.
+        """
+        node = self
+        while node is not None:
+            if node.negative_numbers is not None:
+                return node.negative_numbers
+            node = node.parent
+        return True
+
+    def get_timeout(self) -> Optional[float]:
+        """
+        The number of seconds this node's command has to run before being
+        cancelled, inherited from the nodes above, or None for no limit.
+        """
+        node = self
+        while node is not None:
+            if node.timeout is not None:
+                return _timeout_to_seconds(node.timeout)
+            node = node.parent
+        return None
+
+    def get_signals(self) -> Optional[SignalsSpec]:
+        """
+        The settings for how this node's command handles Ctrl-C and SIGTERM,
+        inherited from the nodes above, or None if there are none.
+        """
+        node = self
+        while node is not None:
+            if node.signals is not None:
+                return node.signals
+            node = node.parent
+        return None
+
+    def get_cwd(self) -> Optional[str]:
+        """
+        The working directory template for this node's command, inherited from
+        the nodes above, or None to leave the working directory unchanged.
+        """
+        node = self
+        while node is not None:
+            if node.cwd is not None:
+                return node.cwd
+            node = node.parent
+        return None
+
+    def get_env(self) -> Dict[str, str]:
+        """
+        The environment variable templates for this node's command, including
+        those of the nodes above, which this node's take precedence over.
+        """
+        env = {}  # type: Dict[str, str]
+        node = self
+        while node is not None:
+            env = dict(node.env, **env)
+            node = node.parent
+        return env
+
+    def get_subnodes_by_prefix(self, prefix: str) -> List["SubNode"]:
+        """
+        Get the visible nodes in this node's subtree with a keyword or visible
+        alias starting with a prefix.
+
+        :param prefix:
+            The start of the keyword.
+        :return:
+            The matching subnodes.
+        """
+        return self._get_subtree_index().find_prefix(prefix)
+
+    def get_subnode(self, keyword: str) -> Optional["SubNode"]:
+        """
+        Get a node in this node's subtree by keyword or alias.
+
+        :param keyword:
+            The keyword or alias of the subnode, including hidden aliases.
+        :return:
+            The subnode, or None if there is no match.
+        """
+        return self._get_subtree_index().keywords.get(keyword)
+
+    def add_subnode(self, node: "SubNode") -> None:
+        """
+        Add a node to this node's subtree, e.g. for commands registered at
+        runtime.
+
+        :param node:
+            The node to add.
+        :raises ValueError:
+            If the node's keyword or aliases clash with those in the subtree.
+        """
+        for keyword in [node.keyword] + node.all_aliases:
+            if self.get_subnode(keyword) is not None:
+                raise ValueError(
+                    "Keyword {!r} already in the subtree of {}".format(
+                        keyword, " ".join(self.path) if self.path else "the root node"
+                    )
+                )
+        node.parent = self
+        self.subtree.append(node)
+        self._subtree_index = None
+        root = self.root
+        if isinstance(root, RootNode):
+            root._entry_flags = None
+
+    def find(self, path: typing.Sequence[str]) -> Optional["NodeBase"]:
+        """
+        Find a node beneath this node.
+
+        :param path:
+            The keywords (or aliases) leading to the node, relative to this node.
+        :return:
+            The node, or None if there is no node at the given path.
+        """
+        node = self
+        for keyword in path:
+            node = node.get_subnode(keyword)
+            if node is None:
+                return None
+        return node
+
+    _FIELDS = {
+        "help": ((str, dict), True),
+        "command": ((str, list), False),
+        "args": ((list,), False),
+        "groups": ((list,), False),
+        "exit_codes": ((dict,), False),
+        "help_template": ((str,), False),
+        "help_footer": ((str,), False),
+        "examples": ((list,), False),
+        "subtree": ((list,), False),
+        "output": ((list, dict), False),
+        "abbreviations": ((bool,), False),
+        "unknown_args": ((str,), False),
+        "negative_numbers": ((bool,), False),
+        "destructive": ((bool,), False),
+        "timeout": ((str, int, float), False),
+        "subtree_file": ((str,), False),
+        "default": ((str,), False),
+        "cwd": ((str,), False),
+        "env": ((dict,), False),
+        "signals": ((dict,), False),
+    }  # type: _FieldSpec
+
+    @classmethod
+    def from_dict(
+        cls, data: Dict[str, typing.Any], *, location: str = "root"
+    ) -> "NodeBase":
+        """
+        Create a node from its loaded schema representation.
+
+        :param data:
+            The loaded mapping.
+        :param location:
+            Description of where in the schema the node is, for error messages.
+        :raises SchemaError:
+            If the mapping is invalid.
+        """
+        _check_fields(
+            data, cls._FIELDS, location=_format_location(location, _get_span(data))
+        )
+        kwargs = cls._process_fields(data.copy(), location=location)
+        try:
+            node = cls(**kwargs)
+        except ValueError as e:
+            raise SchemaError(
+                str(e), location=_format_location(location, _get_span(data))
+            ) from None
+        node.lineno = _get_lineno(data)
+        node.span = _get_span(data)
+        return node
+
+    def to_dict(self) -> Dict[str, typing.Any]:
+        """
+        The schema fields of this node and the nodes beneath it, as in a loaded
+        schema file, with defaults filled in.
+        """
+        data = _fields_to_dict(self)
+        if self.external_command is not None:
+            data["command"] = list(self.external_command)
+        # The subtree is loaded and included instead.
+        data.pop("subtree_file", None)
+        added_args = self.destructive_args
+        if self.output is not None:
+            added_args = added_args + self.output.args
+        if added_args:
+            data["args"] = [a.to_dict() for a in self.args if a not in added_args]
+        return data
+
+    def to_json(self, **kwargs) -> str:
+        """
+        Dump the node and the nodes beneath it as JSON, see `to_dict()`.
+
+        :param kwargs:
+            Passed to `json.dumps()`.
+        """
+        kwargs.setdefault("indent", 2)
+        return json.dumps(self.to_dict(), **kwargs)
+
+    @classmethod
+    def _process_fields(
+        cls, kwargs: Dict[str, typing.Any], *, location: str
+    ) -> Dict[str, typing.Any]:
+        """Convert loaded fields into the kwargs for creating the node."""
+        kwargs["help_"] = _process_help_field(kwargs.pop("help"), location=location)
+        kwargs["args"] = cls._process_args_field(
+            kwargs.pop("args", []), location=location
+        )
+        cls._check_arg_references(kwargs["args"], location=location)
+        if len([a for a in kwargs["args"] if a.passthrough]) > 1:
+            raise SchemaError(
+                "only one arg can have 'passthrough' set", location=location
+            )
+        if "output" in kwargs:
+            kwargs["output"] = OutputSpec.from_value(
+                kwargs["output"], location=location + ": output"
+            )
+            for arg in kwargs["output"].args:
+                if any(a.name == arg.name for a in kwargs["args"]):
+                    raise SchemaError(
+                        "an arg named {!r} can't be declared with these 'output' "
+                        "settings".format(arg.name),
+                        location=location,
+                    )
+        kwargs["examples"] = [
+            Example.from_dict(x, location="{}: examples[{}]".format(location, i))
+            for i, x in enumerate(kwargs.pop("examples", []))
+        ]
+        if "help_template" in kwargs:
+            try:
+                check_template(kwargs["help_template"])
+            except ValueError as e:
+                raise SchemaError(str(e), location=location) from None
+        templates = [("cwd", kwargs["cwd"])] if "cwd" in kwargs else []
+        if isinstance(kwargs.get("command"), list):
+            templates += [
+                ("command[{}]".format(i), x) for i, x in enumerate(kwargs["command"])
+            ]
+        templates += [("env: " + k, v) for k, v in kwargs.get("env", {}).items()]
+        if "signals" in kwargs:
+            kwargs["signals"] = SignalsSpec.from_value(
+                kwargs["signals"], location=location + ": signals"
+            )
+            templates += [
+                ("signals: cleanup[{}]".format(i), x)
+                for i, x in enumerate(kwargs["signals"].cleanup or [])
+            ]
+        for field, template in templates:
+            field_location = "{}: {}".format(location, field)
+            if not isinstance(template, str):
+                raise SchemaError(
+                    "expected a string, got {!r}".format(template),
+                    location=field_location,
+                )
+            try:
+                get_arg_references(template)
+            except TemplateError as e:
+                raise SchemaError(str(e), location=field_location) from None
+        for name, code in kwargs.get("exit_codes", {}).items():
+            if not isinstance(code, int) or isinstance(code, bool):
+                raise SchemaError(
+                    "exit code for {!r} should be an integer, got {!r}".format(
+                        name, code
+                    ),
+                    location=location + ": exit_codes",
+                )
+        kwargs["groups"] = [
+            ArgGroup.from_dict(
+                x,
+                arg_names=[a.name for a in kwargs["args"]],
+                location="{}: groups[{}]".format(location, i),
+            )
+            for i, x in enumerate(kwargs.pop("groups", []))
+        ]
+        kwargs["subtree"] = [
+            n
+            for n in cls._process_subtree_field(
+                kwargs.pop("subtree", []), location=location
+            )
+            if n.is_available
+        ]
+        kwargs["args"], kwargs["groups"] = _filter_platform_args(
+            kwargs["args"], kwargs["groups"]
+        )
+        return kwargs
+
+    @staticmethod
+    def _check_arg_references(args: List[Arg], *, location: str) -> None:
+        """Check the args referenced by each arg's constraints and default."""
+        args_by_name = {a.name: a for a in args}
+        default_refs = {}  # type: Dict[str, List[str]]
+        for i, arg in enumerate(args):
+            arg_location = _format_location(
+                "{}: args[{}] {!r}".format(location, i, arg.name), arg.span
+            )
+            if is_template(arg.default):
+                try:
+                    default_refs[arg.name] = get_arg_references(arg.default)
+                except TemplateError as e:
+                    raise SchemaError(str(e), location=arg_location) from None
+            refs = list(arg.required_if) + arg.conflicts_with
+            for name in refs + default_refs.get(arg.name, []):
+                if name not in args_by_name or name == arg.name:
+                    raise SchemaError(
+                        "unknown arg {!r} referenced".format(name),
+                        location=arg_location,
+                    )
+            for name, value in arg.required_if.items():
+                other = args_by_name[name]
+                try:
+                    other.type.convert(value)
+                except ValueError:
+                    raise SchemaError(
+                        "'required_if' value for {!r} should be {}, got {!r}".format(
+                            name, other.type.description, value
+                        ),
+                        location=arg_location,
+                    ) from None
+
+        # Check there are no cycles in the args referenced by defaults.
+        def find_cycle(name: str, seen: List[str]) -> Optional[List[str]]:
+            if name in seen:
+                return seen[seen.index(name) :] + [name]
+            for ref in default_refs.get(name, []):
+                cycle = find_cycle(ref, seen + [name])
+                if cycle:
+                    return cycle
+            return None
+
+        for name in default_refs:
+            cycle = find_cycle(name, [])
+            if cycle:
+                raise SchemaError(
+                    "args have defaults that reference each other: {}".format(
+                        " -> ".join(cycle)
+                    ),
+                    location=location,
+                )
+
+    @staticmethod
+    def _process_subtree_field(
+        value: List[Dict[str, typing.Any]], *, location: str
+    ) -> List["NodeBase"]:
+        nodes = []
+        errors = []
+        for i, x in enumerate(value):
+            if isinstance(x, dict) and isinstance(x.get("keyword"), str):
+                sub_location = x["keyword"]
+                if location != "root":
+                    sub_location = location + "." + sub_location
+            else:
+                sub_location = "{}: subtree[{}]".format(location, i)
+            try:
+                nodes.append(SubNode.from_dict(x, location=sub_location))
+            except SchemaError as e:
+                errors.append(e)
+        _raise_errors(errors)
+        return nodes
+
+    @staticmethod
+    def _process_args_field(
+        value: List[Dict[str, typing.Any]], *, location: str
+    ) -> List[Arg]:
+        args = []
+        errors = []
+        for i, x in enumerate(value):
+            arg_location = "{}: args[{}]".format(location, i)
+            try:
+                args.append(Arg.from_dict(x, location=arg_location))
+            except SchemaError as e:
+                errors.append(e)
+        _raise_errors(errors)
+        return args
+
+
+class ConfigSpec:
+    """Schema settings for loading arg values from a config file."""
+
+    def __init__(self, *, file: Optional[str] = None, option: bool = True):
+        """
+        :param file:
+            The default config file path, which may start with '~'.
+        :param option:
+            Whether to accept a '--config' option for giving the config file.
+        """
+        self.file = file
+        self.option = option
+
+    _FIELDS = {
+        "file": ((str,), False),
+        "option": ((bool,), False),
+    }  # type: _FieldSpec
+
+    @classmethod
+    def from_dict(cls, data: Dict[str, typing.Any], *, location: str) -> "ConfigSpec":
+        _check_fields(
+            data, cls._FIELDS, location=_format_location(location, _get_span(data))
+        )
+        return cls(**data)
+
+    def to_dict(self) -> Dict[str, typing.Any]:
+        """The schema fields, as in a loaded schema file."""
+        return _fields_to_dict(self)
+
+
+class PluginSpec:
+    """Schema settings for discovering external plugin commands."""
+
+    def __init__(
+        self, *, prefix: str, dirs: Optional[List[str]] = None, path: bool = True
+    ):
+        """
+        :param prefix:
+            The prefix of plugin executable names, e.g. 'myapp-' for a
+            'myapp-sync' executable providing a 'sync' command.
+        :param dirs:
+            Directories to search for plugins before 'PATH', which may start
+            with '~'.
+        :param path:
+            Whether to search the directories in 'PATH'.
+        """
+        self.prefix = prefix
+        self.dirs = dirs if dirs else []
+        self.path = path
+
+    _FIELDS = {
+        "prefix": ((str,), True),
+        "dirs": ((list,), False),
+        "path": ((bool,), False),
+    }  # type: _FieldSpec
+
+    @classmethod
+    def from_dict(cls, data: Dict[str, typing.Any], *, location: str) -> "PluginSpec":
+        _check_fields(
+            data, cls._FIELDS, location=_format_location(location, _get_span(data))
+        )
+        return cls(**data)
+
+    def to_dict(self) -> Dict[str, typing.Any]:
+        """The schema fields, as in a loaded schema file."""
+        return _fields_to_dict(self)
+
+
+class ChainSpec:
+    """
+    Schema settings for giving multiple commands in one invocation, separated
+    by a token, e.g. 'myapp build + test + deploy'.
+    """
+
+    # How a failing command affects the commands after it in the chain.
+    ON_ERROR_POLICIES = ("stop", "continue")
+
+    def __init__(self, *, separator: str = "+", on_error: str = "stop"):
+        """
+        :param separator:
+            The arg separating the commands.
+        :param on_error:
+            Whether to 'stop' at the first command that fails, or 'continue'
+            running the remaining commands.
+        """
+        if not separator or separator.startswith("-"):
+            raise ValueError(
+                "Invalid chain separator {!r}, must be non-empty and not start "
+                "with '-'".format(separator)
+            )
+        if on_error not in self.ON_ERROR_POLICIES:
+            raise ValueError(
+                "Invalid 'on_error' value {!r}, expected one of: {}".format(
+                    on_error, ", ".join(self.ON_ERROR_POLICIES)
+                )
+            )
+        self.separator = separator
+        self.on_error = on_error
+
+    _FIELDS = {
+        "separator": ((str,), False),
+        "on_error": ((str,), False),
+    }  # type: _FieldSpec
+
+    @classmethod
+    def from_value(cls, value: typing.Any, *, location: str) -> "ChainSpec":
+        """
+        Create the chaining settings from the loaded 'chaining' value.
+
+        :param value:
+            Either true for the default settings, or a mapping.
+        :param location:
+            Description of where in the schema the value is, for error messages.
+        :raises SchemaError:
+            If the value is invalid.
+        """
+        if value is True:
+            return cls()
+        location = _format_location(location, _get_span(value))
+        if not isinstance(value, dict):
+            raise SchemaError("expected true or a mapping", location=location)
+        _check_fields(value, cls._FIELDS, location=location)
+        try:
+            return cls(**value)
+        except ValueError as e:
+            raise SchemaError(str(e), location=location) from None
+
+    def to_dict(self) -> Dict[str, typing.Any]:
+        """The schema fields, as in a loaded schema file."""
+        return _fields_to_dict(self)
+
+    def split_args(self, args: List[str]) -> List[List[str]]:
+        """
+        Split args into those for each command in the chain.
+
+        :param args:
+            The args for the whole chain.
+        :return:
+            The args for each command, in order.
+        """
+        chain = [[]]  # type: List[List[str]]
+        for arg in args:
+            if arg == self.separator:
+                chain.append([])
+            else:
+                chain[-1].append(arg)
+        return chain
+
+
+class SelfUpdateSpec:
+    """
+    Schema settings for the 'self update' subcommand, which replaces the
+    application's executable with the latest release.
+    """
+
+    # The command name of the 'self update' node.
+    COMMAND = "self-update"
+
+    def __init__(
+        self,
+        *,
+        github: Optional[str] = None,
+        url: Optional[str] = None,
+        asset: Optional[str] = None,
+        checksums: str = "SHA256SUMS",
+        signature: bool = False,
+    ):
+        """
+        :param github:
+            The GitHub repository to take the latest release from, as
+            'owner/repo'.
+        :param url:
+            URL of a JSON manifest describing the latest release instead, with
+            'version', 'url' and 'sha256' keys, and 'signature_url' if
+            signatures are checked. The URLs must be HTTPS unless signatures
+            are checked, since otherwise the download and its checksum could
+            both be tampered with.
+        :param asset:
+            The name of the GitHub release asset to download, which may
+            contain '{version}', '{os}' and '{arch}' placeholders.
+        :param checksums:
+            The name of the GitHub release asset listing the SHA-256 checksums
+            of the other assets, in the format output by 'sha256sum'.
+        :param signature:
+            Whether to check a detached GPG signature of the download, from
+            the '<asset>.asc' release asset, using the keys known to 'gpg'.
+        """
+        if (github is None) == (url is None):
+            raise ValueError("Exactly one of 'github' or 'url' must be given")
+        if github is not None:
+            if not re.fullmatch(r"[\w.-]+/[\w.-]+", github):
+                raise ValueError(
+                    "Invalid 'github' value {!r}, expected 'owner/repo'".format(github)
+                )
+            if asset is None:
+                raise ValueError("'asset' must be given with 'github'")
+        elif asset is not None:
+            raise ValueError("'asset' is only valid with 'github'")
+        if url is not None and not url.startswith("https://") and not signature:
+            raise ValueError(
+                "Invalid 'url' value {!r}, expected an HTTPS URL unless "
+                "'signature' is set".format(url)
+            )
+        self.github = github
+        self.url = url
+        self.asset = asset
+        self.checksums = checksums
+        self.signature = signature
+
+    _FIELDS = {
+        "github": ((str,), False),
+        "url": ((str,), False),
+        "asset": ((str,), False),
+        "checksums": ((str,), False),
+        "signature": ((bool,), False),
+    }  # type: _FieldSpec
+
+    @classmethod
+    def from_dict(
+        cls, data: Dict[str, typing.Any], *, location: str
+    ) -> "SelfUpdateSpec":
+        location = _format_location(location, _get_span(data))
+        _check_fields(data, cls._FIELDS, location=location)
+        try:
+            return cls(**data)
+        except ValueError as e:
+            raise SchemaError(str(e), location=location) from None
+
+    def to_dict(self) -> Dict[str, typing.Any]:
+        """The schema fields, as in a loaded schema file."""
+        return _fields_to_dict(self)
+
+    def make_node(self) -> "SubNode":
+        """Create the 'self' node, with the 'update' subcommand beneath it."""
+        update_node = SubNode(
+            keyword="update",
+            help_="Update to the latest release",
+            command=self.COMMAND,
+            destructive=True,
+        )
+        return SubNode(
+            keyword="self", help_="Manage this application", subtree=[update_node]
+        )
+
+
+class HistorySpec:
+    """
+    Schema settings for recording the commands run in a history file, with
+    the 'history list' and 'history rerun' subcommands for replaying them.
+    """
+
+    # The command names of the 'history list' and 'history rerun' nodes.
+    LIST_COMMAND = "history-list"
+    RERUN_COMMAND = "history-rerun"
+
+    def __init__(self, *, file: str, size: int = 1000):
+        """
+        :param file:
+            The path of the history file, which may start with '~'.
+        :param size:
+            The number of commands to keep in the history file.
+        """
+        if isinstance(size, bool) or size <= 0:
+            raise ValueError("'size' must be a positive integer, got {!r}".format(size))
+        self.file = file
+        self.size = size
+
+    _FIELDS = {
+        "file": ((str,), True),
+        "size": ((int,), False),
+    }  # type: _FieldSpec
+
+    @classmethod
+    def from_value(cls, value: typing.Any, *, location: str) -> "HistorySpec":
+        """
+        Create the history settings from the loaded 'history' value.
+
+        :param value:
+            Either the history file path, or a mapping.
+        :param location:
+            Description of where in the schema the value is, for error messages.
+        :raises SchemaError:
+            If the value is invalid.
+        """
+        if isinstance(value, str):
+            return cls(file=value)
+        location = _format_location(location, _get_span(value))
+        if not isinstance(value, dict):
+            raise SchemaError("expected a path or a mapping", location=location)
+        _check_fields(value, cls._FIELDS, location=location)
+        try:
+            return cls(**value)
+        except ValueError as e:
+            raise SchemaError(str(e), location=location) from None
+
+    def to_dict(self) -> Dict[str, typing.Any]:
+        """The schema fields, as in a loaded schema file."""
+        return _fields_to_dict(self)
+
+    def get_path(self) -> pathlib.Path:
+        """The path of the history file, with '~' expanded."""
+        return pathlib.Path(self.file).expanduser()
+
+    def make_node(self) -> "SubNode":
+        """Create the 'history' node, with the 'list' and 'rerun' subcommands."""
+        list_node = SubNode(
+            keyword="list",
+            help_="List the commands run, oldest first",
+            command=self.LIST_COMMAND,
+        )
+        rerun_node = SubNode(
+            keyword="rerun",
+            help_="Run a command from the history again",
+            command=self.RERUN_COMMAND,
+            args=[
+                Arg(
+                    name="number",
+                    help_="The number of the command, as shown by 'history list'",
+                    positional=True,
+                    type_=ArgType.INTEGER,
+                )
+            ],
+        )
+        return SubNode(
+            keyword="history",
+            help_="Show and rerun previous commands",
+            subtree=[list_node, rerun_node],
+        )
+
+
+class VersionSpec:
+    """Schema settings for the '--version' option."""
+
+    def __init__(
+        self,
+        *,
+        number: Optional[str] = None,
+        package: Optional[str] = None,
+        commit: Optional[str] = None,
+        date: Optional[str] = None,
+    ):
+        """
+        :param number:
+            The version number.
+        :param package:
+            The name of an installed distribution package to take the version
+            number from, instead of giving it explicitly.
+        :param commit:
+            The source control commit the application was built from.
+        :param date:
+            The date the application was built.
+        """
+        if (number is None) == (package is None):
+            raise ValueError("Exactly one of 'number' or 'package' must be given")
+        self.number = number
+        self.package = package
+        self.commit = commit
+        self.date = date
+
+    _FIELDS = {
+        "number": ((str,), False),
+        "package": ((str,), False),
+        "commit": ((str,), False),
+        "date": ((str,), False),
+    }  # type: _FieldSpec
+
+    @classmethod
+    def from_value(cls, value: typing.Any, *, location: str) -> "VersionSpec":
+        """
+        Create the version settings from the loaded 'version' value.
+
+        :param value:
+            Either a version number string or a mapping.
+        :param location:
+            Description of where in the schema the value is, for error messages.
+        :raises SchemaError:
+            If the value is invalid.
+        """
+        if isinstance(value, str):
+            return cls(number=value)
+        location = _format_location(location, _get_span(value))
+        _check_fields(value, cls._FIELDS, location=location)
+        try:
+            return cls(**value)
+        except ValueError as e:
+            raise SchemaError(str(e), location=location) from None
+
+    def to_dict(self) -> Dict[str, typing.Any]:
+        """The schema fields, as in a loaded schema file."""
+        return _fields_to_dict(self)
+
+    def get_number(self) -> str:
+        """
+        Get the version number, looking up the package version if needed.
+
+        :raises SchemaError:
+            If the package is not installed.
+        """
+        if self.number is not None:
+            return self.number
+        try:
+            from importlib import metadata
+        except ImportError:  # Python < 3.8
+            import importlib_metadata as metadata
+        try:
+            return metadata.version(self.package)
+        except metadata.PackageNotFoundError:
+            raise SchemaError(
+                "Unable to get version, package {!r} is not installed".format(
+                    self.package
+                )
+            ) from None
+
+    def describe(self, prog: str) -> str:
+        """
+        Describe the version, e.g. 'myapp 1.2.0 (commit 3a9671d, built 2026-10-01)'.
+
+        :param prog:
+            The program name.
+        :raises SchemaError:
+            If the version number can't be determined.
+        """
+        text = "{} {}".format(prog, self.get_number())
+        details = []
+        if self.commit:
+            details.append("commit " + self.commit)
+        if self.date:
+            details.append("built " + self.date)
+        if details:
+            text += " ({})".format(", ".join(details))
+        return text
+
+
+class LoggingSpec:
+    """Schema settings for the standard options controlling log verbosity."""
+
+    LEVELS = ("debug", "info", "warning", "error", "critical")
+
+    def __init__(
+        self,
+        *,
+        level: str = "warning",
+        verbose: bool = True,
+        quiet: bool = True,
+        log_level: bool = True,
+        env: Optional[str] = None,
+        format: Optional[str] = None,
+    ):
+        """
+        :param level:
+            The default log level, one of `LEVELS`.
+        :param verbose:
+            Whether to accept the '-v'/'--verbose' flag, which lowers the
+            level by one step each time it's given.
+        :param quiet:
+            Whether to accept the '-q'/'--quiet' flag, which raises the level
+            by one step each time it's given.
+        :param log_level:
+            Whether to accept the '--log-level' option for setting the level,
+            which takes precedence over the verbosity flags.
+        :param env:
+            Environment variable for setting the level, as for '--log-level'.
+        :param format:
+            The log record format, as accepted by `logging.basicConfig()`.
+        """
+        if level not in self.LEVELS:
+            raise ValueError(
+                "Invalid log level {!r}, expected one of: {}".format(
+                    level, ", ".join(self.LEVELS)
+                )
+            )
+        if env is not None and not log_level:
+            raise ValueError("'env' can only be set when 'log_level' is enabled")
+        self.level = level
+        self.verbose = verbose
+        self.quiet = quiet
+        self.log_level = log_level
+        self.env = env
+        self.format = format
+        self.args = self._make_args()
+
+    _FIELDS = {
+        "level": ((str,), False),
+        "verbose": ((bool,), False),
+        "quiet": ((bool,), False),
+        "log_level": ((bool,), False),
+        "env": ((str,), False),
+        "format": ((str,), False),
+    }  # type: _FieldSpec
+
+    def _make_args(self) -> List[Arg]:
+        args = []
+        if self.verbose:
+            args.append(
+                Arg(
+                    name="verbose",
+                    help_="Increase the log verbosity, may be repeated",
+                    short="v",
+                    type_=ArgType.FLAG,
+                    count=True,
+                )
+            )
+        if self.quiet:
+            args.append(
+                Arg(
+                    name="quiet",
+                    help_="Decrease the log verbosity, may be repeated",
+                    short="q",
+                    type_=ArgType.FLAG,
+                    count=True,
+                )
+            )
+        if self.log_level:
+            args.append(
+                Arg(
+                    name="log-level",
+                    help_="The log level, defaults to {!r}".format(self.level),
+                    enum=list(self.LEVELS),
+                    env=self.env,
+                )
+            )
+        return args
+
+    @classmethod
+    def from_value(cls, value: typing.Any, *, location: str) -> "LoggingSpec":
+        """
+        Create the logging settings from the loaded 'logging' value.
+
+        :param value:
+            Either true for the default settings, or a mapping.
+        :param location:
+            Description of where in the schema the value is, for error messages.
+        :raises SchemaError:
+            If the value is invalid.
+        """
+        if value is True:
+            return cls()
+        location = _format_location(location, _get_span(value))
+        if not isinstance(value, dict):
+            raise SchemaError("expected true or a mapping", location=location)
+        _check_fields(value, cls._FIELDS, location=location)
+        try:
+            return cls(**value)
+        except ValueError as e:
+            raise SchemaError(str(e), location=location) from None
+
+    def to_dict(self) -> Dict[str, typing.Any]:
+        """The schema fields, as in a loaded schema file."""
+        return _fields_to_dict(self)
+
+
+class RootNode(NodeBase):
+    """Root schema node."""
+
+    def __init__(
+        self,
+        *,
+        config: Optional[ConfigSpec] = None,
+        global_args: Optional[List[Arg]] = None,
+        response_files: bool = False,
+        plugins: Optional[PluginSpec] = None,
+        version: Optional[VersionSpec] = None,
+        logging: Optional[LoggingSpec] = None,
+        pager: bool = False,
+        default_lang: Optional[str] = None,
+        experimental_env: Optional[str] = None,
+        schema_version: int = SCHEMA_VERSION,
+        short_clusters: bool = True,
+        self_update: Optional[SelfUpdateSpec] = None,
+        chaining: Optional[ChainSpec] = None,
+        aliases_script: Optional[Dict[str, str]] = None,
+        history: Optional[HistorySpec] = None,
+        binaries: Optional[Dict[str, "RootNode"]] = None,
+        **kwargs
+    ):
+        if "keyword" in kwargs:
+            raise TypeError("__init__() got an unexpected keyword argument 'keyword'")
+        super().__init__(**kwargs)
+        if self_update is not None:
+            if version is None:
+                raise ValueError("'self_update' requires 'version' to be given")
+            if self.get_subnode("self") is not None:
+                raise ValueError(
+                    "a command with the keyword 'self' can't be declared with "
+                    "'self_update' set"
+                )
+        if history is not None and self.get_subnode("history") is not None:
+            raise ValueError(
+                "a command with the keyword 'history' can't be declared with "
+                "'history' set"
+            )
+        for name, words in (aliases_script or {}).items():
+            if not re.fullmatch(r"[A-Za-z_][\w.-]*", name):
+                raise ValueError("Invalid shell alias name {!r}".format(name))
+            if not isinstance(words, str) or not words.strip():
+                raise ValueError(
+                    "Invalid command for shell alias {!r}, expected a non-empty "
+                    "string of the args to run".format(name)
+                )
+            try:
+                shlex.split(words)
+            except ValueError as e:
+                raise ValueError(
+                    "Invalid command for shell alias {!r}: {}".format(name, e)
+                ) from None
+        self.global_args = global_args if global_args else []
+        if short_clusters:
+            self._check_short_names(self._iter_entries())
+        if response_files and any(
+            "file" in (getattr(x, "source", None) or []) for x in self._iter_entries()
+        ):
+            raise ValueError(
+                "args with 'file' in 'source' can't be declared with "
+                "'response_files' set, since both use '@file'"
+            )
+        self.config = config
+        self.response_files = response_files
+        self.plugins = plugins
+        self.version = version
+        self.logging = logging
+        self.pager = pager
+        self.default_lang = default_lang
+        self.experimental_env = experimental_env
+        self.schema_version = schema_version
+        self.short_clusters = short_clusters
+        self.self_update = self_update
+        self.chaining = chaining
+        self.aliases_script = aliases_script if aliases_script else {}
+        self.history = history
+        # The schemas of the other programs of a multi-call binary, by name.
+        self.binaries = binaries if binaries else {}  # type: Dict[str, RootNode]
+        # Whether there are experimental entries and translations, cached for
+        # large schemas and reset when nodes are added.
+        self._entry_flags = None  # type: Optional[typing.Tuple[bool, bool]]
+        # The nodes added for the 'self_update' and 'history' settings.
+        self._builtin_nodes = []  # type: List[SubNode]
+        if self_update is not None:
+            self._builtin_nodes.append(self_update.make_node())
+        if history is not None:
+            self._builtin_nodes.append(history.make_node())
+        for node in self._builtin_nodes:
+            self.add_subnode(node)
+        self.lang = None  # type: Optional[str]
+        self._pager_args = []  # type: List[Arg]
+        if pager:
+            self._pager_args.append(
+                Arg(
+                    name="no-pager",
+                    help_="Don't page long help and output",
+                    type_=ArgType.FLAG,
+                )
+            )
+        self.localize(_get_env_lang())
+
+    @property
+    def all_global_args(self) -> List[Arg]:
+        """
+        The global args, including those added for the logging and pager
+        settings.
+        """
+        logging_args = self.logging.args if self.logging else []
+        return logging_args + self._pager_args + self.global_args
+
+    def get_alias_node(self, name: str) -> NodeBase:
+        """
+        Get the node of the command a shell alias runs, found by following the
+        alias's leading keywords.
+
+        :param name:
+            The name of the alias in 'aliases_script'.
+        """
+        node = self  # type: NodeBase
+        for word in shlex.split(self.aliases_script[name]):
+            subnode = node.get_subnode(word)
+            if subnode is None:
+                break
+            node = subnode
+        return node
+
+    def _iter_entries(
+        self, nodes: Optional[List[NodeBase]] = None
+    ) -> typing.Iterator[typing.Union[Arg, NodeBase]]:
+        """
+        Iterate over all the nodes and args in the schema, or beneath the given
+        nodes, excluding subtrees that haven't been loaded.
+        """
+        if nodes is None:
+            yield from self.global_args
+            nodes = [self]
+        for top_node in nodes:
+            for node in top_node.walk(load=False):
+                yield node
+                yield from node.args
+
+    @staticmethod
+    def _check_short_names(entries: Iterable[typing.Union[Arg, NodeBase]]) -> None:
+        """
+        :raises ValueError:
+            If an arg has a short name of more than one character.
+        """
+        for arg in entries:
+            if isinstance(arg, Arg) and arg.short and len(arg.short) > 1:
+                raise ValueError(
+                    "Short name {!r} of arg {!r} is more than one character, "
+                    "which requires 'short_clusters' to be false".format(
+                        arg.short, arg.name
+                    )
+                )
+
+    def _init_loaded_nodes(self, nodes: List[NodeBase]) -> None:
+        """
+        Apply the schema's settings to nodes loaded from a 'subtree_file'.
+
+        :raises ValueError:
+            If the nodes are invalid with the schema's settings.
+        """
+        if self.short_clusters:
+            self._check_short_names(self._iter_entries(nodes))
+        self._localize_entries(self._iter_entries(nodes))
+        self._entry_flags = None
+
+    def _get_entry_flags(self) -> typing.Tuple[bool, bool]:
+        """
+        Get whether any entries are experimental and whether any have
+        translations, found on first use rather than on every parse.
+        """
+        if self._entry_flags is None:
+            experimental = translations = False
+            for x in self._iter_entries():
+                experimental = experimental or getattr(x, "experimental", False)
+                translations = translations or isinstance(x.help, LocalizedText)
+            self._entry_flags = (experimental, translations)
+        return self._entry_flags
+
+    @property
+    def has_experimental(self) -> bool:
+        """Whether any nodes or args in the schema are experimental."""
+        return self._get_entry_flags()[0]
+
+    @property
+    def has_translations(self) -> bool:
+        """Whether any help text in the schema has translations."""
+        return self._get_entry_flags()[1]
+
+    def localize(self, lang: Optional[str]) -> None:
+        """
+        Select the language of help text with translations.
+
+        The translation used is the first found for the language (e.g.
+        'fr_CA'), the language without its region (e.g. 'fr'), the schema's
+        default language, and finally the first translation given.
+
+        :param lang:
+            The language or locale name, e.g. 'fr' or 'fr_CA.UTF-8', or None
+            for the default.
+        """
+        self.lang = lang
+        self._localize_entries(self._iter_entries())
+
+    def _localize_entries(self, entries: Iterable[typing.Union[Arg, NodeBase]]) -> None:
+        langs = _lang_chain(self.lang) if self.lang else []
+        if self.default_lang:
+            langs.append(self.default_lang)
+        for item in entries:
+            if isinstance(item.help, LocalizedText):
+                item.help = item.help.localize(langs)
+
+    _FIELDS = dict(
+        NodeBase._FIELDS,
+        config=((dict,), False),
+        global_args=((list,), False),
+        response_files=((bool,), False),
+        plugins=((dict,), False),
+        version=((str, dict), False),
+        logging=((bool, dict), False),
+        pager=((bool,), False),
+        default_lang=((str,), False),
+        experimental_env=((str,), False),
+        schema_version=((int,), False),
+        short_clusters=((bool,), False),
+        self_update=((dict,), False),
+        chaining=((bool, dict), False),
+        aliases_script=((dict,), False),
+        history=((str, dict), False),
+        binaries=((dict,), False),
+    )
+
+    # Top-level settings that aren't shared with the schemas of 'binaries'.
+    _UNSHARED_FIELDS = ("binaries", "aliases_script")
+
+    @classmethod
+    def from_dict(
+        cls, data: Dict[str, typing.Any], *, location: str = "root"
+    ) -> "RootNode":
+        """
+        Create a root node from its loaded schema representation.
+
+        Schemas in older versions of the format are upgraded, with a warning
+        that the schema file should be migrated, see `migrate()`.
+
+        :param data:
+            The loaded mapping.
+        :param location:
+            Description of where the schema is, for error messages.
+        :raises SchemaError:
+            If the mapping is invalid.
+        """
+        if isinstance(data, dict):
+            data = data.copy()
+            version = _upgrade_schema_data(data, location=location)
+            if version != SCHEMA_VERSION:
+                warnings.warn(
+                    "CLI schema version {} is outdated, upgraded to version {} - "
+                    "the schema file can be updated with 'declarative-cli "
+                    "migrate'".format(version, SCHEMA_VERSION),
+                    FutureWarning,
+                    stacklevel=2,
+                )
+        return super().from_dict(data, location=location)
+
+    @classmethod
+    def _process_fields(
+        cls, kwargs: Dict[str, typing.Any], *, location: str
+    ) -> Dict[str, typing.Any]:
+        if "binaries" in kwargs:
+            kwargs["binaries"] = cls._process_binaries_field(
+                kwargs["binaries"],
+                {
+                    k: v
+                    for k, v in kwargs.items()
+                    if k not in NodeBase._FIELDS and k not in cls._UNSHARED_FIELDS
+                },
+                location=location + ": binaries",
+            )
+        kwargs = super()._process_fields(kwargs, location=location)
+        if "config" in kwargs:
+            kwargs["config"] = ConfigSpec.from_dict(
+                kwargs["config"], location=location + ": config"
+            )
+        if "plugins" in kwargs:
+            kwargs["plugins"] = PluginSpec.from_dict(
+                kwargs["plugins"], location=location + ": plugins"
+            )
+        if "version" in kwargs:
+            kwargs["version"] = VersionSpec.from_value(
+                kwargs["version"], location=location + ": version"
+            )
+        if "self_update" in kwargs:
+            kwargs["self_update"] = SelfUpdateSpec.from_dict(
+                kwargs["self_update"], location=location + ": self_update"
+            )
+        if "history" in kwargs:
+            kwargs["history"] = HistorySpec.from_value(
+                kwargs["history"], location=location + ": history"
+            )
+        if kwargs.get("chaining") is False:
+            del kwargs["chaining"]
+        elif "chaining" in kwargs:
+            kwargs["chaining"] = ChainSpec.from_value(
+                kwargs["chaining"], location=location + ": chaining"
+            )
+        if kwargs.get("logging") is False:
+            del kwargs["logging"]
+        elif "logging" in kwargs:
+            kwargs["logging"] = LoggingSpec.from_value(
+                kwargs["logging"], location=location + ": logging"
+            )
+        global_args = []
+        for i, x in enumerate(kwargs.pop("global_args", [])):
+            arg_location = "{}: global_args[{}]".format(location, i)
+            arg = Arg.from_dict(x, location=arg_location)
+            if arg.positional:
+                raise SchemaError(
+                    "global args can't be positional",
+                    location=_format_location(arg_location, arg.span),
+                )
+            global_args.append(arg)
+        cls._check_arg_references(global_args, location=location + ": global_args")
+        kwargs["global_args"], _ = _filter_platform_args(global_args, [])
+        return kwargs
+
+    @classmethod
+    def _process_binaries_field(
+        cls,
+        binaries: Dict[str, typing.Any],
+        settings: Dict[str, typing.Any],
+        *,
+        location: str
+    ) -> Dict[str, "RootNode"]:
+        """
+        Create the schemas of the 'binaries', each with the top-level settings
+        of the schema and its own node fields.
+        """
+        result = {}
+        for name, data in binaries.items():
+            binary_location = "{}: {}".format(location, name)
+            if not re.fullmatch(r"[\w.-]+", name):
+                raise SchemaError(
+                    "Invalid binary name {!r}".format(name), location=location
+                )
+            if not isinstance(data, dict):
+                raise SchemaError(
+                    "expected a mapping, got {!r}".format(data),
+                    location=binary_location,
+                )
+            _check_fields(
+                data,
+                NodeBase._FIELDS,
+                location=_format_location(binary_location, _get_span(data)),
+            )
+            result[name] = cls.from_dict(
+                dict(settings, **data), location=binary_location
+            )
+        return result
+
+    def get_binary(self, name: str) -> "RootNode":
+        """
+        Get the schema of a program of a multi-call binary, as selected by the
+        name it's run as, e.g. 'myapp-admin'.
+
+        :param name:
+            The program name, e.g. the base name of 'argv[0]', which may end
+            with a file extension such as '.exe'.
+        :return:
+            The schema of the program in 'binaries' with the name, or this
+            schema if there is none.
+        """
+        for candidate in (name, os.path.splitext(name)[0]):
+            if candidate in self.binaries:
+                return self.binaries[candidate]
+        return self
+
+    def to_dict(self) -> Dict[str, typing.Any]:
+        data = super().to_dict()
+        if self._builtin_nodes:
+            data["subtree"] = [
+                n.to_dict() for n in self.subtree if n not in self._builtin_nodes
+            ]
+        if self.binaries:
+            data["binaries"] = {
+                name: {
+                    k: v for k, v in binary.to_dict().items() if k in NodeBase._FIELDS
+                }
+                for name, binary in self.binaries.items()
+            }
+        return data
+
+    def __repr__(self):
+        return "<RootNode>"
+
+
+class SubNode(NodeBase):
+    """Sub schema node."""
+
+    _FIELDS = dict(
+        NodeBase._FIELDS,
+        keyword=((str,), True),
+        aliases=((list,), False),
+        hidden=((bool,), False),
+        deprecated=((bool, dict), False),
+        platforms=((list,), False),
+        experimental=((bool,), False),
+        requires_role=((str, list), False),
+    )
+
+    def __init__(
+        self,
+        *,
+        keyword: str,
+        aliases: Optional[List[str]] = None,
+        hidden_aliases: Optional[List[str]] = None,
+        hidden: bool = False,
+        deprecated: Optional[Deprecation] = None,
+        platforms: Optional[List[str]] = None,
+        experimental: bool = False,
+        requires_role: Optional[typing.Union[str, List[str]]] = None,
+        **kwargs
+    ):
+        """
+        :param keyword:
+            The keyword for selecting the node.
+        :param aliases:
+            Alternative keywords, shown in help.
+        :param hidden_aliases:
+            Alternative keywords that are accepted but not shown, e.g. for
+            renamed commands.
+        :param hidden:
+            Whether to exclude the node from help, completions and docs.
+        :param deprecated:
+            The node's deprecation, if it is deprecated.
+        :param platforms:
+            The platforms the node is available on, from `PLATFORMS`, defaults
+            to all platforms.
+        :param experimental:
+            Whether the node is experimental, in which case it's hidden and
+            rejected unless experimental commands are enabled.
+        :param requires_role:
+            The role, or any of the roles, the user must have for the node to
+            be shown and its commands run, see `find_unauthorized()`.
+        """
+        _check_platforms(platforms)
+        if isinstance(requires_role, str):
+            requires_role = [requires_role]
+        if requires_role is not None and (
+            not requires_role
+            or not all(isinstance(x, str) and x for x in requires_role)
+        ):
+            raise ValueError(
+                "Invalid 'requires_role' value {!r}, expected a role name or a "
+                "non-empty list of role names".format(requires_role)
+            )
+        kwargs["keyword"] = keyword
+        super().__init__(**kwargs)
+        self.aliases = aliases if aliases else []
+        self.hidden_aliases = hidden_aliases if hidden_aliases else []
+        self.hidden = hidden or experimental
+        self.deprecated = deprecated
+        self.platforms = platforms
+        self.experimental = experimental
+        self.requires_role = requires_role
+        # Set for nodes of external plugin commands, see `add_plugin_nodes()`.
+        self.plugin_path = None  # type: Optional[pathlib.Path]
+
+    @property
+    def is_available(self) -> bool:
+        """Whether the node is available on the current platform."""
+        return _is_platform_available(self.platforms)
+
+    @property
+    def all_aliases(self) -> List[str]:
+        """All alternative keywords for the node, including hidden ones."""
+        return self.aliases + self.hidden_aliases
+
+    @classmethod
+    def _process_fields(
+        cls, kwargs: Dict[str, typing.Any], *, location: str
+    ) -> Dict[str, typing.Any]:
+        kwargs = super()._process_fields(kwargs, location=location)
+        aliases = []
+        hidden_aliases = []
+        for i, x in enumerate(kwargs.pop("aliases", [])):
+            alias_location = "{}: aliases[{}]".format(location, i)
+            if isinstance(x, str):
+                aliases.append(x)
+                continue
+            _check_fields(
+                x,
+                {"name": ((str,), True), "hidden": ((bool,), False)},
+                location=_format_location(alias_location, _get_span(x)),
+            )
+            if x.get("hidden", False):
+                hidden_aliases.append(x["name"])
+            else:
+                aliases.append(x["name"])
+        kwargs["aliases"] = aliases
+        kwargs["hidden_aliases"] = hidden_aliases
+        try:
+            _check_platforms(kwargs.get("platforms"))
+        except ValueError as e:
+            raise SchemaError(str(e), location=location) from None
+        if "deprecated" in kwargs:
+            kwargs["deprecated"] = Deprecation.from_value(
+                kwargs["deprecated"], location=location + ": deprecated"
+            )
+        return kwargs
+
+    def to_dict(self) -> Dict[str, typing.Any]:
+        data = super().to_dict()
+        data["aliases"] += [{"name": x, "hidden": True} for x in self.hidden_aliases]
+        return data
+
+    def __repr__(self):
+        return "<SubNode({})>".format(".".join(self.path))
+
+
+class SchemaFormat(enum.Enum):
+    """File formats a schema may be written in."""
+
+    YAML = enum.auto()
+    JSON = enum.auto()
+    TOML = enum.auto()
+
+    @classmethod
+    def from_path(cls, path: PathLike) -> "SchemaFormat":
+        """
+        Determine the format of a schema file from its extension.
+
+        :param path:
+            The path to the schema file.
+        :return:
+            The schema format.
+        :raises SchemaError:
+            If the extension is not recognised.
+        """
+        suffix = pathlib.Path(path).suffix.lower()
+        for fmt, suffixes in _FORMAT_SUFFIXES.items():
+            if suffix in suffixes:
+                return fmt
+        raise SchemaError(
+            "Unable to determine the format of CLI schema file {}, expected one of "
+            "the extensions: {}".format(
+                path, ", ".join(x for s in _FORMAT_SUFFIXES.values() for x in s)
+            )
+        )
+
+    def loads(self, text: str) -> typing.Any:
+        """
+        Parse the text of a schema file in this format.
+
+        :param text:
+            The text to parse.
+        :return:
+            The loaded data.
+        :raises ValueError:
+            If the text is not valid for the format.
+        """
+        if self is self.YAML:
+            return _load_yaml(text, anchors={})[0]
+        elif self is self.JSON:
+            return json.loads(text)
+        elif self is self.TOML:
+            try:
+                import tomllib as toml_lib
+            except ImportError:
+                try:
+                    import toml as toml_lib
+                except ImportError:
+                    raise SchemaError(
+                        "Loading TOML requires Python3.11+ or the 'toml' package"
+                    ) from None
+            try:
+                return toml_lib.loads(text)
+            except Exception as e:
+                raise ValueError(str(e)) from e
+        else:
+            assert False
+
+
+_FORMAT_SUFFIXES = {
+    SchemaFormat.YAML: (".yaml", ".yml"),
+    SchemaFormat.JSON: (".json",),
+    SchemaFormat.TOML: (".toml",),
+}
+
+
+def find_schema_file() -> pathlib.Path:
+    """
+    Find a schema file in one of the default locations.
+
+    The locations checked, in order, are the current working directory and the
+    directory containing the script being run, and the filenames checked are
+    given by `DEFAULT_SCHEMA_FILENAMES`.
+
+    :return:
+        The path to the first schema file found.
+    :raises SchemaError:
+        If no schema file is found.
+    """
+    dirs = [pathlib.Path.cwd()]
+    if sys.argv and sys.argv[0]:
+        script_dir = pathlib.Path(sys.argv[0]).resolve().parent
+        if script_dir not in dirs:
+            dirs.append(script_dir)
+    candidates = [d / name for d in dirs for name in DEFAULT_SCHEMA_FILENAMES]
+    for path in candidates:
+        if path.is_file():
+            return path
+    raise SchemaError(
+        "No CLI schema file found, looked for: {}".format(
+            ", ".join(str(p) for p in candidates)
+        )
+    )
+
+
+_INCLUDE_KEY = "include"
+
+_SUBTREE_FILE_KEY = "subtree_file"
+
+
+def _include_path(base_dir: pathlib.Path, path: str) -> pathlib.Path:
+    """Resolve the path given in an include directive."""
+    return (base_dir / pathlib.Path(path).expanduser()).resolve()
+
+
+def _get_include_paths(
+    data: typing.Any, *, base_dir: pathlib.Path, location: str
+) -> List[pathlib.Path]:
+    """
+    Find the paths of the files included by loaded schema data, in order.
+
+    :raises SchemaError:
+        If an include directive is invalid.
+    """
+    paths = []
+    if isinstance(data, dict):
+        for key, value in data.items():
+            if key != _INCLUDE_KEY:
+                paths += _get_include_paths(value, base_dir=base_dir, location=location)
+                continue
+            if isinstance(value, str):
+                value = [value]
+            if not isinstance(value, list) or not all(
+                isinstance(x, str) for x in value
+            ):
+                raise SchemaError(
+                    "key 'include' should be a path or a list of paths, got "
+                    "{!r}".format(data[key]),
+                    location=_format_location(location, _get_span(data)),
+                )
+            paths += [_include_path(base_dir, x) for x in value]
+    elif isinstance(data, list):
+        for x in data:
+            paths += _get_include_paths(x, base_dir=base_dir, location=location)
+    return paths
+
+
+def _resolve_includes(
+    data: typing.Any,
+    included: Dict[pathlib.Path, typing.Any],
+    *,
+    base_dir: pathlib.Path
+) -> typing.Any:
+    """
+    Replace include directives in loaded schema data with the included data.
+
+    The mappings from included files are merged in the order given, and keys
+    alongside the directive take precedence.
+    """
+    if isinstance(data, list):
+        return [_resolve_includes(x, included, base_dir=base_dir) for x in data]
+    elif not isinstance(data, dict):
+        return data
+    result = _MarkedDict()
+    result.lineno = _get_lineno(data)
+    result.column = getattr(data, "column", None)
+    result.file = getattr(data, "file", None)
+    if _INCLUDE_KEY in data:
+        paths = data[_INCLUDE_KEY]
+        for x in [paths] if isinstance(paths, str) else paths:
+            result.update(included[_include_path(base_dir, x)])
+    for key, value in data.items():
+        if key == _SUBTREE_FILE_KEY and isinstance(value, str):
+            # Resolved relative to the file it's given in, for loading later.
+            result[key] = str(_include_path(base_dir, value))
+        elif key != _INCLUDE_KEY:
+            result[key] = _resolve_includes(value, included, base_dir=base_dir)
+    return result
+
+
+def _load_schema_file(
+    path: pathlib.Path,
+    format_: Optional[SchemaFormat],
+    *,
+    stack: typing.Tuple[pathlib.Path, ...],
+    sources: Optional[Dict[str, str]] = None
+) -> typing.Tuple[typing.Any, Dict[str, yaml.Node]]:
+    """
+    Load the data from a schema file, resolving any include directives.
+
+    :param path:
+        The path to the schema file.
+    :param format_:
+        The format of the file, or None to determine it from the extension.
+    :param stack:
+        The resolved paths of the files being loaded, for detecting cycles.
+    :param sources:
+        Mapping to add the path and content hash of each file read to.
+    :return:
+        The loaded data, and the YAML anchors defined in the file and the
+        files it includes.
+    :raises SchemaError:
+        If the file or an included file can't be read or parsed.
+    """
+    if format_ is None:
+        format_ = SchemaFormat.from_path(path)
+    try:
+        with open(path) as f:
+            text = f.read()
+    except FileNotFoundError:
+        raise SchemaError("CLI schema file not found", location=str(path)) from None
+    except OSError as e:
+        raise SchemaError(
+            "Unable to read CLI schema file: {}".format(
+                e.strerror or e.__class__.__name__
+            ),
+            location=str(path),
+        ) from e
+    if sources is not None:
+        sources[str(path)] = _hash_text(text)
+
+    def parse(anchors: Optional[Dict[str, yaml.Node]]):
+        try:
+            if format_ is SchemaFormat.YAML:
+                return _load_yaml(
+                    text, anchors=anchors if anchors else {}, lenient=anchors is None
+                )
+            return format_.loads(text), {}, False
+        except ValueError as e:
+            raise SchemaError(
+                "Invalid {} in CLI schema file: {}".format(format_.name, e),
+                location=str(path),
+            ) from None
+
+    # The file is first parsed without the anchors from included files, and
+    # only parsed again with them if it has aliases that weren't defined.
+    data, own_anchors, found_undefined = parse(None)
+    included = {}  # type: Dict[pathlib.Path, typing.Any]
+    anchors = {}  # type: Dict[str, yaml.Node]
+    for include_path in _get_include_paths(
+        data, base_dir=path.parent, location=str(path)
+    ):
+        if include_path in stack:
+            raise SchemaError(
+                "Include cycle: {}".format(
+                    " -> ".join(str(p) for p in stack + (include_path,))
+                ),
+                location=str(path),
+            )
+        if include_path not in included:
+            included[include_path], file_anchors = _load_schema_file(
+                include_path, None, stack=stack + (include_path,), sources=sources
+            )
+            anchors.update(file_anchors)
+    if found_undefined:
+        data, anchors, _ = parse(anchors)
+    else:
+        anchors.update(own_anchors)
+    _mark_file(data, str(path))
+    if not isinstance(data, dict):
+        raise SchemaError(
+            "Expected a mapping at the top level of CLI schema file",
+            location=str(path),
+        )
+    return _resolve_includes(data, included, base_dir=path.parent), anchors
+
+
+# Bumped when the format of cached schemas changes, invalidating caches.
+_CACHE_FORMAT = 1
+
+
+def _hash_text(text: str) -> str:
+    return hashlib.sha256(text.encode("utf-8", errors="surrogateescape")).hexdigest()
+
+
+def _get_cache_path(
+    cache_dir: pathlib.Path, path: pathlib.Path, format_: Optional[SchemaFormat]
+) -> pathlib.Path:
+    """
+    Get the path of the cache file for a schema, keyed by everything the
+    loaded schema depends on other than the content of the files.
+    """
+    key = "\0".join(
+        [
+            str(_CACHE_FORMAT),
+            str(SCHEMA_VERSION),
+            # The pickled classes may change between versions of the package.
+            __version__,
+            sys.version,
+            sys.platform,
+            _get_env_lang() or "",
+            str(path.resolve()),
+            format_.name if format_ else "",
+        ]
+    )
+    return cache_dir / "schema-{}.pickle".format(_hash_text(key)[:32])
+
+
+def _is_cache_valid(sources: Dict[str, str]) -> bool:
+    """Whether the files a cached schema was loaded from are unchanged."""
+    for path, digest in sources.items():
+        try:
+            with open(path) as f:
+                if _hash_text(f.read()) != digest:
+                    return False
+        except (OSError, ValueError):
+            return False
+    return True
+
+
+def _read_cache(cache_path: pathlib.Path) -> Optional[RootNode]:
+    try:
+        with open(str(cache_path), "rb") as f:
+            sources, schema = pickle.load(f)
+        if not isinstance(schema, RootNode) or not _is_cache_valid(sources):
+            return None
+    except Exception:
+        # A missing, unreadable or corrupt cache is a cache miss, as is one
+        # that can't be unpickled with the current classes.
+        return None
+    return schema
+
+
+def _write_cache(
+    cache_path: pathlib.Path, sources: Dict[str, str], schema: RootNode
+) -> None:
+    """Write a schema to the cache, ignoring any failure to do so."""
+    try:
+        cache_path.parent.mkdir(parents=True, exist_ok=True)
+        fd, tmp_path = tempfile.mkstemp(dir=str(cache_path.parent), suffix=".tmp")
+        with os.fdopen(fd, "wb") as f:
+            pickle.dump((sources, schema), f, protocol=pickle.HIGHEST_PROTOCOL)
+        os.replace(tmp_path, str(cache_path))
+    except Exception:
+        pass
+
+
+def load_from_path(
+    path: Optional[PathLike] = None,
+    *,
+    format_: Optional[SchemaFormat] = None,
+    cache_dir: Optional[PathLike] = None
+) -> RootNode:
+    """
+    Load a CLI schema from a YAML, JSON or TOML file.
+
+    Any mapping in the schema may contain an 'include' key giving the path of a
+    file (or a list of paths), relative to the including file, whose top-level
+    mapping is merged into it. Keys given alongside the 'include' take
+    precedence. YAML files may also use anchors defined in the files they
+    include.
+
+    Large schemas can be cached to skip parsing and validating them at
+    startup. The loaded schema is stored in the cache directory in a binary
+    form, which is used for as long as the schema file and the files it
+    includes are unchanged (by content hash). The cache directory must only be
+    writable by trusted users, since the cache files are unpickled.
+
+    :param path:
+        The path to the schema file, or None to search the default locations
+        (see `find_schema_file()`).
+    :param format_:
+        The format of the schema file, or None to determine it from the file
+        extension.
+    :param cache_dir:
+        The directory to cache the loaded schema in, defaults to no caching.
+    :return:
+        The loaded schema.
+    :raises SchemaError:
+        If the file can't be found, read or parsed.
+    """
+    if path is None:
+        path = find_schema_file()
+    path = pathlib.Path(path)
+    cache_path = None
+    if cache_dir is not None:
+        cache_path = _get_cache_path(pathlib.Path(cache_dir), path, format_)
+        schema = _read_cache(cache_path)
+        if schema is not None:
+            return schema
+    sources = {}  # type: Dict[str, str]
+    data, _ = _load_schema_file(path, format_, stack=(path.resolve(),), sources=sources)
+    schema = RootNode.from_dict(data)
+    if cache_path is not None:
+        _write_cache(cache_path, sources, schema)
+    return schema
+
+
+def migrate(path: PathLike, *, format_: Optional[SchemaFormat] = None) -> bool:
+    """
+    Rewrite a schema file in an older version of the format to the current
+    version, see `SCHEMA_VERSION`.
+
+    Only the given file is rewritten, not the files it includes, and comments
+    and formatting are not preserved. TOML files can't be rewritten.
+
+    :param path:
+        The path to the schema file.
+    :param format_:
+        The format of the schema file, or None to determine it from the file
+        extension.
+    :return:
+        Whether the file needed migrating.
+    :raises SchemaError:
+        If the file can't be read, parsed or written.
+    """
+    path = pathlib.Path(path)
+    if format_ is None:
+        format_ = SchemaFormat.from_path(path)
+    try:
+        text = path.read_text()
+    except OSError as e:
+        raise SchemaError(
+            "Unable to read CLI schema file: {}".format(
+                e.strerror or e.__class__.__name__
+            ),
+            location=str(path),
+        ) from e
+    try:
+        if format_ is SchemaFormat.YAML:
+            data, _, _ = _load_yaml(text, anchors={}, lenient=True)
+        else:
+            data = format_.loads(text)
+    except ValueError as e:
+        raise SchemaError(
+            "Invalid {} in CLI schema file: {}".format(format_.name, e),
+            location=str(path),
+        ) from None
+    if not isinstance(data, dict):
+        raise SchemaError(
+            "Expected a mapping at the top level of CLI schema file",
+            location=str(path),
+        )
+    if _upgrade_schema_data(data, location=str(path)) == SCHEMA_VERSION:
+        return False
+    if format_ is SchemaFormat.YAML:
+        text = yaml.safe_dump(_to_plain(data), sort_keys=False, allow_unicode=True)
+    elif format_ is SchemaFormat.JSON:
+        text = json.dumps(_to_plain(data), indent=2) + "\n"
+    else:
+        raise SchemaError(
+            "Migrating {} schema files is not supported".format(format_.name),
+            location=str(path),
+        )
+    try:
+        path.write_text(text)
+    except OSError as e:
+        raise SchemaError(
+            "Unable to write CLI schema file: {}".format(
+                e.strerror or e.__class__.__name__
+            ),
+            location=str(path),
+        ) from e
+    return True
+
+
+def json_schema() -> Dict[str, typing.Any]:
+    """
+    Get the JSON Schema describing the structure of CLI schema files.
+
+    This can be used by editors to offer completion and validation when
+    writing a schema file, or by other tools to check schemas.
+
+    :return:
+        The loaded JSON Schema.
+    """
+    with open(pathlib.Path(__file__).parent / "cli-schema.json") as f:
+        return json.load(f)
+
+
+def validate(
+    schema: RootNode,
+    *,
+    commands: Optional[Iterable[str]] = None,
+    validators: Optional[Iterable[str]] = None
+) -> List[str]:
+    """
+    Check a loaded schema for problems.
+
+    The checks made are for duplicate keywords or aliases in a subtree,
+    duplicate arg names or option strings on a node (including clashes with
+    global args), commands run by more than one node (other than external
+    commands, which don't need a handler), missing help strings, help
+    placeholders the arg has no metadata for, configurable args without config
+    settings, shell aliases that don't run a command and, if the available
+    commands or validators are given, commands or validators that don't exist.
+    The schemas of any 'binaries' are checked too.
+
+    :param schema:
+        The schema to check.
+    :param commands:
+        The command names that can be used in the schema, or None to skip
+        checking command references.
+    :param validators:
+        The validator names that can be used in the schema, or None to skip
+        checking validator references.
+    :return:
+        A list of problems found, empty if there are none.
+    """
+    if commands is not None:
+        commands = set(commands)
+    if validators is not None:
+        validators = set(validators)
+    problems = []
+    # The location of the first node running each command.
+    command_locations = {}  # type: Dict[str, str]
+
+    def add_problem(location: str, span: Optional[SourceSpan], msg: str):
+        problems.append("{}: {}".format(_format_location(location, span), msg))
+
+    for node in schema.walk():
+        location = ".".join(node.path) if node.keyword else "root"
+        if not node.help.strip():
+            add_problem(location, node.span, "empty help string")
+        if node.command and node.external_command is None:
+            if node.command in command_locations:
+                add_problem(
+                    location,
+                    node.span,
+                    "duplicate command {!r}, also run by {!r}".format(
+                        node.command, command_locations[node.command]
+                    ),
+                )
+            command_locations.setdefault(node.command, location)
+        if (
+            commands is not None
+            and node.command
+            and node.external_command is None
+            and node.command not in commands
+        ):
+            add_problem(
+                location, node.span, "unknown command {!r}".format(node.command)
+            )
+        if (
+            node.default is not None
+            and not node.shows_help_by_default
+            and node.get_default_subnode() is None
+        ):
+            add_problem(
+                location,
+                node.span,
+                "unknown default subcommand {!r}".format(node.default),
+            )
+        if node.command:
+            arg_names = {a.name for a in node.all_args}
+            templates = [node.get_cwd()] + list(node.get_env().values())
+            templates += node.external_command or []
+            signals = node.get_signals()
+            if signals is not None:
+                templates += signals.cleanup or []
+            for template in [t for t in templates if t is not None]:
+                for name in get_arg_references(template):
+                    if name not in arg_names:
+                        add_problem(
+                            location,
+                            node.span,
+                            "unknown arg {!r} referenced in 'command', 'cwd', "
+                            "'env' or 'signals'".format(name),
+                        )
+        keywords = set()
+        for subnode in node.subtree:
+            for keyword in [subnode.keyword] + subnode.all_aliases:
+                if keyword in keywords:
+                    add_problem(
+                        ".".join(subnode.path),
+                        subnode.span,
+                        "duplicate keyword {!r}".format(keyword),
+                    )
+                keywords.add(keyword)
+        names = set()
+        option_strings = set()
+        for arg in node.all_args:
+            if arg not in node.args:
+                if node is not schema:
+                    # Global args only need checking once, at the root.
+                    names.add(arg.dest)
+                    option_strings.update(arg.option_strings)
+                    if arg.file_option:
+                        option_strings.add(arg.file_option_string)
+                    continue
+                arg_location = "{}: global arg {!r}".format(location, arg.name)
+            else:
+                arg_location = "{}: arg {!r}".format(location, arg.name)
+            if not arg.help.strip():
+                add_problem(arg_location, arg.span, "empty help string")
+            for placeholder, field in (("env", "env"), ("choices", "enum")):
+                if placeholder in arg.help_placeholders and not getattr(arg, field):
+                    add_problem(
+                        arg_location,
+                        arg.span,
+                        "help uses {{{}}} but the arg has no {!r}".format(
+                            placeholder, field
+                        ),
+                    )
+            if arg.configurable and schema.config is None:
+                add_problem(
+                    arg_location,
+                    arg.span,
+                    "configurable arg but no 'config' settings in the schema",
+                )
+            if (
+                validators is not None
+                and arg.validator is not None
+                and arg.validator not in validators
+            ):
+                add_problem(
+                    arg_location,
+                    arg.span,
+                    "unknown validator {!r}".format(arg.validator),
+                )
+            if arg.dest in names:
+                add_problem(arg_location, arg.span, "duplicate arg name")
+            names.add(arg.dest)
+            for opt in arg.option_strings + [arg.file_option_string]:
+                if opt is None:
+                    continue
+                if opt in option_strings:
+                    add_problem(
+                        arg_location, arg.span, "conflicting option {!r}".format(opt)
+                    )
+                option_strings.add(opt)
+    for name in schema.aliases_script:
+        node = schema.get_alias_node(name)
+        if node.command is None and node.default is None:
+            add_problem(
+                "root: aliases_script",
+                schema.span,
+                "shell alias {!r} doesn't run a command".format(name),
+            )
+    for name, binary in schema.binaries.items():
+        problems += [
+            "binaries: {}: {}".format(name, p)
+            for p in validate(binary, commands=commands, validators=validators)
+        ]
+    return problems
+
+
+def check_schema(
+    schema: RootNode,
+    *,
+    commands: Optional[Iterable[str]] = None,
+    validators: Optional[Iterable[str]] = None
+) -> None:
+    """
+    Check a loaded schema for problems, raising an error if any are found.
+
+    :param schema:
+        The schema to check.
+    :param commands:
+        The command names that can be used in the schema, as for `validate()`.
+    :param validators:
+        The validator names that can be used in the schema, as for
+        `validate()`.
+    :raises SchemaValidationError:
+        If there are problems with the schema.
+    """
+    problems = validate(schema, commands=commands, validators=validators)
+    if problems:
+        raise SchemaValidationError(problems)
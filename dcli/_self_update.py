@@ -0,0 +1,335 @@
+"""
+The built-in handler for the 'self update' subcommand, see `SelfUpdateSpec`.
+
+Updating is intended for applications distributed as a single executable file,
+e.g. a zipapp or a frozen binary. Downloads are always checked against their
+published SHA-256 checksum before the executable is replaced, and are only
+made over HTTPS unless signatures are checked.
+
+"""
+
+__all__ = ("Release", "find_latest_release", "self_update", "self_update_handler")
+
+import hashlib
+import json
+import os
+import platform
+import re
+import shutil
+import subprocess
+import sys
+import tempfile
+import urllib.error
+import urllib.request
+import zipfile
+from typing import Any, Callable, NamedTuple, Optional, Tuple
+
+from ._errors import CLIError
+from ._schema import RootNode, SelfUpdateSpec
+from .clis import Namespace
+
+
+_GITHUB_API_URL = "https://api.github.com/repos/{}/releases/latest"
+
+_OS_NAMES = {"darwin": "macos", "win32": "windows"}
+
+_ARCH_NAMES = {"amd64": "x86_64", "x64": "x86_64", "arm64": "aarch64"}
+
+# The number of seconds to wait for a response when downloading.
+_DOWNLOAD_TIMEOUT = 30
+
+# The order of pre-release phases, e.g. in '2.0.0rc1', which all come before
+# the final release.
+_PRE_RELEASE_PHASES = {
+    "dev": 0,
+    "a": 1,
+    "alpha": 1,
+    "b": 2,
+    "beta": 2,
+    "c": 3,
+    "pre": 3,
+    "preview": 3,
+    "rc": 3,
+}
+
+
+class Release(
+    NamedTuple(
+        "Release",
+        [
+            ("version", str),
+            ("url", str),
+            ("sha256", str),
+            ("signature_url", Optional[str]),
+        ],
+    )
+):
+    """The latest release of an application, as published."""
+
+    __slots__ = ()
+
+
+def _update_error(message: str) -> CLIError:
+    return CLIError(message, category="update")
+
+
+def _fetch(
+    url: str, *, accept: Optional[str] = None, allow_http: bool = False
+) -> bytes:
+    """
+    Download the contents of a URL.
+
+    :param allow_http:
+        Whether to allow plain HTTP URLs, e.g. when the download's signature
+        is checked.
+    :raises CLIError:
+        If the URL isn't allowed or the download fails.
+    """
+    if not url.startswith("https://") and not (
+        allow_http and url.startswith("http://")
+    ):
+        raise _update_error("Refusing to download from non-HTTPS URL {}".format(url))
+    headers = {"User-Agent": "dcli-self-update"}
+    if accept:
+        headers["Accept"] = accept
+    if url.startswith("https://api.github.com/") and os.environ.get("GITHUB_TOKEN"):
+        headers["Authorization"] = "Bearer " + os.environ["GITHUB_TOKEN"]
+    try:
+        request = urllib.request.Request(url, headers=headers)
+        with urllib.request.urlopen(request, timeout=_DOWNLOAD_TIMEOUT) as f:
+            return f.read()
+    except (urllib.error.URLError, OSError) as e:
+        raise _update_error("Failed to download {}: {}".format(url, e)) from None
+
+
+def _fetch_json(url: str, **kwargs) -> Any:
+    try:
+        return json.loads(_fetch(url, **kwargs).decode("utf-8"))
+    except ValueError:
+        raise _update_error("Invalid JSON downloaded from {}".format(url)) from None
+
+
+def _parse_checksums(text: str, name: str) -> Optional[str]:
+    """Find the checksum of a file in 'sha256sum' output."""
+    for line in text.splitlines():
+        match = re.fullmatch(r"([0-9a-fA-F]{64}) [ *](.+)", line.strip())
+        if match and match.group(2) == name:
+            return match.group(1).lower()
+    return None
+
+
+def _format_asset_name(template: str, version: str) -> str:
+    os_name = _OS_NAMES.get(sys.platform, sys.platform.rstrip("0123456789"))
+    arch = platform.machine().lower()
+    return template.format(
+        version=version, os=os_name, arch=_ARCH_NAMES.get(arch, arch)
+    )
+
+
+def _find_github_release(spec: SelfUpdateSpec) -> Release:
+    data = _fetch_json(
+        _GITHUB_API_URL.format(spec.github), accept="application/vnd.github+json"
+    )
+    try:
+        version = data["tag_name"].lstrip("v")
+        assets = {a["name"]: a["browser_download_url"] for a in data["assets"]}
+    except (KeyError, TypeError):
+        raise _update_error(
+            "Unexpected response for the latest release of {}".format(spec.github)
+        ) from None
+    name = _format_asset_name(spec.asset, version)
+    if name not in assets:
+        raise _update_error("No release asset {!r} for this platform".format(name))
+    if spec.checksums not in assets:
+        raise _update_error("No {!r} release asset".format(spec.checksums))
+    text = _fetch(assets[spec.checksums], allow_http=spec.signature).decode(
+        "utf-8", errors="replace"
+    )
+    sha256 = _parse_checksums(text, name)
+    if sha256 is None:
+        raise _update_error("No checksum for {!r} in {}".format(name, spec.checksums))
+    signature_url = assets.get(name + ".asc")
+    if spec.signature and signature_url is None:
+        raise _update_error("No signature {!r} release asset".format(name + ".asc"))
+    return Release(version, assets[name], sha256, signature_url)
+
+
+def _find_manifest_release(spec: SelfUpdateSpec) -> Release:
+    data = _fetch_json(spec.url, allow_http=spec.signature)
+    try:
+        release = Release(
+            data["version"].lstrip("v"),
+            data["url"],
+            data["sha256"].lower(),
+            data.get("signature_url"),
+        )
+    except (KeyError, TypeError, AttributeError):
+        raise _update_error(
+            "Invalid release manifest {}, expected 'version', 'url' and 'sha256' "
+            "keys".format(spec.url)
+        ) from None
+    if spec.signature and release.signature_url is None:
+        raise _update_error("No 'signature_url' in release manifest")
+    return release
+
+
+def find_latest_release(spec: SelfUpdateSpec) -> Release:
+    """
+    Find the latest release of the application.
+
+    :param spec:
+        The schema's self update settings.
+    :return:
+        The release for the current platform.
+    :raises CLIError:
+        If the release can't be found, e.g. due to a network error.
+    """
+    if spec.github is not None:
+        return _find_github_release(spec)
+    return _find_manifest_release(spec)
+
+
+def _version_key(version: str) -> Tuple[Tuple[int, ...], Tuple[int, ...]]:
+    """
+    Key for ordering release versions, where pre-releases such as '2.0.0rc1'
+    or '2.0.0-beta.2' come before the final release and post-releases after.
+    """
+    # Build metadata, e.g. '+build.5', doesn't affect the order.
+    version = version.split("+", 1)[0].strip().lower()
+    match = re.match(r"v?(\d+(?:\.\d+)*)[.\-_]?(.*)", version)
+    if match is None:
+        return (), ()
+    numbers = [int(x) for x in match.group(1).split(".")]
+    # Versions with trailing zeros are the same, e.g. '1.0' and '1.0.0'.
+    while len(numbers) > 1 and numbers[-1] == 0:
+        numbers.pop()
+    suffix = re.match(r"([a-z]*)[.\-_]?(\d*)", match.group(2))
+    phase, number = suffix.group(1), int(suffix.group(2) or 0)
+    if not match.group(2):
+        return tuple(numbers), (1,)
+    if phase in ("post", "r", "rev"):
+        return tuple(numbers), (2, number)
+    return tuple(numbers), (0, _PRE_RELEASE_PHASES.get(phase, 0), number)
+
+
+def _verify_signature(path: str, signature_url: str) -> None:
+    """
+    :raises CLIError:
+        If the signature is invalid or 'gpg' isn't available.
+    """
+    if shutil.which("gpg") is None:
+        raise _update_error("Unable to verify signature, 'gpg' not found")
+    signature_path = path + ".asc"
+    with open(signature_path, "wb") as f:
+        f.write(_fetch(signature_url, allow_http=True))
+    try:
+        result = subprocess.run(
+            ["gpg", "--batch", "--verify", signature_path, path],
+            stdout=subprocess.PIPE,
+            stderr=subprocess.STDOUT,
+        )
+    finally:
+        os.remove(signature_path)
+    if result.returncode != 0:
+        raise _update_error(
+            "Invalid signature for downloaded release:\n{}".format(
+                result.stdout.decode(errors="replace").strip()
+            )
+        )
+
+
+def _get_executable() -> str:
+    """
+    The path of the running application's executable, which must be a frozen
+    binary or a zipapp.
+
+    :raises CLIError:
+        If the application isn't run from a single executable file, e.g. when
+        run as a script or a module, which mustn't be overwritten.
+    """
+    if getattr(sys, "frozen", False):
+        return sys.executable
+    path = os.path.realpath(sys.argv[0])
+    if not os.path.isfile(path) or not zipfile.is_zipfile(path):
+        raise _update_error(
+            "Unable to update {}, since it isn't a frozen executable or a "
+            "zipapp".format(path)
+        )
+    return path
+
+
+def _replace_executable(target: str, release: Release, spec: SelfUpdateSpec) -> None:
+    """
+    Download a release and replace the executable with it, after checking
+    its checksum (and signature if required).
+    """
+    data = _fetch(release.url, allow_http=spec.signature)
+    if hashlib.sha256(data).hexdigest() != release.sha256:
+        raise _update_error("Checksum mismatch for downloaded release")
+    directory = os.path.dirname(target)
+    fd, tmp_path = tempfile.mkstemp(dir=directory, prefix=".update-")
+    try:
+        with os.fdopen(fd, "wb") as f:
+            f.write(data)
+        if spec.signature:
+            _verify_signature(tmp_path, release.signature_url)
+        os.chmod(tmp_path, os.stat(target).st_mode)
+        if os.name == "nt":
+            # A running executable can't be replaced on Windows, but it can be
+            # renamed out of the way.
+            os.replace(target, target + ".old")
+        os.replace(tmp_path, target)
+    except OSError as e:
+        raise _update_error("Failed to replace {}: {}".format(target, e)) from None
+    finally:
+        if os.path.exists(tmp_path):
+            os.remove(tmp_path)
+
+
+def self_update(
+    schema: RootNode, *, dry_run: bool = False, target: Optional[str] = None
+) -> Optional[str]:
+    """
+    Update the application's executable to the latest release, if newer.
+
+    :param schema:
+        The CLI schema, which must have 'self_update' settings.
+    :param dry_run:
+        Whether to only check whether there's an update.
+    :param target:
+        The executable to replace, defaults to the running executable.
+    :return:
+        The version of the newer release, or None if already up to date.
+    :raises CLIError:
+        With category 'update' if updating fails.
+    """
+    spec = schema.self_update
+    current = schema.version.get_number()
+    release = find_latest_release(spec)
+    if _version_key(release.version) <= _version_key(current):
+        return None
+    if not dry_run:
+        _replace_executable(target or _get_executable(), release, spec)
+    return release.version
+
+
+def self_update_handler(schema: RootNode) -> Callable[[Namespace], None]:
+    """
+    Create the handler for the 'self update' command, which reports progress
+    on stdout.
+
+    :param schema:
+        The CLI schema, which must have 'self_update' settings.
+    """
+
+    def handler(args: Namespace) -> None:
+        current = schema.version.get_number()
+        version = self_update(schema, dry_run=args.dry_run)
+        if version is None:
+            print("Already up to date ({})".format(current))
+        elif args.dry_run:
+            print("Update available: {} -> {}".format(current, version))
+        else:
+            print("Updated from {} to {}".format(current, version))
+
+    return handler
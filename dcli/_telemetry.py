@@ -0,0 +1,81 @@
+"""
+Opt-in usage metrics, reported to sinks registered by the application.
+
+Events are anonymised: they record which command was run and which args were
+given, but never the values of args.
+
+"""
+
+__all__ = ("Telemetry", "UsageEvent")
+
+import abc
+from typing import List, NamedTuple, Optional
+
+from ._schema import NodeBase
+from .clis import ArgSource, Namespace
+
+
+class UsageEvent(
+    NamedTuple(
+        "UsageEvent",
+        [
+            # The command name from the schema, or None if parsing failed.
+            ("command", Optional[str]),
+            # The keywords leading to the command's node, empty if unknown.
+            ("path", List[str]),
+            # The dests of the args given on the command line, sorted.
+            ("args", List[str]),
+            # The number of seconds taken by the handler, or by parsing if it
+            # failed.
+            ("duration", float),
+            # Either 'success', or the category of the error that occurred,
+            # e.g. 'usage', 'timeout' or 'error' (including for unexpected
+            # exceptions).
+            ("outcome", str),
+        ],
+    )
+):
+    """A record of a single invocation of a CLI."""
+
+    __slots__ = ()
+
+
+class Telemetry(abc.ABC):
+    """
+    Interface for a sink of usage events, e.g. for sending them to a metrics
+    service. Nothing is recorded unless a sink is registered with
+    `Dispatcher.add_telemetry()`.
+
+    Exceptions raised by sinks are ignored, so that metrics can never break
+    the CLI.
+    """
+
+    @abc.abstractmethod
+    def record(self, event: UsageEvent) -> None:
+        """
+        Record a usage event.
+
+        :param event:
+            The event, sent after each invocation has been parsed and
+            dispatched (or has failed to parse).
+        """
+
+
+def _given_args(namespace: Namespace) -> List[str]:
+    """The dests of the args given on the command line, sorted."""
+    sources = getattr(namespace, "arg_sources", {})
+    return sorted(
+        d for d, s in sources.items() if s in (ArgSource.CLI, ArgSource.FILE)
+    )
+
+
+def _make_event(
+    namespace: Namespace, node: Optional[NodeBase], duration: float, outcome: str
+) -> UsageEvent:
+    return UsageEvent(
+        command=getattr(namespace, "command", None),
+        path=node.path if node is not None else [],
+        args=_given_args(namespace),
+        duration=duration,
+        outcome=outcome,
+    )
@@ -0,0 +1,86 @@
+"""
+Interpolation of templates in arg defaults.
+
+Templates may contain '${NAME}' to substitute an environment variable,
+'${args.name}' to substitute the value of another arg of the same node, and
+'$$' for a literal '$'.
+
+"""
+
+__all__ = ("TemplateError", "expand_template", "get_arg_references", "is_template")
+
+import re
+from typing import Any, List, Mapping
+
+
+_REFERENCE_REGEX = re.compile(r"\$(?:\$|\{([^}]*)\})")
+
+_ARGS_PREFIX = "args."
+
+
+class TemplateError(Exception):
+    """Unable to expand a template."""
+
+
+def is_template(value: Any) -> bool:
+    """Whether a value is a string containing template references."""
+    return isinstance(value, str) and "$" in value
+
+
+def get_arg_references(template: str) -> List[str]:
+    """
+    Get the names of the args referenced by a template.
+
+    :param template:
+        The template string.
+    :return:
+        The referenced arg names, in order of appearance.
+    :raises TemplateError:
+        If the template contains an empty reference.
+    """
+    names = []
+    for match in _REFERENCE_REGEX.finditer(template):
+        ref = match.group(1)
+        if ref is None:
+            continue
+        if not ref.strip():
+            raise TemplateError("Empty reference in {!r}".format(template))
+        if ref.startswith(_ARGS_PREFIX):
+            names.append(ref[len(_ARGS_PREFIX) :])
+    return names
+
+
+def expand_template(
+    template: str, *, args: Mapping[str, Any], environ: Mapping[str, str]
+) -> str:
+    """
+    Expand the references in a template.
+
+    :param template:
+        The template string.
+    :param args:
+        Mapping of arg name to value, for '${args.name}' references.
+    :param environ:
+        The environment variables, for '${NAME}' references.
+    :return:
+        The expanded string.
+    :raises TemplateError:
+        If a referenced arg has no value or an environment variable is unset.
+    """
+
+    def replace(match) -> str:
+        ref = match.group(1)
+        if ref is None:
+            return "$"
+        if ref.startswith(_ARGS_PREFIX):
+            name = ref[len(_ARGS_PREFIX) :]
+            if args.get(name) is None:
+                raise TemplateError("referenced arg {!r} has no value".format(name))
+            return str(args[name])
+        if ref not in environ:
+            raise TemplateError(
+                "referenced environment variable {} is not set".format(ref)
+            )
+        return environ[ref]
+
+    return _REFERENCE_REGEX.sub(replace, template)
@@ -0,0 +1,299 @@
+"""
+Arg value types and conversion.
+
+"""
+
+__all__ = ("ArgType", "Secret", "parse_datetime", "parse_duration", "parse_size")
+
+import datetime
+import enum
+import pathlib
+import re
+import urllib.parse
+from typing import Any, Tuple
+
+
+_TRUE_STRINGS = ("1", "true", "yes", "on")
+_FALSE_STRINGS = ("", "0", "false", "no", "off")
+
+_DURATION_UNITS = {
+    "ms": 0.001,
+    "s": 1,
+    "m": 60,
+    "h": 60 * 60,
+    "d": 24 * 60 * 60,
+    "w": 7 * 24 * 60 * 60,
+}
+_DURATION_PART_REGEX = re.compile(
+    r"(\d+(?:\.\d*)?)({})".format("|".join(_DURATION_UNITS))
+)
+
+_DATETIME_REGEX = re.compile(
+    r"(\d{4})-(\d{2})-(\d{2})[Tt ](\d{2}):(\d{2}):(\d{2})(\.\d+)?"
+    r"(?:([Zz])|([+-])(\d{2}):(\d{2}))"
+)
+
+_SIZE_UNITS = {
+    "": 1,
+    "k": 1024,
+    "m": 1024 ** 2,
+    "g": 1024 ** 3,
+    "t": 1024 ** 4,
+    "p": 1024 ** 5,
+}
+_SIZE_REGEX = re.compile(
+    r"(\d+(?:\.\d*)?|\.\d+)\s*(?:([kmgtp])(?:i?b)?|b)?", re.IGNORECASE
+)
+
+
+def parse_duration(value: str) -> datetime.timedelta:
+    """
+    Parse a duration, e.g. '30s', '5m' or '2h15m'.
+
+    A plain number is taken to be a number of seconds.
+
+    :param value:
+        The string to parse.
+    :return:
+        The duration.
+    :raises ValueError:
+        If the string is not a valid duration.
+    """
+    value = value.strip().lower()
+    if not value:
+        raise ValueError("Invalid duration {!r}".format(value))
+    try:
+        return datetime.timedelta(seconds=float(value))
+    except ValueError:
+        pass
+    seconds = 0.0
+    pos = 0
+    while pos < len(value):
+        match = _DURATION_PART_REGEX.match(value, pos)
+        if not match:
+            raise ValueError("Invalid duration {!r}".format(value))
+        seconds += float(match.group(1)) * _DURATION_UNITS[match.group(2)]
+        pos = match.end()
+    return datetime.timedelta(seconds=seconds)
+
+
+def parse_datetime(value: str) -> datetime.datetime:
+    """
+    Parse an RFC 3339 timestamp, e.g. '2026-10-14T09:30:00Z' or
+    '2026-10-14 09:30:00.5+01:00'.
+
+    :param value:
+        The string to parse.
+    :return:
+        The timestamp, with its timezone set from the UTC offset.
+    :raises ValueError:
+        If the string is not a valid timestamp.
+    """
+    match = _DATETIME_REGEX.fullmatch(value.strip())
+    if not match:
+        raise ValueError("Invalid timestamp {!r}".format(value))
+    fields = [int(x) for x in match.group(1, 2, 3, 4, 5, 6)]
+    fraction = match.group(7)
+    microsecond = round(float(fraction) * 1000000) if fraction else 0
+    if match.group(8):
+        tz = datetime.timezone.utc
+    else:
+        offset = datetime.timedelta(
+            hours=int(match.group(10)), minutes=int(match.group(11))
+        )
+        tz = datetime.timezone(-offset if match.group(9) == "-" else offset)
+    try:
+        return datetime.datetime(*fields, min(microsecond, 999999), tzinfo=tz)
+    except ValueError:
+        raise ValueError("Invalid timestamp {!r}".format(value)) from None
+
+
+def _to_aware_datetime(value: datetime.datetime) -> datetime.datetime:
+    """Take a timestamp without a timezone (e.g. from a config file) as UTC."""
+    if value.tzinfo is None:
+        return value.replace(tzinfo=datetime.timezone.utc)
+    return value
+
+
+def parse_size(value: str) -> int:
+    """
+    Parse a size in bytes, e.g. '512', '10K', '1.5MiB' or '2GB'.
+
+    The unit suffixes are case insensitive and use powers of 1024, optionally
+    followed by 'B' or 'iB'.
+
+    :param value:
+        The string to parse.
+    :return:
+        The number of bytes.
+    :raises ValueError:
+        If the string is not a valid size.
+    """
+    match = _SIZE_REGEX.fullmatch(value.strip())
+    if not match:
+        raise ValueError("Invalid size {!r}".format(value))
+    size = float(match.group(1)) * _SIZE_UNITS[(match.group(2) or "").lower()]
+    if size != int(size):
+        raise ValueError("Invalid size {!r}, not a whole number of bytes".format(value))
+    return int(size)
+
+
+def _parse_boolean(value: str) -> bool:
+    if value.lower() in _TRUE_STRINGS:
+        return True
+    elif value.lower() in _FALSE_STRINGS:
+        return False
+    raise ValueError("Invalid boolean {!r}".format(value))
+
+
+def _parse_url(value: str) -> str:
+    parsed = urllib.parse.urlparse(value)
+    if not parsed.scheme or not (parsed.netloc or parsed.scheme == "file"):
+        raise ValueError("Invalid URL {!r}".format(value))
+    return value
+
+
+def _parse_key_value(value: str) -> Tuple[str, str]:
+    key, sep, item = value.partition("=")
+    if not sep or not key:
+        raise ValueError("Invalid key=value pair {!r}".format(value))
+    return key, item
+
+
+class Secret:
+    """
+    A secret value, e.g. a password or token, which is redacted when displayed
+    so that it doesn't end up in output or logs by accident.
+    """
+
+    __slots__ = ("_value",)
+
+    REDACTED = "********"
+
+    def __init__(self, value: str):
+        self._value = value
+
+    def reveal(self) -> str:
+        """Get the secret value."""
+        return self._value
+
+    def __repr__(self):
+        return "Secret({!r})".format(self.REDACTED)
+
+    def __str__(self):
+        return self.REDACTED
+
+    def __eq__(self, other):
+        if isinstance(other, Secret):
+            return self._value == other._value
+        return NotImplemented
+
+    def __hash__(self):
+        return hash(self._value)
+
+    def __bool__(self):
+        return bool(self._value)
+
+
+class ArgType(enum.Enum):
+    """The types of value an arg may take."""
+
+    STRING = "string"
+    INTEGER = "integer"
+    FLOAT = "float"
+    BOOLEAN = "boolean"
+    FLAG = "flag"
+    TEXT = "text"
+    PATH = "path"
+    URL = "url"
+    DURATION = "duration"
+    DATETIME = "datetime"
+    SIZE = "size"
+    MAP = "map"
+
+    @property
+    def description(self) -> str:
+        """Description of the expected values, e.g. 'an integer'."""
+        return _DESCRIPTIONS[self]
+
+    @property
+    def takes_value(self) -> bool:
+        """Whether an option of this type takes a value on the command line."""
+        return self is not ArgType.FLAG
+
+    def convert(self, value: Any) -> Any:
+        """
+        Convert a value to this type.
+
+        Strings are parsed, while values of the correct type (e.g. from a
+        config file or a default in the schema) are accepted as they are. Flag
+        values are booleans, text values are not converted, map values are
+        parsed into a (key, value) pair of strings, and timestamps without a
+        timezone are taken to be UTC.
+
+        :param value:
+            The value to convert.
+        :return:
+            The converted value.
+        :raises ValueError:
+            If the value is not valid for the type.
+        """
+        if self is ArgType.TEXT:
+            return value
+        if self is ArgType.DATETIME and isinstance(value, datetime.datetime):
+            return _to_aware_datetime(value)
+        python_type = _PYTHON_TYPES.get(self)
+        if python_type is not None and isinstance(value, python_type):
+            if not (isinstance(value, bool) and python_type is not bool):
+                return value
+        if isinstance(value, (int, float)) and not isinstance(value, bool):
+            if self is ArgType.FLOAT:
+                return float(value)
+            elif self is ArgType.DURATION:
+                return datetime.timedelta(seconds=value)
+            elif self is ArgType.SIZE and isinstance(value, int):
+                return value
+            value = str(value)
+        if not isinstance(value, str):
+            raise ValueError("Unexpected value {!r}".format(value))
+        return _CONVERTERS[self](value)
+
+
+_DESCRIPTIONS = {
+    ArgType.STRING: "a string",
+    ArgType.INTEGER: "an integer",
+    ArgType.FLOAT: "a number",
+    ArgType.BOOLEAN: "a boolean (true/false)",
+    ArgType.FLAG: "a boolean (true/false)",
+    ArgType.TEXT: "text",
+    ArgType.PATH: "a path",
+    ArgType.URL: "a URL",
+    ArgType.DURATION: "a duration (e.g. '30s', '2h15m')",
+    ArgType.DATETIME: "a timestamp (e.g. '2026-10-14T09:30:00Z')",
+    ArgType.SIZE: "a size (e.g. '512', '10K', '1.5MiB')",
+    ArgType.MAP: "a key=value pair",
+}
+
+_PYTHON_TYPES = {
+    ArgType.STRING: str,
+    ArgType.INTEGER: int,
+    ArgType.FLOAT: float,
+    ArgType.BOOLEAN: bool,
+    ArgType.FLAG: bool,
+    ArgType.PATH: pathlib.PurePath,
+    ArgType.DURATION: datetime.timedelta,
+}
+
+_CONVERTERS = {
+    ArgType.STRING: str,
+    ArgType.INTEGER: int,
+    ArgType.FLOAT: float,
+    ArgType.BOOLEAN: _parse_boolean,
+    ArgType.FLAG: _parse_boolean,
+    ArgType.PATH: pathlib.Path,
+    ArgType.URL: _parse_url,
+    ArgType.DURATION: parse_duration,
+    ArgType.DATETIME: parse_datetime,
+    ArgType.SIZE: parse_size,
+    ArgType.MAP: _parse_key_value,
+}
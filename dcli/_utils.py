@@ -5,8 +5,130 @@ Private utilities.
 
 """
 
+import difflib
+import os
 import pathlib
-from typing import Union
+import shlex
+from typing import Iterable, List, Optional, Tuple, Union
 
 
 PathLike = Union[str, pathlib.Path]
+
+
+def get_close_match(value: str, choices: Iterable[str]) -> Optional[str]:
+    """
+    Find the choice closest to a mistyped value, for a 'did you mean' hint.
+
+    :param value:
+        The mistyped value.
+    :param choices:
+        The valid choices.
+    :return:
+        The closest choice, or None if none are close enough.
+    """
+    matches = difflib.get_close_matches(value, list(choices), n=1)
+    return matches[0] if matches else None
+
+
+def extract_flag(args: List[str], option: str) -> Tuple[bool, List[str]]:
+    """
+    Extract a flag option from a list of args, e.g. '--enable-experimental'.
+
+    Args after a '--' are not considered.
+
+    :param args:
+        The args to search.
+    :param option:
+        The option string.
+    :return:
+        A tuple of whether the flag was given, and the remaining args.
+    """
+    end = args.index("--") if "--" in args else len(args)
+    remaining = [x for x in args[:end] if x != option]
+    return len(remaining) < end, remaining + args[end:]
+
+
+def extract_option(args: List[str], option: str) -> Tuple[Optional[str], List[str]]:
+    """
+    Extract an option taking a value from a list of args, e.g. '--config FILE'
+    or '--config=FILE'.
+
+    Args after a '--' are not considered. If the option is given multiple
+    times the last value is used.
+
+    :param args:
+        The args to search.
+    :param option:
+        The option string, e.g. '--config'.
+    :return:
+        A tuple of the option's value (or None if not given), and the
+        remaining args.
+    :raises ValueError:
+        If the option is given without a value.
+    """
+    value = None
+    remaining = []
+    i = 0
+    while i < len(args):
+        arg = args[i]
+        if arg == "--":
+            remaining += args[i:]
+            break
+        elif arg == option:
+            if i + 1 >= len(args):
+                raise ValueError("argument {}: expected one argument".format(option))
+            value = args[i + 1]
+            i += 1
+        elif arg.startswith(option + "="):
+            value = arg.split("=", 1)[1]
+        else:
+            remaining.append(arg)
+        i += 1
+    return value, remaining
+
+
+def expand_response_files(args: List[str], *, _seen: Tuple[str, ...] = ()) -> List[str]:
+    """
+    Expand '@file' args into the args read from the file.
+
+    Each line of a response file is split using shell quoting rules, and
+    '#' starts a comment. Response files may themselves contain '@file' args,
+    which are resolved relative to the current directory. Args after a '--'
+    are not expanded.
+
+    :param args:
+        The args to expand.
+    :return:
+        The expanded args.
+    :raises ValueError:
+        If a response file can't be read or includes itself.
+    """
+    expanded = []
+    for i, arg in enumerate(args):
+        if arg == "--":
+            expanded += args[i:]
+            break
+        elif not arg.startswith("@") or arg == "@":
+            expanded.append(arg)
+            continue
+        path = arg[1:]
+        real_path = os.path.realpath(path)
+        if real_path in _seen:
+            raise ValueError("response file {!r} includes itself".format(path))
+        try:
+            with open(path) as f:
+                lines = f.read().splitlines()
+        except OSError as e:
+            raise ValueError(
+                "unable to read response file {!r}: {}".format(path, e.strerror)
+            ) from None
+        file_args = []
+        for lineno, line in enumerate(lines, start=1):
+            try:
+                file_args += shlex.split(line, comments=True)
+            except ValueError as e:
+                raise ValueError(
+                    "{}:{}: {}".format(path, lineno, str(e).lower())
+                ) from None
+        expanded += expand_response_files(file_args, _seen=_seen + (real_path,))
+    return expanded
@@ -0,0 +1,139 @@
+"""
+Helpers for generating an application's CLI artifacts at build time, e.g. from
+a setuptools build hook or a CI job.
+
+The schema is checked for problems before anything is written, so that an
+invalid schema fails the build, e.g.:
+
+    from dcli.build import generate_artifacts
+
+    artifacts = generate_artifacts("cli.yaml", "build/cli", prog="myapp")
+
+The returned source files (the schema and any files it includes or loads
+subtrees from) can be used by build systems to only regenerate the artifacts
+when the schema changes.
+
+"""
+
+__all__ = ("BuildArtifacts", "generate_artifacts")
+
+import pathlib
+from typing import Dict, Iterable, List, NamedTuple, Optional
+
+from ._completions import SHELLS, generate_completions
+from ._docs import render_manpages, render_markdown
+from ._schema import RootNode, _load_schema_file, check_schema, load_from_path
+from ._utils import PathLike
+
+
+# The conventional names of completion scripts for each shell.
+_COMPLETION_FILENAMES = {
+    "bash": "{}",
+    "zsh": "_{}",
+    "fish": "{}.fish",
+    "powershell": "{}.ps1",
+}
+
+
+class BuildArtifacts(
+    NamedTuple(
+        "BuildArtifacts",
+        [
+            # The paths of the files written, sorted.
+            ("files", List[pathlib.Path]),
+            # The paths of the schema files read, sorted.
+            ("sources", List[pathlib.Path]),
+        ],
+    )
+):
+    """The result of generating artifacts from a schema."""
+
+    __slots__ = ()
+
+
+def _write_file(path: pathlib.Path, text: str) -> pathlib.Path:
+    """Write a file, leaving it untouched if its contents are unchanged."""
+    path.parent.mkdir(parents=True, exist_ok=True)
+    if not path.is_file() or path.read_text() != text:
+        path.write_text(text)
+    return path
+
+
+def _find_sources(path: pathlib.Path, schema: RootNode) -> List[pathlib.Path]:
+    paths = [path]
+    for node in schema.walk():
+        if node.subtree_file is not None:
+            paths.append(pathlib.Path(node.subtree_file))
+    sources = {}  # type: Dict[str, str]
+    for p in paths:
+        _load_schema_file(p, None, stack=(p.resolve(),), sources=sources)
+    return sorted(pathlib.Path(p) for p in sources)
+
+
+def generate_artifacts(
+    schema_path: PathLike,
+    output_dir: PathLike,
+    *,
+    prog: str,
+    shells: Iterable[str] = SHELLS,
+    manpages: bool = True,
+    markdown: bool = False,
+    version: Optional[str] = None,
+    commands: Optional[Iterable[str]] = None
+) -> BuildArtifacts:
+    """
+    Check a schema and generate its shell completions and documentation.
+
+    Completion scripts are written to 'completions/' in the output directory
+    using each shell's naming convention, man pages to 'man/' and the
+    Markdown reference to '<prog>.md'. Files whose contents are unchanged are
+    not rewritten, so that their modification times are preserved.
+
+    :param schema_path:
+        The path to the schema file.
+    :param output_dir:
+        The directory to write the artifacts to, created if necessary.
+    :param prog:
+        The program name, as invoked by users.
+    :param shells:
+        The shells to generate completion scripts for, defaults to all.
+    :param manpages:
+        Whether to generate man pages.
+    :param markdown:
+        Whether to generate a Markdown reference.
+    :param version:
+        The program version to include in the man pages, defaults to the
+        schema's version (if any).
+    :param commands:
+        The command names that can be used in the schema, to check the schema's
+        command references as for `validate()`.
+    :return:
+        The files written and the schema files read.
+    :raises SchemaError:
+        If the schema is invalid, including if any problems are found.
+    """
+    schema_path = pathlib.Path(schema_path)
+    output_dir = pathlib.Path(output_dir)
+    schema = load_from_path(schema_path)
+    check_schema(schema, commands=commands)
+    if version is None and schema.version is not None:
+        version = schema.version.get_number()
+    files = []
+    for shell in shells:
+        files.append(
+            _write_file(
+                output_dir / "completions" / _COMPLETION_FILENAMES[shell].format(prog),
+                generate_completions(schema, shell, prog=prog),
+            )
+        )
+    if manpages:
+        pages = render_manpages(schema, prog=prog, version=version)
+        for filename, page in pages.items():
+            files.append(_write_file(output_dir / "man" / filename, page))
+    if markdown:
+        files.append(
+            _write_file(
+                output_dir / "{}.md".format(prog), render_markdown(schema, prog=prog)
+            )
+        )
+    return BuildArtifacts(sorted(files), _find_sources(schema_path, schema))
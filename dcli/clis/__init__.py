@@ -5,28 +5,1418 @@ CLI parsing implementations.
 
 """
 
-__all__ = ("AbstractCLIParser", "Namespace")
+__all__ = (
+    "AbstractCLIParser",
+    "ArgLike",
+    "ArgSource",
+    "ArgValueError",
+    "InputReader",
+    "Namespace",
+    "Validator",
+)
 
 import abc
 import argparse
-from typing import List, Optional
+import collections
+import contextlib
+import enum
+import io
+import getpass
+import inspect
+import os
+import re
+import shlex
+import sys
+import urllib.parse
+import urllib.request
+from typing import (
+    Any,
+    BinaryIO,
+    Callable,
+    Dict,
+    FrozenSet,
+    Iterable,
+    Iterator,
+    List,
+    Mapping,
+    Optional,
+    TextIO,
+    Type,
+    TypeVar,
+    Union,
+)
 
-from .._schema import RootNode
+from .._config import get_node_config
+from .._errors import ParseError, SchemaError, UnauthorizedError
+from .._help import DEFAULT_HELP_TEMPLATE, HelpTheme, render_help
+from .._pager import page
+from .._schema import Arg, NodeBase, RootNode, VersionSpec, _format_location
+from .._templates import (
+    TemplateError,
+    expand_template,
+    get_arg_references,
+    is_template,
+)
+from .._types import ArgType, Secret
+from .._utils import get_close_match
 
 
 Namespace = argparse.Namespace
 
+T = TypeVar("T")
+
+# A validator registered by name, which raises ValueError for invalid values.
+Validator = Callable[[Any], None]
+
+# A callback giving the roles of the user running the CLI, for commands with
+# 'requires_role'.
+RoleProvider = Callable[[], Iterable[str]]
+
+# An arg to parse, where bytes and path-like args are decoded with the
+# filesystem encoding, as for 'sys.argv'.
+ArgLike = Union[str, bytes, "os.PathLike"]
+
+
+def _decode_args(args: Iterable[ArgLike]) -> List[str]:
+    """
+    Convert args to strings, so that bytes that aren't valid in the
+    filesystem encoding are preserved as surrogate escapes, as in 'sys.argv'.
+    """
+    return [x if isinstance(x, str) else os.fsdecode(x) for x in args]
+
+
+def _has_surrogates(value: str) -> bool:
+    """Whether a string contains surrogate escapes from undecodable bytes."""
+    try:
+        value.encode("utf-8")
+    except UnicodeEncodeError:
+        return True
+    return False
+
+
+class ArgSource(enum.Enum):
+    """Where the value for a parsed arg came from."""
+
+    CLI = "cli"
+    ENV = "env"
+    CONFIG = "config"
+    FILE = "file"
+    PROMPT = "prompt"
+    DEFAULT = "default"
+
+
+class ArgValueError(ParseError, ValueError):
+    """Invalid value for an arg."""
+
+
+class InputReader:
+    """
+    Reader for the input of an arg accepting '-' for stdin.
+
+    For 'path' args the input is the contents of the file, and for 'string'
+    args it is the value itself, unless the value is '-'.
+    """
+
+    def __init__(self, arg: Arg, value: Any):
+        """
+        :param arg:
+            The schema arg.
+        :param value:
+            The arg's parsed value.
+        """
+        self.arg = arg
+        self.value = value
+
+    @property
+    def is_stdin(self) -> bool:
+        """Whether the input is read from stdin."""
+        return str(self.value) == "-"
+
+    @contextlib.contextmanager
+    def open(self) -> Iterator[TextIO]:
+        """
+        Open the input as a text stream, which is closed on exit unless it's
+        stdin.
+
+        :raises OSError:
+            If the file can't be opened.
+        """
+        if self.is_stdin:
+            yield sys.stdin
+        elif self.arg.type is ArgType.PATH:
+            with open(self.value) as f:
+                yield f
+        else:
+            yield io.StringIO(self.value)
+
+    def read(self) -> str:
+        """
+        Read the whole input.
+
+        :raises OSError:
+            If the file can't be read.
+        """
+        with self.open() as f:
+            return f.read()
+
+
+class _MultipleValuesAction(argparse.Action):
+    """Argparse action for storing multiple values within count limits."""
+
+    def __init__(self, *args, min_count: int, max_count: Optional[int], **kwargs):
+        super().__init__(*args, **kwargs)
+        self.min_count = min_count
+        self.max_count = max_count
+
+    def __call__(self, parser, namespace, values, option_string=None):
+        if len(values) < self.min_count:
+            raise argparse.ArgumentError(
+                self, "expected at least {} values".format(self.min_count)
+            )
+        if self.max_count is not None and len(values) > self.max_count:
+            raise argparse.ArgumentError(
+                self, "expected at most {} values".format(self.max_count)
+            )
+        setattr(namespace, self.dest, values)
+
+
+class _VersionAction(argparse.Action):
+    """Argparse action for printing the version declared in the schema."""
+
+    def __init__(self, *args, version: VersionSpec, prog: str, **kwargs):
+        super().__init__(*args, nargs=0, default=argparse.SUPPRESS, **kwargs)
+        self.version = version
+        self.prog = prog
+
+    def __call__(self, parser, namespace, values, option_string=None):
+        try:
+            print(self.version.describe(self.prog))
+        except SchemaError as e:
+            parser.error(str(e))
+        parser.exit()
+
+
+def _add_version_option(
+    parser: argparse.ArgumentParser, schema: RootNode, *, prog: str
+) -> None:
+    """
+    Add the '--version' option if the schema declares a version, along with a
+    '-V' short option unless the node already uses it.
+    """
+    if schema.version is None:
+        return
+    for names in (["-V", "--version"], ["--version"]):
+        try:
+            parser.add_argument(
+                *names,
+                action=_VersionAction,
+                version=schema.version,
+                prog=prog,
+                help="Show the version and exit",
+            )
+            return
+        except argparse.ArgumentError:
+            pass
+
+
+def _get_multiple_kwargs(arg: Arg) -> Dict[str, Any]:
+    """Get the argparse kwargs for an arg accepting multiple values."""
+    if arg.min is not None:
+        min_count = arg.min
+    elif arg.positional and arg.default is not None:
+        min_count = 0
+    else:
+        min_count = 1
+    return dict(
+        nargs="*" if min_count == 0 else "+",
+        action=_MultipleValuesAction,
+        min_count=min_count,
+        max_count=arg.max,
+    )
+
+
+def _help_text(item: Union[Arg, NodeBase]) -> str:
+    """
+    The help text for an arg or node, with an arg's placeholders filled and
+    tagged if it is deprecated.
+    """
+    text = item.render_help() if isinstance(item, Arg) else item.help
+    if item.deprecated is not None:
+        return "{} (deprecated)".format(text)
+    return text
+
+
+def _add_node_args(
+    parser: argparse.ArgumentParser, node: NodeBase, *, short_options: bool = True
+) -> None:
+    """
+    Add the args accepted at a schema node to an argparse parser, including
+    the schema's global args.
+
+    Values are only stored when given on the command line, with other sources
+    being handled by `_resolve_values()`.
+
+    :param parser:
+        The parser to add the args to.
+    :param node:
+        The schema node.
+    :param short_options:
+        Whether to include the short option strings.
+    """
+    for arg in node.all_args:
+        if arg.passthrough:
+            # Handled by the caller, which splits off the args after '--'.
+            continue
+        kwargs = dict()
+        kwargs["help"] = argparse.SUPPRESS if arg.hidden else _help_text(arg)
+        kwargs["default"] = argparse.SUPPRESS
+        if arg.positional:
+            names = [arg.dest]
+            if arg.default is not None or _has_fallback(arg):
+                kwargs["nargs"] = "?"
+        else:
+            names = arg.option_strings if short_options else ["--" + arg.long]
+            kwargs["dest"] = arg.dest
+            kwargs["required"] = arg.required and not _has_fallback(arg)
+        if arg.enum:
+            kwargs["metavar"] = "{" + ",".join(str(x) for x in arg.enum) + "}"
+        if arg.count:
+            kwargs["action"] = "count"
+        elif arg.type is ArgType.FLAG:
+            kwargs["action"] = "store_true"
+        elif arg.repeat:
+            kwargs["action"] = "append"
+        elif arg.type is ArgType.MAP:
+            kwargs["action"] = "append"
+            kwargs["metavar"] = "KEY=VALUE"
+        elif arg.type is ArgType.TEXT:
+            kwargs["nargs"] = argparse.REMAINDER
+        elif arg.multiple:
+            kwargs.update(_get_multiple_kwargs(arg))
+        parser.add_argument(*names, **kwargs)
+        if arg.file_option:
+            parser.add_argument(
+                arg.file_option_string,
+                dest=_file_option_dest(arg),
+                metavar="FILE",
+                default=argparse.SUPPRESS,
+                help=argparse.SUPPRESS
+                if arg.hidden
+                else "Read the value for --{} from a file".format(arg.long),
+            )
+
+
+def _file_option_dest(arg: Arg) -> str:
+    """The namespace attribute for an arg's file option, see `Arg.file_option`."""
+    return arg.dest + "__file"
+
+
+def _read_value_file(arg: Arg, path: str) -> str:
+    """
+    Read the value for an arg from the file given with its file option, without
+    any trailing newline.
+
+    :raises ArgValueError:
+        If the file can't be read.
+    """
+    try:
+        with open(path) as f:
+            return f.read().rstrip("\r\n")
+    except OSError as e:
+        raise ArgValueError(
+            "unable to read {} for {}: {}".format(
+                path, arg.file_option_string, e.strerror or e.__class__.__name__
+            )
+        ) from None
+
+
+# The number of seconds to wait for a URL given for an arg with 'source' set.
+_URL_TIMEOUT = 30
+
+# How each kind of value given for an arg with 'source' set is written.
+_SOURCE_FORMS = {
+    "literal": "a literal value",
+    "file": "'@path' or 'file://path'",
+    "url": "an 'http://' or 'https://' URL",
+}
+
+
+def _read_limited(f: BinaryIO, arg: Arg, name: str) -> str:
+    """
+    Read at most the arg's maximum size of data, decoded as UTF-8.
+
+    :raises ArgValueError:
+        If there's too much data or it's not valid UTF-8.
+    """
+    data = f.read(arg.get_max_size() + 1)
+    if len(data) > arg.get_max_size():
+        raise ArgValueError(
+            "{} for {} is larger than the limit of {} bytes".format(
+                name, _display_name(arg), arg.get_max_size()
+            )
+        )
+    try:
+        return data.decode("utf-8")
+    except UnicodeDecodeError:
+        raise ArgValueError(
+            "{} for {} is not valid UTF-8".format(name, _display_name(arg))
+        ) from None
+
+
+def _load_source_value(arg: Arg, value: str) -> str:
+    """
+    Load the value given for an arg with 'source' set, from a file given as
+    '@path' or 'file://path', or a URL.
+
+    :raises ArgValueError:
+        If the value's source isn't accepted for the arg, or loading fails.
+    """
+    if value.startswith("@"):
+        kind, location = "file", value[1:]
+    elif value.startswith("file://"):
+        kind, location = "file", urllib.parse.unquote(value[len("file://") :])
+    elif value.startswith(("http://", "https://")):
+        kind, location = "url", value
+    else:
+        kind, location = "literal", value
+    if kind not in arg.source:
+        raise ArgValueError(
+            "{} values not accepted for {}, expected {}".format(
+                kind,
+                _display_name(arg),
+                " or ".join(_SOURCE_FORMS[x] for x in arg.source),
+            )
+        )
+    if kind == "literal":
+        return value
+    try:
+        if kind == "file":
+            with open(location, "rb") as f:
+                return _read_limited(f, arg, location)
+        request = urllib.request.Request(location, headers={"User-Agent": "dcli"})
+        with urllib.request.urlopen(request, timeout=_URL_TIMEOUT) as f:
+            return _read_limited(f, arg, location)
+    except OSError as e:
+        reason = getattr(e, "reason", None) or e.strerror or str(e)
+        raise ArgValueError(
+            "unable to read {} for {}: {}".format(location, _display_name(arg), reason)
+        ) from None
+
+
+def _has_fallback(arg: Arg) -> bool:
+    """Whether a required arg may get its value other than from the CLI."""
+    return arg.env is not None or arg.prompt or arg.file_option
+
+
+def _parse_env_value(arg: Arg, value: str) -> Any:
+    """Parse the value of an environment variable or prompt for an arg."""
+    if arg.type in (ArgType.TEXT, ArgType.MAP) or arg.is_list:
+        return shlex.split(value)
+    else:
+        return value
+
+
+def _display_name(arg: Arg) -> str:
+    """The name of an arg for use in error messages."""
+    return "/".join(arg.option_strings) if not arg.positional else arg.dest
+
+
+def _describe_source(arg: Arg, source: ArgSource) -> str:
+    """Describe where a value came from, for error messages."""
+    if source is ArgSource.ENV:
+        return " (from environment variable {})".format(arg.env)
+    elif source is ArgSource.CONFIG:
+        return " (from config file)"
+    elif source is ArgSource.FILE:
+        return " (from {})".format(arg.file_option_string)
+    elif source is ArgSource.PROMPT:
+        return " (entered at prompt)"
+    elif source is ArgSource.DEFAULT:
+        return " (default in schema)"
+    else:
+        return ""
+
+
+def _display_value(arg: Arg, value: Any) -> str:
+    """A value given for an arg for use in error messages, redacting secrets."""
+    return repr(Secret.REDACTED if arg.secret else value)
+
+
+def _redact_secrets(
+    node: NodeBase, args: List[str], *, short_options: bool = True
+) -> List[str]:
+    """
+    Redact the values of secret args in a list of the args that were parsed,
+    e.g. for 'remaining_args'.
+
+    The values are found by their position after a secret arg's option, given
+    as a separate arg or attached to the option (as '--token=...', '-t...' or
+    at the end of a cluster such as '-vt...'), including when the option is
+    abbreviated. Other args are left as given, even if they contain the value.
+
+    :param node:
+        The node the args were parsed for.
+    :param args:
+        The args, as passed to argparse.
+    :param short_options:
+        Whether the parser accepts short options.
+    """
+    options = {
+        opt: arg
+        for arg in node.all_args
+        for opt in arg.option_strings
+        if short_options or opt.startswith("--")
+    }  # type: Dict[str, Arg]
+    if not any(arg.secret for arg in options.values()):
+        return args
+
+    def find_long_option(option: str) -> Optional[Arg]:
+        # Argparse accepts unambiguous prefixes of long options.
+        if option in options:
+            return options[option]
+        matches = [o for o in options if o.startswith("--") and o.startswith(option)]
+        return options[matches[0]] if len(matches) == 1 else None
+
+    result = []
+    redact_next = False
+    for i, word in enumerate(args):
+        if redact_next:
+            word = Secret.REDACTED
+            redact_next = False
+        elif word == "--":
+            result += args[i:]
+            break
+        elif word.startswith("--"):
+            option, sep, _ = word.partition("=")
+            arg = find_long_option(option)
+            if arg is not None and arg.secret:
+                if sep:
+                    word = option + sep + Secret.REDACTED
+                else:
+                    redact_next = True
+        elif word in options:
+            redact_next = options[word].secret
+        elif short_options and word.startswith("-"):
+            # Flags may be combined with the option, e.g. '-vtVALUE'.
+            for j, char in enumerate(word[1:], start=1):
+                arg = options.get("-" + char)
+                if arg is None:
+                    break
+                if arg.type.takes_value:
+                    if arg.secret and j + 1 == len(word):
+                        redact_next = True
+                    elif arg.secret:
+                        sep = "=" if word[j + 1 : j + 2] == "=" else ""
+                        word = word[: j + 1] + sep + Secret.REDACTED
+                    break
+        result.append(word)
+    return result
+
+
+def _convert_value(arg: Arg, value: Any, source: ArgSource) -> Any:
+    """
+    Convert a resolved value to the arg's type, checking it against the arg's
+    enum values and validation rules if it has them. Values of secret args are
+    wrapped in `Secret`.
+
+    :raises ArgValueError:
+        If the value is not valid for the arg.
+    """
+    if value is None:
+        return None
+    if arg.type is ArgType.MAP:
+        return _convert_map(arg, value, source)
+    values = value if arg.is_list and isinstance(value, list) else [value]
+    if arg.repeat:
+        _check_value_count(arg, values, source)
+    if arg.glob is not None and source in (ArgSource.CLI, ArgSource.ENV):
+        values = _expand_globs(arg, values, source)
+    if arg.source is not None and source in (ArgSource.CLI, ArgSource.ENV):
+        values = [_load_source_value(arg, v) for v in values]
+    type_ = ArgType.INTEGER if arg.count else arg.type
+    choices = [arg.type.convert(x) for x in arg.enum] if arg.enum else None
+    converted = []
+    for v in values:
+        # Only paths and verbatim values may contain undecodable bytes.
+        if (
+            isinstance(v, str)
+            and type_ not in (ArgType.PATH, ArgType.TEXT)
+            and not arg.passthrough
+            and _has_surrogates(v)
+        ):
+            raise ArgValueError(
+                "invalid UTF-8 in value for {}: {}{}".format(
+                    _display_name(arg),
+                    _display_value(arg, v),
+                    _describe_source(arg, source),
+                )
+            )
+        try:
+            converted.append(type_.convert(v))
+        except ValueError:
+            raise ArgValueError(
+                "expected {} for {}, got {}{}".format(
+                    type_.description,
+                    _display_name(arg),
+                    _display_value(arg, v),
+                    _describe_source(arg, source),
+                )
+            ) from None
+        if choices is not None and converted[-1] not in choices:
+            msg = "invalid choice for {}: {}{} (choose from {})".format(
+                _display_name(arg),
+                _display_value(arg, v),
+                _describe_source(arg, source),
+                ", ".join(repr(str(x)) for x in arg.enum),
+            )
+            suggestion = get_close_match(str(v), [str(x) for x in arg.enum])
+            if suggestion is not None:
+                msg += ", did you mean {!r}?".format(suggestion)
+            raise ArgValueError(msg)
+        if arg.validate is not None and not (arg.stdin and str(v) == "-"):
+            rule = arg.validate.check(converted[-1])
+            if rule is not None:
+                raise ArgValueError(
+                    "invalid value for {}: {}{} (fails rule {!r})".format(
+                        _display_name(arg),
+                        _display_value(arg, v),
+                        _describe_source(arg, source),
+                        rule,
+                    )
+                )
+    if arg.secret:
+        return Secret(converted[0])
+    return converted if arg.is_list else converted[0]
+
+
+def _convert_map(arg: Arg, value: Any, source: ArgSource) -> Dict[str, Any]:
+    """
+    Convert the key=value pairs for a 'map' arg into an ordered mapping,
+    converting the values of keys declared in the schema to their types.
+
+    :raises ArgValueError:
+        If a pair is invalid, or a key is repeated when not permitted.
+    """
+    if isinstance(value, dict):
+        pairs = list(value.items())
+    else:
+        pairs = []
+        for v in value if isinstance(value, list) else [value]:
+            try:
+                pairs.append(ArgType.MAP.convert(v))
+            except ValueError:
+                raise ArgValueError(
+                    "expected {} for {}, got {!r}{}".format(
+                        ArgType.MAP.description,
+                        _display_name(arg),
+                        v,
+                        _describe_source(arg, source),
+                    )
+                ) from None
+    result = collections.OrderedDict()  # type: Dict[str, Any]
+    for key, item in pairs:
+        key = str(key)
+        if key in result and arg.duplicates == "error":
+            raise ArgValueError(
+                "key {!r} given multiple times for {}{}".format(
+                    key, _display_name(arg), _describe_source(arg, source)
+                )
+            )
+        type_ = (arg.keys or {}).get(key, ArgType.STRING)
+        try:
+            result[key] = type_.convert(item)
+        except ValueError:
+            raise ArgValueError(
+                "expected {} for key {!r} of {}, got {!r}{}".format(
+                    type_.description,
+                    key,
+                    _display_name(arg),
+                    item,
+                    _describe_source(arg, source),
+                )
+            ) from None
+    return result
+
+
+def _expand_globs(arg: Arg, values: List[Any], source: ArgSource) -> List[Any]:
+    """
+    Expand the glob patterns given for a path arg with 'glob' set.
+
+    :raises ArgValueError:
+        If a pattern matches no paths when that's an error.
+    """
+    expanded = []
+    for value in values:
+        if not isinstance(value, str):
+            expanded.append(value)
+            continue
+        try:
+            expanded += arg.glob.expand(value)
+        except ValueError:
+            raise ArgValueError(
+                "no paths match {!r} for {}{}".format(
+                    value, _display_name(arg), _describe_source(arg, source)
+                )
+            ) from None
+    return expanded
+
+
+def _check_value_count(arg: Arg, values: List[Any], source: ArgSource) -> None:
+    """
+    Check the number of values for a 'repeat' arg.
+
+    :raises ArgValueError:
+        If the count is outside the arg's limits.
+    """
+    if arg.min is not None and len(values) < arg.min:
+        limit = "at least {}".format(arg.min)
+    elif arg.max is not None and len(values) > arg.max:
+        limit = "at most {}".format(arg.max)
+    else:
+        return
+    raise ArgValueError(
+        "expected {} values for {}, got {}{}".format(
+            limit, _display_name(arg), len(values), _describe_source(arg, source)
+        )
+    )
+
+
+def _resolve_values(
+    node: NodeBase,
+    namespace: Namespace,
+    config: Optional[Mapping[str, Any]] = None,
+    *,
+    global_args: bool = True,
+    validators: Optional[Mapping[str, Validator]] = None
+) -> None:
+    """
+    Resolve the values of a node's args that weren't given on the command line.
+
+    The precedence is command line, then environment variable, then config
+    file (for args marked as configurable), then default. Defaults containing
+    templates are expanded last, see `_expand_defaults()`. The source of each
+    value is recorded in the namespace's 'arg_sources' attribute, a mapping of
+    arg dest to `ArgSource`, and values are converted to the arg's type. The
+    node's arg groups are then checked.
+
+    Args accepting '-' for stdin are given an `InputReader` in the namespace's
+    'readers' attribute, a mapping of arg dest to reader.
+
+    :param node:
+        The schema node.
+    :param namespace:
+        The namespace populated by argparse.
+    :param config:
+        The loaded config file, if any.
+    :param global_args:
+        Whether to resolve the schema's global args as well as the node's args.
+    :param validators:
+        The validators registered by name, for args with a 'validator'.
+    :raises ArgValueError:
+        If a value is invalid.
+    :raises SchemaError:
+        If an arg's validator is not registered.
+    """
+    if not hasattr(namespace, "arg_sources"):
+        namespace.arg_sources = {}
+    node_config = get_node_config(config, node) if config else {}
+    templated = []
+    for arg in node.all_args if global_args else node.args:
+        if arg in node.args:
+            arg_config = node_config
+        else:
+            # Global args are configured at the top level.
+            arg_config = config if config else {}
+        file_path = None
+        if arg.file_option and hasattr(namespace, _file_option_dest(arg)):
+            file_path = getattr(namespace, _file_option_dest(arg))
+            delattr(namespace, _file_option_dest(arg))
+        if hasattr(namespace, arg.dest):
+            if file_path is not None:
+                raise ArgValueError(
+                    "argument {}: not allowed with argument {}".format(
+                        arg.file_option_string, _display_name(arg)
+                    )
+                )
+            source = ArgSource.CLI
+            value = getattr(namespace, arg.dest)
+        elif file_path is not None:
+            source = ArgSource.FILE
+            value = _read_value_file(arg, file_path)
+        elif arg.env is not None and arg.env in os.environ:
+            source = ArgSource.ENV
+            value = _parse_env_value(arg, os.environ[arg.env])
+        elif arg.configurable and arg.name in arg_config:
+            source = ArgSource.CONFIG
+            value = arg_config[arg.name]
+        else:
+            source = ArgSource.DEFAULT
+            # Positionals that may be given no values are empty when omitted.
+            empty_list = arg.passthrough or (
+                arg.positional and arg.multiple and arg.min == 0
+            )
+            if arg.default is not None:
+                value = arg.default
+            elif arg.type is ArgType.FLAG:
+                value = 0 if arg.count else False
+            elif empty_list:
+                value = []
+            else:
+                value = None
+            # Argparse only checks required args when there's no fallback.
+            required = arg.required or (
+                arg.positional and arg.default is None and not empty_list
+            )
+            if required and _has_fallback(arg):
+                source = ArgSource.PROMPT
+                value = _prompt_for_value(arg)
+            elif is_template(value):
+                templated.append(arg)
+                continue
+        setattr(namespace, arg.dest, _convert_value(arg, value, source))
+        namespace.arg_sources[arg.dest] = source
+    _expand_defaults(node, templated, namespace)
+    _set_readers(node, namespace, global_args=global_args)
+    _run_validators(
+        node, namespace, validators if validators else {}, global_args=global_args
+    )
+    _check_groups(node, namespace)
+    _check_arg_constraints(node, namespace, global_args=global_args)
+
+
+def _set_readers(node: NodeBase, namespace: Namespace, *, global_args: bool) -> None:
+    """
+    Set the readers for args accepting '-' for stdin.
+
+    :raises ArgValueError:
+        If more than one arg is given as '-'.
+    """
+    if not hasattr(namespace, "readers"):
+        namespace.readers = {}
+    for arg in node.all_args if global_args else node.args:
+        value = getattr(namespace, arg.dest)
+        if not arg.stdin or value is None:
+            continue
+        reader = InputReader(arg, value)
+        if reader.is_stdin:
+            for other in namespace.readers.values():
+                if other.is_stdin:
+                    raise ArgValueError(
+                        "{} and {} cannot both read from stdin".format(
+                            _short_display_name(other.arg), _short_display_name(arg)
+                        )
+                    )
+        namespace.readers[arg.dest] = reader
+
+
+def _run_validators(
+    node: NodeBase,
+    namespace: Namespace,
+    validators: Mapping[str, Validator],
+    *,
+    global_args: bool
+) -> None:
+    """
+    Run the registered validators named by a node's args on their values.
+
+    :raises ArgValueError:
+        If a validator rejects a value.
+    :raises SchemaError:
+        If a validator is not registered.
+    """
+    for arg in node.all_args if global_args else node.args:
+        value = getattr(namespace, arg.dest)
+        if arg.validator is None or value is None:
+            continue
+        try:
+            validator = validators[arg.validator]
+        except KeyError:
+            raise SchemaError(
+                "No validator registered named {!r}".format(arg.validator),
+                location=_format_location("arg {!r}".format(arg.name), arg.span),
+            ) from None
+        source = namespace.arg_sources[arg.dest]
+        for v in value if arg.is_list else [value]:
+            if arg.stdin and str(v) == "-":
+                continue
+            try:
+                validator(v)
+            except ValueError as e:
+                raise ArgValueError(
+                    "invalid value for {}: {!r}{} ({})".format(
+                        _display_name(arg), v, _describe_source(arg, source), e
+                    )
+                ) from None
+
+
+def _prompt_for_value(arg: Arg) -> Any:
+    """
+    Prompt for the value of a missing required arg, if it accepts prompting
+    and stdin is a terminal.
+
+    :raises ArgValueError:
+        If unable to prompt or no value is entered.
+    """
+    if arg.prompt and sys.stdin.isatty():
+        prompt = "{}: ".format(arg.name)
+        try:
+            if arg.password or arg.secret:
+                value = getpass.getpass(prompt)
+            else:
+                value = input(prompt)
+        except EOFError:
+            value = ""
+        if value:
+            return _parse_env_value(arg, value)
+    msg = "the following arguments are required: {}".format(_display_name(arg))
+    alternatives = []
+    if arg.file_option:
+        alternatives.append("use {}".format(arg.file_option_string))
+    if arg.env is not None:
+        alternatives.append("set {}".format(arg.env))
+    if alternatives:
+        msg += " (or {})".format(" or ".join(alternatives))
+    raise ArgValueError(msg)
+
+
+def _expand_defaults(node: NodeBase, args: List[Arg], namespace: Namespace) -> None:
+    """
+    Expand the templated defaults of args that weren't otherwise given.
+
+    Defaults referencing other args are expanded after the referenced args
+    have their values, and the schema ensures there are no cycles.
+
+    :raises ArgValueError:
+        If a template can't be expanded.
+    """
+    pending = {a.name: a for a in args}
+    all_args = {a.name: a for a in node.all_args}
+
+    def expand(arg: Arg) -> None:
+        del pending[arg.name]
+        for name in get_arg_references(arg.default):
+            if name in pending:
+                expand(pending[name])
+        values = {
+            n: getattr(namespace, a.dest, None)
+            for n, a in all_args.items()
+            if n not in pending and n != arg.name
+        }
+        try:
+            value = expand_template(arg.default, args=values, environ=os.environ)
+        except TemplateError as e:
+            raise ArgValueError(
+                "unable to expand default for {}: {}".format(_display_name(arg), e)
+            ) from None
+        setattr(
+            namespace, arg.dest, _convert_value(arg, value, ArgSource.DEFAULT)
+        )
+        namespace.arg_sources[arg.dest] = ArgSource.DEFAULT
+
+    while pending:
+        expand(next(iter(pending.values())))
+
+
+def _join_names(names: List[str]) -> str:
+    """Join names for an error message, e.g. 'a, b and c'."""
+    if len(names) == 1:
+        return names[0]
+    return "{} and {}".format(", ".join(names[:-1]), names[-1])
+
+
+def _is_given(arg: Arg, namespace: Namespace) -> bool:
+    """Whether an arg was given, i.e. its value didn't come from the default."""
+    return namespace.arg_sources[arg.dest] is not ArgSource.DEFAULT
+
+
+def _short_display_name(arg: Arg) -> str:
+    """The name of an arg for use in error messages, e.g. '--port'."""
+    return arg.dest if arg.positional else arg.option_strings[-1]
+
+
+def _check_groups(node: NodeBase, namespace: Namespace) -> None:
+    """
+    Check the relationships declared by a node's arg groups.
+
+    Args are considered to be given if their value didn't come from the
+    default.
+
+    :raises ArgValueError:
+        If a group's relationship is not satisfied.
+    """
+    args = {a.name: a for a in node.args}
+
+    def display_names(names: List[str]) -> List[str]:
+        return [_short_display_name(args[n]) for n in names]
+
+    for group in node.groups:
+        given = [n for n in group.args if _is_given(args[n], namespace)]
+        if group.exclusive and len(given) > 1:
+            raise ArgValueError(
+                "{} cannot be used together".format(
+                    _join_names(display_names(given))
+                )
+            )
+        missing = [n for n in group.requires if not _is_given(args[n], namespace)]
+        if given and missing:
+            raise ArgValueError(
+                "{} requires {}".format(
+                    display_names(given)[0], _join_names(display_names(missing))
+                )
+            )
+
+
+def _check_arg_constraints(
+    node: NodeBase, namespace: Namespace, *, global_args: bool
+) -> None:
+    """
+    Check the 'required_if' and 'conflicts_with' constraints of a node's args.
+
+    :raises ArgValueError:
+        If a constraint is not satisfied.
+    """
+    args = {a.name: a for a in node.all_args}
+    for arg in node.all_args if global_args else node.args:
+        given = _is_given(arg, namespace)
+        for name in arg.conflicts_with:
+            if given and _is_given(args[name], namespace):
+                raise ArgValueError(
+                    "{} cannot be used with {}".format(
+                        _short_display_name(arg), _short_display_name(args[name])
+                    )
+                )
+        if given:
+            continue
+        for name, value in arg.required_if.items():
+            other = args[name]
+            if getattr(namespace, other.dest) == other.type.convert(value):
+                raise ArgValueError(
+                    "{} is required when {} is {!r}".format(
+                        _short_display_name(arg), _short_display_name(other), value
+                    )
+                )
+
+
+def _is_shown(node: NodeBase, roles: Optional[FrozenSet[str]]) -> bool:
+    """
+    Whether a subnode is shown in help and suggestions, i.e. it isn't hidden
+    and the user has any role it requires.
+    """
+    return not node.hidden and node.find_unauthorized(roles) is None
+
+
+def _unknown_command_message(
+    node: NodeBase, keyword: str, roles: Optional[FrozenSet[str]]
+) -> str:
+    """
+    The error message for an unrecognised keyword, with a suggestion drawn from
+    the keywords and visible aliases of the node's subtree.
+    """
+    msg = "unknown command {!r}".format(keyword)
+    choices = [
+        k
+        for n in node.subtree
+        if _is_shown(n, roles)
+        for k in [n.keyword] + n.aliases
+    ]
+    suggestion = get_close_match(keyword, choices)
+    if suggestion is not None:
+        msg += ", did you mean {!r}?".format(suggestion)
+    return msg
+
+
+def _unauthorized_exit_code(node: NodeBase) -> int:
+    """The exit code for rejecting a command the user isn't authorized for."""
+    code = node.get_exit_code(UnauthorizedError.category)
+    return UnauthorizedError.exit_code if code is None else code
+
+
+def _unauthorized_message(
+    node: NodeBase, roles: Optional[Iterable[str]]
+) -> Optional[str]:
+    """
+    The error message for a command requiring a role the user doesn't have,
+    or None if the user may run the command.
+    """
+    unauthorized = node.find_unauthorized(roles)
+    if unauthorized is None:
+        return None
+    roles = unauthorized.requires_role
+    if len(roles) == 1:
+        required = "the role {!r}".format(roles[0])
+    else:
+        required = "one of the roles: {}".format(", ".join(roles))
+    return "command {!r} requires {}".format(" ".join(unauthorized.path), required)
+
+
+def _get_deprecation_warnings(node: NodeBase, namespace: Namespace) -> List[str]:
+    """
+    Get warnings for deprecated commands and args used in a parse.
+
+    :param node:
+        The node that was reached.
+    :param namespace:
+        The resolved namespace.
+    :return:
+        The warning messages.
+    """
+    warnings = []
+    nodes = []
+    n = node
+    while n is not None:
+        nodes.insert(0, n)
+        n = n.parent
+    for n in nodes:
+        if n.deprecated is not None:
+            warnings.append(
+                n.deprecated.describe("command {!r}".format(" ".join(n.path)))
+            )
+    for arg in node.all_args:
+        if arg.deprecated is not None and _is_given(arg, namespace):
+            if arg.positional:
+                what = "argument {!r}".format(arg.name)
+            else:
+                what = "option {}".format(arg.option_strings[-1])
+            warnings.append(arg.deprecated.describe(what))
+    return warnings
+
+
+_NEGATIVE_NUMBER_REGEX = re.compile(r"-(\d+\.?\d*|\.\d+)([eE][-+]?\d+)?")
+
+
+def _join_negative_number_values(node: NodeBase, args: List[str]) -> List[str]:
+    """
+    Join negative numbers given as the values of numeric options onto the
+    option, e.g. ['--offset', '-5'] to ['--offset=-5'], so that argparse
+    doesn't take them to be options.
+    """
+    numeric_options = {
+        opt
+        for arg in node.all_args
+        if arg.type in (ArgType.INTEGER, ArgType.FLOAT) and not arg.multiple
+        for opt in arg.option_strings
+    }
+    result = []
+    args = iter(args)
+    for arg in args:
+        if arg == "--":
+            result.append(arg)
+            result += args
+            break
+        if arg in numeric_options:
+            value = next(args, None)
+            if value is not None and _NEGATIVE_NUMBER_REGEX.fullmatch(value):
+                arg = "{}={}".format(arg, value)
+            elif value is not None:
+                result += [arg, value]
+                continue
+        result.append(arg)
+    return result
+
+
+class _NodeArgumentParser(argparse.ArgumentParser):
+    """Argparse parser for a schema node, resolving values after parsing."""
+
+    def __init__(
+        self,
+        *args,
+        node: NodeBase,
+        config: Optional[Mapping[str, Any]] = None,
+        resolve_global_args: bool = True,
+        theme: Optional[HelpTheme] = None,
+        validators: Optional[Mapping[str, Validator]] = None,
+        use_pager: bool = False,
+        roles: Optional[FrozenSet[str]] = None,
+        **kwargs
+    ):
+        super().__init__(*args, **kwargs)
+        self.node = node
+        self.roles = roles
+        self.config = config
+        self.resolve_global_args = resolve_global_args
+        self.theme = theme
+        self.validators = validators
+        self.use_pager = use_pager
+
+    def print_help(self, file=None):
+        if file is None and self.use_pager:
+            page(self.format_help())
+        else:
+            super().print_help(file)
+
+    def parse_args(self, args=None, namespace=None):
+        namespace, extras = self.parse_known_args(args, namespace)
+        if self.node.unknown_args == "collect":
+            namespace.unknown_args = extras
+        if self.node.unknown_args != "error":
+            extras = []
+        if extras:
+            msg = "unrecognized arguments: {}".format(" ".join(extras))
+            options = [x.split("=", 1)[0] for x in extras if x.startswith("-")]
+            if options:
+                suggestion = get_close_match(options[0], self._option_names())
+                if suggestion is not None:
+                    msg += ", did you mean {!r}?".format(suggestion)
+            self.error(msg)
+        return namespace
+
+    def format_usage(self) -> str:
+        usage = super().format_usage()
+        for arg in self.node.all_args:
+            if arg.passthrough and not arg.hidden:
+                usage = usage.rstrip("\n") + " [-- {} ...]\n".format(arg.dest)
+        return usage
+
+    def format_help(self) -> str:
+        template = self.node.get_help_template()
+        footer = self.node.get_help_footer()
+        examples = [
+            ("{} {}".format(self.prog, x.command), x.description)
+            for x in self.node.examples
+        ]
+        arguments = []
+        options = []
+        for action in self._actions:
+            if action.help is argparse.SUPPRESS or action.dest is argparse.SUPPRESS:
+                continue
+            if action.option_strings:
+                term = ", ".join(action.option_strings)
+                if action.nargs != 0:
+                    term += " " + (action.metavar or action.dest.upper())
+                    if action.nargs in ("*", "+"):
+                        term += " ..."
+                options.append((term, action.help or ""))
+            else:
+                arguments.append((action.metavar or action.dest, action.help or ""))
+        for arg in self.node.all_args:
+            if arg.passthrough and not arg.hidden:
+                arguments.append(("-- {} ...".format(arg.dest), _help_text(arg)))
+        commands = [
+            (", ".join([n.keyword] + n.aliases), _help_text(n).strip().split("\n")[0])
+            for n in self.node.subtree
+            if _is_shown(n, self.roles)
+        ]
+        usage = self.format_usage().strip()
+        if usage.startswith("usage: "):
+            usage = usage[len("usage: ") :]
+        return render_help(
+            template if template is not None else DEFAULT_HELP_TEMPLATE,
+            usage=usage,
+            description=self.description or "",
+            commands=commands,
+            arguments=arguments,
+            options=options,
+            examples=examples,
+            footer=footer or "",
+            theme=self.theme,
+        )
+
+    def _option_names(self) -> List[str]:
+        """The visible option strings accepted by the parser."""
+        return [
+            opt
+            for action in self._actions
+            if action.help is not argparse.SUPPRESS
+            for opt in action.option_strings
+        ]
+
+    def _check_no_short_clusters(self, args: List[str]) -> None:
+        """
+        Reject short options combined with other short options or with
+        attached values, e.g. '-abc' or '-fvalue'.
+        """
+        for arg in args:
+            if arg == "--":
+                break
+            if (
+                arg[:1] == "-"
+                and arg[:2] != "--"
+                and arg.split("=", 1)[0] not in self._option_string_actions
+                and arg[:2] in self._option_string_actions
+                and not _NEGATIVE_NUMBER_REGEX.fullmatch(arg)
+            ):
+                self.error(
+                    "unrecognized option {!r}, short options can't be combined "
+                    "or have attached values".format(arg)
+                )
+
+    def parse_known_args(self, args=None, namespace=None):
+        args = sys.argv[1:] if args is None else list(args)
+        if not getattr(self.node.root, "short_clusters", True):
+            self._check_no_short_clusters(args)
+        if self.node.get_negative_numbers():
+            args = _join_negative_number_values(self.node, args)
+        namespace, extras = super().parse_known_args(args, namespace)
+        try:
+            _resolve_values(
+                self.node,
+                namespace,
+                self.config,
+                global_args=self.resolve_global_args,
+                validators=self.validators,
+            )
+        except ArgValueError as e:
+            self.error(str(e))
+        return namespace, extras
+
 
 class AbstractCLIParser(metaclass=abc.ABCMeta):
     """Abstract base class for CLI parsers."""
 
-    def __init__(self, schema: RootNode, **kwargs):
+    def __init__(
+        self,
+        schema: RootNode,
+        *,
+        validators: Optional[Mapping[str, Validator]] = None,
+        role_provider: Optional[RoleProvider] = None,
+        **kwargs
+    ):
         """
         :param schema:
             The schema for the arg parsing.
+        :param validators:
+            Mapping of names to validators, for args declaring a 'validator'.
+            Validators are called with each converted value and raise
+            ValueError with a message if the value is invalid.
+        :param role_provider:
+            Callback giving the roles of the user, called once here. Commands
+            with 'requires_role' are hidden and rejected by this parser unless
+            the user has one of their roles, including when no callback is
+            given.
+        """
+        self._schema = schema
+        self._validators = dict(validators) if validators else {}
+        roles = role_provider() if role_provider is not None else None
+        self._roles = frozenset(roles or [])
+
+    @property
+    def schema(self) -> RootNode:
+        """The schema for the arg parsing."""
+        return self._schema
+
+    @property
+    def roles(self) -> FrozenSet[str]:
+        """The roles of the user, given by the 'role_provider'."""
+        return self._roles
+
+    def register_validator(self, name: str, validator: Optional[Validator] = None):
+        """
+        Register a validator for args declaring 'validator: <name>'.
+
+        May be used as a decorator if the validator is not given, e.g.:
+
+            @parser.register_validator("port_available")
+            def check_port(port): ...
+
+        :param name:
+            The validator name, as given in the schema.
+        :param validator:
+            The function to call with each of the arg's converted values,
+            raising ValueError with a message if the value is invalid.
+        :raises ValueError:
+            If a validator is already registered with the name.
         """
-        pass
+        if validator is None:
+
+            def decorator(func: Validator) -> Validator:
+                self.register_validator(name, func)
+                return func
+
+            return decorator
+
+        if name in self._validators:
+            raise ValueError("Validator already registered named {!r}".format(name))
+        self._validators[name] = validator
+        return validator
 
     @abc.abstractmethod
-    def parse_args(self, args: Optional[List[str]] = None) -> Namespace:
-        pass
+    def parse_args(self, args: Optional[Iterable[ArgLike]] = None) -> Namespace:
+        """
+        Parse args according to the schema.
+
+        Any iterable of args may be given, e.g. for tests or when embedding
+        the CLI in another program, without needing to go through 'sys.argv'.
+
+        Args that aren't valid UTF-8 (surrogate escaped in 'sys.argv', or
+        given as bytes) are only accepted for args of type 'path' or 'text'
+        and passthrough args, which preserve them.
+
+        As well as the parsed values, the namespace has the 'command' to run
+        and the 'command_node' it was parsed for, since different nodes may
+        run the same command.
+
+        :param args:
+            The args to parse, defaults to the process's args.
+        """
+
+    def parse_chain(self, args: Optional[Iterable[ArgLike]] = None) -> List[Namespace]:
+        """
+        Parse args that may contain multiple commands, if the schema has
+        'chaining' settings, e.g. 'build + test'.
+
+        All the commands are parsed before any are run, so that a usage error
+        in any of them is reported up front. Without 'chaining' settings the
+        args are parsed as a single command.
+
+        :param args:
+            The args to parse, defaults to the process's args.
+        :return:
+            The parsed args for each command, in order.
+        """
+        if args is None:
+            args = sys.argv[1:]
+        args = _decode_args(args)
+        if self._schema.chaining is None:
+            return [self.parse_args(args)]
+        return [self.parse_args(x) for x in self._schema.chaining.split_args(args)]
+
+    def parse_into(
+        self,
+        target: Union[Type[T], Mapping[str, Type[T]]],
+        args: Optional[Iterable[ArgLike]] = None,
+    ) -> T:
+        """
+        Parse args and use them to construct an instance of a user class.
+
+        The class is called with keyword arguments taken from the parsed
+        namespace, matched against the parameters of its constructor. This
+        works with plain classes, named tuples and dataclasses.
+
+        :param target:
+            The class to construct, or a mapping of command names (as given in
+            the schema) to the class to construct for that command.
+        :param args:
+            The args to parse.
+        :return:
+            The constructed instance.
+        :raises ValueError:
+            If the parsed command is not in the given mapping.
+        :raises TypeError:
+            If the class requires a parameter that isn't a parsed arg.
+        """
+        namespace = self.parse_args(args)
+        if isinstance(target, Mapping):
+            try:
+                cls = target[namespace.command]
+            except KeyError:
+                raise ValueError(
+                    "No class given for command {!r}".format(namespace.command)
+                ) from None
+        else:
+            cls = target
+        return _construct_from_namespace(cls, namespace)
+
+
+def _construct_from_namespace(cls: Type[T], namespace: Namespace) -> T:
+    """Construct a class using the matching attributes of a namespace."""
+    values = vars(namespace)
+    params = inspect.signature(cls).parameters.values()
+    if any(p.kind is p.VAR_KEYWORD for p in params):
+        return cls(**values)
+    kwargs = {
+        p.name: values[p.name]
+        for p in params
+        if p.name in values
+        and p.kind in (p.POSITIONAL_OR_KEYWORD, p.KEYWORD_ONLY)
+    }
+    return cls(**kwargs)
@@ -7,19 +7,31 @@ CLI parsing in bot style.
 
 __all__ = ("CLIParser",)
 
-import argparse
 import sys
-from typing import List, NoReturn, Optional, Text
+from typing import Iterable, NoReturn, Optional, Text
 
+from .._config import ConfigError, load_config
 from .._schema import NodeBase, RootNode
-from . import AbstractCLIParser, Namespace
+from .._types import ArgType
+from . import (
+    AbstractCLIParser,
+    ArgLike,
+    Namespace,
+    _add_node_args,
+    _decode_args,
+    _NodeArgumentParser,
+    _redact_secrets,
+    _is_shown,
+    _unauthorized_exit_code,
+    _unauthorized_message,
+)
 
 
 class _ArgParseError(Exception):
     """Error parsing args."""
 
 
-class _CustomArgumentParser(argparse.ArgumentParser):
+class _CustomArgumentParser(_NodeArgumentParser):
     """Customised version of an argument parser from argparse."""
 
     def error(self, message: Text) -> NoReturn:
@@ -37,16 +49,17 @@ class CLIParser(AbstractCLIParser):
             Passed to base class.
         """
         super().__init__(schema, **kwargs)
-        self._schema = schema
 
-    def parse_args(self, args: Optional[List[str]] = None, namespace=None) -> Namespace:
+    def parse_args(
+        self, args: Optional[Iterable[ArgLike]] = None, namespace=None
+    ) -> Namespace:
         if args is None:
-            args = sys.argv
+            args = sys.argv[1:]
         if namespace is None:
             namespace = Namespace()
 
-        # Take a copy of the args.
-        remaining_args = list(args)
+        # Take a copy of the args, as strings.
+        remaining_args = _decode_args(args)
 
         # Determine whether to show help output.
         show_help = False
@@ -65,38 +78,51 @@ class CLIParser(AbstractCLIParser):
         consumed_args = []
         while node.subtree and remaining_args:
             arg = remaining_args[0]
-            keywords = {x.keyword: x for x in node.subtree}
-            if arg in keywords:
+            subnode = node.get_subnode(arg)
+            if subnode is not None:
                 consumed_args.append(remaining_args.pop(0))
-                node = keywords[arg]
+                node = subnode
             else:
                 break
+        while node.default is not None and not remaining_args:
+            if node.shows_help_by_default:
+                show_help = True
+                break
+            subnode = node.get_default_subnode()
+            if subnode is None:
+                break
+            node = subnode
+
+        unauthorized_message = _unauthorized_message(node, self._roles)
+        if unauthorized_message is not None:
+            print("Error:", unauthorized_message, file=sys.stderr)
+            sys.exit(_unauthorized_exit_code(node))
 
         if show_help:
             print(self.format_help(node))
             sys.exit(0)
 
+        try:
+            config = load_config(self._schema)
+        except ConfigError as e:
+            print("Error loading config:", e, file=sys.stderr)
+            sys.exit(2)
+
         # Use argparse to parse the command, but don't let it give error output.
-        parser = _CustomArgumentParser(add_help=False)
+        parser = _CustomArgumentParser(
+            node=node, config=config, add_help=False, validators=self._validators
+        )
 
         argv_for_argparse = remaining_args.copy()
-        for arg in node.args:
-            if arg.positional:
-                name = arg.name.replace("-", "_")
-            else:
+        for arg in node.all_args:
+            if not arg.positional:
                 # Convert optional args to use dashes.
-                name = "--" + arg.name
                 # TODO: This is a hack, relying on no arg name/value clashes.
                 #  This also unintentionally allows specifying with the dashes!
                 argv_for_argparse = [
-                    "--" + a if a == arg.name else a for a in argv_for_argparse
+                    "--" + a if a == arg.long else a for a in argv_for_argparse
                 ]
-            kwargs = dict()
-            if arg.type is bool:
-                kwargs["action"] = "store_true"
-            elif arg.type is list:
-                kwargs["nargs"] = argparse.REMAINDER
-            parser.add_argument(name, **kwargs)
+        _add_node_args(parser, node, short_options=False)
 
         try:
             namespace = parser.parse_args(argv_for_argparse, namespace)
@@ -106,23 +132,25 @@ class CLIParser(AbstractCLIParser):
             sys.exit(2)
 
         namespace.command = node.command
-        namespace.remaining_args = remaining_args
+        namespace.command_node = node
+        # The options were converted one for one, so are redacted by position.
+        redacted = _redact_secrets(node, argv_for_argparse, short_options=False)
+        namespace.remaining_args = [
+            r if r != a else w
+            for r, a, w in zip(redacted, argv_for_argparse, remaining_args)
+        ]
 
         return namespace
 
     def format_help(self, node: NodeBase) -> str:
         """Format help text for a given node."""
         # Start with the keywords already entered.
-        tmp_node = node
-        keywords = ["<bot>"]
-        while tmp_node.keyword:
-            keywords.insert(1, tmp_node.keyword)
-            tmp_node = tmp_node.parent
+        keywords = ["<bot>"] + node.path
 
         # Include subnode options if not at the end of a chain.
         valid_end_of_chain = node.command is not None
         if node.subtree:
-            options = [n.keyword for n in node.subtree]
+            options = [n.keyword for n in node.subtree if _is_shown(n, self._roles)]
             if valid_end_of_chain:
                 brace_chars = "[]"
             else:
@@ -133,11 +161,13 @@ class CLIParser(AbstractCLIParser):
                 opts_string = ""
         else:
             options = []
-            for arg_opt in node.args:
-                if arg_opt.type is bool:
-                    options.append(arg_opt.name)
+            for arg_opt in node.all_args:
+                if arg_opt.hidden:
+                    continue
+                if arg_opt.type is ArgType.FLAG:
+                    options.append(arg_opt.long)
                 else:
-                    options.append(arg_opt.name + " ...")
+                    options.append(arg_opt.long + " ...")
             opts_string = " ".join("[{}]".format(opt) for opt in options)
 
         return " ".join(keywords + [opts_string])
@@ -5,44 +5,326 @@ CLI parsing in argparse style.
 
 """
 
-__all__ = ("CLIParser",)
+__all__ = ("CLIParser", "build_argparse_parser")
 
 import argparse
+import copy
+import datetime
+import json
+import os
+import pathlib
+import subprocess
 import sys
-from typing import List, Optional
+from typing import Any, FrozenSet, Iterable, List, Mapping, NoReturn, Optional
 
-from .._schema import RootNode
-from . import AbstractCLIParser, Namespace
+from .._completions import SHELLS, generate_completions
+from .._config import ConfigError, extract_config_option, load_config
+from .._help import COLOR_MODES, DEFAULT_THEME, HelpTheme, use_color
+from .._plugins import add_plugin_nodes, find_plugin
+from .._schema import NodeBase, RootNode
+from .._utils import expand_response_files, extract_flag, extract_option
+from . import (
+    AbstractCLIParser,
+    ArgLike,
+    ArgSource,
+    Namespace,
+    Validator,
+    _add_node_args,
+    _get_deprecation_warnings,
+    _help_text,
+    _is_shown,
+    _add_version_option,
+    _decode_args,
+    _NodeArgumentParser,
+    _redact_secrets,
+    _resolve_values,
+    _unauthorized_exit_code,
+    _unauthorized_message,
+    _unknown_command_message,
+)
+
+
+def _add_subtree(
+    parser: argparse.ArgumentParser,
+    node: NodeBase,
+    config: Mapping[str, Any],
+    *,
+    prog: str,
+    validators: Mapping[str, Validator]
+) -> None:
+    """Recursively add a node's args and subtree to an argparse parser."""
+    parser.set_defaults(command=node.command)
+    _add_node_args(parser, node)
+    _add_version_option(parser, node.root, prog=prog)
+    if node.subtree:
+        subparsers = parser.add_subparsers(
+            title="submodes", metavar=_subtree_metavar(node, None)
+        )
+        subparsers.required = node.command is None
+        for subnode in node.subtree:
+            # Hidden aliases get their own parser so they aren't shown in help.
+            names = [(subnode.keyword, subnode.aliases)]
+            names += [(alias, []) for alias in subnode.hidden_aliases]
+            for i, (name, aliases) in enumerate(names):
+                kwargs = dict(description=subnode.help)
+                if i == 0 and not subnode.hidden:
+                    kwargs["help"] = _help_text(subnode)
+                subparser = subparsers.add_parser(
+                    name,
+                    aliases=aliases,
+                    node=subnode,
+                    config=config,
+                    # Global args are resolved by the root parser, since
+                    # argparse overwrites values given before the subcommand.
+                    resolve_global_args=False,
+                    validators=validators,
+                    formatter_class=argparse.RawDescriptionHelpFormatter,
+                    **kwargs
+                )
+                _add_subtree(
+                    subparser, subnode, config, prog=prog, validators=validators
+                )
+
+
+def _subtree_metavar(node: NodeBase, roles: Optional[FrozenSet[str]]) -> str:
+    """The metavar listing the visible keywords of a node's subtree."""
+    return "{" + ",".join(n.keyword for n in node.subtree if _is_shown(n, roles)) + "}"
+
+
+def build_argparse_parser(
+    schema: RootNode,
+    *,
+    prog: Optional[str] = None,
+    validators: Optional[Mapping[str, Validator]] = None
+) -> argparse.ArgumentParser:
+    """
+    Build a complete argparse parser for a schema.
+
+    Each node in the schema's subtree becomes a nested subparser, and the
+    'command' attribute of the parsed namespace is set to the command of the
+    node that was reached.
+
+    Note that argparse is unable to handle nodes that have both positional
+    args and a subtree - use `CLIParser` for full support of the schema. The
+    '--config' option, response files and the 'unknown_args' settings of nodes
+    other than the root and nodes' 'default' subcommands are also not
+    supported, although the config file declared in the schema is used.
+
+    :param schema:
+        The schema to build the parser from.
+    :param prog:
+        The program name.
+    :param validators:
+        Mapping of names to validators, for args declaring a 'validator'.
+    :return:
+        The argparse parser.
+    :raises ConfigError:
+        If the schema's config file can't be loaded.
+    """
+    config = load_config(schema)
+    parser = _NodeArgumentParser(
+        prog=prog,
+        node=schema,
+        config=config,
+        validators=validators,
+        description=schema.help,
+        formatter_class=argparse.RawDescriptionHelpFormatter,
+    )
+    _add_subtree(
+        parser, schema, config, prog=parser.prog, validators=validators or {}
+    )
+    return parser
 
 
 class CLIParser(AbstractCLIParser):
     """Argument parser based on argparse."""
 
-    def __init__(self, schema: RootNode, *, prog: Optional[str] = None, **kwargs):
+    def __init__(
+        self,
+        schema: RootNode,
+        *,
+        prog: Optional[str] = None,
+        completions_command: bool = True,
+        deprecation_warnings: bool = True,
+        color: str = "auto",
+        color_option: bool = False,
+        theme: HelpTheme = DEFAULT_THEME,
+        introspect_command: bool = False,
+        debug_args_option: bool = False,
+        **kwargs
+    ):
         """
         :param schema:
-            The schema for the arg parsing.
+            The schema for the arg parsing. If it declares 'binaries', the
+            schema of the program named by 'prog' (or 'argv[0]') is used, see
+            `RootNode.get_binary()`. If it declares plugin settings, nodes are
+            added to it for the plugins that ship a schema fragment, see
+            `add_plugin_nodes()`. Unknown top-level commands are run as
+            plugins if found, with the args after the command.
         :param prog:
             The program name.
+        :param completions_command:
+            Whether to accept a hidden 'completions <shell>' command for
+            printing a shell completion script. This is ignored if the schema
+            declares a 'completions' keyword at the top level.
+
+            Similarly, a hidden 'resolved-config [<keyword> ...]' command is
+            accepted for schemas declaring a config file, for printing the
+            value and source of each arg of the given command.
+        :param deprecation_warnings:
+            Whether to print a warning to stderr when deprecated commands or
+            args are used.
+        :param color:
+            When to colour the help output, one of 'auto', 'always' or
+            'never'. In 'auto' mode colour is used when writing to a terminal
+            and the 'NO_COLOR' environment variable is not set.
+        :param color_option:
+            Whether to accept a '--color=WHEN' option overriding 'color'.
+        :param theme:
+            The colour theme for the help output.
+        :param introspect_command:
+            Whether to accept a hidden '__introspect' command for printing the
+            command tree as JSON, for use by wrapper tools.
+        :param debug_args_option:
+            Whether to accept a hidden '--debug-args' flag, which prints the
+            command's resolved args as JSON and exits instead of returning
+            them, for diagnosing where each value came from. Off by default, as
+            it's meant for the CLI's developers rather than its users.
         :param kwargs:
             Passed to base class.
         """
+        if schema.binaries:
+            schema = schema.get_binary(prog or os.path.basename(sys.argv[0]))
         super().__init__(schema, **kwargs)
-        self._schema = schema
         self._prog = prog
+        self._completions_command = completions_command
+        self._deprecation_warnings = deprecation_warnings
+        if color not in COLOR_MODES:
+            raise ValueError("Invalid color mode {!r}".format(color))
+        self._color = color
+        self._color_option = color_option
+        self._theme = theme
+        self._introspect_command = introspect_command
+        self._debug_args_option = debug_args_option
+        if schema.plugins is not None:
+            add_plugin_nodes(schema)
 
-    def parse_args(self, args: Optional[List[str]] = None, namespace=None) -> Namespace:
+    def parse_args(
+        self, args: Optional[Iterable[ArgLike]] = None, namespace=None
+    ) -> Namespace:
         if args is None:
-            args = sys.argv
+            args = sys.argv[1:]
+
+        # Take a copy of the args, as strings.
+        remaining_args = _decode_args(args)
+
+        if self._schema.response_files:
+            try:
+                remaining_args = expand_response_files(remaining_args)
+            except ValueError as e:
+                self._error(str(e))
+
+        if (
+            self._completions_command
+            and remaining_args[:1] == ["completions"]
+            and "completions" not in {x.keyword for x in self._schema.subtree}
+        ):
+            self._print_completions(remaining_args[1:])
 
-        # Take a copy of the args.
-        remaining_args = list(args)
-        # Loop through the args until we find a non-keyword.
-        node = self._schema
+        if self._introspect_command and remaining_args == ["__introspect"]:
+            print(self._schema.to_json())
+            sys.exit(0)
+
+        debug_args = False
+        if self._debug_args_option:
+            debug_args, remaining_args = extract_flag(remaining_args, "--debug-args")
+
+        schema = self._schema
+        if schema.has_translations:
+            try:
+                lang, remaining_args = extract_option(remaining_args, "--lang")
+            except ValueError as e:
+                self._error(str(e))
+            if lang is not None:
+                # Localize a copy, since the schema may be shared, e.g. by
+                # other parsers or threads.
+                schema = copy.deepcopy(schema)
+                schema.localize(lang)
+
+        experimental_enabled = False
+        if schema.has_experimental:
+            experimental_enabled, remaining_args = extract_flag(
+                remaining_args, "--enable-experimental"
+            )
+            env_var = schema.experimental_env
+            if env_var and os.environ.get(env_var, "") not in ("", "0"):
+                experimental_enabled = True
+
+        color = self._color
+        if self._color_option:
+            try:
+                color_arg, remaining_args = extract_option(remaining_args, "--color")
+            except ValueError as e:
+                self._error(str(e))
+            if color_arg is not None:
+                if color_arg not in COLOR_MODES:
+                    self._error(
+                        "argument --color: invalid choice: {!r} "
+                        "(choose from {})".format(color_arg, ", ".join(COLOR_MODES))
+                    )
+                color = color_arg
+
+        # Load the config file, which may be given with '--config'.
+        config_path = None
+        if schema.config is not None and schema.config.option:
+            try:
+                config_path, remaining_args = extract_config_option(remaining_args)
+            except ConfigError as e:
+                self._error(str(e))
+        try:
+            config = load_config(schema, config_path)
+        except ConfigError as e:
+            self._error(str(e))
+
+        if (
+            schema.config is not None
+            and remaining_args[:1] == ["resolved-config"]
+            and "resolved-config" not in {x.keyword for x in schema.subtree}
+        ):
+            self._print_resolved_config(remaining_args[1:], config)
+
+        # Loop through the args until we find a non-keyword. Global args may
+        # appear before keywords, so are set aside to be parsed at the end.
+        global_options = {
+            opt: arg
+            for arg in schema.all_global_args
+            for opt in arg.option_strings + [arg.file_option_string]
+            if opt is not None
+        }
+        node = schema
         consumed_args = []
+        global_args = []
         show_help = False
         while node.subtree and remaining_args:
             arg = remaining_args[0]
+            option = arg.split("=", 1)[0]
+            if option in global_options:
+                global_args.append(remaining_args.pop(0))
+                if (
+                    "=" not in arg
+                    and global_options[option].type.takes_value
+                    and remaining_args
+                ):
+                    global_args.append(remaining_args.pop(0))
+                continue
+            if (
+                schema.short_clusters
+                and arg[:2] in global_options
+                and not arg.startswith("--")
+            ):
+                # Combined short options or an attached value, e.g. '-vv'.
+                global_args.append(remaining_args.pop(0))
+                continue
             if arg in ["-h", "--help"]:
                 # TODO: Not sure how best to handle a 'help' arg:
                 #   - Accept anywhere or only after the last given keyword
@@ -52,13 +334,58 @@ class CLIParser(AbstractCLIParser):
                 show_help = True
                 remaining_args.pop(0)
                 continue
-            keywords = {x.keyword: x for x in node.subtree}
-            if arg in keywords:
+            subnode = node.get_subnode(arg)
+            if subnode is None and node.get_abbreviations() and arg:
+                subnode = self._match_abbreviation(node, arg)
+            if subnode is not None and subnode.plugin_path is not None:
+                self._run_plugin(subnode.plugin_path, remaining_args[1:])
+            elif subnode is not None:
+                if subnode.experimental and not experimental_enabled:
+                    self._error(
+                        "{!r} is an experimental command, {}".format(
+                            " ".join(subnode.path), self._describe_experimental()
+                        )
+                    )
                 consumed_args.append(remaining_args.pop(0))
-                node = keywords[arg]
+                node = subnode
             else:
+                if node is schema and schema.plugins is not None:
+                    plugin_path = find_plugin(schema.plugins, arg)
+                    if plugin_path is not None:
+                        self._run_plugin(plugin_path, remaining_args[1:])
+                break
+
+        # Nodes without a command may have a default subcommand to run (or show
+        # their help) when no subcommand is given.
+        while (
+            node.default is not None
+            and not show_help
+            and not (remaining_args and not remaining_args[0].startswith("-"))
+        ):
+            if node.shows_help_by_default:
+                show_help = True
+                break
+            subnode = node.get_default_subnode()
+            if subnode is None:
                 break
+            consumed_args.append(subnode.keyword)
+            node = subnode
 
+        unauthorized_message = _unauthorized_message(node, self._roles)
+        if unauthorized_message is not None:
+            self._error(unauthorized_message, exit_code=_unauthorized_exit_code(node))
+
+        # A word that isn't a keyword is only valid as a positional arg.
+        unknown_keyword = None
+        if (
+            node.subtree
+            and remaining_args
+            and not remaining_args[0].startswith("-")
+            and not any(a.positional for a in node.all_args)
+        ):
+            unknown_keyword = remaining_args[0]
+
+        remaining_args = global_args + remaining_args
         if show_help:
             remaining_args.insert(0, "--help")
 
@@ -67,29 +394,243 @@ class CLIParser(AbstractCLIParser):
             prog_args = [self._prog] + consumed_args
         else:
             prog_args = consumed_args
-        parser = argparse.ArgumentParser(
+        parser = _NodeArgumentParser(
             prog=" ".join(prog_args),
+            node=node,
+            config=config,
+            theme=self._theme if use_color(color, sys.stdout) else None,
+            validators=self._validators,
+            use_pager=schema.pager and "--no-pager" not in remaining_args,
+            roles=self._roles,
             description=node.help,
             formatter_class=argparse.RawDescriptionHelpFormatter,
         )
         # Use subparsers to represent the subnodes in displayed help.
         if node.subtree and show_help:
-            subparsers = parser.add_subparsers(title="submodes")
-            subparsers.required = node.command is None
+            subparsers = parser.add_subparsers(
+                title="submodes", metavar=_subtree_metavar(node, self._roles)
+            )
+            subparsers.required = node.command is None and node.default is None
             for subnode in node.subtree:
-                subparsers.add_parser(subnode.keyword, help=subnode.help)
+                if _is_shown(subnode, self._roles):
+                    subparsers.add_parser(
+                        subnode.keyword,
+                        aliases=subnode.aliases,
+                        node=subnode,
+                        help=_help_text(subnode),
+                    )
         # Add arguments for end-of-command.
-        for arg in node.args:
-            name = arg.name.replace("-", "_") if arg.positional else "--" + arg.name
-            kwargs = dict()
-            kwargs["help"] = arg.help
-            if arg.type is bool:
-                kwargs["action"] = "store_true"
-            elif arg.type is list:
-                kwargs["nargs"] = argparse.REMAINDER
-            parser.add_argument(name, **kwargs)
+        _add_node_args(parser, node)
+        _add_version_option(parser, schema, prog=self._get_prog())
+        if schema.config is not None and schema.config.option:
+            # The option has already been handled, this is just for the help.
+            try:
+                parser.add_argument(
+                    "--config",
+                    metavar="FILE",
+                    default=argparse.SUPPRESS,
+                    help="Config file to load arg values from",
+                )
+            except argparse.ArgumentError:
+                pass
+        if schema.has_experimental:
+            # Similarly, this is just for the help.
+            try:
+                parser.add_argument(
+                    "--enable-experimental",
+                    action="store_true",
+                    default=argparse.SUPPRESS,
+                    help="Enable experimental commands and args",
+                )
+            except argparse.ArgumentError:
+                pass
+        if schema.has_translations:
+            # Similarly, this is just for the help.
+            try:
+                parser.add_argument(
+                    "--lang",
+                    metavar="LANG",
+                    default=argparse.SUPPRESS,
+                    help="The language for help text, e.g. 'fr'",
+                )
+            except argparse.ArgumentError:
+                pass
+        if self._color_option:
+            # Similarly, this is just for the help.
+            try:
+                parser.add_argument(
+                    "--color",
+                    metavar="WHEN",
+                    default=argparse.SUPPRESS,
+                    help="When to use colour: auto, always or never",
+                )
+            except argparse.ArgumentError:
+                pass
+
+        if unknown_keyword is not None and not show_help:
+            parser.error(_unknown_command_message(node, unknown_keyword, self._roles))
+
+        # Args after '--' are captured verbatim by a passthrough arg.
+        passthrough_arg = next((a for a in node.all_args if a.passthrough), None)
+        if passthrough_arg is not None and "--" in remaining_args:
+            index = remaining_args.index("--")
+            if namespace is None:
+                namespace = Namespace()
+            setattr(namespace, passthrough_arg.dest, remaining_args[index + 1 :])
+            remaining_args = remaining_args[:index]
 
         args_ns = parser.parse_args(remaining_args, namespace)
         args_ns.command = node.command
-        args_ns.remaining_args = remaining_args
+        args_ns.command_node = node
+        args_ns.remaining_args = _redact_secrets(node, remaining_args)
+        self._check_experimental(node, args_ns, enabled=experimental_enabled)
+        if self._deprecation_warnings:
+            for msg in _get_deprecation_warnings(node, args_ns):
+                print("{}: warning: {}".format(self._get_prog(), msg), file=sys.stderr)
+        if debug_args:
+            self._print_debug_args(node, args_ns)
         return args_ns
+
+    def _match_abbreviation(self, node: NodeBase, prefix: str) -> Optional[NodeBase]:
+        """
+        Find the subnode a keyword prefix selects.
+
+        :return:
+            The subnode, or None if there is no match.
+        """
+        matches = [
+            n
+            for n in node.get_subnodes_by_prefix(prefix)
+            if n.find_unauthorized(self._roles) is None
+        ]
+        if len(matches) > 1:
+            self._error(
+                "ambiguous command {!r}, could be: {}".format(
+                    prefix, ", ".join(n.keyword for n in matches)
+                )
+            )
+        return matches[0] if matches else None
+
+    def _describe_experimental(self) -> str:
+        """Describe how to enable experimental commands, for error messages."""
+        msg = "enable experimental commands with --enable-experimental"
+        if self._schema.experimental_env:
+            msg += " or by setting {}".format(self._schema.experimental_env)
+        return msg
+
+    def _check_experimental(
+        self, node: NodeBase, namespace: Namespace, *, enabled: bool
+    ) -> None:
+        """
+        Reject experimental args given on the command line unless enabled, and
+        warn about any experimental command or args being used.
+        """
+        given = [
+            arg
+            for arg in node.all_args
+            if arg.experimental
+            and namespace.arg_sources.get(arg.dest, ArgSource.DEFAULT)
+            is not ArgSource.DEFAULT
+        ]
+        if given and not enabled:
+            self._error(
+                "{} is an experimental option, {}".format(
+                    given[0].option_strings[-1], self._describe_experimental()
+                )
+            )
+        used = []
+        parent = node  # type: Optional[NodeBase]
+        while parent is not None:
+            if getattr(parent, "experimental", False):
+                used.insert(0, "command {!r}".format(" ".join(parent.path)))
+            parent = parent.parent
+        used += ["option {}".format(a.option_strings[-1]) for a in given]
+        for what in used:
+            print(
+                "{}: warning: {} is experimental and may change or be "
+                "removed".format(self._get_prog(), what),
+                file=sys.stderr,
+            )
+
+    def _get_prog(self) -> str:
+        return self._prog if self._prog else os.path.basename(sys.argv[0])
+
+    def _run_plugin(self, path: pathlib.Path, args: List[str]) -> NoReturn:
+        """Run an external plugin command, exiting with its exit code."""
+        try:
+            sys.exit(subprocess.call([str(path)] + args))
+        except OSError as e:
+            self._error("unable to run plugin {}: {}".format(path, e.strerror))
+
+    def _error(self, message: str, *, exit_code: int = 2) -> NoReturn:
+        """Report a usage error in the same format as argparse."""
+        print("{}: error: {}".format(self._get_prog(), message), file=sys.stderr)
+        sys.exit(exit_code)
+
+    def _print_completions(self, args: List[str]) -> NoReturn:
+        """Handle the hidden 'completions' command."""
+        prog = self._get_prog()
+        parser = argparse.ArgumentParser(
+            prog=prog + " completions",
+            description="Print a shell completion script.",
+        )
+        parser.add_argument("shell", choices=SHELLS, help="The shell to complete")
+        shell = parser.parse_args(args).shell
+        print(
+            generate_completions(self._schema, shell, prog=prog, roles=self._roles),
+            end="",
+        )
+        sys.exit(0)
+
+    def _print_debug_args(self, node: NodeBase, namespace: Namespace) -> NoReturn:
+        """
+        Handle the hidden '--debug-args' flag, printing the command and the
+        type, value and source of each of its args as JSON.
+        """
+
+        def to_json(value: Any) -> Any:
+            if isinstance(value, datetime.timedelta):
+                return value.total_seconds()
+            elif isinstance(value, datetime.datetime):
+                return value.isoformat()
+            # Secrets are redacted, and paths and readers given as strings.
+            return str(value)
+
+        data = {
+            "command": node.command,
+            "path": node.path,
+            "args": {
+                arg.name: {
+                    "type": arg.type.value,
+                    "value": getattr(namespace, arg.dest, None),
+                    "source": namespace.arg_sources.get(
+                        arg.dest, ArgSource.DEFAULT
+                    ).value,
+                }
+                for arg in node.all_args
+            },
+        }
+        print(json.dumps(data, indent=2, default=to_json))
+        sys.exit(0)
+
+    def _print_resolved_config(
+        self, keywords: List[str], config: Mapping[str, Any]
+    ) -> NoReturn:
+        """Handle the hidden 'resolved-config' command."""
+        node = self._schema.find(keywords)
+        if node is None:
+            self._error("unknown command: {}".format(" ".join(keywords)))
+        namespace = Namespace()
+        try:
+            _resolve_values(node, namespace, config, validators=self._validators)
+        except ValueError as e:
+            self._error(str(e))
+        for arg in node.all_args:
+            print(
+                "{} = {!r} ({})".format(
+                    arg.name,
+                    getattr(namespace, arg.dest),
+                    namespace.arg_sources[arg.dest].value,
+                )
+            )
+        sys.exit(0)
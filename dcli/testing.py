@@ -0,0 +1,180 @@
+"""
+Helpers for applications to regression-test their CLI.
+
+Snapshots of the help output for every node are stored as text files in a
+directory, which can be (re)generated by passing 'update=True' or setting the
+'DCLI_UPDATE_SNAPSHOTS' environment variable, e.g.:
+
+    parser = CLIParser(schema, prog="myapp")
+
+    def test_help():
+        assert_help_snapshots(parser, "tests/snapshots")
+
+    def test_venv_args():
+        assert_parses(parser, ["venv", "--name", "x"], command="make-venv", name="x")
+
+"""
+
+__all__ = (
+    "assert_help_snapshots",
+    "assert_parses",
+    "check_help_snapshots",
+    "render_all_help",
+)
+
+import contextlib
+import difflib
+import io
+import os
+import pathlib
+from typing import Any, Dict, List, Optional
+
+from ._utils import PathLike
+from .clis import AbstractCLIParser, Namespace
+
+
+# The terminal width used when rendering help, so that snapshots don't depend
+# on the terminal the tests are run in.
+_HELP_WIDTH = 80
+
+
+@contextlib.contextmanager
+def _fixed_terminal_width():
+    old_columns = os.environ.get("COLUMNS")
+    os.environ["COLUMNS"] = str(_HELP_WIDTH)
+    try:
+        yield
+    finally:
+        if old_columns is None:
+            del os.environ["COLUMNS"]
+        else:
+            os.environ["COLUMNS"] = old_columns
+
+
+def render_all_help(parser: AbstractCLIParser) -> Dict[str, str]:
+    """
+    Render the help output for every node in the parser's schema, excluding
+    hidden nodes.
+
+    :param parser:
+        The CLI parser.
+    :return:
+        Mapping of each node's keywords, joined with spaces (an empty string
+        for the root node), to its help output.
+    """
+    result = {}
+    for node in parser.schema.walk():
+        if node.is_hidden:
+            continue
+        stdout = io.StringIO()
+        with _fixed_terminal_width(), contextlib.redirect_stdout(stdout):
+            try:
+                parser.parse_args(node.path + ["--help"])
+            except SystemExit:
+                pass
+        result[" ".join(node.path)] = stdout.getvalue()
+    return result
+
+
+def _snapshot_path(directory: pathlib.Path, command: str) -> pathlib.Path:
+    return directory / "{}.txt".format(command.replace(" ", "_") or "root")
+
+
+def check_help_snapshots(
+    parser: AbstractCLIParser, directory: PathLike, *, update: Optional[bool] = None
+) -> List[str]:
+    """
+    Compare the help output for every node against stored snapshots.
+
+    :param parser:
+        The CLI parser.
+    :param directory:
+        The directory containing the snapshot files.
+    :param update:
+        Whether to write the current help output to the snapshot files instead
+        of comparing, defaults to whether 'DCLI_UPDATE_SNAPSHOTS' is set.
+    :return:
+        A list of the mismatches, each with a diff, empty if all match.
+    """
+    if update is None:
+        update = bool(os.environ.get("DCLI_UPDATE_SNAPSHOTS"))
+    directory = pathlib.Path(directory)
+    problems = []
+    for command, text in render_all_help(parser).items():
+        path = _snapshot_path(directory, command)
+        if update:
+            directory.mkdir(parents=True, exist_ok=True)
+            path.write_text(text)
+            continue
+        try:
+            expected = path.read_text()
+        except FileNotFoundError:
+            problems.append("{}: missing help snapshot".format(path))
+            continue
+        if text != expected:
+            diff = difflib.unified_diff(
+                expected.splitlines(keepends=True),
+                text.splitlines(keepends=True),
+                fromfile=str(path),
+                tofile="current help",
+            )
+            problems.append(
+                "{}: help output differs from snapshot:\n{}".format(
+                    path, "".join(diff)
+                )
+            )
+    return problems
+
+
+def assert_help_snapshots(
+    parser: AbstractCLIParser, directory: PathLike, *, update: Optional[bool] = None
+) -> None:
+    """
+    Assert the help output for every node matches the stored snapshots, see
+    `check_help_snapshots()`.
+
+    :raises AssertionError:
+        If any help output differs or has no snapshot.
+    """
+    problems = check_help_snapshots(parser, directory, update=update)
+    if problems:
+        raise AssertionError("\n".join(problems))
+
+
+def assert_parses(
+    parser: AbstractCLIParser, args: List[str], **expected: Any
+) -> Namespace:
+    """
+    Assert that args parse, and that the parsed values are as expected.
+
+    :param parser:
+        The CLI parser.
+    :param args:
+        The args to parse.
+    :param expected:
+        Expected attributes of the parsed namespace, e.g. 'command' or the
+        dest of an arg.
+    :return:
+        The parsed namespace.
+    :raises AssertionError:
+        If parsing fails or a value differs from what's expected.
+    """
+    stderr = io.StringIO()
+    try:
+        with contextlib.redirect_stderr(stderr):
+            namespace = parser.parse_args(list(args))
+    except SystemExit:
+        raise AssertionError(
+            "Failed to parse {!r}: {}".format(args, stderr.getvalue().strip())
+        ) from None
+    for name, value in expected.items():
+        if not hasattr(namespace, name):
+            raise AssertionError("Parsing {!r} gave no {!r}".format(args, name))
+        actual = getattr(namespace, name)
+        if actual != value:
+            raise AssertionError(
+                "Parsing {!r} gave {}={!r}, expected {!r}".format(
+                    args, name, actual, value
+                )
+            )
+    return namespace
@@ -401,16 +401,18 @@ def remove_bot_player(args):
     print("Not implemented")
 
 
-_COMMANDS = {
-    "run": run_app,
-    "make-venv": make_venv,
-    "run-tests": run_tests,
-    "bump-version": lambda args: print("Not implemented"),
-    "start-server": lambda args: print("Not implemented"),
-    "bot": run_bot_cli,
-    "bot-add-player": add_bot_player,
-    "bot-remove-player": remove_bot_player,
-}
+_DISPATCHER = dcli.Dispatcher(
+    {
+        "run": run_app,
+        "make-venv": make_venv,
+        "run-tests": run_tests,
+        "bump-version": lambda args: print("Not implemented"),
+        "start-server": lambda args: print("Not implemented"),
+        "bot": run_bot_cli,
+        "bot-add-player": add_bot_player,
+        "bot-remove-player": remove_bot_player,
+    }
+)
 
 
 # ------------------------------------------------------------------------------
@@ -439,7 +441,7 @@ def main(argv) -> int:
 
     # Run the command!
     try:
-        exit_code = _COMMANDS[args.command](args)
+        exit_code = _DISPATCHER.dispatch(args)
     except UserFacingError as e:
         print("ERROR:", e.user_msg, file=sys.stderr)
         _save_error_to_file(e)
@@ -0,0 +1,273 @@
+"""
+Fuzzing of the schema loader and the arg parser with randomly generated
+schemas and args.
+
+Run from the repository root with:
+
+    python3 fuzz/fuzz_parser.py [--iterations N] [--seed N]
+
+Each iteration generates a schema and checks that:
+ - loading a randomly corrupted copy of it only ever raises SchemaError;
+ - parsing random args either succeeds or fails with a usage error;
+ - args that parse are parsed the same way after being normalized with
+   `dcli.canonicalize()`.
+
+Failures are reported with the seed of the iteration, which can be given with
+'--seed' to reproduce them.
+
+"""
+
+import argparse
+import contextlib
+import copy
+import io
+import pathlib
+import random
+import string
+import sys
+import traceback
+from typing import Any, Dict, List, Optional
+
+
+sys.path.insert(0, str(pathlib.Path(__file__).parent.parent))
+
+import dcli  # noqa: E402
+from dcli._schema import NodeBase  # noqa: E402
+from dcli.clis import Namespace  # noqa: E402
+
+
+# Attributes of a parsed namespace that aren't arg values.
+_INTERNAL_ATTRS = ("arg_sources", "command_node", "readers", "remaining_args")
+
+_CHARS = string.ascii_letters + string.digits + "-_=.,:/ @~*é☃"
+
+
+class Failure(Exception):
+    """A check failed for a generated schema and args."""
+
+
+def _random_name(rng: random.Random, used: set) -> str:
+    while True:
+        name = "".join(
+            rng.choice(string.ascii_lowercase) for _ in range(rng.randint(1, 6))
+        )
+        if rng.random() < 0.3:
+            name += "-" + rng.choice(string.ascii_lowercase)
+        if name not in used and name not in ("help", "h"):
+            used.add(name)
+            return name
+
+
+def _random_text(rng: random.Random) -> str:
+    return "".join(rng.choice(_CHARS) for _ in range(rng.randint(0, 8)))
+
+
+def _random_value(rng: random.Random, type_: str) -> str:
+    """A random value for an arg of a type, which may be invalid."""
+    if rng.random() < 0.1:
+        return _random_text(rng)
+    if type_ == "integer":
+        return str(rng.randint(-1000, 1000))
+    if type_ == "float":
+        return str(round(rng.uniform(-100, 100), 3))
+    if type_ == "duration":
+        return "{}{}".format(rng.randint(0, 90), rng.choice(["s", "m", "h", ""]))
+    if type_ == "size":
+        return "{}{}".format(rng.randint(0, 2048), rng.choice(["", "K", "MiB"]))
+    if type_ == "boolean":
+        return rng.choice(["true", "false", "yes", "no"])
+    if type_ == "map":
+        return "{}={}".format(rng.choice("abc"), _random_text(rng))
+    return _random_text(rng)
+
+
+def _make_arg(rng: random.Random, used: set, *, positional: bool) -> Dict[str, Any]:
+    arg = {"name": _random_name(rng, used), "help": "Generated arg"}
+    types = ["string", "integer", "float", "path", "duration", "size", "boolean"]
+    if positional:
+        arg["positional"] = True
+        arg["type"] = rng.choice(types)
+        if rng.random() < 0.2:
+            arg["multiple"] = True
+        elif arg["type"] == "string" and rng.random() < 0.3:
+            arg["default"] = _random_name(rng, set())
+        return arg
+    arg["type"] = rng.choice(types + ["flag", "flag", "map"])
+    if arg["type"] == "flag":
+        arg["count"] = rng.random() < 0.2
+    elif arg["type"] == "map":
+        return arg
+    elif rng.random() < 0.2:
+        arg["repeat"] = True
+    elif rng.random() < 0.2:
+        arg["multiple"] = True
+    elif arg["type"] == "string" and rng.random() < 0.3:
+        arg["enum"] = sorted({_random_name(rng, set()) for _ in range(3)})
+    if rng.random() < 0.3:
+        arg["short"] = rng.choice(string.ascii_letters.replace("h", ""))
+    return arg
+
+
+def make_schema(rng: random.Random, depth: int = 0) -> Dict[str, Any]:
+    """Generate a random schema node, along with its subtree."""
+    used = set()  # type: set
+    shorts = set()
+    args = []
+    num_positionals = rng.randint(0, 2)
+    for i in range(rng.randint(0, 6)):
+        arg = _make_arg(rng, used, positional=i < num_positionals)
+        if "short" in arg:
+            if arg["short"] in shorts:
+                del arg["short"]
+            shorts.add(arg.get("short"))
+        args.append(arg)
+    # Only the last positional may take multiple values or have a default.
+    for arg in args[: num_positionals - 1]:
+        arg.pop("multiple", None)
+        arg.pop("default", None)
+    node = {"help": "Generated node", "args": args}
+    if depth < 2 and rng.random() < 0.6:
+        node["subtree"] = []
+        for _ in range(rng.randint(1, 3)):
+            subnode = make_schema(rng, depth + 1)
+            subnode["keyword"] = _random_name(rng, used)
+            node["subtree"].append(subnode)
+        # Args of nodes with subcommands can't be positional.
+        node["args"] = [a for a in args if not a.get("positional")]
+    else:
+        node["command"] = "cmd-{}".format(rng.randrange(10 ** 6))
+    return node
+
+
+def _corrupt(rng: random.Random, data: Any) -> Any:
+    """Make a random change somewhere in loaded schema data."""
+    if isinstance(data, dict) and data and rng.random() < 0.7:
+        key = rng.choice(list(data))
+        choice = rng.random()
+        if choice < 0.2:
+            del data[key]
+        elif choice < 0.5:
+            data[key] = rng.choice([None, 1, -1, "", "x", [], {}, True, 1.5])
+        else:
+            data[key] = _corrupt(rng, data[key])
+    elif isinstance(data, list) and data and rng.random() < 0.7:
+        i = rng.randrange(len(data))
+        data[i] = _corrupt(rng, data[i])
+    else:
+        data = rng.choice([None, 0, "", "x", [], {}, {"name": "x"}, False])
+    return data
+
+
+def _random_args(rng: random.Random, schema: dcli.RootNode) -> List[str]:
+    """Random args, mostly made up of the schema's keywords and options."""
+    node = schema  # type: NodeBase
+    args = []
+    while node.subtree and rng.random() < 0.9:
+        node = rng.choice(node.subtree)
+        args.append(node.keyword)
+    for _ in range(rng.randint(0, 8)):
+        arg = rng.choice(node.all_args) if node.all_args else None
+        choice = rng.random()
+        if arg is None or choice < 0.1:
+            args.append(rng.choice(["--", "-", "--help", "-x", _random_text(rng)]))
+        elif arg.positional:
+            args.append(_random_value(rng, arg.type.value))
+        elif arg.type.takes_value:
+            option = rng.choice(arg.option_strings)
+            value = (
+                rng.choice(arg.enum) if arg.enum else _random_value(rng, arg.type.value)
+            )
+            if option.startswith("--") and rng.random() < 0.5:
+                args.append("{}={}".format(option, value))
+            else:
+                args += [option, value]
+        else:
+            args.append(rng.choice(arg.option_strings))
+    return args
+
+
+def _parse(parser: dcli.CLIParser, args: List[str]) -> Optional[Namespace]:
+    """Parse args, returning None for a usage error."""
+    output = io.StringIO()
+    with contextlib.redirect_stderr(output), contextlib.redirect_stdout(output):
+        try:
+            return parser.parse_args(args)
+        except SystemExit:
+            return None
+
+
+def _values(namespace: Namespace) -> Dict[str, Any]:
+    return {k: v for k, v in vars(namespace).items() if k not in _INTERNAL_ATTRS}
+
+
+def check(seed: int) -> None:
+    """
+    Run the checks for one generated schema.
+
+    :raises Failure:
+        If a check fails.
+    """
+    rng = random.Random(seed)
+    data = make_schema(rng)
+    corrupted = _corrupt(rng, copy.deepcopy(data))
+    try:
+        dcli.RootNode.from_dict(corrupted)
+    except dcli.SchemaError:
+        pass
+    except Exception:
+        raise Failure(
+            "loading corrupted schema {!r} raised:\n{}".format(
+                corrupted, traceback.format_exc()
+            )
+        )
+
+    try:
+        schema = dcli.RootNode.from_dict(data)
+    except dcli.SchemaError:
+        # The generator doesn't avoid every invalid combination of fields.
+        return
+    parser = dcli.CLIParser(schema, prog="fuzz")
+    for _ in range(20):
+        args = _random_args(rng, schema)
+        try:
+            namespace = _parse(parser, args)
+        except Exception:
+            raise Failure(
+                "parsing {!r} raised:\n{}".format(args, traceback.format_exc())
+            )
+        if namespace is None:
+            continue
+        node = namespace.command_node
+        canonical = dcli.canonicalize(node, namespace)
+        reparsed = _parse(parser, canonical)
+        if reparsed is None or _values(reparsed) != _values(namespace):
+            raise Failure(
+                "args {!r} normalized to {!r} parse differently:\n{!r}\n{!r}".format(
+                    args,
+                    canonical,
+                    _values(namespace),
+                    reparsed and _values(reparsed),
+                )
+            )
+
+
+def main(argv=None) -> int:
+    parser = argparse.ArgumentParser(description=__doc__.strip().splitlines()[0])
+    parser.add_argument("--iterations", type=int, default=1000)
+    parser.add_argument("--seed", type=int, help="Only run the iteration with a seed")
+    args = parser.parse_args(argv)
+
+    seeds = [args.seed] if args.seed is not None else range(args.iterations)
+    failures = 0
+    for seed in seeds:
+        try:
+            check(seed)
+        except Failure as e:
+            failures += 1
+            print("FAILED with --seed {}: {}".format(seed, e))
+    print("{} iterations, {} failures".format(len(seeds), failures))
+    return 1 if failures else 0
+
+
+if __name__ == "__main__":
+    sys.exit(main())
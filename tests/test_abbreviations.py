@@ -0,0 +1,91 @@
+"""
+Tests for selecting subcommands by an unambiguous prefix of their keyword,
+for nodes with 'abbreviations' set.
+
+"""
+
+import contextlib
+import io
+import unittest
+from typing import Any, Dict, List
+
+from dcli import CLIParser, RootNode
+from dcli.testing import assert_parses
+
+
+def _leaf(keyword: str, **fields: Any) -> Dict[str, Any]:
+    return dict(
+        {"keyword": keyword, "help": keyword.title(), "command": keyword}, **fields
+    )
+
+
+_SCHEMA = {
+    "help": "Abbreviation tests",
+    "abbreviations": True,
+    "subtree": [
+        _leaf("status"),
+        _leaf("stash"),
+        _leaf("commit", aliases=["ci"]),
+        _leaf("secret", hidden=True),
+        {
+            "keyword": "remote",
+            "help": "Remote",
+            "subtree": [_leaf("rename"), _leaf("remove")],
+        },
+        {
+            "keyword": "branch",
+            "help": "Branch",
+            "abbreviations": False,
+            "subtree": [_leaf("delete")],
+        },
+    ],
+}  # type: Dict[str, Any]
+
+
+class AbbreviationsTest(unittest.TestCase):
+    def setUp(self):
+        self.parser = CLIParser(RootNode.from_dict(_SCHEMA), prog="prog")
+
+    def assert_parse_error(self, args: List[str]) -> str:
+        """Assert that parsing args fails with a usage error, returning it."""
+        stderr = io.StringIO()
+        with contextlib.redirect_stderr(stderr):
+            with self.assertRaises(SystemExit) as ctx:
+                self.parser.parse_args(args)
+        self.assertEqual(ctx.exception.code, 2)
+        return stderr.getvalue()
+
+    def test_full_keyword(self):
+        assert_parses(self.parser, ["status"], command="status")
+
+    def test_unambiguous_prefix(self):
+        assert_parses(self.parser, ["stat"], command="status")
+        assert_parses(self.parser, ["co"], command="commit")
+
+    def test_ambiguous_prefix(self):
+        error = self.assert_parse_error(["st"])
+        self.assertIn("ambiguous command 'st', could be: ", error)
+        self.assertIn("status", error)
+        self.assertIn("stash", error)
+
+    def test_hidden_not_matched(self):
+        self.assert_parse_error(["sec"])
+        assert_parses(self.parser, ["secret"], command="secret")
+
+    def test_inherited(self):
+        assert_parses(self.parser, ["rem", "ren"], command="rename")
+        self.assertIn("ambiguous command 're'", self.assert_parse_error(["remo", "re"]))
+
+    def test_disabled_on_subnode(self):
+        assert_parses(self.parser, ["br", "delete"], command="delete")
+        self.assertIn("unknown command 'del'", self.assert_parse_error(["br", "del"]))
+
+    def test_disabled_by_default(self):
+        data = dict(_SCHEMA)
+        del data["abbreviations"]
+        self.parser = CLIParser(RootNode.from_dict(data), prog="prog")
+        self.assertIn("unknown command 'stat'", self.assert_parse_error(["stat"]))
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,123 @@
+"""
+Tests for running several commands given in one invocation, e.g.
+'build + test', for schemas with 'chaining' settings.
+
+"""
+
+import contextlib
+import io
+import unittest
+from typing import Any, Dict, List, Tuple
+
+from dcli import (
+    CLIError,
+    CLIParser,
+    ChainError,
+    Dispatcher,
+    RootNode,
+    SchemaError,
+    run_and_exit,
+)
+
+
+def _make_schema(chaining: Any) -> Dict[str, Any]:
+    data = {
+        "help": "Chaining tests",
+        "subtree": [
+            {
+                "keyword": name,
+                "help": name.title(),
+                "command": name,
+                "args": [{"name": "fail", "help": "Fail", "type": "flag"}],
+            }
+            for name in ["build", "test", "deploy"]
+        ],
+    }  # type: Dict[str, Any]
+    if chaining is not None:
+        data["chaining"] = chaining
+    return data
+
+
+class ChainingTest(unittest.TestCase):
+    def setUp(self):
+        self.calls = []  # type: List[str]
+
+    def make_dispatcher(self) -> Dispatcher:
+        dispatcher = Dispatcher()
+        for command in ["build", "test", "deploy"]:
+            dispatcher.register(command, self.handler)
+        return dispatcher
+
+    def handler(self, args: Any) -> None:
+        self.calls.append(args.command)
+        if args.fail:
+            raise CLIError("{} failed".format(args.command), exit_code=3)
+
+    def run_args(self, chaining: Any, args: List[str]) -> Tuple[int, str]:
+        """Run args with `run_and_exit()`, returning the exit code and stderr."""
+        parser = CLIParser(RootNode.from_dict(_make_schema(chaining)), prog="prog")
+        stderr = io.StringIO()
+        with contextlib.redirect_stderr(stderr):
+            with self.assertRaises(SystemExit) as ctx:
+                run_and_exit(parser, self.make_dispatcher(), args, prog="prog")
+        return ctx.exception.code, stderr.getvalue()
+
+    def test_parse_chain(self):
+        parser = CLIParser(RootNode.from_dict(_make_schema(True)), prog="prog")
+        chain = parser.parse_chain(["build", "+", "test", "--fail", "+", "deploy"])
+        self.assertEqual([x.command for x in chain], ["build", "test", "deploy"])
+        self.assertEqual([x.fail for x in chain], [False, True, False])
+
+    def test_custom_separator(self):
+        parser = CLIParser(
+            RootNode.from_dict(_make_schema({"separator": "then"})), prog="prog"
+        )
+        chain = parser.parse_chain(["build", "then", "test"])
+        self.assertEqual([x.command for x in chain], ["build", "test"])
+
+    def test_without_chaining(self):
+        parser = CLIParser(RootNode.from_dict(_make_schema(None)), prog="prog")
+        stderr = io.StringIO()
+        with contextlib.redirect_stderr(stderr):
+            with self.assertRaises(SystemExit) as ctx:
+                parser.parse_chain(["build", "+", "test"])
+        self.assertEqual(ctx.exception.code, 2)
+
+    def test_usage_error_before_running(self):
+        code, _ = self.run_args(True, ["build", "+", "test", "--nope"])
+        self.assertEqual(code, 2)
+        self.assertEqual(self.calls, [])
+
+    def test_run_all(self):
+        self.assertEqual(self.run_args(True, ["build", "+", "test"]), (0, ""))
+        self.assertEqual(self.calls, ["build", "test"])
+
+    def test_stop_on_error(self):
+        code, stderr = self.run_args(True, ["build", "--fail", "+", "test"])
+        self.assertEqual(code, 3)
+        self.assertIn("build failed", stderr)
+        self.assertEqual(self.calls, ["build"])
+
+    def test_continue_on_error(self):
+        args = ["build", "--fail", "+", "test", "+", "deploy", "--fail"]
+        code, stderr = self.run_args({"on_error": "continue"}, args)
+        self.assertEqual(code, 3)
+        self.assertEqual(self.calls, ["build", "test", "deploy"])
+        self.assertIn("deploy failed", stderr)
+
+    def test_dispatch_chain_continue(self):
+        parser = CLIParser(RootNode.from_dict(_make_schema(True)), prog="prog")
+        chain = parser.parse_chain(["build", "--fail", "+", "test", "--fail"])
+        with self.assertRaises(ChainError) as ctx:
+            self.make_dispatcher().dispatch_chain(chain, on_error="continue")
+        self.assertEqual(len(ctx.exception.errors), 2)
+        self.assertEqual(ctx.exception.exit_code, 3)
+        self.assertEqual(self.calls, ["build", "test"])
+
+    def test_invalid_separator(self):
+        with self.assertRaisesRegex(SchemaError, "Invalid chain separator"):
+            RootNode.from_dict(_make_schema({"separator": "-then"}))
+
+
+if __name__ == "__main__":
+    unittest.main()
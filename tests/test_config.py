@@ -0,0 +1,108 @@
+"""
+Tests for layering the values of args from a config file under those given in
+the environment and on the command line.
+
+"""
+
+import json
+import os.path
+import tempfile
+import unittest
+from typing import Any, Dict
+from unittest import mock
+
+from dcli import CLIParser, RootNode
+from dcli.clis import ArgSource
+from dcli.testing import assert_parses
+
+
+def _make_schema(config_file: str) -> Dict[str, Any]:
+    return {
+        "help": "Config layering tests",
+        "config": {"file": config_file},
+        "global_args": [
+            {
+                "name": "verbose",
+                "help": "Verbose",
+                "type": "flag",
+                "configurable": True,
+            },
+        ],
+        "subtree": [
+            {
+                "keyword": "serve",
+                "help": "Serve",
+                "command": "serve",
+                "args": [
+                    {
+                        "name": "port",
+                        "help": "Port",
+                        "type": "integer",
+                        "default": 80,
+                        "env": "TEST_PORT",
+                        "configurable": True,
+                    },
+                    {"name": "host", "help": "Host", "default": "localhost"},
+                ],
+            }
+        ],
+    }
+
+
+class ConfigLayeringTest(unittest.TestCase):
+    def setUp(self):
+        tmpdir = tempfile.TemporaryDirectory()
+        self.addCleanup(tmpdir.cleanup)
+        self.tmpdir = tmpdir.name
+        self.config_file = self.write_config("config.json", {})
+        self.parser = CLIParser(
+            RootNode.from_dict(_make_schema(self.config_file)), prog="prog"
+        )
+        patcher = mock.patch.dict("os.environ", clear=True)
+        patcher.start()
+        self.addCleanup(patcher.stop)
+
+    def write_config(self, name: str, config: Dict[str, Any]) -> str:
+        path = os.path.join(self.tmpdir, name)
+        with open(path, "w") as f:
+            json.dump(config, f)
+        return path
+
+    def test_default(self):
+        namespace = assert_parses(self.parser, ["serve"], port=80, verbose=False)
+        self.assertIs(namespace.arg_sources["port"], ArgSource.DEFAULT)
+
+    def test_config_file(self):
+        self.write_config("config.json", {"verbose": True, "serve": {"port": 8080}})
+        namespace = assert_parses(self.parser, ["serve"], port=8080, verbose=True)
+        self.assertIs(namespace.arg_sources["port"], ArgSource.CONFIG)
+        self.assertIs(namespace.arg_sources["verbose"], ArgSource.CONFIG)
+
+    def test_env_over_config(self):
+        self.write_config("config.json", {"serve": {"port": 8080}})
+        with mock.patch.dict("os.environ", TEST_PORT="9090"):
+            namespace = assert_parses(self.parser, ["serve"], port=9090)
+        self.assertIs(namespace.arg_sources["port"], ArgSource.ENV)
+
+    def test_cli_over_config(self):
+        self.write_config("config.json", {"serve": {"port": 8080}})
+        with mock.patch.dict("os.environ", TEST_PORT="9090"):
+            namespace = assert_parses(self.parser, ["serve", "--port", "1"], port=1)
+        self.assertIs(namespace.arg_sources["port"], ArgSource.CLI)
+
+    def test_config_option(self):
+        self.write_config("config.json", {"serve": {"port": 8080}})
+        other = self.write_config("other.json", {"serve": {"port": 8181}})
+        assert_parses(self.parser, ["--config", other, "serve"], port=8181)
+
+    def test_not_configurable(self):
+        self.write_config("config.json", {"serve": {"host": "example.com"}})
+        assert_parses(self.parser, ["serve"], host="localhost")
+
+    def test_missing_config_file(self):
+        os.remove(self.config_file)
+        assert_parses(self.parser, ["serve"], port=80)
+
+
+if __name__ == "__main__":
+    unittest.main()
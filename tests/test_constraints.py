@@ -0,0 +1,84 @@
+"""
+Tests for the 'required_if' and 'conflicts_with' constraints between args.
+
+"""
+
+import contextlib
+import io
+import unittest
+from typing import Any, Dict, List
+
+from dcli import CLIParser, RootNode, SchemaError
+from dcli.testing import assert_parses
+
+
+_SCHEMA = {
+    "help": "Arg constraint tests",
+    "args": [
+        {"name": "mode", "help": "M", "enum": ["local", "remote"], "default": "local"},
+        {"name": "host", "help": "Host", "required_if": {"mode": "remote"}},
+        {"name": "workers", "help": "Workers", "type": "integer"},
+        {"name": "limit", "help": "Limit", "required_if": {"workers": "0"}},
+        {"name": "quiet", "help": "Q", "type": "flag", "conflicts_with": ["verbose"]},
+        {"name": "verbose", "help": "Verbose", "type": "flag"},
+    ],
+}  # type: Dict[str, Any]
+
+
+class ConstraintsTest(unittest.TestCase):
+    def setUp(self):
+        self.parser = CLIParser(RootNode.from_dict(_SCHEMA), prog="prog")
+
+    def assert_parse_error(self, args: List[str]) -> str:
+        """Assert that parsing args fails with a usage error, returning it."""
+        stderr = io.StringIO()
+        with contextlib.redirect_stderr(stderr):
+            with self.assertRaises(SystemExit) as ctx:
+                self.parser.parse_args(args)
+        self.assertEqual(ctx.exception.code, 2)
+        return stderr.getvalue()
+
+    def test_required_if_not_applicable(self):
+        assert_parses(self.parser, [], mode="local", host=None)
+
+    def test_required_if_given(self):
+        assert_parses(self.parser, ["--mode", "remote", "--host", "h"], host="h")
+
+    def test_required_if_missing(self):
+        error = self.assert_parse_error(["--mode", "remote"])
+        self.assertIn("--host is required when --mode is 'remote'", error)
+
+    def test_required_if_converted_value(self):
+        assert_parses(self.parser, ["--workers", "1"], limit=None)
+        error = self.assert_parse_error(["--workers", "00"])
+        self.assertIn("--limit is required when --workers is '0'", error)
+
+    def test_conflicts_with(self):
+        error = self.assert_parse_error(["--quiet", "--verbose"])
+        self.assertIn("--quiet cannot be used with --verbose", error)
+
+    def test_conflicts_with_one_given(self):
+        assert_parses(self.parser, ["--verbose"], quiet=False, verbose=True)
+
+    def test_unknown_arg_referenced(self):
+        data = {
+            "help": "Unknown arg",
+            "args": [{"name": "a", "help": "A", "conflicts_with": ["b"]}],
+        }
+        with self.assertRaisesRegex(SchemaError, "unknown arg 'b' referenced"):
+            RootNode.from_dict(data)
+
+    def test_invalid_required_if_value(self):
+        data = {
+            "help": "Invalid value",
+            "args": [
+                {"name": "n", "help": "N", "type": "integer"},
+                {"name": "a", "help": "A", "required_if": {"n": "x"}},
+            ],
+        }
+        with self.assertRaisesRegex(SchemaError, "'required_if' value for 'n'"):
+            RootNode.from_dict(data)
+
+
+if __name__ == "__main__":
+    unittest.main()
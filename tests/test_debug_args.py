@@ -0,0 +1,53 @@
+"""
+Tests for the hidden '--debug-args' flag, enabled with 'debug_args_option'.
+
+"""
+
+import contextlib
+import io
+import json
+import unittest
+from typing import Any, Dict
+
+from dcli import CLIParser, RootNode
+
+
+_SCHEMA = {
+    "help": "Debug args tests",
+    "command": "run",
+    "args": [
+        {"name": "name", "help": "Name", "default": "x"},
+        {"name": "count", "help": "Count", "type": "integer"},
+    ],
+}  # type: Dict[str, Any]
+
+
+class DebugArgsTest(unittest.TestCase):
+    def test_disabled_by_default(self):
+        parser = CLIParser(RootNode.from_dict(_SCHEMA), prog="prog")
+        stderr = io.StringIO()
+        with contextlib.redirect_stderr(stderr):
+            with self.assertRaises(SystemExit) as ctx:
+                parser.parse_args(["--debug-args"])
+        self.assertEqual(ctx.exception.code, 2)
+        self.assertIn("--debug-args", stderr.getvalue())
+
+    def test_enabled(self):
+        parser = CLIParser(
+            RootNode.from_dict(_SCHEMA), prog="prog", debug_args_option=True
+        )
+        stdout = io.StringIO()
+        with contextlib.redirect_stdout(stdout):
+            with self.assertRaises(SystemExit) as ctx:
+                parser.parse_args(["--count", "3", "--debug-args"])
+        self.assertEqual(ctx.exception.code, 0)
+        data = json.loads(stdout.getvalue())
+        self.assertEqual(data["command"], "run")
+        self.assertEqual(
+            data["args"]["count"], {"type": "integer", "value": 3, "source": "cli"}
+        )
+        self.assertEqual(data["args"]["name"]["source"], "default")
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,93 @@
+"""
+Tests for the 'default' subcommand run, or help shown, when a node is given
+without a subcommand.
+
+"""
+
+import contextlib
+import io
+import unittest
+from typing import Any, Dict, List, Tuple
+
+from dcli import CLIParser, RootNode, SchemaError, validate
+from dcli.testing import assert_parses
+
+
+_SCHEMA = {
+    "help": "Default command tests",
+    "default": "status",
+    "subtree": [
+        {
+            "keyword": "status",
+            "help": "Show status",
+            "command": "status",
+            "args": [{"name": "short", "help": "Short", "type": "flag"}],
+        },
+        {
+            "keyword": "remote",
+            "help": "Manage remotes",
+            "default": "list",
+            "subtree": [
+                {"keyword": "list", "help": "List remotes", "command": "remote-list"},
+                {"keyword": "add", "help": "Add a remote", "command": "remote-add"},
+            ],
+        },
+        {
+            "keyword": "config",
+            "help": "Manage config",
+            "default": "help",
+            "subtree": [{"keyword": "get", "help": "Get config", "command": "get"}],
+        },
+    ],
+}  # type: Dict[str, Any]
+
+
+class DefaultCommandTest(unittest.TestCase):
+    def setUp(self):
+        self.parser = CLIParser(RootNode.from_dict(_SCHEMA), prog="prog")
+
+    def parse_exit(self, args: List[str]) -> Tuple[int, str]:
+        """Parse args that exit, returning the exit code and the output."""
+        output = io.StringIO()
+        with contextlib.redirect_stdout(output), contextlib.redirect_stderr(output):
+            with self.assertRaises(SystemExit) as ctx:
+                self.parser.parse_args(args)
+        return ctx.exception.code, output.getvalue()
+
+    def test_root_default(self):
+        assert_parses(self.parser, [], command="status", short=False)
+
+    def test_root_default_with_option(self):
+        assert_parses(self.parser, ["--short"], command="status", short=True)
+
+    def test_subcommand_given(self):
+        assert_parses(self.parser, ["remote", "add"], command="remote-add")
+
+    def test_nested_default(self):
+        namespace = assert_parses(self.parser, ["remote"], command="remote-list")
+        self.assertEqual(namespace.command_node.path, ["remote", "list"])
+
+    def test_default_help(self):
+        code, output = self.parse_exit(["config"])
+        self.assertEqual(code, 0)
+        self.assertIn("Manage config", output)
+
+    def test_unknown_subcommand_not_defaulted(self):
+        code, output = self.parse_exit(["remote", "rm"])
+        self.assertEqual(code, 2)
+        self.assertIn("unknown command 'rm'", output)
+
+    def test_unknown_default(self):
+        data = dict(_SCHEMA, default="nope")
+        problems = validate(RootNode.from_dict(data))
+        self.assertEqual(len(problems), 1)
+        self.assertIn("unknown default subcommand 'nope'", problems[0])
+
+    def test_default_on_command_node(self):
+        data = {"help": "Leaf", "command": "leaf", "default": "help"}
+        with self.assertRaisesRegex(SchemaError, "can't be given with 'command'"):
+            RootNode.from_dict(data)
+
+
+if __name__ == "__main__":
+    unittest.main()
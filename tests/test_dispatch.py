@@ -0,0 +1,352 @@
+"""
+Tests for dispatching parsed args to handlers, using the settings of the node
+the args were parsed for.
+
+"""
+
+import asyncio
+import contextlib
+import io
+import os
+import sys
+import tempfile
+import unittest
+from typing import Any, Dict, List
+
+from dcli import (
+    CLIError,
+    CLIParser,
+    CancellationToken,
+    CommandTimeoutError,
+    DispatchError,
+    Dispatcher,
+    RootNode,
+    SchemaError,
+    UnauthorizedError,
+    run_and_exit,
+)
+from dcli.clis import Namespace
+
+
+# Two leaves running the same command, with different settings.
+_SHARED_COMMAND_SCHEMA = {
+    "help": "Shared command tests",
+    "subtree": [
+        {
+            "keyword": "slow",
+            "help": "Slow",
+            "subtree": [
+                {"keyword": "wait", "help": "Wait", "command": "wait", "timeout": 5}
+            ],
+        },
+        {
+            "keyword": "fast",
+            "help": "Fast",
+            "subtree": [
+                {"keyword": "wait", "help": "Wait", "command": "wait", "timeout": 0.05}
+            ],
+        },
+    ],
+}  # type: Dict[str, Any]
+
+
+def _wait(args: Any, token: Any) -> str:
+    """A handler waiting until it's cancelled, or for a moment."""
+    token.wait(0.2)
+    return "done"
+
+
+class DispatchTest(unittest.TestCase):
+    def setUp(self):
+        self.calls = []  # type: List[str]
+        self.dispatcher = Dispatcher()
+
+    def test_handler_called(self):
+        self.dispatcher.register("build", lambda args: args.target)
+        args = Namespace(command="build", target="all")
+        self.assertEqual(self.dispatcher.dispatch(args), "all")
+
+    def test_decorator(self):
+        @self.dispatcher.register("build")
+        def build(args):
+            return "built"
+
+        self.assertEqual(self.dispatcher.dispatch(Namespace(command="build")), "built")
+        self.assertEqual(self.dispatcher.commands, {"build": build})
+
+    def test_already_registered(self):
+        self.dispatcher.register("build", lambda args: None)
+        with self.assertRaisesRegex(ValueError, "'build'"):
+            self.dispatcher.register("build", lambda args: None)
+
+    def test_no_command(self):
+        with self.assertRaisesRegex(DispatchError, "No command selected"):
+            self.dispatcher.dispatch(Namespace(command=None))
+
+    def test_no_handler(self):
+        with self.assertRaisesRegex(DispatchError, "No handler registered"):
+            self.dispatcher.dispatch(Namespace(command="build"))
+
+    def test_async_handler(self):
+        async def build(args):
+            return "built"
+
+        self.dispatcher.register("build", build)
+        self.assertEqual(self.dispatcher.dispatch(Namespace(command="build")), "built")
+
+    def test_hooks(self):
+        self.dispatcher.register("build", lambda args: self.calls.append("handler"))
+        self.dispatcher.add_pre_hook(lambda args: self.calls.append("pre all"))
+        self.dispatcher.add_pre_hook(
+            lambda args: self.calls.append("pre build"), command="build"
+        )
+        self.dispatcher.add_pre_hook(
+            lambda args: self.calls.append("pre other"), command="other"
+        )
+        self.dispatcher.add_post_hook(
+            lambda args, result: self.calls.append("post all")
+        )
+        self.dispatcher.add_post_hook(
+            lambda args, result: self.calls.append("post build"), command="build"
+        )
+        self.dispatcher.dispatch(Namespace(command="build"))
+        self.assertEqual(
+            self.calls, ["pre all", "pre build", "handler", "post build", "post all"]
+        )
+
+    def test_pre_hook_error(self):
+        def check(args):
+            raise CLIError("not logged in")
+
+        self.dispatcher.register("build", lambda args: self.calls.append("handler"))
+        self.dispatcher.add_pre_hook(check)
+        with self.assertRaisesRegex(CLIError, "not logged in"):
+            self.dispatcher.dispatch(Namespace(command="build"))
+        self.assertEqual(self.calls, [])
+
+
+class TimeoutTest(unittest.TestCase):
+    def setUp(self):
+        self.calls = []  # type: List[Any]
+        self.dispatcher = Dispatcher()
+
+    def wait(self, args: Any, token: CancellationToken) -> str:
+        self.calls.append(token)
+        token.wait(0.2)
+        return "done"
+
+    def test_finishes_in_time(self):
+        self.dispatcher.register("wait", lambda args, token: "done")
+        args = Namespace(command="wait")
+        self.assertEqual(self.dispatcher.dispatch(args, timeout=1), "done")
+
+    def test_timeout(self):
+        self.dispatcher.register("wait", self.wait)
+        with self.assertRaisesRegex(CommandTimeoutError, "timed out after 0.05") as ctx:
+            self.dispatcher.dispatch(Namespace(command="wait"), timeout=0.05)
+        self.assertEqual(ctx.exception.exit_code, 124)
+        self.assertEqual(self.calls[0].reason, "timeout")
+
+    def test_cleanup_hook(self):
+        self.dispatcher.register("wait", self.wait)
+        self.dispatcher.add_cleanup_hook(lambda args, e: self.calls.append(e))
+        with self.assertRaises(CommandTimeoutError) as ctx:
+            self.dispatcher.dispatch(Namespace(command="wait"), timeout=0.05)
+        self.assertEqual(self.calls[1:], [ctx.exception])
+
+    def test_schema_timeout(self):
+        data = {"help": "Timeout", "command": "wait", "timeout": "50ms"}
+        schema = RootNode.from_dict(data)
+        self.assertEqual(schema.get_timeout(), 0.05)
+        self.dispatcher.register("wait", self.wait)
+        args = CLIParser(schema, prog="prog").parse_args([])
+        with self.assertRaises(CommandTimeoutError):
+            self.dispatcher.dispatch(args)
+
+    def test_invalid_timeout(self):
+        data = {"help": "Timeout", "command": "wait", "timeout": -1}
+        with self.assertRaisesRegex(SchemaError, "'timeout' must be positive"):
+            RootNode.from_dict(data)
+
+    def test_timeout_exit_code(self):
+        data = {"help": "Timeout", "command": "wait", "timeout": 0.05}
+        parser = CLIParser(RootNode.from_dict(data), prog="prog")
+        self.dispatcher.register("wait", self.wait)
+        stderr = io.StringIO()
+        with contextlib.redirect_stderr(stderr):
+            with self.assertRaises(SystemExit) as ctx:
+                run_and_exit(parser, self.dispatcher, [], prog="prog")
+        self.assertEqual(ctx.exception.code, 124)
+        self.assertIn("timed out", stderr.getvalue())
+
+
+class ParsedNodeTest(unittest.TestCase):
+    def setUp(self):
+        self.schema = RootNode.from_dict(_SHARED_COMMAND_SCHEMA)
+        self.parser = CLIParser(self.schema, prog="prog")
+        self.dispatcher = Dispatcher({"wait": _wait}, schema=self.schema)
+
+    def test_timeout_of_parsed_node(self):
+        args = self.parser.parse_args(["fast", "wait"])
+        with self.assertRaises(CommandTimeoutError):
+            self.dispatcher.dispatch(args)
+
+    def test_timeout_of_parsed_node_async(self):
+        args = self.parser.parse_args(["fast", "wait"])
+        loop = asyncio.new_event_loop()
+        try:
+            with self.assertRaises(CommandTimeoutError):
+                loop.run_until_complete(self.dispatcher.dispatch_async(args))
+        finally:
+            loop.close()
+
+    def test_timeout_not_taken_from_other_node(self):
+        args = self.parser.parse_args(["slow", "wait"])
+        self.assertEqual(self.dispatcher.dispatch(args), "done")
+
+    def test_node_given(self):
+        args = self.parser.parse_args(["slow", "wait"])
+        node = self.schema.get_subnode("fast").get_subnode("wait")
+        with self.assertRaises(CommandTimeoutError):
+            self.dispatcher.dispatch(args, node=node)
+
+    def test_unparsed_args_ambiguous(self):
+        with self.assertRaisesRegex(DispatchError, "more than one node"):
+            self.dispatcher.dispatch(Namespace(command="wait"))
+
+
+class ParsedNodeRolesTest(unittest.TestCase):
+    def setUp(self):
+        data = {
+            "help": "Shared command roles tests",
+            "subtree": [
+                {
+                    "keyword": "prod",
+                    "help": "Production",
+                    "requires_role": "admin",
+                    "subtree": [
+                        {"keyword": "deploy", "help": "Deploy", "command": "deploy"}
+                    ],
+                },
+                {
+                    "keyword": "staging",
+                    "help": "Staging",
+                    "subtree": [
+                        {"keyword": "deploy", "help": "Deploy", "command": "deploy"}
+                    ],
+                },
+            ],
+        }
+        self.schema = RootNode.from_dict(data)
+        self.parser = CLIParser(
+            self.schema, prog="prog", role_provider=lambda: ["admin"]
+        )
+        self.dispatcher = Dispatcher(
+            {"deploy": lambda args: "deployed"},
+            schema=self.schema,
+            role_provider=lambda: ["dev"],
+        )
+
+    def test_authorized(self):
+        args = self.parser.parse_args(["staging", "deploy"])
+        self.assertEqual(self.dispatcher.dispatch(args), "deployed")
+
+    def test_unauthorized(self):
+        args = self.parser.parse_args(["prod", "deploy"])
+        with self.assertRaisesRegex(UnauthorizedError, "admin"):
+            self.dispatcher.dispatch(args)
+
+    def test_roles_given(self):
+        args = self.parser.parse_args(["prod", "deploy"])
+        self.assertEqual(self.dispatcher.dispatch(args, roles=["admin"]), "deployed")
+
+
+class PathHandlerTest(unittest.TestCase):
+    def setUp(self):
+        self.schema = RootNode.from_dict(_SHARED_COMMAND_SCHEMA)
+        self.parser = CLIParser(self.schema, prog="prog")
+        self.dispatcher = Dispatcher({"wait": lambda args: "by command"})
+        self.dispatcher.register(["slow", "wait"], lambda args: "by path")
+
+    def test_path_handler(self):
+        args = self.parser.parse_args(["slow", "wait"])
+        self.assertEqual(self.dispatcher.dispatch(args), "by path")
+
+    def test_command_handler(self):
+        args = self.parser.parse_args(["fast", "wait"])
+        self.assertEqual(self.dispatcher.dispatch(args), "by command")
+
+    def test_decorator(self):
+        @self.dispatcher.register(("fast", "wait"))
+        def handler(args):
+            return "decorated"
+
+        args = self.parser.parse_args(["fast", "wait"])
+        self.assertEqual(self.dispatcher.dispatch(args), "decorated")
+
+    def test_already_registered(self):
+        with self.assertRaisesRegex(ValueError, "'slow wait'"):
+            self.dispatcher.register(["slow", "wait"], lambda args: None)
+
+
+class ParsedNodeEnvironmentTest(unittest.TestCase):
+    def setUp(self):
+        self.tmpdir = tempfile.TemporaryDirectory()
+        self.addCleanup(self.tmpdir.cleanup)
+        exit_with_target = [
+            sys.executable,
+            "-c",
+            "import os, sys; sys.exit(int(os.environ['TARGET']))",
+        ]
+        data = {
+            "help": "Shared command environment tests",
+            "subtree": [
+                {
+                    "keyword": name,
+                    "help": name.title(),
+                    "subtree": [
+                        {
+                            "keyword": "build",
+                            "help": "Build",
+                            "command": "build",
+                            "cwd": cwd,
+                            "env": {"TARGET": target},
+                        },
+                        {
+                            "keyword": "run",
+                            "help": "Run",
+                            "command": exit_with_target,
+                            "env": {"TARGET": target},
+                        },
+                    ],
+                }
+                for name, cwd, target in [
+                    ("prod", os.path.dirname(self.tmpdir.name), "3"),
+                    ("staging", self.tmpdir.name, "4"),
+                ]
+            ],
+        }
+        self.schema = RootNode.from_dict(data)
+        self.parser = CLIParser(self.schema, prog="prog")
+
+    def test_handler(self):
+        dispatcher = Dispatcher(
+            {"build": lambda args: (os.getcwd(), os.environ["TARGET"])},
+            schema=self.schema,
+        )
+        args = self.parser.parse_args(["staging", "build"])
+        cwd, target = dispatcher.dispatch(args)
+        self.assertEqual(os.path.realpath(cwd), os.path.realpath(self.tmpdir.name))
+        self.assertEqual(target, "4")
+
+    def test_external_command(self):
+        args = self.parser.parse_args(["staging", "run"])
+        self.assertEqual(Dispatcher(schema=self.schema).dispatch(args), 4)
+
+    def test_external_command_without_schema(self):
+        args = self.parser.parse_args(["staging", "run"])
+        self.assertEqual(Dispatcher().dispatch(args), 4)
+
+
+if __name__ == "__main__":
+    unittest.main()
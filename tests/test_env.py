@@ -0,0 +1,75 @@
+"""
+Tests for taking the values of args from environment variables, when they're
+not given on the command line.
+
+"""
+
+import contextlib
+import io
+import unittest
+from typing import Any, Dict
+from unittest import mock
+
+from dcli import CLIParser, RootNode
+from dcli.clis import ArgSource
+from dcli.testing import assert_parses
+
+
+_SCHEMA = {
+    "help": "Env fallback tests",
+    "args": [
+        {"name": "host", "help": "Host", "env": "TEST_HOST", "default": "localhost"},
+        {"name": "port", "help": "Port", "type": "integer", "env": "TEST_PORT"},
+        {"name": "debug", "help": "Debug", "type": "flag", "env": "TEST_DEBUG"},
+        {"name": "tags", "help": "Tags", "multiple": True, "env": "TEST_TAGS"},
+    ],
+}  # type: Dict[str, Any]
+
+
+class EnvFallbackTest(unittest.TestCase):
+    def setUp(self):
+        self.parser = CLIParser(RootNode.from_dict(_SCHEMA), prog="prog")
+        patcher = mock.patch.dict("os.environ", clear=True)
+        patcher.start()
+        self.addCleanup(patcher.stop)
+
+    def set_env(self, **env: str) -> None:
+        patcher = mock.patch.dict("os.environ", env)
+        patcher.start()
+        self.addCleanup(patcher.stop)
+
+    def test_unset(self):
+        namespace = assert_parses(self.parser, [], host="localhost", port=None)
+        self.assertIs(namespace.arg_sources["host"], ArgSource.DEFAULT)
+
+    def test_env(self):
+        self.set_env(TEST_HOST="example.com", TEST_PORT="8080")
+        namespace = assert_parses(self.parser, [], host="example.com", port=8080)
+        self.assertIs(namespace.arg_sources["host"], ArgSource.ENV)
+        self.assertIs(namespace.arg_sources["port"], ArgSource.ENV)
+
+    def test_cli_takes_precedence(self):
+        self.set_env(TEST_HOST="example.com")
+        namespace = assert_parses(self.parser, ["--host", "cli.com"], host="cli.com")
+        self.assertIs(namespace.arg_sources["host"], ArgSource.CLI)
+
+    def test_flag(self):
+        self.set_env(TEST_DEBUG="true")
+        assert_parses(self.parser, [], debug=True)
+
+    def test_list_split(self):
+        self.set_env(TEST_TAGS="a 'b c'")
+        assert_parses(self.parser, [], tags=["a", "b c"])
+
+    def test_invalid_value(self):
+        self.set_env(TEST_PORT="eighty")
+        stderr = io.StringIO()
+        with contextlib.redirect_stderr(stderr):
+            with self.assertRaises(SystemExit) as ctx:
+                self.parser.parse_args([])
+        self.assertEqual(ctx.exception.code, 2)
+        self.assertIn("environment variable TEST_PORT", stderr.getvalue())
+
+
+if __name__ == "__main__":
+    unittest.main()
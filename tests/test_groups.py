@@ -0,0 +1,90 @@
+"""
+Tests for arg groups, i.e. mutually exclusive args and args that require
+others.
+
+"""
+
+import contextlib
+import io
+import unittest
+from typing import Any, Dict, List
+from unittest import mock
+
+from dcli import CLIParser, RootNode, SchemaError
+from dcli.testing import assert_parses
+
+
+_SCHEMA = {
+    "help": "Arg group tests",
+    "args": [
+        {"name": "json", "help": "JSON", "type": "flag"},
+        {"name": "yaml", "help": "YAML", "type": "flag"},
+        {"name": "csv", "help": "CSV", "type": "flag"},
+        {"name": "user", "help": "User", "env": "TEST_USER"},
+        {"name": "password", "help": "Password"},
+    ],
+    "groups": [
+        {"args": ["json", "yaml", "csv"], "exclusive": True},
+        {"args": ["user"], "requires": ["password"]},
+    ],
+}  # type: Dict[str, Any]
+
+
+class ArgGroupsTest(unittest.TestCase):
+    def setUp(self):
+        self.parser = CLIParser(RootNode.from_dict(_SCHEMA), prog="prog")
+        patcher = mock.patch.dict("os.environ", clear=True)
+        patcher.start()
+        self.addCleanup(patcher.stop)
+
+    def assert_parse_error(self, args: List[str]) -> str:
+        """Assert that parsing args fails with a usage error, returning it."""
+        stderr = io.StringIO()
+        with contextlib.redirect_stderr(stderr):
+            with self.assertRaises(SystemExit) as ctx:
+                self.parser.parse_args(args)
+        self.assertEqual(ctx.exception.code, 2)
+        return stderr.getvalue()
+
+    def test_exclusive_one_given(self):
+        assert_parses(self.parser, ["--yaml"], json=False, yaml=True, csv=False)
+
+    def test_exclusive_none_given(self):
+        assert_parses(self.parser, [], json=False, yaml=False, csv=False)
+
+    def test_exclusive_two_given(self):
+        error = self.assert_parse_error(["--json", "--csv"])
+        self.assertIn("--json and --csv cannot be used together", error)
+
+    def test_exclusive_all_given(self):
+        error = self.assert_parse_error(["--csv", "--yaml", "--json"])
+        self.assertIn("--json, --yaml and --csv cannot be used together", error)
+
+    def test_requires_given(self):
+        assert_parses(self.parser, ["--user", "u", "--password", "p"], user="u")
+
+    def test_requires_missing(self):
+        error = self.assert_parse_error(["--user", "u"])
+        self.assertIn("--user requires --password", error)
+
+    def test_requires_only_needed_when_given(self):
+        assert_parses(self.parser, ["--password", "p"], user=None)
+
+    def test_requires_given_from_env(self):
+        with mock.patch.dict("os.environ", TEST_USER="u"):
+            error = self.assert_parse_error([])
+        self.assertIn("--user requires --password", error)
+
+    def test_exclusive_group_of_one_arg(self):
+        data = dict(_SCHEMA, groups=[{"args": ["json"], "exclusive": True}])
+        with self.assertRaisesRegex(SchemaError, "at least two args"):
+            RootNode.from_dict(data)
+
+    def test_unknown_arg(self):
+        data = dict(_SCHEMA, groups=[{"args": ["json", "xml"], "exclusive": True}])
+        with self.assertRaisesRegex(SchemaError, "unknown arg 'xml'"):
+            RootNode.from_dict(data)
+
+
+if __name__ == "__main__":
+    unittest.main()
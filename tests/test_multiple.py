@@ -0,0 +1,60 @@
+"""
+Tests for args taking multiple values, with 'min' and 'max' counts.
+
+"""
+
+import contextlib
+import io
+import unittest
+from typing import Any, List
+
+from dcli import CLIParser, RootNode
+from dcli.clis import ArgSource
+from dcli.testing import assert_parses
+
+
+def _make_parser(**fields: Any) -> CLIParser:
+    arg = dict({"name": "files", "help": "Files", "multiple": True}, **fields)
+    data = {"help": "Multiple tests", "command": "run", "args": [arg]}
+    return CLIParser(RootNode.from_dict(data), prog="prog")
+
+
+class MultipleTest(unittest.TestCase):
+    def assert_parse_error(self, parser: CLIParser, args: List[str]) -> str:
+        """Assert that parsing args fails with a usage error, returning it."""
+        stderr = io.StringIO()
+        with contextlib.redirect_stderr(stderr):
+            with self.assertRaises(SystemExit) as ctx:
+                parser.parse_args(args)
+        self.assertEqual(ctx.exception.code, 2)
+        return stderr.getvalue()
+
+    def test_option(self):
+        parser = _make_parser()
+        assert_parses(parser, ["--files", "a", "b"], files=["a", "b"])
+        assert_parses(parser, [], files=None)
+
+    def test_positional_required(self):
+        parser = _make_parser(positional=True)
+        assert_parses(parser, ["a", "b"], files=["a", "b"])
+        self.assert_parse_error(parser, [])
+
+    def test_positional_min_zero(self):
+        parser = _make_parser(positional=True, min=0)
+        namespace = assert_parses(parser, [], files=[])
+        self.assertIs(namespace.arg_sources["files"], ArgSource.DEFAULT)
+        assert_parses(parser, ["a"], files=["a"])
+
+    def test_positional_default(self):
+        parser = _make_parser(positional=True, min=0, default="x")
+        assert_parses(parser, [], files=["x"])
+
+    def test_counts(self):
+        parser = _make_parser(positional=True, min=2, max=3)
+        assert_parses(parser, ["a", "b", "c"], files=["a", "b", "c"])
+        self.assert_parse_error(parser, ["a"])
+        self.assert_parse_error(parser, ["a", "b", "c", "d"])
+
+
+if __name__ == "__main__":
+    unittest.main()
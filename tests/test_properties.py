@@ -0,0 +1,242 @@
+"""
+Property-based tests of the schema loader and the arg parser, with schemas
+and args generated by hypothesis (installed with the 'test' extra).
+
+These check that for any generated schema:
+ - parsing any args either succeeds or fails with a usage error;
+ - args that parse are parsed the same way after being normalized with
+   `dcli.canonicalize()`, which gives the same args when applied again;
+ - loading the schema data with a field replaced only ever raises
+   SchemaError.
+
+See also 'fuzz/fuzz_parser.py', for running many more random cases than is
+practical in the tests.
+
+"""
+
+import contextlib
+import copy
+import io
+import string
+import unittest
+from typing import Any, Dict, List, Optional
+
+import dcli
+from dcli._schema import NodeBase
+from dcli.clis import Namespace
+
+
+try:
+    from hypothesis import given, settings
+    from hypothesis import strategies as st
+except ImportError:
+    raise unittest.SkipTest("hypothesis is not installed")
+
+
+# Attributes of a parsed namespace that aren't arg values.
+_INTERNAL_ATTRS = ("arg_sources", "command_node", "readers", "remaining_args")
+
+_CHARS = string.ascii_letters + string.digits + "-_=.,:/ @~*é☃"
+
+_TYPES = ["string", "integer", "float", "path", "duration", "size", "boolean"]
+
+_SHORTS = string.ascii_letters.replace("h", "")
+
+
+@st.composite
+def _names(draw) -> str:
+    name = draw(st.text(alphabet=string.ascii_lowercase, min_size=1, max_size=6))
+    if draw(st.booleans()):
+        name += "-" + draw(st.sampled_from(string.ascii_lowercase))
+    return name
+
+
+_unique_names = st.lists(
+    _names().filter(lambda x: x not in ("help", "h")), unique=True, max_size=6
+)
+
+_texts = st.text(alphabet=_CHARS, max_size=8)
+
+
+@st.composite
+def _values(draw, type_: str) -> str:
+    """A value for an arg of a type, which may be invalid."""
+    if draw(st.integers(0, 9)) == 0:
+        return draw(_texts)
+    if type_ == "integer":
+        return str(draw(st.integers(-1000, 1000)))
+    if type_ == "float":
+        return str(draw(st.integers(-10 ** 5, 10 ** 5)) / 1000)
+    if type_ == "duration":
+        unit = draw(st.sampled_from(["s", "m", "h", ""]))
+        return "{}{}".format(draw(st.integers(0, 90)), unit)
+    if type_ == "size":
+        unit = draw(st.sampled_from(["", "K", "MiB"]))
+        return "{}{}".format(draw(st.integers(0, 2048)), unit)
+    if type_ == "boolean":
+        return draw(st.sampled_from(["true", "false", "yes", "no"]))
+    if type_ == "map":
+        return "{}={}".format(draw(st.sampled_from("abc")), draw(_texts))
+    return draw(_texts)
+
+
+@st.composite
+def _args(draw, name: str, *, positional: bool, last: bool) -> Dict[str, Any]:
+    arg = {"name": name, "help": "Generated arg"}  # type: Dict[str, Any]
+    if positional:
+        arg["positional"] = True
+        arg["type"] = draw(st.sampled_from(_TYPES))
+        # Only the last positional may take multiple values or have a default.
+        if last and draw(st.booleans()):
+            if draw(st.booleans()):
+                arg["multiple"] = True
+            elif arg["type"] == "string":
+                arg["default"] = draw(_names())
+        return arg
+    arg["type"] = draw(st.sampled_from(_TYPES + ["flag", "map"]))
+    if arg["type"] == "flag":
+        arg["count"] = draw(st.booleans())
+    elif arg["type"] == "map":
+        return arg
+    else:
+        kind = draw(st.sampled_from(["single", "repeat", "multiple", "enum"]))
+        if kind == "enum" and arg["type"] == "string":
+            arg["enum"] = draw(st.lists(_names(), min_size=1, max_size=3, unique=True))
+        elif kind in ("repeat", "multiple"):
+            arg[kind] = True
+    return arg
+
+
+@st.composite
+def _nodes(draw, depth: int = 0) -> Dict[str, Any]:
+    """A schema node, along with its subtree."""
+    names = draw(_unique_names)
+    has_subtree = depth < 2 and len(names) > 0 and draw(st.booleans())
+    keywords = names[: draw(st.integers(1, 3))] if has_subtree else []
+    arg_names = names[len(keywords) :]
+    # Args of nodes with subcommands can't be positional.
+    num_positionals = 0 if has_subtree else draw(st.integers(0, 2))
+    args = []
+    shorts = set()
+    for i, name in enumerate(arg_names):
+        positional = i < num_positionals
+        arg = draw(
+            _args(name, positional=positional, last=i == num_positionals - 1)
+        )
+        if not positional and draw(st.booleans()):
+            short = draw(st.sampled_from(_SHORTS))
+            if short not in shorts:
+                arg["short"] = short
+                shorts.add(short)
+        args.append(arg)
+    node = {"help": "Generated node", "args": args}  # type: Dict[str, Any]
+    if keywords:
+        node["subtree"] = []
+        for keyword in keywords:
+            subnode = draw(_nodes(depth + 1))
+            subnode["keyword"] = keyword
+            node["subtree"].append(subnode)
+    else:
+        node["command"] = "cmd-{}".format(draw(st.integers(0, 10 ** 6)))
+    return node
+
+
+def _load(data: Dict[str, Any]) -> Optional[dcli.RootNode]:
+    """Load generated schema data, or None if it's invalid."""
+    try:
+        return dcli.RootNode.from_dict(copy.deepcopy(data))
+    except dcli.SchemaError:
+        # The strategy doesn't avoid every invalid combination of fields.
+        return None
+
+
+@st.composite
+def _argvs(draw, schema: dcli.RootNode) -> List[str]:
+    """Args mostly made up of the schema's keywords and options."""
+    node = schema  # type: NodeBase
+    argv = []
+    while node.subtree and draw(st.integers(0, 9)) > 0:
+        node = draw(st.sampled_from(node.subtree))
+        argv.append(node.keyword)
+    for _ in range(draw(st.integers(0, 8))):
+        if not node.all_args or draw(st.integers(0, 9)) == 0:
+            argv.append(draw(st.sampled_from(["--", "-", "-x", "--y"]) | _texts))
+            continue
+        arg = draw(st.sampled_from(node.all_args))
+        if arg.positional:
+            argv.append(draw(_values(arg.type.value)))
+        elif arg.type.takes_value:
+            option = draw(st.sampled_from(arg.option_strings))
+            if arg.enum:
+                value = draw(st.sampled_from(arg.enum))
+            else:
+                value = draw(_values(arg.type.value))
+            if option.startswith("--") and draw(st.booleans()):
+                argv.append("{}={}".format(option, value))
+            else:
+                argv += [option, value]
+        else:
+            argv.append(draw(st.sampled_from(arg.option_strings)))
+    return argv
+
+
+def _parse(parser: dcli.CLIParser, argv: List[str]) -> Optional[Namespace]:
+    """Parse args, returning None for a usage error."""
+    output = io.StringIO()
+    with contextlib.redirect_stderr(output), contextlib.redirect_stdout(output):
+        try:
+            return parser.parse_args(argv)
+        except SystemExit:
+            return None
+
+
+def _parsed_values(namespace: Namespace) -> Dict[str, Any]:
+    return {k: v for k, v in vars(namespace).items() if k not in _INTERNAL_ATTRS}
+
+
+class ParsePropertiesTest(unittest.TestCase):
+    @settings(max_examples=200, deadline=None)
+    @given(st.data())
+    def test_parse_only_raises_usage_errors(self, data):
+        schema = _load(data.draw(_nodes()))
+        if schema is None:
+            return
+        parser = dcli.CLIParser(schema, prog="prop")
+        # Any exception other than SystemExit fails the test.
+        _parse(parser, data.draw(_argvs(schema)))
+
+    @settings(max_examples=200, deadline=None)
+    @given(st.data())
+    def test_canonicalize_round_trip(self, data):
+        schema = _load(data.draw(_nodes()))
+        if schema is None:
+            return
+        parser = dcli.CLIParser(schema, prog="prop")
+        argv = data.draw(_argvs(schema))
+        namespace = _parse(parser, argv)
+        if namespace is None:
+            return
+        node = namespace.command_node
+        canonical = dcli.canonicalize(node, namespace)
+        reparsed = _parse(parser, canonical)
+        message = "{!r} normalized to {!r}".format(argv, canonical)
+        self.assertIsNotNone(reparsed, message)
+        self.assertEqual(_parsed_values(reparsed), _parsed_values(namespace))
+        self.assertEqual(dcli.canonicalize(node, reparsed), canonical)
+
+
+class LoadPropertiesTest(unittest.TestCase):
+    @settings(max_examples=200, deadline=None)
+    @given(
+        _nodes(),
+        st.sampled_from(["help", "args", "subtree", "command", "keyword", "x"]),
+        st.sampled_from([None, 1, -1, "", "x", [], {}, True, 1.5, {"name": "x"}]),
+    )
+    def test_invalid_field_only_raises_schema_error(self, data, key, value):
+        data[key] = value
+        # Any exception other than SchemaError fails the test.
+        _load(data)
+
+
+if __name__ == "__main__":
+    unittest.main()
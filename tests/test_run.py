@@ -0,0 +1,116 @@
+"""
+Tests for running parsed commands with `run_and_exit()`.
+
+"""
+
+import contextlib
+import copy
+import io
+import json
+import os.path
+import tempfile
+import unittest
+from typing import Any, Dict, List, Tuple
+
+from dcli import CLIParser, Dispatcher, RootNode, run_and_exit
+
+
+# Two leaves running the same command, with different settings.
+_SHARED_COMMAND_SCHEMA = {
+    "help": "Shared command tests",
+    "subtree": [
+        {
+            "keyword": "prod",
+            "help": "Production",
+            "requires_role": "admin",
+            "subtree": [
+                {
+                    "keyword": "deploy",
+                    "help": "Deploy to production",
+                    "command": "deploy",
+                    "destructive": True,
+                    "exit_codes": {"failed": 4},
+                }
+            ],
+        },
+        {
+            "keyword": "staging",
+            "help": "Staging",
+            "subtree": [
+                {
+                    "keyword": "deploy",
+                    "help": "Deploy to staging",
+                    "command": "deploy",
+                    "exit_codes": {"failed": 3},
+                }
+            ],
+        },
+    ],
+}  # type: Dict[str, Any]
+
+
+class SharedCommandTest(unittest.TestCase):
+    def setUp(self):
+        self.calls = []  # type: List[Any]
+        self.result = None  # type: Any
+        self.dispatcher = Dispatcher()
+        self.dispatcher.register("deploy", self.deploy)
+
+    def deploy(self, args: Any) -> Any:
+        self.calls.append(args)
+        return self.result
+
+    def run_args(
+        self, args: List[str], data: Dict[str, Any] = _SHARED_COMMAND_SCHEMA
+    ) -> Tuple[int, str]:
+        """Run args with `run_and_exit()`, returning the exit code and stderr."""
+        parser = CLIParser(
+            RootNode.from_dict(copy.deepcopy(data)),
+            prog="prog",
+            role_provider=lambda: ["admin"],
+        )
+        stderr = io.StringIO()
+        with contextlib.redirect_stderr(stderr):
+            with self.assertRaises(SystemExit) as ctx:
+                run_and_exit(parser, self.dispatcher, args, prog="prog")
+        return ctx.exception.code, stderr.getvalue()
+
+    def test_not_destructive(self):
+        self.assertEqual(self.run_args(["staging", "deploy"]), (0, ""))
+        self.assertEqual(len(self.calls), 1)
+
+    def test_destructive(self):
+        code, stderr = self.run_args(["prod", "deploy"])
+        self.assertNotEqual(code, 0)
+        self.assertIn("without confirmation", stderr)
+        self.assertEqual(self.calls, [])
+
+    def test_destructive_confirmed(self):
+        self.assertEqual(self.run_args(["prod", "deploy", "--yes"]), (0, ""))
+        self.assertEqual(len(self.calls), 1)
+
+    def test_exit_code(self):
+        self.result = "failed"
+        self.assertEqual(self.run_args(["staging", "deploy"]), (3, ""))
+        self.assertEqual(self.run_args(["prod", "deploy", "--yes"]), (4, ""))
+
+    def test_exit_code_in_subtree_file(self):
+        deploy = {
+            "keyword": "deploy",
+            "help": "Deploy to development",
+            "command": "deploy",
+            "exit_codes": {"failed": 5},
+        }
+        data = copy.deepcopy(_SHARED_COMMAND_SCHEMA)
+        self.result = "failed"
+        with tempfile.TemporaryDirectory() as tmpdir:
+            path = os.path.join(tmpdir, "dev.json")
+            with open(path, "w") as f:
+                json.dump({"subtree": [deploy]}, f)
+            data["subtree"].append(
+                {"keyword": "dev", "help": "Development", "subtree_file": path}
+            )
+            self.assertEqual(self.run_args(["dev", "deploy"], data), (5, ""))
+
+if __name__ == "__main__":
+    unittest.main()
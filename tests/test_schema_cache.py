@@ -0,0 +1,72 @@
+"""
+Tests for caching loaded schemas with `load_from_path(cache_dir=...)`.
+
+"""
+
+import json
+import pathlib
+import pickle
+import tempfile
+import unittest
+from unittest import mock
+
+from dcli import RootNode, load_from_path
+
+
+class SchemaCacheTest(unittest.TestCase):
+    def setUp(self):
+        tmp_dir = tempfile.TemporaryDirectory()
+        self.addCleanup(tmp_dir.cleanup)
+        self.tmp_path = pathlib.Path(tmp_dir.name)
+        self.cache_dir = self.tmp_path / "cache"
+        self.schema_path = self.tmp_path / "cli.json"
+        self.write_schema("Cached")
+
+    def write_schema(self, help_text: str) -> None:
+        with open(str(self.schema_path), "w") as f:
+            json.dump({"help": help_text, "command": "run"}, f)
+
+    def load(self) -> RootNode:
+        return load_from_path(self.schema_path, cache_dir=self.cache_dir)
+
+    def get_cache_files(self):
+        return list(self.cache_dir.glob("*.pickle"))
+
+    def test_cached(self):
+        self.assertEqual(self.load().help, "Cached")
+        self.assertEqual(len(self.get_cache_files()), 1)
+        with mock.patch("dcli._schema._load_schema_file") as load_file:
+            self.assertEqual(self.load().help, "Cached")
+        load_file.assert_not_called()
+
+    def test_file_changed(self):
+        self.load()
+        self.write_schema("Changed")
+        self.assertEqual(self.load().help, "Changed")
+
+    def test_corrupt_cache(self):
+        self.load()
+        (cache_file,) = self.get_cache_files()
+        schema = RootNode.from_dict({"help": "Other", "command": "run"})
+        for content in [b"not a pickle", pickle.dumps((["sources"], schema))]:
+            cache_file.write_bytes(content)
+            self.assertEqual(self.load().help, "Cached")
+        # The cache is rewritten.
+        with mock.patch("dcli._schema._load_schema_file") as load_file:
+            self.load()
+        load_file.assert_not_called()
+
+    def test_unpickling_error(self):
+        self.load()
+        with mock.patch("pickle.load", side_effect=AttributeError("no attr")):
+            self.assertEqual(self.load().help, "Cached")
+
+    def test_package_version_in_key(self):
+        self.load()
+        with mock.patch("dcli._schema.__version__", "999.0"):
+            self.load()
+        self.assertEqual(len(self.get_cache_files()), 2)
+
+
+if __name__ == "__main__":
+    unittest.main()
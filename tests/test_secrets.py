@@ -0,0 +1,122 @@
+"""
+Tests for secret args, whose values are wrapped in `Secret` and redacted from
+the recorded args and error messages.
+
+"""
+
+import contextlib
+import io
+import unittest
+from typing import Any, Dict, List
+from unittest import mock
+
+from dcli import CLIParser, RootNode, SchemaError, Secret, bot_cli
+from dcli.testing import assert_parses
+
+
+_SCHEMA = {
+    "help": "Secret tests",
+    "args": [
+        {"name": "token", "short": "t", "help": "T", "secret": True, "env": "TOK"},
+        {"name": "name", "short": "n", "help": "Name"},
+        {"name": "port", "short": "p", "help": "Port", "type": "integer"},
+        {"name": "verbose", "short": "v", "help": "Verbose", "type": "flag"},
+        {"name": "rest", "help": "R", "positional": True, "multiple": True, "min": 0},
+    ],
+}  # type: Dict[str, Any]
+
+_R = Secret.REDACTED
+
+
+class SecretTest(unittest.TestCase):
+    def setUp(self):
+        self.parser = CLIParser(RootNode.from_dict(_SCHEMA), prog="prog")
+        patcher = mock.patch.dict("os.environ", clear=True)
+        patcher.start()
+        self.addCleanup(patcher.stop)
+
+    def assert_redacted(self, args: List[str], expected: List[str]) -> None:
+        namespace = assert_parses(self.parser, args)
+        self.assertEqual(namespace.remaining_args, expected)
+
+    def test_value_wrapped(self):
+        namespace = assert_parses(self.parser, ["--token", "abc"])
+        self.assertEqual(namespace.token, Secret("abc"))
+        self.assertEqual(namespace.token.reveal(), "abc")
+        self.assertEqual(str(namespace.token), _R)
+        self.assertNotIn("abc", repr(namespace.token))
+
+    def test_env_value_wrapped(self):
+        with mock.patch.dict("os.environ", TOK="abc"):
+            assert_parses(self.parser, [], token=Secret("abc"))
+
+    def test_separate_value(self):
+        self.assert_redacted(["--token", "abc", "x"], ["--token", _R, "x"])
+        self.assert_redacted(["-t", "abc"], ["-t", _R])
+
+    def test_attached_value(self):
+        self.assert_redacted(["--token=abc"], ["--token=" + _R])
+        self.assert_redacted(["-tabc"], ["-t" + _R])
+        self.assert_redacted(["-t=abc"], ["-t=" + _R])
+
+    def test_cluster(self):
+        self.assert_redacted(["-vtabc"], ["-vt" + _R])
+        self.assert_redacted(["-vt", "abc"], ["-vt", _R])
+
+    def test_abbreviated_option(self):
+        self.assert_redacted(["--tok", "abc"], ["--tok", _R])
+        self.assert_redacted(["--tok=abc"], ["--tok=" + _R])
+
+    def test_other_args_containing_value(self):
+        self.assert_redacted(
+            ["--token", "a", "--name=bla", "-na", "a"],
+            ["--token", _R, "--name=bla", "-na", "a"],
+        )
+        self.assert_redacted(["-t", "80", "-p", "80"], ["-t", _R, "-p", "80"])
+
+    def test_value_of_other_option(self):
+        self.assert_redacted(["-ntabc"], ["-ntabc"])
+        self.assert_redacted(["--name=-tabc"], ["--name=-tabc"])
+
+    def test_after_double_dash(self):
+        self.assert_redacted(["-t", "abc", "--", "-tabc"], ["-t", _R, "--", "-tabc"])
+
+    def test_error_message_redacted(self):
+        data = dict(
+            _SCHEMA,
+            args=[
+                {
+                    "name": "pin",
+                    "help": "PIN",
+                    "secret": True,
+                    "validate": {"regex": "[0-9]+"},
+                }
+            ],
+        )
+        parser = CLIParser(RootNode.from_dict(data), prog="prog")
+        stderr = io.StringIO()
+        with contextlib.redirect_stderr(stderr):
+            with self.assertRaises(SystemExit):
+                parser.parse_args(["--pin", "12ab"])
+        self.assertNotIn("12ab", stderr.getvalue())
+        self.assertIn(_R, stderr.getvalue())
+
+    def test_positional_secret(self):
+        data = {
+            "help": "Positional secret",
+            "args": [{"name": "t", "help": "T", "positional": True, "secret": True}],
+        }
+        with self.assertRaises(SchemaError):
+            RootNode.from_dict(data)
+
+
+class BotSecretTest(unittest.TestCase):
+    def test_redacted(self):
+        parser = bot_cli.CLIParser(RootNode.from_dict(_SCHEMA), prog="prog")
+        namespace = parser.parse_args(["token", "abc", "name", "abc"])
+        self.assertEqual(namespace.token, Secret("abc"))
+        self.assertEqual(namespace.remaining_args, ["token", _R, "name", "abc"])
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,164 @@
+"""
+Tests for how the parser splits args into options and values, i.e. short
+option clusters, attached values and the 'short_clusters' setting.
+
+"""
+
+import contextlib
+import io
+import unittest
+from typing import Any, Dict, List
+
+from dcli import CLIParser, RootNode
+from dcli.testing import assert_parses
+
+
+def _make_parser(**settings: Any) -> CLIParser:
+    data = {
+        "help": "Tokenizer tests",
+        "args": [
+            {"name": "all", "short": "a", "help": "All", "type": "flag"},
+            {"name": "brief", "short": "b", "help": "Brief", "type": "flag"},
+            {"name": "count", "short": "c", "help": "Count", "type": "flag"},
+            {"name": "file", "short": "f", "help": "File"},
+            {"name": "level", "help": "Level", "type": "integer"},
+            {
+                "name": "rest",
+                "help": "Rest",
+                "positional": True,
+                "multiple": True,
+                "min": 0,
+            },
+        ],
+    }  # type: Dict[str, Any]
+    data.update(settings)
+    return CLIParser(RootNode.from_dict(data), prog="prog")
+
+
+class _ParserTestCase(unittest.TestCase):
+    settings = {}  # type: Dict[str, Any]
+
+    def setUp(self):
+        self.parser = _make_parser(**self.settings)
+
+    def assert_parse_error(self, args: List[str]) -> str:
+        """Assert that parsing args fails with a usage error, returning it."""
+        stderr = io.StringIO()
+        with contextlib.redirect_stderr(stderr):
+            with self.assertRaises(SystemExit) as ctx:
+                self.parser.parse_args(args)
+        self.assertEqual(ctx.exception.code, 2)
+        return stderr.getvalue()
+
+
+class ShortClustersTest(_ParserTestCase):
+    def test_separate_flags(self):
+        assert_parses(self.parser, ["-a", "-b"], all=True, brief=True, count=False)
+
+    def test_cluster(self):
+        assert_parses(self.parser, ["-abc"], all=True, brief=True, count=True)
+
+    def test_cluster_order(self):
+        assert_parses(self.parser, ["-ca"], all=True, brief=False, count=True)
+
+    def test_cluster_ending_with_option_value(self):
+        assert_parses(self.parser, ["-abf", "x.txt"], all=True, file="x.txt")
+
+    def test_cluster_with_attached_value(self):
+        assert_parses(self.parser, ["-abfx.txt"], all=True, file="x.txt")
+
+    def test_unknown_flag_in_cluster(self):
+        error = self.assert_parse_error(["-az"])
+        self.assertIn("ignored explicit argument 'z'", error)
+
+
+class OptionValueTest(_ParserTestCase):
+    def test_short_separate_value(self):
+        assert_parses(self.parser, ["-f", "x.txt"], file="x.txt")
+
+    def test_short_attached_value(self):
+        assert_parses(self.parser, ["-fx.txt"], file="x.txt")
+
+    def test_short_equals_value(self):
+        assert_parses(self.parser, ["-f=x.txt"], file="x.txt")
+
+    def test_long_separate_value(self):
+        assert_parses(self.parser, ["--file", "x.txt"], file="x.txt")
+
+    def test_long_equals_value(self):
+        assert_parses(self.parser, ["--file=x.txt"], file="x.txt")
+
+    def test_long_equals_value_containing_equals(self):
+        assert_parses(self.parser, ["--file=a=b"], file="a=b")
+
+    def test_long_equals_empty_value(self):
+        assert_parses(self.parser, ["--file="], file="")
+
+    def test_long_equals_converted_value(self):
+        assert_parses(self.parser, ["--level=3"], level=3)
+
+    def test_missing_value(self):
+        self.assertIn("--file", self.assert_parse_error(["--file"]))
+
+    def test_forms_agree(self):
+        forms = [["-f", "x"], ["-fx"], ["--file", "x"], ["--file=x"]]
+        results = []
+        for args in forms:
+            result = vars(self.parser.parse_args(args))
+            # The args as given, which differ between the forms.
+            del result["remaining_args"]
+            results.append(result)
+        for args, result in zip(forms[1:], results[1:]):
+            with self.subTest(args=args):
+                self.assertEqual(result, results[0])
+
+    def test_options_after_double_dash(self):
+        assert_parses(self.parser, ["--", "-abc", "--file=x"], all=False, file=None)
+
+
+class NoShortClustersTest(_ParserTestCase):
+    settings = {"short_clusters": False}
+
+    def test_separate_flags(self):
+        assert_parses(self.parser, ["-a", "-b", "-c"], all=True, brief=True)
+
+    def test_cluster_rejected(self):
+        error = self.assert_parse_error(["-abc"])
+        self.assertIn("short options can't be combined", error)
+
+    def test_attached_value_rejected(self):
+        error = self.assert_parse_error(["-fx.txt"])
+        self.assertIn("short options can't be combined", error)
+
+    def test_short_separate_value(self):
+        assert_parses(self.parser, ["-f", "x.txt"], file="x.txt")
+
+    def test_short_equals_value(self):
+        assert_parses(self.parser, ["-f=x.txt"], file="x.txt")
+
+    def test_long_equals_value(self):
+        assert_parses(self.parser, ["--file=x.txt"], file="x.txt")
+
+    def test_cluster_after_double_dash(self):
+        assert_parses(self.parser, ["--", "-abc"], all=False)
+
+    def test_multi_char_short_name(self):
+        data = {
+            "help": "Multi-char short names",
+            "short_clusters": False,
+            "args": [{"name": "verbose", "short": "vv", "help": "V", "type": "flag"}],
+        }
+        parser = CLIParser(RootNode.from_dict(data), prog="prog")
+        assert_parses(parser, ["-vv"], verbose=True)
+
+    def test_multi_char_short_name_requires_setting(self):
+        data = {
+            "help": "Multi-char short names",
+            "args": [{"name": "verbose", "short": "vv", "help": "V", "type": "flag"}],
+        }
+        with self.assertRaisesRegex(Exception, "short_clusters"):
+            RootNode.from_dict(data)
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,73 @@
+"""
+Tests for help text with translations, selected with '--lang'.
+
+"""
+
+import contextlib
+import copy
+import io
+import pickle
+import unittest
+from typing import Any, Dict, List
+from unittest import mock
+
+from dcli import CLIParser, RootNode
+
+
+_SCHEMA = {
+    "help": {"en": "Greet people", "fr": "Saluer les gens"},
+    "subtree": [
+        {
+            "keyword": "hello",
+            "help": {"en": "Say hello", "fr": "Dire bonjour"},
+            "command": "hello",
+            "args": [{"name": "name", "help": {"en": "The name", "fr": "Le nom"}}],
+        }
+    ],
+}  # type: Dict[str, Any]
+
+
+class TranslationsTest(unittest.TestCase):
+    def setUp(self):
+        patcher = mock.patch.dict("os.environ", clear=True)
+        patcher.start()
+        self.addCleanup(patcher.stop)
+        self.schema = RootNode.from_dict(_SCHEMA)
+        self.parser = CLIParser(self.schema, prog="prog")
+
+    def get_help(self, args: List[str]) -> str:
+        stdout = io.StringIO()
+        with contextlib.redirect_stdout(stdout):
+            with self.assertRaises(SystemExit):
+                self.parser.parse_args(args)
+        return stdout.getvalue()
+
+    def test_default_lang(self):
+        output = self.get_help(["hello", "--help"])
+        self.assertIn("Say hello", output)
+        self.assertIn("The name", output)
+
+    def test_lang_option(self):
+        output = self.get_help(["--lang", "fr_CA", "hello", "--help"])
+        self.assertIn("Dire bonjour", output)
+        self.assertIn("Le nom", output)
+
+    def test_schema_not_modified(self):
+        self.get_help(["--lang", "fr", "hello", "--help"])
+        self.assertEqual(self.schema.help, "Greet people")
+        self.assertEqual(self.schema.subtree[0].args[0].help, "The name")
+        self.assertIn("Say hello", self.get_help(["hello", "--help"]))
+
+    def test_copied(self):
+        self.schema.localize("fr")
+        translations = _SCHEMA["subtree"][0]["help"]
+        for schema in [
+            copy.deepcopy(self.schema),
+            pickle.loads(pickle.dumps(self.schema)),
+        ]:
+            self.assertEqual(schema.subtree[0].help, "Dire bonjour")
+            self.assertEqual(schema.subtree[0].help.translations, translations)
+
+
+if __name__ == "__main__":
+    unittest.main()
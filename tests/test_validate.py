@@ -0,0 +1,85 @@
+"""
+Tests for checking schemas with `validate()`, and for the errors reported when
+loading invalid schemas.
+
+"""
+
+import unittest
+from typing import Any, Dict
+
+from dcli import RootNode, SchemaError, SchemaValidationError, validate
+
+
+def _leaf(keyword: str, command: Any) -> Dict[str, Any]:
+    return {"keyword": keyword, "help": keyword.title(), "command": command}
+
+
+class DuplicateCommandTest(unittest.TestCase):
+    def test_duplicate_command(self):
+        prod = {"keyword": "prod", "help": "P", "subtree": [_leaf("deploy", "deploy")]}
+        staging = {
+            "keyword": "staging",
+            "help": "S",
+            "subtree": [_leaf("up", "deploy")],
+        }
+        data = {"help": "Duplicate commands", "subtree": [prod, staging]}
+        problems = validate(RootNode.from_dict(data))
+        self.assertEqual(len(problems), 1)
+        self.assertIn("staging.up", problems[0])
+        self.assertIn(
+            "duplicate command 'deploy', also run by 'prod.deploy'", problems[0]
+        )
+
+    def test_duplicate_external_command(self):
+        data = {
+            "help": "Duplicate external commands",
+            "subtree": [_leaf("a", ["make", "all"]), _leaf("b", ["make", "all"])],
+        }
+        self.assertEqual(validate(RootNode.from_dict(data)), [])
+
+    def test_unique_commands(self):
+        data = {
+            "help": "Unique commands",
+            "subtree": [_leaf("a", "run-a"), _leaf("b", "run-b")],
+        }
+        self.assertEqual(validate(RootNode.from_dict(data)), [])
+
+
+class LoadErrorsTest(unittest.TestCase):
+    def test_single_error(self):
+        data = {"help": "One error", "subtree": [{"keyword": "a", "command": "a"}]}
+        with self.assertRaises(SchemaError) as ctx:
+            RootNode.from_dict(data)
+        self.assertNotIsInstance(ctx.exception, SchemaValidationError)
+        self.assertIn("missing required key 'help'", str(ctx.exception))
+
+    def test_errors_collected(self):
+        data = {
+            "help": "Several errors",
+            "subtree": [
+                {"keyword": "a", "command": "a"},
+                {
+                    "keyword": "b",
+                    "help": "B",
+                    "command": "b",
+                    "args": [{"name": "x"}, {"name": "y", "help": "Y", "type": "?"}],
+                },
+                {
+                    "keyword": "c",
+                    "help": "C",
+                    "subtree": [{"keyword": "d", "help": "D", "typo": True}],
+                },
+            ],
+        }
+        with self.assertRaises(SchemaValidationError) as ctx:
+            RootNode.from_dict(data)
+        problems = ctx.exception.problems
+        self.assertEqual(len(problems), 4, problems)
+        self.assertIn("missing required key 'help'", problems[0])
+        self.assertIn("b: args[0]", problems[1])
+        self.assertIn("b: args[1]", problems[2])
+        self.assertIn("unknown key 'typo'", problems[3])
+
+
+if __name__ == "__main__":
+    unittest.main()